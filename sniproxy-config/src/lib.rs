@@ -25,6 +25,12 @@ pub struct Config {
     /// Connection pooling configuration (optional)
     #[serde(default)]
     pub connection_pool: Option<ConnectionPool>,
+    /// Pooled backend HTTP/2 channels for gRPC over h2c (optional)
+    #[serde(default)]
+    pub grpc_pool: Option<GrpcPool>,
+    /// Limits and keep-alive for WebSocket tunnels (optional)
+    #[serde(default)]
+    pub websocket: Option<WebSocket>,
     /// Protocol routing configuration for web protocols (optional)
     #[serde(default)]
     pub protocol_routing: Option<ProtocolRouting>,
@@ -40,6 +46,21 @@ pub struct Config {
     /// SSH port routing configuration (optional)
     #[serde(default)]
     pub ssh_routes: Option<Vec<SshRoute>>,
+    /// UDP port forwarding for datagrams without SNI (optional)
+    #[serde(default)]
+    pub udp_routes: Option<Vec<UdpRoute>>,
+    /// Per-host route configuration matched against SNI or Host header (optional)
+    #[serde(default)]
+    pub routes: Option<Vec<Route>>,
+    /// Error responses sent to plain HTTP clients (optional)
+    #[serde(default)]
+    pub error_responses: Option<ErrorResponses>,
+    /// Certificates for routes in TLS termination mode (optional)
+    #[serde(default)]
+    pub tls_certificates: Option<TlsCertificates>,
+    /// Automatic certificates for terminate-mode routes via ACME (optional)
+    #[serde(default)]
+    pub acme: Option<AcmeConfig>,
 }
 
 /// Connection pooling configuration.
@@ -94,6 +115,161 @@ impl Default for ConnectionPool {
     }
 }
 
+/// Backend channel pooling for gRPC over h2c.
+///
+/// When enabled, the proxy terminates the client's HTTP/2 connection and
+/// multiplexes its calls onto long-lived HTTP/2 connections ("channels") to
+/// each backend instead of opening one backend connection per client.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcPool {
+    /// Enable gRPC channel pooling (default: true)
+    #[serde(default = "default_pool_enabled")]
+    pub enabled: bool,
+    /// Maximum channels per backend (default: 10)
+    #[serde(default = "default_grpc_max_channels")]
+    pub max_channels_per_host: usize,
+    /// Maximum concurrent calls per channel (default: 100)
+    #[serde(default = "default_grpc_max_streams")]
+    pub max_concurrent_streams: usize,
+    /// Channel lifetime in seconds (default: 300)
+    #[serde(default = "default_grpc_channel_ttl")]
+    pub channel_ttl: u64,
+    /// Close channels without calls for this many seconds (default: 120)
+    #[serde(default = "default_grpc_idle_timeout")]
+    pub idle_timeout: u64,
+    /// Seconds between grpc.health.v1 checks of each channel (default: 30)
+    #[serde(default = "default_grpc_health_check_interval")]
+    pub health_check_interval: u64,
+}
+
+fn default_grpc_max_channels() -> usize {
+    10
+}
+
+fn default_grpc_max_streams() -> usize {
+    100
+}
+
+fn default_grpc_channel_ttl() -> u64 {
+    300
+}
+
+fn default_grpc_idle_timeout() -> u64 {
+    120
+}
+
+fn default_grpc_health_check_interval() -> u64 {
+    30
+}
+
+impl Default for GrpcPool {
+    fn default() -> Self {
+        Self {
+            enabled: default_pool_enabled(),
+            max_channels_per_host: default_grpc_max_channels(),
+            max_concurrent_streams: default_grpc_max_streams(),
+            channel_ttl: default_grpc_channel_ttl(),
+            idle_timeout: default_grpc_idle_timeout(),
+            health_check_interval: default_grpc_health_check_interval(),
+        }
+    }
+}
+
+/// Limits and keep-alive for WebSocket tunnels.
+///
+/// After a WebSocket handshake completes, the proxy relays frames instead of
+/// raw bytes. Without this section the defaults apply.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSocket {
+    /// Largest message, summed over its fragments, in bytes (default: 16 MiB)
+    #[serde(default = "default_websocket_max_message_size")]
+    pub max_message_size: u64,
+    /// Close tunnels where a side sent nothing for this many seconds (default: 300)
+    #[serde(default = "default_websocket_idle_timeout")]
+    pub idle_timeout: u64,
+    /// Ping a side that has been quiet for this many seconds, 0 to disable (default: 30)
+    #[serde(default = "default_websocket_ping_interval")]
+    pub ping_interval: u64,
+    /// Negotiate permessage-deflate with clients on the proxy's side (optional)
+    #[serde(default)]
+    pub compression: Option<WebSocketCompression>,
+}
+
+fn default_websocket_max_message_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_websocket_idle_timeout() -> u64 {
+    300
+}
+
+fn default_websocket_ping_interval() -> u64 {
+    30
+}
+
+impl Default for WebSocket {
+    fn default() -> Self {
+        Self {
+            max_message_size: default_websocket_max_message_size(),
+            idle_timeout: default_websocket_idle_timeout(),
+            ping_interval: default_websocket_ping_interval(),
+            compression: None,
+        }
+    }
+}
+
+/// permessage-deflate (RFC 7692) between the proxy and WebSocket clients
+///
+/// When the backend's 101 response accepts no extension, the proxy accepts the
+/// client's permessage-deflate offer itself and compresses and decompresses
+/// messages in flight; the backend keeps exchanging uncompressed frames.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSocketCompression {
+    /// Compression level 0-9 (default: 6)
+    #[serde(default = "default_compression_level")]
+    pub compression_level: u32,
+    /// Reset the proxy's compression context after every message (default: false)
+    #[serde(default)]
+    pub server_no_context_takeover: bool,
+    /// Ask clients to reset their compression context after every message (default: false)
+    #[serde(default)]
+    pub client_no_context_takeover: bool,
+    /// LZ77 window the proxy compresses with, 8-15 bits (default: 15)
+    #[serde(default = "default_max_window_bits")]
+    pub server_max_window_bits: u8,
+    /// Window clients may compress with when they allow limiting it, 8-15 bits (default: 15)
+    #[serde(default = "default_max_window_bits")]
+    pub client_max_window_bits: u8,
+    /// Messages to clients smaller than this many bytes are sent uncompressed (default: 256)
+    #[serde(default = "default_min_compress_size")]
+    pub min_compress_size: usize,
+}
+
+fn default_compression_level() -> u32 {
+    6
+}
+
+fn default_max_window_bits() -> u8 {
+    15
+}
+
+fn default_min_compress_size() -> usize {
+    256
+}
+
+impl Default for WebSocketCompression {
+    fn default() -> Self {
+        Self {
+            compression_level: default_compression_level(),
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: default_max_window_bits(),
+            client_max_window_bits: default_max_window_bits(),
+            min_compress_size: default_min_compress_size(),
+        }
+    }
+}
+
 /// Timeout settings for proxy operations (all values in seconds).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Timeouts {
@@ -135,8 +311,7 @@ impl Config {
     /// ```
     pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
         let contents = fs::read_to_string(path)?;
-        let config = serde_yaml_ng::from_str(&contents)?;
-        Ok(config)
+        Self::parse(&contents)
     }
 
     /// Parses configuration from a YAML string.
@@ -168,9 +343,80 @@ impl Config {
     /// assert_eq!(config.listen_addrs[0], "0.0.0.0:443");
     /// ```
     pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = serde_yaml_ng::from_str(contents)?;
+        let config: Self = serde_yaml_ng::from_str(contents)?;
+        config.validate()?;
         Ok(config)
     }
+
+    /// Checks settings that deserialize fine but cannot work together.
+    ///
+    /// `upstream_sni` and `strip_sni` need a TLS handshake with the backend:
+    /// the forwarded ClientHello on passthrough routes, or the proxy's own
+    /// handshake on terminate routes with `upstream_tls`. Terminate routes to a
+    /// plaintext backend are rejected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sniproxy_config::Config;
+    ///
+    /// let yaml = r#"
+    /// listen_addrs: ["0.0.0.0:443"]
+    /// timeouts: { connect: 10, client_hello: 10, idle: 300 }
+    /// metrics: { enabled: false, address: "127.0.0.1:9000" }
+    /// routes:
+    ///   - host: "legacy.example.com"
+    ///     mode: terminate
+    ///     upstream_sni: "new.example.net"
+    /// "#;
+    ///
+    /// assert!(Config::parse(yaml).is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for route in self.routes.iter().flatten() {
+            if (route.upstream_sni.is_some() || route.strip_sni)
+                && route.mode == RouteMode::Terminate
+                && route.upstream_tls.is_none()
+            {
+                return Err(format!(
+                    "route {}: upstream_sni and strip_sni on a terminate route require upstream_tls",
+                    route.host
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the first route whose host pattern matches `host`.
+    ///
+    /// Patterns use the same syntax as the allowlist and are compared
+    /// case-insensitively. Routes are evaluated in configuration order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sniproxy_config::Config;
+    ///
+    /// let yaml = r#"
+    /// listen_addrs: ["0.0.0.0:443"]
+    /// timeouts: { connect: 10, client_hello: 10, idle: 300 }
+    /// metrics: { enabled: false, address: "127.0.0.1:9000" }
+    /// routes:
+    ///   - host: "*.example.com"
+    /// "#;
+    ///
+    /// let config = Config::parse(yaml).unwrap();
+    /// assert!(config.route_for("API.example.com").is_some());
+    /// assert!(config.route_for("example.org").is_none());
+    /// ```
+    pub fn route_for(&self, host: &str) -> Option<&Route> {
+        let host_lower = host.to_lowercase();
+        self.routes
+            .as_ref()?
+            .iter()
+            .find(|route| matches_allowlist_pattern(&host_lower, &route.host.to_lowercase()))
+    }
 }
 
 /// Checks if a hostname matches an allowlist pattern.
@@ -390,6 +636,44 @@ pub struct QuicConfig {
     /// Enable 0-RTT resumption (default: true)
     #[serde(default = "default_true")]
     pub enable_0rtt: bool,
+    /// Length of the connection IDs backends issue (default: 8)
+    ///
+    /// Short-header packets do not encode their Destination Connection ID
+    /// length, so this is needed to find the session of a client whose address
+    /// changed (NAT rebinding, migration). Valid range is 0-20.
+    #[serde(default = "default_connection_id_length")]
+    pub connection_id_length: usize,
+    /// Seconds without traffic after which a UDP session is closed (default: 30)
+    #[serde(default = "default_session_timeout")]
+    pub session_timeout: u64,
+    /// Maximum number of concurrent UDP sessions (default: 10000)
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    /// Validate client addresses with a stateless Retry before opening a
+    /// session (default: false)
+    ///
+    /// The proxy answers each new client's Initial with a Retry and forwards
+    /// only the retried Initial carrying a valid token. Backends must accept
+    /// that token and echo the original and Retry connection IDs in their
+    /// transport parameters (Retry offload); other QUIC servers fail the
+    /// handshake. Sessions of terminate-mode routes skip the Retry, as the
+    /// HTTP/3 terminator would fail it the same way; they rely on its
+    /// anti-amplification limit and `new_sessions_per_prefix` instead.
+    #[serde(default)]
+    pub retry: bool,
+    /// Seconds a Retry token stays valid (default: 10)
+    #[serde(default = "default_retry_token_lifetime")]
+    pub retry_token_lifetime: u64,
+    /// New sessions per second allowed from one source prefix; 0 disables
+    /// the limit (default: 0)
+    #[serde(default)]
+    pub new_sessions_per_prefix: u32,
+    /// IPv4 prefix length grouping clients for the rate limit (default: 24)
+    #[serde(default = "default_rate_limit_ipv4_prefix")]
+    pub rate_limit_ipv4_prefix: u8,
+    /// IPv6 prefix length grouping clients for the rate limit (default: 56)
+    #[serde(default = "default_rate_limit_ipv6_prefix")]
+    pub rate_limit_ipv6_prefix: u8,
 }
 
 impl Default for QuicConfig {
@@ -401,6 +685,14 @@ impl Default for QuicConfig {
             keep_alive_interval: default_keep_alive_interval(),
             max_datagram_size: default_max_datagram_size(),
             enable_0rtt: true,
+            connection_id_length: default_connection_id_length(),
+            session_timeout: default_session_timeout(),
+            max_sessions: default_max_sessions(),
+            retry: false,
+            retry_token_lifetime: default_retry_token_lifetime(),
+            new_sessions_per_prefix: 0,
+            rate_limit_ipv4_prefix: default_rate_limit_ipv4_prefix(),
+            rate_limit_ipv6_prefix: default_rate_limit_ipv6_prefix(),
         }
     }
 }
@@ -450,6 +742,30 @@ fn default_max_datagram_size() -> usize {
     1350
 }
 
+fn default_connection_id_length() -> usize {
+    8
+}
+
+fn default_session_timeout() -> u64 {
+    30
+}
+
+fn default_max_sessions() -> usize {
+    10_000
+}
+
+fn default_retry_token_lifetime() -> u64 {
+    10
+}
+
+fn default_rate_limit_ipv4_prefix() -> u8 {
+    24
+}
+
+fn default_rate_limit_ipv6_prefix() -> u8 {
+    56
+}
+
 // HTTP/3 default value helpers
 fn default_max_field_section_size() -> usize {
     8192
@@ -483,6 +799,398 @@ fn default_ssh_port() -> u16 {
     22
 }
 
+/// UDP port forwarding configuration
+///
+/// DNS, WireGuard, syslog or game traffic carries no hostname, so like SSH it
+/// is routed by the port it arrives on: every datagram on `listen_port` is
+/// forwarded to the destination. Each client address is a separate flow with
+/// its own backend socket. The port must also be in `udp_listen_addrs`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UdpRoute {
+    /// UDP listen port whose datagrams are forwarded (e.g., 53, 51820, 514)
+    pub listen_port: u16,
+    /// Destination host for datagrams on this port (e.g., "10.0.0.53")
+    pub destination_host: String,
+    /// Destination port
+    pub destination_port: u16,
+    /// Seconds without traffic before a flow closes (default: the QUIC session timeout)
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
+}
+
+/// Error responses sent to plain HTTP clients
+///
+/// When the proxy refuses or cannot route an HTTP request it answers with
+/// 403 (blocked by policy), 421 (no routable Host), 502 (backend unreachable)
+/// or 504 (backend connect timeout) instead of closing the connection.
+///
+/// The body template supports the placeholders `{status}`, `{reason}`,
+/// `{host}` and `{message}`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ErrorResponses {
+    /// Response body template (default: "{status} {reason}\n{message}\n")
+    #[serde(default = "default_error_body_template")]
+    pub body_template: String,
+    /// Content-Type of the response body (default: "text/plain; charset=utf-8")
+    #[serde(default = "default_error_content_type")]
+    pub content_type: String,
+}
+
+impl Default for ErrorResponses {
+    fn default() -> Self {
+        Self {
+            body_template: default_error_body_template(),
+            content_type: default_error_content_type(),
+        }
+    }
+}
+
+fn default_error_body_template() -> String {
+    "{status} {reason}\n{message}\n".to_string()
+}
+
+fn default_error_content_type() -> String {
+    "text/plain; charset=utf-8".to_string()
+}
+
+/// Per-host route configuration
+///
+/// Routes attach behaviour to connections whose SNI (TLS) or Host header (HTTP)
+/// matches `host`. The first matching route in configuration order wins.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Route {
+    /// Hostname pattern (same syntax as the allowlist, e.g. "*.example.com")
+    pub host: String,
+    /// TLS ClientHello policy enforced before forwarding (optional)
+    #[serde(default)]
+    pub tls_policy: Option<TlsPolicy>,
+    /// Backend address ("host:port") overriding the SNI-derived target (optional)
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// SNI presented to the backend instead of the client's (optional)
+    ///
+    /// Passthrough routes rewrite the forwarded ClientHello; terminate routes
+    /// need `upstream_tls`. Without `upstream`, the proxy also connects to this
+    /// name.
+    #[serde(default)]
+    pub upstream_sni: Option<String>,
+    /// Send no SNI to the backend, e.g. for IP-only backends (default: false)
+    ///
+    /// Passthrough routes remove the extension from the forwarded ClientHello;
+    /// terminate routes need `upstream_tls`.
+    #[serde(default = "default_false")]
+    pub strip_sni: bool,
+    /// Whether the proxy passes TLS through or terminates it (default: passthrough)
+    #[serde(default)]
+    pub mode: RouteMode,
+    /// Encrypt traffic to the backend (optional)
+    ///
+    /// Applies to terminate-mode routes and to plain HTTP requests whose Host
+    /// matches the route, which are then sent to `upstream` or `<host>:443`.
+    /// The backend certificate is verified against `upstream_tls.server_name`,
+    /// else `upstream_sni` (terminate mode), else the requested host.
+    #[serde(default)]
+    pub upstream_tls: Option<UpstreamTls>,
+    /// Client certificate authentication (terminate mode only, optional)
+    #[serde(default)]
+    pub client_auth: Option<ClientAuth>,
+    /// Obtain and renew this route's certificate via `acme` (terminate mode, exact hosts only; default: false)
+    #[serde(default = "default_false")]
+    pub acme: bool,
+    /// HTTP version of requests forwarded from terminated HTTP/3 connections (default: http1)
+    #[serde(default)]
+    pub upstream_http: UpstreamHttp,
+    /// Rules for gRPC calls over h2c matched against the method path, first match wins (optional)
+    ///
+    /// A connection goes to the backend of its first call. A later call that
+    /// is denied or belongs to another backend closes the connection.
+    #[serde(default)]
+    pub grpc_rules: Vec<GrpcRule>,
+}
+
+impl Route {
+    /// Returns the first gRPC rule whose path pattern matches `path`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sniproxy_config::{GrpcRule, Route};
+    ///
+    /// let route = Route {
+    ///     host: "grpc.example.com".to_string(),
+    ///     grpc_rules: vec![GrpcRule {
+    ///         path: "/admin.Console/*".to_string(),
+    ///         deny: true,
+    ///         ..Default::default()
+    ///     }],
+    ///     ..Default::default()
+    /// };
+    /// assert!(route.grpc_rule_for("/admin.Console/Shutdown").unwrap().deny);
+    /// assert!(route.grpc_rule_for("/helloworld.Greeter/SayHello").is_none());
+    /// ```
+    pub fn grpc_rule_for(&self, path: &str) -> Option<&GrpcRule> {
+        self.grpc_rules
+            .iter()
+            .find(|rule| matches_grpc_path(path, &rule.path))
+    }
+}
+
+/// How the proxy handles TLS for a route
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteMode {
+    /// Forward the encrypted stream untouched
+    #[default]
+    Passthrough,
+    /// Complete the handshake with a certificate from `tls_certificates` and
+    /// forward the decrypted stream
+    ///
+    /// Without `upstream`, the backend is `<sni>:80`, or `<sni>:443` when
+    /// `upstream_tls` is set. QUIC connections on `udp_listen_addrs` are
+    /// terminated too, and their HTTP/3 requests forwarded per `upstream_http`.
+    Terminate,
+}
+
+/// HTTP version the proxy speaks to the backend of a terminated HTTP/3 route
+///
+/// With `upstream_tls`, the version is also offered via ALPN unless
+/// `upstream_tls.alpn` is set.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamHttp {
+    /// HTTP/1.1, one backend connection per request
+    #[default]
+    Http1,
+    /// HTTP/2 (h2c with prior knowledge in plaintext), one backend connection
+    /// per HTTP/3 connection
+    Http2,
+}
+
+/// TLS settings for connections from the proxy to a backend
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpstreamTls {
+    /// PEM bundle of CA certificates trusted for the backend (default: system roots)
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// Server name sent as SNI and verified in the backend certificate
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// ALPN protocols offered to the backend (e.g. ["http/1.1"]; default: none)
+    #[serde(default)]
+    pub alpn: Vec<String>,
+    /// Accepted SHA-256 hashes of the backend certificate's public key, as
+    /// "sha256/<base64>" (default: any key that passes verification)
+    #[serde(default)]
+    pub pins: Vec<String>,
+}
+
+/// Mutual TLS settings for a terminate-mode route
+///
+/// Clients must present a certificate issued by `ca_file` that is not revoked
+/// by any of `crl_files`. The verified identity (first URI SAN, else first DNS
+/// SAN, else subject CN) is matched against `rules` and forwarded to the backend.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ClientAuth {
+    /// PEM bundle of CA certificates that issue client certificates
+    pub ca_file: String,
+    /// Certificate revocation lists (PEM or DER) checked for every client certificate
+    #[serde(default)]
+    pub crl_files: Vec<String>,
+    /// Admit clients that present no certificate (default: false)
+    #[serde(default = "default_false")]
+    pub optional: bool,
+    /// Identity rules, first match wins; without rules any verified client is admitted
+    #[serde(default)]
+    pub rules: Vec<IdentityRule>,
+    /// How the verified identity is passed to the backend (default: header)
+    #[serde(default)]
+    pub forward_identity: IdentityForwarding,
+    /// Request header carrying the identity in `header` mode (default: "X-Client-Identity")
+    ///
+    /// Any value sent by the client under this name is removed.
+    #[serde(default = "default_identity_header")]
+    pub identity_header: String,
+}
+
+fn default_identity_header() -> String {
+    "X-Client-Identity".to_string()
+}
+
+/// Maps client certificate identities to a backend
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct IdentityRule {
+    /// Pattern matched against the subject CN and every SAN (allowlist syntax, e.g. "*.payments.internal")
+    pub identity: String,
+    /// Backend for matching clients (default: the route's backend)
+    #[serde(default)]
+    pub upstream: Option<String>,
+}
+
+/// Routes or blocks gRPC calls by method path
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GrpcRule {
+    /// Pattern matched against "/package.Service/Method" (see `matches_grpc_path`)
+    pub path: String,
+    /// Backend for matching calls (default: the host and port of `:authority`, port 80 if absent)
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// Refuse matching calls with gRPC status PERMISSION_DENIED (default: false)
+    #[serde(default = "default_false")]
+    pub deny: bool,
+}
+
+/// Checks if a gRPC method path matches a rule pattern.
+///
+/// - Exact match: `"/helloworld.Greeter/SayHello"`
+/// - Prefix match with a trailing `*`: `"/helloworld.Greeter/*"` matches every
+///   method of the service, `"/helloworld.*"` every service of the package
+/// - `"*"` matches any path
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_config::matches_grpc_path;
+///
+/// assert!(matches_grpc_path("/helloworld.Greeter/SayHello", "/helloworld.Greeter/SayHello"));
+/// assert!(matches_grpc_path("/helloworld.Greeter/SayHello", "/helloworld.Greeter/*"));
+/// assert!(!matches_grpc_path("/helloworld.GreeterAdmin/Reset", "/helloworld.Greeter/*"));
+/// assert!(matches_grpc_path("/anything", "*"));
+/// ```
+pub fn matches_grpc_path(path: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == pattern,
+    }
+}
+
+/// How a verified client identity reaches the backend
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityForwarding {
+    /// Add `identity_header` to every HTTP/1.x request
+    #[default]
+    Header,
+    /// Send a PROXY protocol v2 header with TLS TLVs before any data (raw TCP)
+    ProxyProtocol,
+    /// Do not forward the identity
+    None,
+}
+
+/// Certificate directory for TLS termination
+///
+/// The directory holds `<name>.crt` (or `<name>.pem`) and `<name>.key` PEM
+/// pairs, where `<name>` is the hostname served by the certificate. Wildcard
+/// certificates use a `_wildcard.` prefix, e.g. `_wildcard.example.com.crt`
+/// serves `*.example.com`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TlsCertificates {
+    /// Directory containing certificate and key files
+    pub directory: String,
+    /// Seconds between checks for changed certificate files, 0 disables reloading (default: 30)
+    #[serde(default = "default_cert_reload_interval")]
+    pub reload_interval: u64,
+}
+
+fn default_cert_reload_interval() -> u64 {
+    30
+}
+
+/// ACME (RFC 8555) client configuration
+///
+/// Certificates for routes with `acme: true` are written to the
+/// `tls_certificates` directory and renewed before they expire.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AcmeConfig {
+    /// ACME directory URL (e.g. "https://acme-v02.api.letsencrypt.org/directory")
+    pub directory_url: String,
+    /// Account contact URLs (e.g. "mailto:ops@example.com")
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// Directory where the account key is persisted
+    pub state_dir: String,
+    /// Challenge type used to prove control of a domain (default: http-01)
+    #[serde(default)]
+    pub challenge: AcmeChallengeType,
+    /// PEM bundle trusted for the ACME server (default: system roots)
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// Renew certificates expiring within this many days (default: 30)
+    #[serde(default = "default_acme_renew_before_days")]
+    pub renew_before_days: u64,
+    /// Seconds between certificate expiry checks (default: 43200)
+    #[serde(default = "default_acme_check_interval")]
+    pub check_interval: u64,
+}
+
+fn default_acme_renew_before_days() -> u64 {
+    30
+}
+
+fn default_acme_check_interval() -> u64 {
+    43200
+}
+
+/// ACME challenge type
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AcmeChallengeType {
+    /// Token served over plain HTTP on port 80
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    /// Self-signed certificate served on port 443 for the `acme-tls/1` ALPN protocol
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+}
+
+/// TLS ClientHello policy for a route
+///
+/// Evaluated against the client's ClientHello before any bytes reach the
+/// backend, and on passthrough routes against the backend's ServerHello
+/// before it reaches the client. Violations are answered with a fatal TLS alert.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TlsPolicy {
+    /// Minimum TLS version the client must offer and the backend must select (e.g. "1.2")
+    #[serde(default)]
+    pub min_version: Option<TlsVersion>,
+    /// Cipher suite code points the proxy refuses (e.g. 0x000a)
+    ///
+    /// A ClientHello offering no cipher suite outside this list is rejected,
+    /// as is a ServerHello selecting one from the list.
+    #[serde(default)]
+    pub forbidden_cipher_suites: Vec<u16>,
+    /// Named group code points the proxy refuses (e.g. 0x0017 for secp256r1)
+    ///
+    /// A ClientHello offering no supported group outside this list is rejected,
+    /// as is a TLS 1.3 ServerHello whose key share uses one from the list.
+    #[serde(default)]
+    pub forbidden_groups: Vec<u16>,
+}
+
+/// TLS protocol version
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls10,
+    #[serde(rename = "1.1")]
+    Tls11,
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    /// Returns the on-the-wire protocol version (e.g. 0x0303 for TLS 1.2)
+    pub fn wire_version(&self) -> u16 {
+        match self {
+            TlsVersion::Tls10 => 0x0301,
+            TlsVersion::Tls11 => 0x0302,
+            TlsVersion::Tls12 => 0x0303,
+            TlsVersion::Tls13 => 0x0304,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -600,6 +1308,351 @@ metrics:
         assert!(!matches_allowlist_pattern("test.org", "*test.com"));
     }
 
+    #[test]
+    fn test_routes_with_tls_policy() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+routes:
+  - host: "legacy.example.com"
+  - host: "*.example.com"
+    tls_policy:
+      min_version: "1.2"
+      forbidden_cipher_suites: [0x000a, 0x0005]
+      forbidden_groups: [0x0017]
+"#;
+        let config = Config::parse(yaml).unwrap();
+        assert_eq!(config.routes.as_ref().unwrap().len(), 2);
+
+        // First match wins
+        let route = config.route_for("legacy.example.com").unwrap();
+        assert!(route.tls_policy.is_none());
+
+        let policy = config
+            .route_for("api.example.com")
+            .unwrap()
+            .tls_policy
+            .as_ref()
+            .unwrap();
+        assert_eq!(policy.min_version, Some(TlsVersion::Tls12));
+        assert_eq!(policy.forbidden_cipher_suites, vec![0x000a, 0x0005]);
+        assert_eq!(policy.forbidden_groups, vec![0x0017]);
+
+        assert!(config.route_for("example.org").is_none());
+    }
+
+    #[test]
+    fn test_route_sni_rewrite_options() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+routes:
+  - host: "legacy.example.com"
+    upstream_sni: "new.example.net"
+  - host: "ip-only.example.com"
+    upstream: "10.0.0.5:8443"
+    strip_sni: true
+"#;
+        let config = Config::parse(yaml).unwrap();
+
+        let legacy = config.route_for("legacy.example.com").unwrap();
+        assert_eq!(legacy.upstream_sni.as_deref(), Some("new.example.net"));
+        assert!(legacy.upstream.is_none());
+        assert!(!legacy.strip_sni);
+
+        let ip_only = config.route_for("ip-only.example.com").unwrap();
+        assert_eq!(ip_only.upstream.as_deref(), Some("10.0.0.5:8443"));
+        assert!(ip_only.strip_sni);
+    }
+
+    #[test]
+    fn test_route_sni_rewrite_requires_upstream_tls() {
+        let config = |route: &str| {
+            Config::parse(&format!(
+                r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+routes:
+  - {}
+"#,
+                route
+            ))
+        };
+
+        // Passthrough routes rewrite the forwarded ClientHello
+        assert!(config(r#"{ host: "a.example.com", upstream_sni: "b.example.net" }"#).is_ok());
+        assert!(config(r#"{ host: "a.example.com", strip_sni: true }"#).is_ok());
+
+        // Terminate routes have no backend handshake without upstream_tls
+        let error = config(r#"{ host: "a.example.com", mode: terminate, strip_sni: true }"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("a.example.com"), "{}", error);
+        assert!(
+            config(
+                r#"{ host: "a.example.com", mode: terminate, strip_sni: true, upstream_tls: {} }"#
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_route_terminate_mode() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+tls_certificates:
+  directory: "/etc/sniproxy/certs"
+routes:
+  - host: "*.internal.example.com"
+    mode: terminate
+    upstream: "10.0.0.7:8080"
+  - host: "secure.example.com"
+    mode: terminate
+    upstream_http: http2
+    upstream_tls:
+      ca_file: "/etc/sniproxy/backend-ca.pem"
+  - host: "*"
+"#;
+        let config = Config::parse(yaml).unwrap();
+
+        let certs = config.tls_certificates.as_ref().unwrap();
+        assert_eq!(certs.directory, "/etc/sniproxy/certs");
+        assert_eq!(certs.reload_interval, 30);
+
+        let internal = config.route_for("wiki.internal.example.com").unwrap();
+        assert_eq!(internal.mode, RouteMode::Terminate);
+        assert!(internal.upstream_tls.is_none());
+        assert_eq!(internal.upstream_http, UpstreamHttp::Http1);
+
+        let secure = config.route_for("secure.example.com").unwrap();
+        assert_eq!(
+            secure.upstream_tls.as_ref().unwrap().ca_file.as_deref(),
+            Some("/etc/sniproxy/backend-ca.pem")
+        );
+        assert_eq!(secure.upstream_http, UpstreamHttp::Http2);
+
+        let other = config.route_for("example.org").unwrap();
+        assert_eq!(other.mode, RouteMode::Passthrough);
+    }
+
+    #[test]
+    fn test_route_upstream_tls_origination() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+routes:
+  - host: "legacy.example.com"
+    upstream: "10.0.0.9:8443"
+    upstream_tls:
+      server_name: "api.internal.example.com"
+      alpn: ["http/1.1"]
+      pins: ["sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="]
+"#;
+        let config = Config::parse(yaml).unwrap();
+
+        let route = config.route_for("legacy.example.com").unwrap();
+        assert_eq!(route.mode, RouteMode::Passthrough);
+        let settings = route.upstream_tls.as_ref().unwrap();
+        assert!(settings.ca_file.is_none());
+        assert_eq!(
+            settings.server_name.as_deref(),
+            Some("api.internal.example.com")
+        );
+        assert_eq!(settings.alpn, vec!["http/1.1"]);
+        assert_eq!(settings.pins.len(), 1);
+    }
+
+    #[test]
+    fn test_route_client_auth() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+routes:
+  - host: "payments.example.com"
+    mode: terminate
+    upstream: "10.0.0.8:8080"
+    client_auth:
+      ca_file: "/etc/sniproxy/clients-ca.pem"
+      crl_files: ["/etc/sniproxy/clients.crl"]
+      rules:
+        - identity: "*.billing.internal"
+          upstream: "10.0.0.9:8080"
+        - identity: "spiffe://example.org/ledger"
+  - host: "db.example.com"
+    mode: terminate
+    client_auth:
+      ca_file: "/etc/sniproxy/clients-ca.pem"
+      optional: true
+      forward_identity: proxy_protocol
+"#;
+        let config = Config::parse(yaml).unwrap();
+
+        let payments = config.route_for("payments.example.com").unwrap();
+        let auth = payments.client_auth.as_ref().unwrap();
+        assert_eq!(auth.crl_files, vec!["/etc/sniproxy/clients.crl"]);
+        assert!(!auth.optional);
+        assert_eq!(auth.rules.len(), 2);
+        assert_eq!(auth.rules[0].upstream.as_deref(), Some("10.0.0.9:8080"));
+        assert!(auth.rules[1].upstream.is_none());
+        assert_eq!(auth.forward_identity, IdentityForwarding::Header);
+        assert_eq!(auth.identity_header, "X-Client-Identity");
+
+        let db = config.route_for("db.example.com").unwrap();
+        let auth = db.client_auth.as_ref().unwrap();
+        assert!(auth.optional);
+        assert!(auth.rules.is_empty());
+        assert_eq!(auth.forward_identity, IdentityForwarding::ProxyProtocol);
+    }
+
+    #[test]
+    fn test_route_grpc_rules() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+routes:
+  - host: "grpc.example.com"
+    grpc_rules:
+      - path: "/admin.*"
+        deny: true
+      - path: "/billing.Ledger/*"
+        upstream: "10.0.0.12:50051"
+      - path: "/helloworld.Greeter/SayHello"
+"#;
+        let config = Config::parse(yaml).unwrap();
+        let route = config.route_for("grpc.example.com").unwrap();
+        assert_eq!(route.grpc_rules.len(), 3);
+
+        let rule = route.grpc_rule_for("/admin.Console/Shutdown").unwrap();
+        assert!(rule.deny);
+        let rule = route.grpc_rule_for("/billing.Ledger/Post").unwrap();
+        assert!(!rule.deny);
+        assert_eq!(rule.upstream.as_deref(), Some("10.0.0.12:50051"));
+        let rule = route.grpc_rule_for("/helloworld.Greeter/SayHello").unwrap();
+        assert!(rule.upstream.is_none());
+        assert!(
+            route
+                .grpc_rule_for("/helloworld.Greeter/SayGoodbye")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_grpc_pool_config() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+grpc_pool:
+  max_channels_per_host: 4
+  health_check_interval: 10
+"#;
+        let config = Config::parse(yaml).unwrap();
+        let pool = config.grpc_pool.unwrap();
+        assert!(pool.enabled);
+        assert_eq!(pool.max_channels_per_host, 4);
+        assert_eq!(pool.max_concurrent_streams, 100);
+        assert_eq!(pool.channel_ttl, 300);
+        assert_eq!(pool.idle_timeout, 120);
+        assert_eq!(pool.health_check_interval, 10);
+    }
+
+    #[test]
+    fn test_websocket_config() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+websocket:
+  max_message_size: 65536
+  ping_interval: 0
+"#;
+        let config = Config::parse(yaml).unwrap();
+        let websocket = config.websocket.unwrap();
+        assert_eq!(websocket.max_message_size, 65536);
+        assert_eq!(websocket.idle_timeout, 300);
+        assert_eq!(websocket.ping_interval, 0);
+        assert!(websocket.compression.is_none());
+    }
+
+    #[test]
+    fn test_websocket_compression_config() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+websocket:
+  compression:
+    server_max_window_bits: 10
+    client_no_context_takeover: true
+"#;
+        let config = Config::parse(yaml).unwrap();
+        let compression = config.websocket.unwrap().compression.unwrap();
+        assert_eq!(compression.compression_level, 6);
+        assert_eq!(compression.server_max_window_bits, 10);
+        assert_eq!(compression.client_max_window_bits, 15);
+        assert!(compression.client_no_context_takeover);
+        assert!(!compression.server_no_context_takeover);
+        assert_eq!(compression.min_compress_size, 256);
+    }
+
+    #[test]
+    fn test_acme_config() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:443", "0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+tls_certificates:
+  directory: "/var/lib/sniproxy/certs"
+acme:
+  directory_url: "https://localhost:14000/dir"
+  contact: ["mailto:ops@example.com"]
+  state_dir: "/var/lib/sniproxy/acme"
+  challenge: tls-alpn-01
+routes:
+  - host: "app.example.com"
+    mode: terminate
+    acme: true
+"#;
+        let config = Config::parse(yaml).unwrap();
+
+        let acme = config.acme.as_ref().unwrap();
+        assert_eq!(acme.challenge, AcmeChallengeType::TlsAlpn01);
+        assert_eq!(acme.renew_before_days, 30);
+        assert_eq!(acme.check_interval, 43200);
+        assert!(acme.ca_file.is_none());
+        assert!(config.route_for("app.example.com").unwrap().acme);
+    }
+
+    #[test]
+    fn test_error_responses_defaults() {
+        let yaml = r#"
+listen_addrs: ["0.0.0.0:80"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+error_responses:
+  content_type: "text/html"
+"#;
+        let config = Config::parse(yaml).unwrap();
+        let errors = config.error_responses.unwrap();
+        assert_eq!(errors.content_type, "text/html");
+        assert_eq!(errors.body_template, "{status} {reason}\n{message}\n");
+    }
+
+    #[test]
+    fn test_tls_version_ordering() {
+        assert!(TlsVersion::Tls10 < TlsVersion::Tls13);
+        assert_eq!(TlsVersion::Tls12.wire_version(), 0x0303);
+        assert_eq!(TlsVersion::Tls13.wire_version(), 0x0304);
+    }
+
     #[test]
     fn test_allowlist_no_match() {
         assert!(!matches_allowlist_pattern("example.com", "other.com"));
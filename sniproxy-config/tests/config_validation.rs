@@ -64,9 +64,18 @@ fn test_full_config_loads() {
     let udp_addrs = config
         .udp_listen_addrs
         .expect("UDP addresses should be configured");
-    assert_eq!(udp_addrs.len(), 1);
+    assert_eq!(udp_addrs.len(), 2);
     assert_eq!(udp_addrs[0], "0.0.0.0:8443");
 
+    // Optional fields - UDP port forwarding
+    let udp_routes = config.udp_routes.expect("UDP routes should be configured");
+    assert_eq!(udp_routes.len(), 2);
+    assert_eq!(udp_routes[0].listen_port, 5353);
+    assert_eq!(udp_routes[0].destination_host, "10.0.0.53");
+    assert_eq!(udp_routes[0].destination_port, 53);
+    assert_eq!(udp_routes[0].idle_timeout, Some(10));
+    assert_eq!(udp_routes[1].idle_timeout, None);
+
     // Optional fields - QUIC
     let quic = config.quic_config.expect("QUIC config should be present");
     assert!(quic.enabled);
@@ -75,6 +84,14 @@ fn test_full_config_loads() {
     assert_eq!(quic.keep_alive_interval, 10);
     assert_eq!(quic.max_datagram_size, 1200);
     assert!(!quic.enable_0rtt);
+    assert_eq!(quic.connection_id_length, 16);
+    assert_eq!(quic.session_timeout, 45);
+    assert_eq!(quic.max_sessions, 500);
+    assert!(quic.retry);
+    assert_eq!(quic.retry_token_lifetime, 5);
+    assert_eq!(quic.new_sessions_per_prefix, 20);
+    assert_eq!(quic.rate_limit_ipv4_prefix, 32);
+    assert_eq!(quic.rate_limit_ipv6_prefix, 64);
 
     // Optional fields - HTTP/3
     let http3 = config
@@ -0,0 +1,241 @@
+//! UDP/QUIC session load benchmarks
+//!
+//! Drives many concurrent QUIC clients through `UdpConnectionHandler` to a
+//! local fake QUIC backend, measuring session setup (Initial decryption,
+//! routing, assignment to the backend's socket pool) and steady-state
+//! datagram forwarding, with responses matched to sessions by the client's
+//! connection ID on the pooled sockets.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use sniproxy_config::Config;
+use sniproxy_core::quic;
+use sniproxy_core::udp_connection::UdpConnectionHandler;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::runtime::Runtime;
+
+const SNI: &str = "bench.example.com";
+
+/// Client retransmission policy for lost datagrams
+const RETRANSMITS: usize = 20;
+const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Fake QUIC server: answers every datagram with a packet addressed to the
+/// client's connection ID, announcing a per-client server connection ID
+async fn run_backend(socket: UdpSocket) {
+    let mut buf = vec![0u8; 1500];
+    loop {
+        let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+            return;
+        };
+        let packet = &buf[..len];
+        let reply = if packet[0] & 0x80 != 0 {
+            // Handshake packet: DCID = client SCID, SCID = server CID
+            let client_cid = quic::source_cid(packet).unwrap_or_default().to_vec();
+            let mut reply = vec![0xe0, 0x00, 0x00, 0x00, 0x01, client_cid.len() as u8];
+            reply.extend_from_slice(&client_cid);
+            reply.push(8);
+            reply.extend(client_cid.iter().map(|b| b ^ 0xff));
+            reply.resize(64, 0);
+            reply
+        } else {
+            // 1-RTT: the server CID is the client CID inverted
+            let mut reply = vec![0x40];
+            reply.extend(packet[1..9].iter().map(|b| b ^ 0xff));
+            reply.extend_from_slice(&packet[9..]);
+            reply
+        };
+        let _ = socket.send_to(&reply, from).await;
+    }
+}
+
+/// A running proxy with a fake backend behind it
+struct Harness {
+    proxy_addr: SocketAddr,
+    next_cid: AtomicU64,
+}
+
+impl Harness {
+    async fn start() -> Self {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(run_backend(backend));
+
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["127.0.0.1:0"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+quic_config:
+  session_timeout: 600
+  max_sessions: 1000000
+routes:
+  - host: "{}"
+    upstream: "{}"
+"#,
+            SNI, backend_addr
+        ))
+        .unwrap();
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        tokio::spawn(async move {
+            if let Err(e) = handler.run(listener).await {
+                eprintln!("UDP handler failed: {}", e);
+            }
+        });
+
+        Self {
+            proxy_addr,
+            next_cid: AtomicU64::new(1),
+        }
+    }
+
+    /// Opens `count` QUIC sessions concurrently and returns the connected clients
+    async fn open_sessions(&self, count: usize) -> Vec<(Arc<UdpSocket>, [u8; 8])> {
+        let tasks: Vec<_> = (0..count)
+            .map(|_| {
+                let cid = self.next_cid.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+                let proxy_addr = self.proxy_addr;
+                tokio::spawn(async move {
+                    let client = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+                    client.connect(proxy_addr).await.unwrap();
+                    let initial = quic::seal_client_initial(
+                        quic::QUIC_V1,
+                        &cid,
+                        &cid,
+                        &[],
+                        0,
+                        &quic::crypto_frame(0, &client_hello(SNI)),
+                    )
+                    .unwrap();
+                    exchange(&client, &initial).await;
+                    (client, cid)
+                })
+            })
+            .collect();
+
+        let mut clients = Vec::with_capacity(count);
+        for task in tasks {
+            clients.push(task.await.unwrap());
+        }
+        clients
+    }
+}
+
+/// Each client sends one 1-RTT datagram and waits for the backend's answer
+async fn round_trip(clients: &[(Arc<UdpSocket>, [u8; 8])]) {
+    let tasks: Vec<_> = clients
+        .iter()
+        .map(|(client, cid)| {
+            let client = Arc::clone(client);
+            let mut packet = vec![0x40];
+            packet.extend(cid.iter().map(|b| b ^ 0xff));
+            packet.resize(1200, 0xab);
+            tokio::spawn(async move {
+                exchange(&client, &packet).await;
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+/// Sends `packet` and waits for one answer, resending it like a QUIC client
+/// would when a burst overflows the proxy's receive buffer
+async fn exchange(client: &UdpSocket, packet: &[u8]) {
+    let mut buf = [0u8; 1500];
+    for _ in 0..RETRANSMITS {
+        client.send(packet).await.unwrap();
+        if tokio::time::timeout(RETRANSMIT_INTERVAL, client.recv(&mut buf))
+            .await
+            .is_ok()
+        {
+            return;
+        }
+    }
+    panic!("no answer after {} retransmits", RETRANSMITS);
+}
+
+/// Minimal ClientHello carrying `sni`
+fn client_hello(sni: &str) -> Vec<u8> {
+    let name = sni.as_bytes();
+    let mut extensions = vec![0x00, 0x00];
+    extensions.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+    extensions.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+    extensions.push(0x00);
+    extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    extensions.extend_from_slice(name);
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0; 32]);
+    body.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut hello = vec![0x01, 0x00];
+    hello.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&body);
+    hello
+}
+
+/// Benchmark opening many QUIC sessions at once
+fn session_setup_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let harness = rt.block_on(Harness::start());
+    let mut group = c.benchmark_group("udp_session_setup");
+    group.sample_size(10);
+
+    for sessions in [100, 1000] {
+        group.throughput(Throughput::Elements(sessions as u64));
+        group.bench_with_input(
+            BenchmarkId::new("open_sessions", sessions),
+            &sessions,
+            |b, &sessions| {
+                b.iter_custom(|iters| {
+                    rt.block_on(async {
+                        let mut total = Duration::ZERO;
+                        for _ in 0..iters {
+                            let start = Instant::now();
+                            let clients = harness.open_sessions(sessions).await;
+                            total += start.elapsed();
+                            drop(clients);
+                        }
+                        total
+                    })
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Benchmark forwarding over established sessions sharing backend sockets
+fn forwarding_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let harness = rt.block_on(Harness::start());
+    let mut group = c.benchmark_group("udp_forwarding");
+    group.sample_size(10);
+
+    for sessions in [100, 1000] {
+        let clients = rt.block_on(harness.open_sessions(sessions));
+        group.throughput(Throughput::Elements(sessions as u64));
+        group.bench_with_input(
+            BenchmarkId::new("round_trip", sessions),
+            &clients,
+            |b, clients| {
+                b.iter(|| rt.block_on(round_trip(clients)));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, session_setup_benchmark, forwarding_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,1182 @@
+//! Automatic certificates for terminate-mode routes via ACME (RFC 8555)
+//!
+//! The proxy is its own ACME client: it registers an account, orders a
+//! certificate for every route with `acme: true`, proves control of the domain
+//! and writes the issued chain into the `tls_certificates` directory, where the
+//! `CertificateStore` serves it. Challenges are answered by the proxy's own
+//! listeners:
+//! - http-01 (RFC 8555 §8.3): `handle_http` serves
+//!   `/.well-known/acme-challenge/<token>` before any routing
+//! - tls-alpn-01 (RFC 8737): `handle_https` completes `acme-tls/1` handshakes
+//!   with a self-signed validation certificate
+//!
+//! The account key is persisted as `account.key` in `state_dir`, so restarts
+//! and renewals reuse the same account.
+
+use crate::termination::{CertificateStore, crypto_provider, upstream_client_config};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use bytes::Bytes;
+use dashmap::DashMap;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{CONTENT_TYPE, HOST, LOCATION, USER_AGENT};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, KeyPair};
+use ring::digest::{SHA256, digest};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair as _};
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use serde_json::{Value, json};
+use sniproxy_config::{AcmeChallengeType, AcmeConfig, Config, RouteMode, UpstreamTls};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+use tracing::{debug, info, warn};
+
+/// ALPN protocol identifying tls-alpn-01 validation handshakes
+pub const ACME_TLS_ALPN: &str = "acme-tls/1";
+/// Path prefix of http-01 validation requests
+pub const HTTP01_PATH_PREFIX: &str = "/.well-known/acme-challenge/";
+
+const ACCOUNT_KEY_FILE: &str = "account.key";
+const BAD_NONCE: &str = "urn:ietf:params:acme:error:badNonce";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_POLLS: u32 = 60;
+/// Delay before retrying after a failed renewal (capped by `check_interval`)
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Errors from the ACME client
+#[derive(Debug)]
+pub enum AcmeError {
+    /// Reading or writing key and certificate files failed
+    Io(io::Error),
+    /// The ACME server could not be reached
+    Transport(String),
+    /// The ACME server answered with an error (RFC 7807 problem document)
+    Problem {
+        status: u16,
+        kind: String,
+        detail: String,
+    },
+    /// The ACME server sent a response this client cannot use
+    Protocol(String),
+    /// Key, CSR or certificate generation failed
+    Crypto(String),
+    /// Domain validation failed or did not finish in time
+    Validation { domain: String, detail: String },
+}
+
+impl fmt::Display for AcmeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcmeError::Io(e) => write!(f, "ACME I/O error: {}", e),
+            AcmeError::Transport(msg) => write!(f, "ACME server unreachable: {}", msg),
+            AcmeError::Problem {
+                status,
+                kind,
+                detail,
+            } => write!(f, "ACME server error {} ({}): {}", status, kind, detail),
+            AcmeError::Protocol(msg) => write!(f, "Unexpected ACME response: {}", msg),
+            AcmeError::Crypto(msg) => write!(f, "ACME key or certificate error: {}", msg),
+            AcmeError::Validation { domain, detail } => {
+                write!(f, "ACME validation of {} failed: {}", domain, detail)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AcmeError {}
+
+impl From<io::Error> for AcmeError {
+    fn from(e: io::Error) -> Self {
+        AcmeError::Io(e)
+    }
+}
+
+impl From<hyper::Error> for AcmeError {
+    fn from(e: hyper::Error) -> Self {
+        AcmeError::Transport(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AcmeError {
+    fn from(e: serde_json::Error) -> Self {
+        AcmeError::Protocol(e.to_string())
+    }
+}
+
+impl From<rcgen::Error> for AcmeError {
+    fn from(e: rcgen::Error) -> Self {
+        AcmeError::Crypto(e.to_string())
+    }
+}
+
+impl From<rustls::Error> for AcmeError {
+    fn from(e: rustls::Error) -> Self {
+        AcmeError::Crypto(e.to_string())
+    }
+}
+
+impl From<ring::error::Unspecified> for AcmeError {
+    fn from(_: ring::error::Unspecified) -> Self {
+        AcmeError::Crypto("signing failed".to_string())
+    }
+}
+
+impl From<ring::error::KeyRejected> for AcmeError {
+    fn from(e: ring::error::KeyRejected) -> Self {
+        AcmeError::Crypto(format!("account key rejected: {}", e))
+    }
+}
+
+/// Pending challenge responses, shared between the ACME client and the listeners
+#[derive(Debug, Default)]
+pub struct AcmeChallenges {
+    /// http-01 token → key authorization
+    http01: DashMap<String, String>,
+    /// Domain → tls-alpn-01 validation certificate
+    tls_alpn01: DashMap<String, Arc<CertifiedKey>>,
+}
+
+impl AcmeChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key authorization for a pending http-01 token
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.http01.get(token).map(|entry| entry.clone())
+    }
+
+    /// Builds the response to an http-01 validation request
+    ///
+    /// Returns `None` unless `request` is a GET for a pending token, so any
+    /// other request is routed as usual.
+    pub fn http01_response(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let line_end = request.iter().position(|&b| b == b'\n')?;
+        let line = std::str::from_utf8(&request[..line_end]).ok()?.trim_end();
+
+        let mut parts = line.split(' ');
+        if parts.next()? != "GET" {
+            return None;
+        }
+        let token = parts.next()?.strip_prefix(HTTP01_PATH_PREFIX)?;
+        let key_authorization = self.key_authorization(token)?;
+
+        Some(
+            format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\
+                 \r\n\
+                 {}",
+                key_authorization.len(),
+                key_authorization
+            )
+            .into_bytes(),
+        )
+    }
+
+    /// Returns true if a tls-alpn-01 validation is pending for `domain`
+    pub fn has_tls_alpn01(&self, domain: &str) -> bool {
+        self.tls_alpn01.contains_key(&domain.to_ascii_lowercase())
+    }
+
+    /// Builds the server configuration answering `acme-tls/1` handshakes
+    ///
+    /// Only the `acme-tls/1` protocol is offered, so ordinary clients are
+    /// refused rather than served the validation certificate.
+    pub fn tls_alpn01_server_config(self: &Arc<Self>) -> Result<Arc<ServerConfig>, rustls::Error> {
+        let mut config = ServerConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![ACME_TLS_ALPN.as_bytes().to_vec()];
+
+        Ok(Arc::new(config))
+    }
+
+    fn publish(
+        &self,
+        domain: &str,
+        challenge: AcmeChallengeType,
+        token: &str,
+        key_authorization: &str,
+    ) -> Result<(), AcmeError> {
+        match challenge {
+            AcmeChallengeType::Http01 => {
+                self.http01
+                    .insert(token.to_string(), key_authorization.to_string());
+            }
+            AcmeChallengeType::TlsAlpn01 => {
+                let certificate = tls_alpn01_certificate(domain, key_authorization)?;
+                self.tls_alpn01
+                    .insert(domain.to_ascii_lowercase(), Arc::new(certificate));
+            }
+        }
+        Ok(())
+    }
+
+    fn withdraw(&self, domain: &str, token: &str) {
+        self.http01.remove(token);
+        self.tls_alpn01.remove(&domain.to_ascii_lowercase());
+    }
+}
+
+impl ResolvesServerCert for AcmeChallenges {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name()?.to_ascii_lowercase();
+        self.tls_alpn01.get(&server_name).map(|entry| entry.clone())
+    }
+}
+
+/// Builds the self-signed tls-alpn-01 validation certificate for `domain`
+///
+/// The certificate carries the SHA-256 digest of the key authorization in
+/// the critical acmeIdentifier extension (RFC 8737 §3).
+pub fn tls_alpn01_certificate(
+    domain: &str,
+    key_authorization: &str,
+) -> Result<CertifiedKey, AcmeError> {
+    let key_pair = KeyPair::generate()?;
+    let mut params = CertificateParams::new(vec![domain.to_string()])?;
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(
+        digest(&SHA256, key_authorization.as_bytes()).as_ref(),
+    )];
+    let certificate = params.self_signed(&key_pair)?;
+
+    // `CertifiedKey::from_der` would reject the critical acmeIdentifier extension
+    let signing_key = crypto_provider()
+        .key_provider
+        .load_private_key(PrivateKeyDer::Pkcs8(key_pair.serialize_der().into()))?;
+    Ok(CertifiedKey::new(
+        vec![certificate.der().clone()],
+        signing_key,
+    ))
+}
+
+/// ACME account key (ECDSA P-256), signing requests as JWS with ES256
+pub struct AccountKey {
+    key_pair: EcdsaKeyPair,
+    pkcs8: Vec<u8>,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    pub fn generate() -> Result<Self, AcmeError> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, AcmeError> {
+        let rng = SystemRandom::new();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)?;
+        Ok(Self {
+            key_pair,
+            pkcs8: pkcs8.to_vec(),
+            rng,
+        })
+    }
+
+    /// Loads the key from a PEM file, generating and saving one if it does not exist
+    pub fn load_or_generate(path: &Path) -> Result<Self, AcmeError> {
+        match fs::read(path) {
+            Ok(pem) => match PrivateKeyDer::from_pem_slice(&pem) {
+                Ok(PrivateKeyDer::Pkcs8(der)) => Self::from_pkcs8(der.secret_pkcs8_der()),
+                Ok(_) => Err(AcmeError::Crypto(format!(
+                    "{}: account key must be PKCS#8",
+                    path.display()
+                ))),
+                Err(e) => Err(AcmeError::Crypto(format!("{}: {}", path.display(), e))),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let key = Self::generate()?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_private_file(path, key.to_pem()?.as_bytes())?;
+                info!(path = %path.display(), "Generated ACME account key");
+                Ok(key)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// PKCS#8 PEM encoding of the private key
+    pub fn to_pem(&self) -> Result<String, AcmeError> {
+        Ok(KeyPair::try_from(self.pkcs8.as_slice())?.serialize_pem())
+    }
+
+    /// Public key as a JSON Web Key (RFC 7517)
+    pub fn jwk(&self) -> Value {
+        let (x, y) = self.coordinates();
+        json!({ "crv": "P-256", "kty": "EC", "x": x, "y": y })
+    }
+
+    /// JWK thumbprint (RFC 7638), base64url-encoded
+    pub fn thumbprint(&self) -> String {
+        // Required members in lexicographic order, no whitespace
+        let (x, y) = self.coordinates();
+        let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y);
+        URL_SAFE_NO_PAD.encode(digest(&SHA256, canonical.as_bytes()))
+    }
+
+    /// Key authorization for a challenge token (RFC 8555 §8.1)
+    pub fn key_authorization(&self, token: &str) -> String {
+        format!("{}.{}", token, self.thumbprint())
+    }
+
+    /// Signs a request body as a flattened JWS (RFC 8555 §6.2)
+    ///
+    /// Requests carry the account URL as `kid` once registered and the public
+    /// key itself before that. A `None` payload produces a POST-as-GET.
+    pub fn sign(
+        &self,
+        url: &str,
+        nonce: &str,
+        kid: Option<&str>,
+        payload: Option<&Value>,
+    ) -> Result<Value, AcmeError> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+
+        let protected = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&protected)?);
+        let payload = match payload {
+            Some(payload) => URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?),
+            None => String::new(),
+        };
+        let signature = self
+            .key_pair
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())?;
+
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+
+    /// Base64url-encoded x and y coordinates of the public key
+    fn coordinates(&self) -> (String, String) {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.key_pair.public_key().as_ref();
+        (
+            URL_SAFE_NO_PAD.encode(&point[1..33]),
+            URL_SAFE_NO_PAD.encode(&point[33..65]),
+        )
+    }
+}
+
+impl fmt::Debug for AccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountKey")
+            .field("thumbprint", &self.thumbprint())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Response from the ACME server
+struct AcmeResponse {
+    status: StatusCode,
+    location: Option<String>,
+    nonce: Option<String>,
+    body: Bytes,
+}
+
+impl AcmeResponse {
+    fn json(&self) -> Result<Value, AcmeError> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// Converts an error response into an `AcmeError::Problem`
+    fn into_problem(self) -> AcmeError {
+        let problem: Value = serde_json::from_slice(&self.body).unwrap_or(Value::Null);
+        AcmeError::Problem {
+            status: self.status.as_u16(),
+            kind: problem["type"].as_str().unwrap_or("unknown").to_string(),
+            detail: problem["detail"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| String::from_utf8_lossy(&self.body).into_owned()),
+        }
+    }
+}
+
+/// One-request-per-connection HTTP/1.1 client for the ACME server
+struct HttpClient {
+    tls: TlsConnector,
+}
+
+impl HttpClient {
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<AcmeResponse, AcmeError> {
+        timeout(REQUEST_TIMEOUT, self.exchange(method, url, body))
+            .await
+            .map_err(|_| AcmeError::Transport(format!("request to {} timed out", url)))?
+    }
+
+    async fn exchange(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<AcmeResponse, AcmeError> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| AcmeError::Protocol(format!("invalid URL {}: {}", url, e)))?;
+        let https = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(AcmeError::Protocol(format!("unsupported URL {}", url))),
+        };
+        let (Some(authority), Some(host)) = (uri.authority(), uri.host()) else {
+            return Err(AcmeError::Protocol(format!("URL has no host: {}", url)));
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(HOST, authority.as_str())
+            .header(USER_AGENT, concat!("sniproxy/", env!("CARGO_PKG_VERSION")));
+        if body.is_some() {
+            request = request.header(CONTENT_TYPE, "application/jose+json");
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body.unwrap_or_default())))
+            .map_err(|e| AcmeError::Protocol(e.to_string()))?;
+
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| AcmeError::Transport(format!("{}: {}", authority, e)))?;
+        if !https {
+            return send(TokioIo::new(stream), request).await;
+        }
+
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| AcmeError::Protocol(format!("{}: {}", host, e)))?;
+        let stream = self
+            .tls
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| AcmeError::Transport(format!("TLS to {}: {}", authority, e)))?;
+        send(TokioIo::new(stream), request).await
+    }
+}
+
+async fn send<T>(io: T, request: Request<Full<Bytes>>) -> Result<AcmeResponse, AcmeError>
+where
+    T: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(io).await?;
+    let connection = tokio::spawn(connection);
+
+    let result = async {
+        let (parts, body) = sender.send_request(request).await?.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Ok(AcmeResponse {
+            status: parts.status,
+            location: header(LOCATION.as_str()),
+            nonce: header("replay-nonce"),
+            body,
+        })
+    }
+    .await;
+
+    connection.abort();
+    result
+}
+
+/// Minimal RFC 8555 client: one account, one single-domain order at a time
+pub struct AcmeClient {
+    http: HttpClient,
+    key: AccountKey,
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// Fetches the server's directory
+    ///
+    /// The server certificate is verified against `ca_file` if given (e.g.
+    /// a test CA), otherwise against the system trust store.
+    pub async fn connect(
+        directory_url: &str,
+        ca_file: Option<&str>,
+        key: AccountKey,
+    ) -> Result<Self, AcmeError> {
+        let settings = UpstreamTls {
+            ca_file: ca_file.map(str::to_string),
+            ..Default::default()
+        };
+        let tls =
+            upstream_client_config(&settings).map_err(|e| AcmeError::Crypto(e.to_string()))?;
+        let http = HttpClient {
+            tls: TlsConnector::from(tls),
+        };
+
+        let response = http.request(Method::GET, directory_url, None).await?;
+        if !response.status.is_success() {
+            return Err(response.into_problem());
+        }
+        let directory = response.json()?;
+        let endpoint = |name: &str| {
+            directory[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| AcmeError::Protocol(format!("directory has no {}", name)))
+        };
+
+        Ok(Self {
+            new_nonce: endpoint("newNonce")?,
+            new_account: endpoint("newAccount")?,
+            new_order: endpoint("newOrder")?,
+            http,
+            key,
+            account_url: None,
+            nonce: None,
+        })
+    }
+
+    pub fn key(&self) -> &AccountKey {
+        &self.key
+    }
+
+    /// Registers an account for the key, or looks up the existing one
+    ///
+    /// Returns the account URL.
+    pub async fn register(&mut self, contact: &[String]) -> Result<String, AcmeError> {
+        let payload = json!({ "termsOfServiceAgreed": true, "contact": contact });
+        let new_account = self.new_account.clone();
+        let response = self.post(&new_account, Some(&payload)).await?;
+
+        let account_url = response
+            .location
+            .ok_or_else(|| AcmeError::Protocol("account response has no Location".to_string()))?;
+        self.account_url = Some(account_url.clone());
+        Ok(account_url)
+    }
+
+    /// Orders a certificate for `domain`, answering the challenge via `challenges`
+    ///
+    /// Returns the PEM certificate chain and the PEM private key.
+    pub async fn order_certificate(
+        &mut self,
+        domain: &str,
+        challenge: AcmeChallengeType,
+        challenges: &AcmeChallenges,
+    ) -> Result<(String, String), AcmeError> {
+        let payload = json!({ "identifiers": [{ "type": "dns", "value": domain }] });
+        let new_order = self.new_order.clone();
+        let response = self.post(&new_order, Some(&payload)).await?;
+        let order_url = response
+            .location
+            .clone()
+            .ok_or_else(|| AcmeError::Protocol("order response has no Location".to_string()))?;
+        let order = response.json()?;
+
+        for authorization in order["authorizations"].as_array().into_iter().flatten() {
+            let url = authorization
+                .as_str()
+                .ok_or_else(|| AcmeError::Protocol("invalid authorization URL".to_string()))?;
+            self.authorize(domain, url, challenge, challenges).await?;
+        }
+
+        let finalize = required_str(&order, "finalize")?.to_string();
+        let key_pair = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec![domain.to_string()])?;
+        params.distinguished_name = DistinguishedName::new();
+        let csr = params.serialize_request(&key_pair)?;
+        self.post(
+            &finalize,
+            Some(&json!({ "csr": URL_SAFE_NO_PAD.encode(csr.der()) })),
+        )
+        .await?;
+
+        let order = self.poll(&order_url, domain).await?;
+        let certificate_url = required_str(&order, "certificate")?.to_string();
+        let response = self.post(&certificate_url, None).await?;
+        let chain = String::from_utf8(response.body.to_vec())
+            .map_err(|_| AcmeError::Protocol("certificate chain is not PEM".to_string()))?;
+
+        Ok((chain, key_pair.serialize_pem()))
+    }
+
+    /// Completes one authorization with the configured challenge type
+    async fn authorize(
+        &mut self,
+        domain: &str,
+        url: &str,
+        challenge: AcmeChallengeType,
+        challenges: &AcmeChallenges,
+    ) -> Result<(), AcmeError> {
+        let authorization = self.post(url, None).await?.json()?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+
+        let kind = match challenge {
+            AcmeChallengeType::Http01 => "http-01",
+            AcmeChallengeType::TlsAlpn01 => "tls-alpn-01",
+        };
+        let offered = authorization["challenges"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|offered| offered["type"] == kind)
+            .ok_or_else(|| AcmeError::Validation {
+                domain: domain.to_string(),
+                detail: format!("server offered no {} challenge", kind),
+            })?;
+        let token = required_str(offered, "token")?;
+        let challenge_url = required_str(offered, "url")?;
+
+        debug!(domain, challenge = kind, "Answering ACME challenge");
+        challenges.publish(domain, challenge, token, &self.key.key_authorization(token))?;
+        let result = async {
+            self.post(challenge_url, Some(&json!({}))).await?;
+            self.poll(url, domain).await
+        }
+        .await;
+        challenges.withdraw(domain, token);
+
+        result.map(|_| ())
+    }
+
+    /// Polls an order or authorization until it is valid
+    async fn poll(&mut self, url: &str, domain: &str) -> Result<Value, AcmeError> {
+        for _ in 0..MAX_POLLS {
+            let resource = self.post(url, None).await?.json()?;
+            match resource["status"].as_str() {
+                Some("valid") => return Ok(resource),
+                Some("pending") | Some("processing") | Some("ready") => sleep(POLL_INTERVAL).await,
+                status => {
+                    return Err(AcmeError::Validation {
+                        domain: domain.to_string(),
+                        detail: format!(
+                            "status {}: {}",
+                            status.unwrap_or("missing"),
+                            problem_detail(&resource)
+                        ),
+                    });
+                }
+            }
+        }
+
+        Err(AcmeError::Validation {
+            domain: domain.to_string(),
+            detail: format!("{} did not become valid in time", url),
+        })
+    }
+
+    /// Sends a signed POST, retrying once if the server rejects the nonce
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<AcmeResponse, AcmeError> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.fetch_nonce().await?,
+            };
+            let body = self
+                .key
+                .sign(url, &nonce, self.account_url.as_deref(), payload)?;
+            let response = self
+                .http
+                .request(Method::POST, url, Some(serde_json::to_vec(&body)?))
+                .await?;
+            self.nonce = response.nonce.clone();
+
+            if response.status.is_success() {
+                return Ok(response);
+            }
+            let problem = response.into_problem();
+            match problem {
+                AcmeError::Problem { ref kind, .. } if kind == BAD_NONCE && !retried => {
+                    retried = true;
+                }
+                problem => return Err(problem),
+            }
+        }
+    }
+
+    async fn fetch_nonce(&self) -> Result<String, AcmeError> {
+        let response = self
+            .http
+            .request(Method::HEAD, &self.new_nonce, None)
+            .await?;
+        response
+            .nonce
+            .ok_or_else(|| AcmeError::Protocol("newNonce response has no Replay-Nonce".to_string()))
+    }
+}
+
+fn required_str<'a>(resource: &'a Value, field: &str) -> Result<&'a str, AcmeError> {
+    resource[field]
+        .as_str()
+        .ok_or_else(|| AcmeError::Protocol(format!("response has no {}", field)))
+}
+
+/// Error detail of a failed order or authorization, if the server gave one
+fn problem_detail(resource: &Value) -> String {
+    let challenge_errors = resource["challenges"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|challenge| &challenge["error"]);
+    std::iter::once(&resource["error"])
+        .chain(challenge_errors)
+        .find_map(|error| error["detail"].as_str())
+        .unwrap_or("no detail")
+        .to_string()
+}
+
+/// Hosts of terminate-mode routes with `acme: true`
+///
+/// Wildcard hosts are skipped: they can only be validated with dns-01.
+pub fn managed_domains(config: &Config) -> Vec<String> {
+    let mut domains = Vec::new();
+    for route in config.routes.iter().flatten().filter(|route| route.acme) {
+        if route.mode != RouteMode::Terminate {
+            warn!(
+                route = route.host,
+                "acme requires mode: terminate, skipping route"
+            );
+        } else if route.host.contains('*') {
+            warn!(
+                route = route.host,
+                "ACME cannot issue wildcard certificates, skipping route"
+            );
+        } else {
+            domains.push(route.host.to_ascii_lowercase());
+        }
+    }
+    domains
+}
+
+/// Keeps the certificates of ACME-managed routes issued and renewed
+#[derive(Debug)]
+pub struct AcmeManager {
+    settings: AcmeConfig,
+    domains: Vec<String>,
+    store: Arc<CertificateStore>,
+    challenges: Arc<AcmeChallenges>,
+}
+
+impl AcmeManager {
+    pub fn new(
+        settings: AcmeConfig,
+        domains: Vec<String>,
+        store: Arc<CertificateStore>,
+        challenges: Arc<AcmeChallenges>,
+    ) -> Self {
+        Self {
+            settings,
+            domains,
+            store,
+            challenges,
+        }
+    }
+
+    /// Domains whose certificate is missing or expires within `renew_before_days`
+    pub fn due_for_renewal(&self) -> Vec<String> {
+        let window = Duration::from_secs(self.settings.renew_before_days * 24 * 60 * 60);
+        self.domains
+            .iter()
+            .filter(|domain| needs_renewal(&self.certificate_path(domain, "crt"), window))
+            .cloned()
+            .collect()
+    }
+
+    /// Issues every certificate that is due and reloads the store
+    ///
+    /// Returns the number of certificates issued; if any order failed, the
+    /// last error is returned after the successful ones are installed.
+    pub async fn renew_due(&self) -> Result<usize, AcmeError> {
+        let due = self.due_for_renewal();
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let key = AccountKey::load_or_generate(
+            &Path::new(&self.settings.state_dir).join(ACCOUNT_KEY_FILE),
+        )?;
+        let mut client = AcmeClient::connect(
+            &self.settings.directory_url,
+            self.settings.ca_file.as_deref(),
+            key,
+        )
+        .await?;
+        let account = client.register(&self.settings.contact).await?;
+        debug!(account, "ACME account ready");
+
+        let mut issued = 0;
+        let mut failure = None;
+        for domain in due {
+            match client
+                .order_certificate(&domain, self.settings.challenge, &self.challenges)
+                .await
+            {
+                Ok((chain, key)) => {
+                    self.install(&domain, &chain, &key)?;
+                    info!(domain, "Installed certificate issued via ACME");
+                    issued += 1;
+                }
+                Err(e) => {
+                    warn!(domain, error = %e, "ACME certificate order failed");
+                    failure = Some(e);
+                }
+            }
+        }
+
+        if issued > 0 {
+            self.store.reload()?;
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(issued),
+        }
+    }
+
+    /// Checks for due certificates every `check_interval` seconds, forever
+    pub async fn run(self) {
+        let check_interval = Duration::from_secs(self.settings.check_interval.max(1));
+        info!(domains = ?self.domains, "ACME certificate manager started");
+
+        loop {
+            let delay = match self.renew_due().await {
+                Ok(_) => check_interval,
+                Err(e) => {
+                    warn!(error = %e, "ACME renewal failed, retrying later");
+                    RETRY_INTERVAL.min(check_interval)
+                }
+            };
+            sleep(delay).await;
+        }
+    }
+
+    fn certificate_path(&self, domain: &str, extension: &str) -> PathBuf {
+        self.store
+            .directory()
+            .join(format!("{}.{}", domain, extension))
+    }
+
+    /// Writes the key before the chain so the store never pairs a new chain with an old key
+    fn install(&self, domain: &str, chain: &str, key: &str) -> io::Result<()> {
+        fs::create_dir_all(self.store.directory())?;
+        write_private_file(&self.certificate_path(domain, "key"), key.as_bytes())?;
+        write_private_file(&self.certificate_path(domain, "crt"), chain.as_bytes())
+    }
+}
+
+/// Returns true if the certificate at `path` is missing, unreadable or expires within `window`
+fn needs_renewal(path: &Path, window: Duration) -> bool {
+    let Ok(certificate) = CertificateDer::from_pem_file(path) else {
+        return true;
+    };
+    let Ok((_, parsed)) = x509_parser::parse_x509_certificate(&certificate) else {
+        return true;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    parsed.validity().not_after.timestamp() - now <= window.as_secs() as i64
+}
+
+/// Atomically replaces `path`, readable by the owner only
+///
+/// The temporary file has a `.tmp` extension so a concurrent directory scan
+/// ignores it.
+fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+    use sniproxy_config::Route;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sniproxy-acme-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn decode_json(encoded: &str) -> Value {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(encoded).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_jwk_and_thumbprint() {
+        let key = AccountKey::generate().unwrap();
+        let jwk = key.jwk();
+
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["crv"], "P-256");
+        // 32-byte coordinates encode to 43 base64url characters
+        assert_eq!(jwk["x"].as_str().unwrap().len(), 43);
+        assert_eq!(jwk["y"].as_str().unwrap().len(), 43);
+
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            jwk["x"].as_str().unwrap(),
+            jwk["y"].as_str().unwrap()
+        );
+        assert_eq!(
+            key.thumbprint(),
+            URL_SAFE_NO_PAD.encode(digest(&SHA256, canonical.as_bytes()))
+        );
+        assert_eq!(
+            key.key_authorization("token-123"),
+            format!("token-123.{}", key.thumbprint())
+        );
+    }
+
+    #[test]
+    fn test_jws_signature_verifies() {
+        let key = AccountKey::generate().unwrap();
+        let payload = json!({ "termsOfServiceAgreed": true });
+
+        let jws = key
+            .sign("https://ca.test/new-acct", "nonce-1", None, Some(&payload))
+            .unwrap();
+        let protected = decode_json(jws["protected"].as_str().unwrap());
+        assert_eq!(protected["alg"], "ES256");
+        assert_eq!(protected["nonce"], "nonce-1");
+        assert_eq!(protected["url"], "https://ca.test/new-acct");
+        assert_eq!(protected["jwk"], key.jwk());
+        assert!(protected.get("kid").is_none());
+        assert_eq!(decode_json(jws["payload"].as_str().unwrap()), payload);
+
+        let signing_input = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(signature.len(), 64);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key.key_pair.public_key().as_ref())
+            .verify(signing_input.as_bytes(), &signature)
+            .unwrap();
+
+        // Registered accounts sign with kid; POST-as-GET has an empty payload
+        let jws = key
+            .sign(
+                "https://ca.test/order/1",
+                "nonce-2",
+                Some("https://ca.test/acct/1"),
+                None,
+            )
+            .unwrap();
+        let protected = decode_json(jws["protected"].as_str().unwrap());
+        assert_eq!(protected["kid"], "https://ca.test/acct/1");
+        assert!(protected.get("jwk").is_none());
+        assert_eq!(jws["payload"], "");
+    }
+
+    #[test]
+    fn test_account_key_persisted() {
+        let dir = scratch_dir("account");
+        let path = dir.join("state").join(ACCOUNT_KEY_FILE);
+
+        let created = AccountKey::load_or_generate(&path).unwrap();
+        assert!(path.exists());
+        let loaded = AccountKey::load_or_generate(&path).unwrap();
+        assert_eq!(created.thumbprint(), loaded.thumbprint());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_http01_response() {
+        let challenges = AcmeChallenges::new();
+        challenges
+            .publish(
+                "a.example.com",
+                AcmeChallengeType::Http01,
+                "tok",
+                "tok.thumb",
+            )
+            .unwrap();
+
+        let response = challenges
+            .http01_response(
+                b"GET /.well-known/acme-challenge/tok HTTP/1.1\r\nHost: a.example.com\r\n\r\n",
+            )
+            .unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 9\r\n"));
+        assert!(response.ends_with("\r\n\r\ntok.thumb"));
+
+        assert!(
+            challenges
+                .http01_response(b"GET /.well-known/acme-challenge/other HTTP/1.1\r\n\r\n")
+                .is_none()
+        );
+        assert!(
+            challenges
+                .http01_response(b"POST /.well-known/acme-challenge/tok HTTP/1.1\r\n\r\n")
+                .is_none()
+        );
+        assert!(
+            challenges
+                .http01_response(b"GET / HTTP/1.1\r\n\r\n")
+                .is_none()
+        );
+
+        challenges.withdraw("a.example.com", "tok");
+        assert!(challenges.key_authorization("tok").is_none());
+    }
+
+    #[test]
+    fn test_tls_alpn01_certificate_has_acme_identifier() {
+        let challenges = AcmeChallenges::new();
+        challenges
+            .publish(
+                "A.example.com",
+                AcmeChallengeType::TlsAlpn01,
+                "tok",
+                "tok.thumb",
+            )
+            .unwrap();
+        assert!(challenges.has_tls_alpn01("a.example.com"));
+
+        let certificate = challenges.tls_alpn01.get("a.example.com").unwrap().cert[0].clone();
+        let (_, parsed) = x509_parser::parse_x509_certificate(&certificate).unwrap();
+        let extension = parsed
+            .extensions()
+            .iter()
+            .find(|ext| ext.oid.to_id_string() == "1.3.6.1.5.5.7.1.31")
+            .unwrap();
+        assert!(extension.critical);
+
+        // DER OCTET STRING wrapping the SHA-256 digest of the key authorization
+        let mut expected = vec![0x04, 0x20];
+        expected.extend_from_slice(digest(&SHA256, b"tok.thumb").as_ref());
+        assert_eq!(extension.value, expected.as_slice());
+
+        let san = parsed.subject_alternative_name().unwrap().unwrap();
+        assert_eq!(
+            san.value.general_names,
+            vec![x509_parser::extensions::GeneralName::DNSName(
+                "A.example.com"
+            )]
+        );
+
+        challenges.withdraw("a.example.com", "tok");
+        assert!(!challenges.has_tls_alpn01("a.example.com"));
+    }
+
+    #[test]
+    fn test_needs_renewal() {
+        let dir = scratch_dir("renewal");
+        let write_cert = |name: &str, days: u64| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            params.not_after =
+                rcgen::date_time_ymd(1970, 1, 1) + (now + Duration::from_secs(days * 24 * 60 * 60));
+            let path = dir.join(format!("{}.crt", name));
+            fs::write(&path, params.self_signed(&key).unwrap().pem()).unwrap();
+            path
+        };
+
+        let window = Duration::from_secs(30 * 24 * 60 * 60);
+        assert!(needs_renewal(&write_cert("soon.example.com", 10), window));
+        assert!(!needs_renewal(&write_cert("later.example.com", 60), window));
+        assert!(needs_renewal(&dir.join("missing.example.com.crt"), window));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_managed_domains() {
+        let route = |host: &str, mode: RouteMode, acme: bool| Route {
+            host: host.to_string(),
+            mode,
+            acme,
+            ..Default::default()
+        };
+        let config = Config {
+            routes: Some(vec![
+                route("App.example.com", RouteMode::Terminate, true),
+                route("*.example.com", RouteMode::Terminate, true),
+                route("pass.example.com", RouteMode::Passthrough, true),
+                route("manual.example.com", RouteMode::Terminate, false),
+            ]),
+            ..Config::parse(
+                "listen_addrs: []\ntimeouts: { connect: 1, client_hello: 1, idle: 1 }\nmetrics: { enabled: false, address: \"127.0.0.1:0\" }\n",
+            )
+            .unwrap()
+        };
+
+        assert_eq!(managed_domains(&config), vec!["app.example.com"]);
+    }
+}
@@ -1,17 +1,33 @@
 use crate::SniError;
-use crate::connection_pool::{ConnectionPool, PoolConfig};
+use crate::acme::{self, AcmeChallenges};
+use crate::connection_pool::{
+    ConnectionLease, ConnectionPool, HttpVersion, PoolConfig, PooledStream,
+};
+use crate::grpc_pool::{GrpcConnectionPool, GrpcPoolConfig, GrpcPoolError};
+use crate::hpack::HpackDecoder;
 use crate::http::{self, HttpError};
 use crate::metrics_cache::MetricLabelCache;
 use crate::protocols;
+use crate::termination::{self, CertificateStore, ReplayStream};
+use crate::tls;
+use crate::websocket::{self, TunnelSettings, WebSocketMetrics};
+use crate::websocket_compression::{WebSocketCompression, WebSocketCompressionConfig};
 use prometheus::{
     HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
 };
-use sniproxy_config::{Config, matches_allowlist_pattern};
+use rustls::pki_types::ServerName;
+use rustls::server::WebPkiClientVerifier;
+use sniproxy_config::{
+    Config, GrpcRule, IdentityForwarding, Route, RouteMode, TlsPolicy, matches_allowlist_pattern,
+};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpStream, lookup_host};
 use tokio::time::{Duration, timeout};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tracing::{debug, error, info, warn};
 
 const MAX_TLS_HEADER_SIZE: usize = 16384; // Increased size for TLS header
@@ -37,12 +53,6 @@ const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 const ALPN_HTTP2: &str = "h2";
 const ALPN_HTTP3: &[&str] = &["h3", "h3-29", "h3-32"];
 
-// WebSocket detection constants (reserved for future use)
-#[allow(dead_code)]
-const WEBSOCKET_UPGRADE: &str = "websocket";
-#[allow(dead_code)]
-const SWITCHING_PROTOCOLS: &[u8] = b"HTTP/1.1 101";
-
 // gRPC detection constants (reserved for future use)
 #[allow(dead_code)]
 const GRPC_CONTENT_TYPE: &str = "application/grpc";
@@ -169,12 +179,7 @@ fn detect_web_protocol(
         return Ok(Protocol::Rpc);
     }
 
-    // 3. Check for gRPC if this is HTTP/2
-    if matches!(base_protocol, Protocol::Http2) && http::is_grpc_request(buffer) {
-        return Ok(Protocol::Grpc);
-    }
-
-    // 4. For POST requests, check body-based protocols (SOAP, JSON-RPC, XML-RPC)
+    // 3. For POST requests, check body-based protocols (SOAP, JSON-RPC, XML-RPC)
     if request_str.to_lowercase().starts_with("post ") {
         // Find the end of headers (double CRLF)
         if let Some(body_start) = find_body_start(buffer) {
@@ -226,11 +231,80 @@ fn find_body_start(buffer: &[u8]) -> Option<usize> {
     None
 }
 
+/// Errors that can occur while connecting to a backend
+#[derive(Debug)]
+enum UpstreamError {
+    /// The backend hostname could not be resolved
+    Resolve(String, io::Error),
+    /// The TCP connect did not complete within the connect timeout
+    Timeout(String),
+    /// The TCP connect failed (refused, unreachable, ...)
+    Connect(String, io::Error),
+    /// The TLS handshake with the backend failed (including certificate verification)
+    Tls(String, io::Error),
+}
+
+impl std::fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamError::Resolve(target, e) => write!(f, "Failed to resolve {}: {}", target, e),
+            UpstreamError::Timeout(target) => write!(f, "Connection to {} timed out", target),
+            UpstreamError::Connect(target, e) => {
+                write!(f, "Failed to connect to {}: {}", target, e)
+            }
+            UpstreamError::Tls(target, e) => {
+                write!(f, "TLS handshake with {} failed: {}", target, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+impl UpstreamError {
+    /// TLS alert sent to a client whose backend could not be reached
+    #[inline]
+    fn tls_alert(&self) -> tls::AlertDescription {
+        match self {
+            UpstreamError::Resolve(..) => tls::AlertDescription::UnrecognizedName,
+            UpstreamError::Timeout(_) | UpstreamError::Connect(..) | UpstreamError::Tls(..) => {
+                tls::AlertDescription::InternalError
+            }
+        }
+    }
+
+    /// HTTP status sent to a client whose backend could not be reached
+    #[inline]
+    fn http_status(&self) -> u16 {
+        match self {
+            UpstreamError::Timeout(_) => 504,
+            UpstreamError::Resolve(..) | UpstreamError::Connect(..) | UpstreamError::Tls(..) => 502,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ConnectionHandler {
     config: Arc<Config>,
     metrics: Option<Arc<ConnectionMetrics>>,
-    pool: Option<Arc<ConnectionPool>>,
+    pool: Option<Arc<ConnectionPool<Box<dyn PooledStream>>>>,
+    /// Backend HTTP/2 channels for gRPC calls, if `grpc_pool` is enabled
+    grpc_pool: Option<Arc<GrpcConnectionPool>>,
+    certificates: Option<Arc<CertificateStore>>,
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Acceptors for mutual TLS routes keyed by route host pattern
+    client_auth_acceptors: Arc<HashMap<String, TlsAcceptor>>,
+    /// Backend TLS client configurations keyed by route host pattern
+    upstream_tls: Arc<HashMap<String, TlsConnector>>,
+    /// Pending ACME challenge responses, if `acme` is configured
+    acme_challenges: Option<Arc<AcmeChallenges>>,
+    /// Acceptor answering tls-alpn-01 validation handshakes
+    acme_acceptor: Option<TlsAcceptor>,
+    /// Limits and keep-alive for WebSocket tunnels
+    websocket: TunnelSettings,
+    /// permessage-deflate the proxy offers clients when backends accept none
+    websocket_compression: Option<WebSocketCompression>,
+    websocket_metrics: Option<WebSocketMetrics>,
 }
 
 struct ConnectionMetrics {
@@ -241,6 +315,9 @@ struct ConnectionMetrics {
     connection_duration: HistogramVec,
     errors_total: IntCounterVec,
     protocol_distribution: IntCounterVec,
+    tls_policy_rejections: IntCounterVec,
+    tls_terminations: IntCounterVec,
+    grpc_requests: IntCounterVec,
     label_cache: MetricLabelCache,
 }
 
@@ -313,6 +390,40 @@ impl ConnectionMetrics {
             .register(Box::new(protocol_distribution.clone()))
             .unwrap();
 
+        let tls_policy_rejections = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_tls_policy_rejections_total",
+                "Handshakes rejected by per-route TLS policy (ClientHello or backend ServerHello)",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(tls_policy_rejections.clone()))
+            .unwrap();
+
+        let tls_terminations = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_tls_terminations_total",
+                "TLS handshakes completed by the proxy for terminate-mode routes",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(tls_terminations.clone()))
+            .unwrap();
+
+        let grpc_requests = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_grpc_requests_total",
+                "gRPC calls over h2c by service and routing result",
+            ),
+            &["service", "result"],
+        )
+        .unwrap();
+        registry.register(Box::new(grpc_requests.clone())).unwrap();
+
         Self {
             bytes_transferred,
             connections_total,
@@ -320,17 +431,33 @@ impl ConnectionMetrics {
             connection_duration,
             errors_total,
             protocol_distribution,
+            tls_policy_rejections,
+            tls_terminations,
+            grpc_requests,
             label_cache: MetricLabelCache::new(),
         }
     }
 }
 
 impl ConnectionHandler {
-    pub fn new(config: Arc<Config>, registry: Option<&Registry>) -> Self {
+    /// Builds the handler and every TLS configuration its routes need
+    ///
+    /// Fails if a TLS configuration can't be built, for example because a
+    /// `ca_file` is missing or holds no certificates, so that a broken route
+    /// stops startup instead of failing every connection.
+    pub fn new(
+        config: Arc<Config>,
+        registry: Option<&Registry>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        config.validate()?;
         let metrics = registry.map(|r| Arc::new(ConnectionMetrics::new(r)));
 
-        // Initialize connection pool if configured
-        let pool = if let Some(pool_config) = &config.connection_pool {
+        // Idle HTTP/1.x backend connections are pooled if enabled
+        let pool = if let Some(pool_config) = config
+            .connection_pool
+            .as_ref()
+            .filter(|pool_config| pool_config.enabled)
+        {
             let pool_cfg = PoolConfig {
                 enabled: pool_config.enabled,
                 max_per_host: pool_config.max_per_host,
@@ -350,11 +477,137 @@ impl ConnectionHandler {
             None
         };
 
-        Self {
+        // gRPC calls are multiplexed onto pooled backend channels if enabled
+        let grpc_pool = config
+            .grpc_pool
+            .as_ref()
+            .filter(|pool_config| pool_config.enabled)
+            .and_then(|pool_config| {
+                let pool_cfg = GrpcPoolConfig {
+                    enabled: true,
+                    max_channels_per_host: pool_config.max_channels_per_host,
+                    max_concurrent_streams: pool_config.max_concurrent_streams,
+                    channel_ttl: pool_config.channel_ttl,
+                    idle_timeout: pool_config.idle_timeout,
+                    health_check_interval: pool_config.health_check_interval,
+                    connect_timeout: config.timeouts.connect,
+                };
+
+                let pool = if let Some(reg) = registry {
+                    GrpcConnectionPool::with_metrics(pool_cfg, reg).ok()
+                } else {
+                    Some(GrpcConnectionPool::new(pool_cfg))
+                };
+
+                pool.map(Arc::new)
+            });
+
+        // Certificates and acceptor for routes in TLS termination mode
+        let certificates = config
+            .tls_certificates
+            .as_ref()
+            .map(|certs| Arc::new(CertificateStore::new(&certs.directory)));
+        let tls_acceptor = certificates
+            .as_ref()
+            .map(|store| store.server_config(WebPkiClientVerifier::no_client_auth()))
+            .transpose()
+            .map_err(|e| format!("failed to build TLS termination config: {}", e))?
+            .map(TlsAcceptor::from);
+
+        // Mutual TLS routes get their own acceptor with a client certificate verifier
+        let mut client_auth_acceptors = HashMap::new();
+        if let Some(ref store) = certificates {
+            for route in config.routes.iter().flatten() {
+                let Some(ref auth) = route.client_auth else {
+                    continue;
+                };
+                let server_config = termination::client_verifier(auth)
+                    .and_then(|verifier| Ok(store.server_config(verifier)?))
+                    .map_err(|e| {
+                        format!(
+                            "failed to build client certificate verifier for route {}: {}",
+                            route.host, e
+                        )
+                    })?;
+                client_auth_acceptors.insert(route.host.clone(), TlsAcceptor::from(server_config));
+            }
+        }
+
+        // Backend TLS configurations, built once per route
+        let mut upstream_tls = HashMap::new();
+        for route in config.routes.iter().flatten() {
+            let Some(ref settings) = route.upstream_tls else {
+                continue;
+            };
+            let mut client_config = termination::upstream_client_config(settings).map_err(|e| {
+                format!(
+                    "failed to build upstream TLS config for route {}: {}",
+                    route.host, e
+                )
+            })?;
+            if route.strip_sni {
+                Arc::make_mut(&mut client_config).enable_sni = false;
+            }
+            upstream_tls.insert(route.host.clone(), TlsConnector::from(client_config));
+        }
+
+        // Challenge responses for the ACME client, answered on the proxy's own listeners
+        let acme_challenges = config
+            .acme
+            .as_ref()
+            .map(|_| Arc::new(AcmeChallenges::new()));
+        let acme_acceptor = acme_challenges
+            .as_ref()
+            .map(|challenges| challenges.tls_alpn01_server_config())
+            .transpose()
+            .map_err(|e| format!("failed to build ACME tls-alpn-01 config: {}", e))?
+            .map(TlsAcceptor::from);
+
+        let websocket = TunnelSettings::from(&config.websocket.clone().unwrap_or_default());
+        let websocket_compression = config
+            .websocket
+            .as_ref()
+            .and_then(|websocket| websocket.compression.as_ref())
+            .map(|compression| {
+                WebSocketCompression::new(WebSocketCompressionConfig::from(compression))
+            });
+        let websocket_metrics = registry.and_then(|reg| WebSocketMetrics::new(reg).ok());
+
+        Ok(Self {
             config,
             metrics,
             pool,
-        }
+            grpc_pool,
+            certificates,
+            tls_acceptor,
+            client_auth_acceptors: Arc::new(client_auth_acceptors),
+            upstream_tls: Arc::new(upstream_tls),
+            acme_challenges,
+            acme_acceptor,
+            websocket,
+            websocket_compression,
+            websocket_metrics,
+        })
+    }
+
+    /// Certificate store used by terminate-mode routes, if configured
+    pub fn certificate_store(&self) -> Option<Arc<CertificateStore>> {
+        self.certificates.clone()
+    }
+
+    /// Pool of idle HTTP/1.x backend connections, if `connection_pool` is enabled
+    pub fn connection_pool(&self) -> Option<Arc<ConnectionPool<Box<dyn PooledStream>>>> {
+        self.pool.clone()
+    }
+
+    /// Pool of backend channels for gRPC calls, if `grpc_pool` is enabled
+    pub fn grpc_pool(&self) -> Option<Arc<GrpcConnectionPool>> {
+        self.grpc_pool.clone()
+    }
+
+    /// Pending ACME challenge responses, if `acme` is configured
+    pub fn acme_challenges(&self) -> Option<Arc<AcmeChallenges>> {
+        self.acme_challenges.clone()
     }
 
     pub async fn handle_connection(&self, mut client: TcpStream, client_addr: SocketAddr) {
@@ -392,6 +645,7 @@ impl ConnectionHandler {
                     || error_msg.contains("timeout")
                     || error_msg.contains("ClientHello")
                     || error_msg.contains("Host header")
+                    || error_msg.contains("Request denied")
                     || error_msg.contains("Unknown protocol")
                     || error_msg.contains("Connection reset")
                     || error_msg.contains("Broken pipe");
@@ -476,7 +730,7 @@ impl ConnectionHandler {
                 }
             }
             Protocol::WebSocket => self.handle_http(client, protocol).await?,
-            Protocol::Grpc => self.handle_http2(client, true).await?,
+            Protocol::Grpc => self.handle_http2_cleartext(client).await?,
             // Phase 2: Web Protocol Support - All HTTP-based protocols
             Protocol::SocketIO
             | Protocol::JsonRpc
@@ -571,104 +825,379 @@ impl ConnectionHandler {
         Ok(Protocol::Unknown)
     }
 
+    /// Proxies HTTP/1.x requests, routing each one on its own Host header
+    ///
+    /// A keep-alive connection may carry requests for different hosts, so the
+    /// allowlist and routing are applied per request and the backend is
+    /// switched when a request targets another one. After a protocol switch
+    /// (101 or a successful CONNECT) the rest of the connection is tunneled,
+    /// frame by frame for a validated WebSocket handshake.
+    /// Backend connections left idle after a complete response go back to the
+    /// connection pool, if one is configured.
     async fn handle_http(
         &self,
         client: &mut TcpStream,
         protocol: Protocol,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut buffer = Vec::with_capacity(16384); // Increased capacity
+        let mut upstream = None;
+        let result = self
+            .route_http_requests(client, protocol, &mut upstream)
+            .await;
+        if let Some(upstream) = upstream {
+            self.return_to_pool(upstream);
+        }
+        result
+    }
 
-        // Extract host from HTTP headers
-        let (host, bytes_read) = match http::extract_host(client, &mut buffer).await {
-            Ok(result) => result,
-            Err(HttpError::NoHostHeader) => {
+    /// Forwards the requests of one client connection, keeping its current
+    /// backend connection in `upstream`
+    async fn route_http_requests(
+        &self,
+        client: &mut TcpStream,
+        protocol: Protocol,
+        upstream: &mut Option<HttpUpstream>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let idle_timeout = Duration::from_secs(self.config.timeouts.idle);
+        let mut client = http::MessageReader::new(client, idle_timeout);
+
+        while let Some(head) = client.read_head().await? {
+            let request = match http::parse_request_head(&head) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.send_http_error(client.get_mut(), 400, "", "Malformed request")
+                        .await;
+                    return Err(Box::new(e));
+                }
+            };
+            let Some(host) = request.host.clone() else {
                 warn!("No Host header in HTTP request");
+                self.send_http_error(client.get_mut(), 421, "", "Request has no Host header")
+                    .await;
                 return Ok(());
-            }
-            Err(e) => return Err(Box::new(e)),
-        };
+            };
 
-        // Detect specific web protocols from the request
-        let effective_protocol = detect_web_protocol(&buffer[..bytes_read], protocol)?;
+            // Answer ACME http-01 validation requests before any routing
+            if let Some(ref challenges) = self.acme_challenges
+                && let Some(response) = challenges.http01_response(&head)
+            {
+                info!(host, "Answering ACME http-01 validation request");
+                client.get_mut().write_all(&response).await?;
+                client.get_mut().shutdown().await?;
+                return Ok(());
+            }
 
-        debug!(
-            host,
-            protocol = effective_protocol.as_str(),
-            "Detected web protocol from HTTP request"
-        );
+            // Detect specific web protocols from the request
+            let effective_protocol = match detect_web_protocol(
+                &[head.as_slice(), client.buffered()].concat(),
+                protocol,
+            )? {
+                Protocol::SocketIO => Protocol::SocketIO,
+                _ if request.websocket => Protocol::WebSocket,
+                detected => detected,
+            };
 
-        // Check allowlist if configured
-        if let Some(ref allowlist) = self.config.allowlist
-            && !self.is_host_allowed(&host, allowlist)
-        {
-            warn!(host, "Host not in allowlist");
-            return Ok(());
-        }
+            debug!(
+                host,
+                method = request.method,
+                protocol = effective_protocol.as_str(),
+                "Detected web protocol from HTTP request"
+            );
 
-        // Setup metrics if enabled
-        let metrics = self.metrics.as_ref().map(|m| {
-            let label = m
-                .label_cache
-                .get_or_insert(&host, effective_protocol.as_str());
-            // Static string references for direction labels
-            const TX: &str = "tx";
-            const RX: &str = "rx";
-            (
-                m.bytes_transferred.with_label_values(&[label.as_ref(), TX]),
-                m.bytes_transferred.with_label_values(&[label.as_ref(), RX]),
-            )
-        });
+            // Check allowlist if configured
+            if let Some(ref allowlist) = self.config.allowlist
+                && !self.is_host_allowed(&host, allowlist)
+            {
+                warn!(host, "Host not in allowlist");
+                self.send_http_error(client.get_mut(), 403, &host, "Host not in allowlist")
+                    .await;
+                return Ok(());
+            }
 
-        // Parse host and port (Host header may include port like "example.com:8080")
-        let (hostname, port) = if let Some(colon_pos) = host.rfind(':') {
-            // Check if the part after colon is a valid port number
-            if let Ok(p) = host[colon_pos + 1..].parse::<u16>() {
-                (host[..colon_pos].to_string(), p)
+            // The backend's 101 must carry the accept value for this handshake
+            let mut websocket_handshake = if request.websocket {
+                let handshake = http::validate_websocket_upgrade(&String::from_utf8_lossy(&head))
+                    .and_then(|accept| {
+                        if request.method == "GET" {
+                            Ok(accept)
+                        } else {
+                            Err(HttpError::WebSocketUpgradeFailed)
+                        }
+                    });
+                match handshake {
+                    Ok(accept) => Some(websocket::Handshake {
+                        accept,
+                        compression: self
+                            .websocket_compression
+                            .as_ref()
+                            .zip(request.websocket_extensions.as_deref())
+                            .and_then(|(compression, offers)| compression.negotiate(offers)),
+                    }),
+                    Err(e) => {
+                        warn!(host, "Invalid WebSocket handshake");
+                        self.send_http_error(
+                            client.get_mut(),
+                            400,
+                            &host,
+                            "Invalid WebSocket handshake",
+                        )
+                        .await;
+                        return Err(Box::new(e));
+                    }
+                }
             } else {
-                // Not a valid port, treat entire string as hostname
-                (host.clone(), effective_protocol.default_port())
-            }
-        } else {
-            // No port specified, use default
-            (host.clone(), effective_protocol.default_port())
-        };
+                None
+            };
 
-        // Tunnel the connection
-        match protocol {
-            Protocol::WebSocket => {
-                // For WebSockets, we need to monitor the upgrade
-                http::tunnel_websocket(client, &buffer[..bytes_read], &hostname, port, metrics)
-                    .await?
-            }
-            _ => {
-                // Standard HTTP tunneling
-                http::tunnel_http(client, &buffer[..bytes_read], &hostname, port, metrics).await?
+            // Setup metrics if enabled
+            let metrics = self.metrics.as_ref().map(|m| {
+                let label = m
+                    .label_cache
+                    .get_or_insert(&host, effective_protocol.as_str());
+                // Static string references for direction labels
+                const TX: &str = "tx";
+                const RX: &str = "rx";
+                (
+                    m.bytes_transferred.with_label_values(&[label.as_ref(), TX]),
+                    m.bytes_transferred.with_label_values(&[label.as_ref(), RX]),
+                )
+            });
+
+            // Parse host and port (Host header may include port like "example.com:8080")
+            let (hostname, port) = split_host_port(&host, effective_protocol.default_port());
+
+            // Routes with upstream_tls carry the plaintext request to the backend over TLS
+            let tls_route = self
+                .config
+                .route_for(&hostname)
+                .filter(|route| route.upstream_tls.is_some());
+            let target_addr = match tls_route {
+                Some(route) => route
+                    .upstream
+                    .clone()
+                    .unwrap_or_else(|| format!("{}:443", hostname)),
+                None => format!("{}:{}", hostname, port),
+            };
+            let tls_route_host = tls_route.map(|route| route.host.clone());
+
+            // Keep the backend connection if this request goes to the same
+            // place and the connection is within the pool's TTL and request limit
+            let mut server = match upstream.take() {
+                Some(mut current)
+                    if current.target_addr == target_addr
+                        && current.tls_route == tls_route_host
+                        && self
+                            .pool
+                            .as_ref()
+                            .is_none_or(|pool| pool.can_reuse(&current.lease)) =>
+                {
+                    current.lease.mark_used();
+                    upstream.insert(current)
+                }
+                previous => {
+                    if let Some(previous) = previous {
+                        debug!(
+                            host,
+                            previous = previous.target_addr,
+                            upstream = target_addr,
+                            "Switching backend for request"
+                        );
+                        self.return_to_pool(previous);
+                    }
+                    match self
+                        .checkout_http_upstream(&target_addr, &hostname, tls_route, idle_timeout)
+                        .await
+                    {
+                        Ok(checkout) => upstream.insert(checkout),
+                        Err(e) => {
+                            self.send_http_error(
+                                client.get_mut(),
+                                e.http_status(),
+                                &host,
+                                &e.to_string(),
+                            )
+                            .await;
+                            return Err(Box::new(e));
+                        }
+                    }
+                }
+            };
+
+            let exchange = loop {
+                server.reusable = false;
+                let exchange = forward_http_exchange(
+                    &mut client,
+                    &mut server.server,
+                    &request,
+                    &head,
+                    websocket_handshake.as_mut(),
+                    &metrics,
+                )
+                .await;
+
+                // The backend may close a reused connection as the request goes
+                // out; without a body it is safe to retry (RFC 9112 §9.3.1)
+                if server.reused
+                    && request.framing == http::BodyFraming::Empty
+                    && matches!(exchange, Err(HttpError::UpstreamClosed))
+                {
+                    debug!(
+                        host,
+                        upstream = target_addr,
+                        "Backend closed a reused connection, retrying request"
+                    );
+                    upstream.take();
+                    self.mark_connection_inactive();
+                    server = match self
+                        .checkout_http_upstream(&target_addr, &hostname, tls_route, idle_timeout)
+                        .await
+                    {
+                        Ok(checkout) => upstream.insert(checkout),
+                        Err(e) => {
+                            self.send_http_error(
+                                client.get_mut(),
+                                e.http_status(),
+                                &host,
+                                &e.to_string(),
+                            )
+                            .await;
+                            return Err(Box::new(e));
+                        }
+                    };
+                    continue;
+                }
+                break exchange;
+            };
+            match exchange {
+                Ok(HttpExchange::KeepAlive) => {
+                    server.reusable = true;
+                    server.reused = true;
+                }
+                Ok(HttpExchange::Close) => break,
+                Ok(HttpExchange::Upgraded) => {
+                    let Some(HttpUpstream { server, .. }) = upstream.take() else {
+                        break;
+                    };
+                    // A switched connection never returns to the pool
+                    self.mark_connection_inactive();
+                    let (mut server, server_buffered) = server.into_parts();
+                    let (client, client_buffered) = client.into_parts();
+                    if let Some(handshake) = websocket_handshake {
+                        let compression = handshake.compression.map(|(compression, _)| compression);
+                        debug!(
+                            host,
+                            compressed = compression.is_some(),
+                            "WebSocket handshake complete, relaying frames"
+                        );
+                        websocket::tunnel(
+                            ReplayStream::new(client_buffered, client),
+                            ReplayStream::new(server_buffered, server),
+                            &self.websocket,
+                            compression.as_ref(),
+                            self.websocket_metrics.as_ref(),
+                            metrics,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                    server.write_all(&client_buffered).await?;
+                    client.write_all(&server_buffered).await?;
+                    copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
+                    return Ok(());
+                }
+                Err(e @ (HttpError::UpstreamClosed | HttpError::WebSocketUpgradeFailed)) => {
+                    self.send_http_error(client.get_mut(), 502, &host, &e.to_string())
+                        .await;
+                    return Err(Box::new(e));
+                }
+                Err(e) => return Err(Box::new(e)),
             }
         }
 
+        let _ = client.get_mut().shutdown().await;
         Ok(())
     }
 
+    /// Wraps a backend connection in TLS for a route with `upstream_tls`
+    ///
+    /// The name sent as SNI and verified in the backend certificate is
+    /// `upstream_tls.server_name`, or `default_name` if unset.
+    async fn originate_tls(
+        &self,
+        route: &Route,
+        default_name: &str,
+        server: TcpStream,
+    ) -> Result<TlsStream<TcpStream>, UpstreamError> {
+        let name = upstream_server_name(route, default_name);
+        let Some(connector) = self.upstream_tls.get(&route.host) else {
+            return Err(UpstreamError::Tls(
+                name.to_string(),
+                io::Error::other(format!(
+                    "upstream TLS for route {} is unavailable",
+                    route.host
+                )),
+            ));
+        };
+        let server_name = ServerName::try_from(name.to_string()).map_err(|e| {
+            UpstreamError::Tls(
+                name.to_string(),
+                io::Error::new(io::ErrorKind::InvalidInput, e),
+            )
+        })?;
+
+        let connect_timeout = Duration::from_secs(self.config.timeouts.connect);
+        match timeout(connect_timeout, connector.connect(server_name, server)).await {
+            Ok(Ok(stream)) => Ok(stream),
+            Ok(Err(e)) => Err(UpstreamError::Tls(name.to_string(), e)),
+            Err(_) => Err(UpstreamError::Timeout(name.to_string())),
+        }
+    }
+
+    /// Sends a proxy-generated HTTP error response and closes the write side
+    ///
+    /// Failures are ignored: the client may already have gone away.
+    async fn send_http_error(
+        &self,
+        client: &mut TcpStream,
+        status: u16,
+        host: &str,
+        message: &str,
+    ) {
+        let defaults = sniproxy_config::ErrorResponses::default();
+        let error_responses = self.config.error_responses.as_ref().unwrap_or(&defaults);
+
+        let response = http::error_response(
+            status,
+            host,
+            message,
+            &error_responses.body_template,
+            &error_responses.content_type,
+        );
+        let _ = client.write_all(&response).await;
+        let _ = client.shutdown().await;
+    }
+
     async fn handle_http2_cleartext(
         &self,
         client: &mut TcpStream,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // For h2c, we need to extract the host from the HTTP/2 headers
-        // This requires parsing the HTTP/2 frames
+        // For h2c, the host comes from the first request's HEADERS frame
 
         // Read the preface (we already peeked at it, but now we need to consume it)
         let mut preface_buffer = vec![0u8; HTTP2_PREFACE.len()];
         client.read_exact(&mut preface_buffer).await?;
 
-        // Extract :authority pseudo-header from HTTP/2 HEADERS frame
-        let (host, headers_frame) = match http::extract_http2_authority(client).await {
-            Ok((authority, frame_data)) => {
+        // Read up to the first request's header block and decode it
+        let mut decoder = HpackDecoder::default();
+        let head = match http::read_http2_request_head(client, &mut decoder).await {
+            Ok(head) => {
                 debug!(
-                    authority = authority,
+                    authority = head.authority,
+                    path = head.path.as_deref(),
+                    grpc = head.is_grpc(),
                     protocol = "http2",
-                    "Extracted :authority from HTTP/2 HEADERS frame"
+                    "Decoded first HTTP/2 request"
                 );
-                (authority, frame_data)
+                head
             }
             Err(e) => {
                 // Don't log as error - many clients send malformed HTTP/2 probes
@@ -676,18 +1205,38 @@ impl ConnectionHandler {
                 return Ok(()); // Close connection gracefully
             }
         };
+        let host = head.authority.clone();
+        let protocol = if head.is_grpc() {
+            Protocol::Grpc
+        } else {
+            Protocol::Http2
+        };
 
         // Check allowlist if configured
         if let Some(ref allowlist) = self.config.allowlist
             && !self.is_host_allowed(&host, allowlist)
         {
             warn!(host, "Host not in allowlist");
-            return Ok(());
+            let response = if protocol == Protocol::Grpc {
+                protocols::grpc::trailers_only_response(
+                    head.stream_id,
+                    protocols::grpc::GRPC_STATUS_PERMISSION_DENIED,
+                    "Host not in allowlist",
+                )
+            } else {
+                protocols::grpc::status_only_response(head.stream_id, 403)
+            };
+            let _ = client.write_all(&response).await;
+            let _ = client.shutdown().await;
+            return Err(Box::new(HttpError::RequestDenied(format!(
+                "{} is not in the allowlist",
+                host
+            ))));
         }
 
         // Setup metrics if enabled
         let metrics = self.metrics.as_ref().map(|m| {
-            let label = m.label_cache.get_or_insert(&host, "http2");
+            let label = m.label_cache.get_or_insert(&host, protocol.as_str());
             // Static string references for direction labels
             const TX: &str = "tx";
             const RX: &str = "rx";
@@ -697,16 +1246,120 @@ impl ConnectionHandler {
             )
         });
 
-        // Connect to the target server
-        let target_addr = format!("{}:80", host); // HTTP/2 cleartext typically uses port 80
-        let mut server = self.connect_to_server(&target_addr).await?;
+        // HTTP/2 cleartext typically uses port 80
+        let (hostname, port) = split_host_port(&host, 80);
+        let default_target = format!("{}:{}", hostname, port);
+        if protocol != Protocol::Grpc {
+            let mut server = self.resolve_and_connect(&default_target).await?;
+
+            // Send the HTTP/2 preface and every frame read so far to the server
+            server.write_all(&preface_buffer).await?;
+            server.write_all(&head.frames).await?;
+
+            // Start bidirectional copy
+            let idle_timeout = Duration::from_secs(self.config.timeouts.idle);
+            copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
+            return Ok(());
+        }
+
+        // gRPC calls are routed by the first call's method path
+        let grpc_rules = self
+            .config
+            .route_for(&hostname)
+            .map(|route| route.grpc_rules.clone())
+            .unwrap_or_default();
+        let grpc_requests = self.metrics.as_ref().map(|m| m.grpc_requests.clone());
+
+        // With a channel pool the proxy terminates HTTP/2 and routes every call
+        if let Some(ref grpc_pool) = self.grpc_pool {
+            let client = ReplayStream::new([preface_buffer, head.frames].concat(), client);
+            let calls = PooledGrpcCalls {
+                pool: grpc_pool.clone(),
+                rules: Arc::new(grpc_rules),
+                default_target,
+                grpc_requests,
+                byte_counters: metrics,
+            };
+            return calls.serve(client).await;
+        }
+
+        let path = head.path.clone().unwrap_or_default();
+        let Some(target_addr) = grpc_call_target(&grpc_rules, &path, &default_target) else {
+            warn!(host, path, "gRPC call denied by route rule");
+            count_grpc_call(grpc_requests.as_ref(), &path, "denied");
+            let response = protocols::grpc::trailers_only_response(
+                head.stream_id,
+                protocols::grpc::GRPC_STATUS_PERMISSION_DENIED,
+                "Call denied by proxy policy",
+            );
+            let _ = client.write_all(&response).await;
+            let _ = client.shutdown().await;
+            return Ok(());
+        };
+
+        let mut server = match self.resolve_and_connect(&target_addr).await {
+            Ok(server) => server,
+            Err(e) => {
+                count_grpc_call(grpc_requests.as_ref(), &path, "unavailable");
+                let response = protocols::grpc::trailers_only_response(
+                    head.stream_id,
+                    protocols::grpc::GRPC_STATUS_UNAVAILABLE,
+                    &e.to_string(),
+                );
+                let _ = client.write_all(&response).await;
+                let _ = client.shutdown().await;
+                return Err(Box::new(e));
+            }
+        };
+        count_grpc_call(grpc_requests.as_ref(), &path, "forwarded");
+        debug!(
+            host,
+            path,
+            upstream = target_addr,
+            "Routing gRPC connection"
+        );
 
-        // Send the HTTP/2 preface and HEADERS frame to the server
         server.write_all(&preface_buffer).await?;
-        server.write_all(&headers_frame).await?;
+        server.write_all(&head.frames).await?;
 
-        // Start bidirectional copy
         let idle_timeout = Duration::from_secs(self.config.timeouts.idle);
+        if grpc_rules.is_empty() && grpc_requests.is_none() {
+            copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
+            return Ok(());
+        }
+
+        // Later calls are checked against the rules and counted as well
+        let check: http::Http2RequestCheck = Box::new(move |fields| {
+            // Trailers carry no :path
+            let Some((_, path)) = fields.iter().find(|(name, _)| name == ":path") else {
+                return Ok(());
+            };
+            match grpc_call_target(&grpc_rules, path, &default_target) {
+                Some(target) if target == target_addr => {
+                    count_grpc_call(grpc_requests.as_ref(), path, "forwarded");
+                    Ok(())
+                }
+                Some(target) => {
+                    warn!(
+                        path,
+                        upstream = target,
+                        "gRPC call for another backend, closing connection"
+                    );
+                    count_grpc_call(grpc_requests.as_ref(), path, "misrouted");
+                    Err(HttpError::RequestDenied(format!(
+                        "{} is routed to another backend",
+                        path
+                    )))
+                }
+                None => {
+                    warn!(path, "gRPC call denied by route rule, closing connection");
+                    count_grpc_call(grpc_requests.as_ref(), path, "denied");
+                    Err(HttpError::RequestDenied(format!("{} is denied", path)))
+                }
+            }
+        });
+        let client =
+            http::RequestRewriteStream::new(client, http::Http2RequestFilter::new(decoder, check));
         copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
 
         Ok(())
@@ -806,7 +1459,7 @@ impl ConnectionHandler {
         });
 
         // Connect to the target SSH server
-        let server = self.connect_to_server(&target_addr).await?;
+        let server = self.resolve_and_connect(&target_addr).await?;
 
         debug!(
             destination = %target_addr,
@@ -820,68 +1473,6 @@ impl ConnectionHandler {
         Ok(())
     }
 
-    async fn handle_http2(
-        &self,
-        client: &mut TcpStream,
-        is_grpc: bool,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // This is similar to handle_http2_cleartext but with gRPC-specific handling
-
-        // Read the preface (we already peeked at it, but now we need to consume it)
-        let mut buffer = vec![0u8; HTTP2_PREFACE.len()];
-        client.read_exact(&mut buffer).await?;
-
-        // For gRPC, we might want to extract additional headers or do specific handling
-        let host = if is_grpc {
-            // For gRPC, try to extract the authority from headers
-            // Placeholder until we implement full HTTP/2 frame parsing
-            "grpc.service".to_string()
-        } else {
-            "default.host".to_string()
-        };
-
-        debug!(
-            host,
-            protocol = if is_grpc { "grpc" } else { "http2" },
-            "Extracted host"
-        );
-
-        // Check allowlist if configured
-        if let Some(ref allowlist) = self.config.allowlist
-            && !self.is_host_allowed(&host, allowlist)
-        {
-            warn!(host, "Host not in allowlist");
-            return Ok(());
-        }
-
-        // Setup metrics if enabled
-        let metrics = self.metrics.as_ref().map(|m| {
-            let protocol = if is_grpc { "grpc" } else { "http2" };
-            let label = m.label_cache.get_or_insert(&host, protocol);
-            // Static string references for direction labels
-            const TX: &str = "tx";
-            const RX: &str = "rx";
-            (
-                m.bytes_transferred.with_label_values(&[label.as_ref(), TX]),
-                m.bytes_transferred.with_label_values(&[label.as_ref(), RX]),
-            )
-        });
-
-        // Connect to the target server
-        let default_port = if is_grpc { 443 } else { 80 }; // gRPC typically uses TLS
-        let target_addr = format!("{}:{}", host, default_port);
-        let mut server = self.connect_to_server(&target_addr).await?;
-
-        // Send the HTTP/2 preface to the server
-        server.write_all(&buffer).await?;
-
-        // Start bidirectional copy
-        let idle_timeout = Duration::from_secs(self.config.timeouts.idle);
-        copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
-
-        Ok(())
-    }
-
     /// Get original destination address (before NAT/iptables REDIRECT)
     ///
     /// On Linux, when using iptables REDIRECT rules, the original destination
@@ -937,49 +1528,137 @@ impl ConnectionHandler {
         }
     }
 
-    /// Helper method to connect to a server with timeout
-    async fn connect_to_server(
-        &self,
-        target_addr: &str,
-    ) -> Result<TcpStream, Box<dyn std::error::Error>> {
-        // Try to get connection from pool first
-        if let Some(ref pool) = self.pool
-            && let Some(stream) = pool.get(target_addr)
-        {
-            debug!("Using pooled connection to {}", target_addr);
-            return Ok(stream);
-        }
+    /// Resolves `target_addr` and opens a new TCP connection within the connect timeout
+    ///
+    /// Tunnels always get a connection of their own; only HTTP/1.x requests
+    /// share backend connections through the pool.
+    async fn resolve_and_connect(&self, target_addr: &str) -> Result<TcpStream, UpstreamError> {
+        let addr = self.resolve_upstream(target_addr).await?;
+        self.connect_upstream(target_addr, addr).await
+    }
 
-        // No pooled connection available, create new one
+    /// Resolves `target_addr` to the address a backend connection is opened to
+    async fn resolve_upstream(&self, target_addr: &str) -> Result<SocketAddr, UpstreamError> {
         debug!("Resolving target address: {}", target_addr);
-        let addr = lookup_host(target_addr)
-            .await?
+        lookup_host(target_addr)
+            .await
+            .map_err(|e| UpstreamError::Resolve(target_addr.to_string(), e))?
             .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Failed to resolve target"))?;
+            .ok_or_else(|| {
+                UpstreamError::Resolve(
+                    target_addr.to_string(),
+                    io::Error::new(io::ErrorKind::NotFound, "no addresses returned"),
+                )
+            })
+    }
 
+    /// Opens a TCP connection to the resolved `addr` within the connect timeout
+    async fn connect_upstream(
+        &self,
+        target_addr: &str,
+        addr: SocketAddr,
+    ) -> Result<TcpStream, UpstreamError> {
         let connect_timeout = Duration::from_secs(self.config.timeouts.connect);
         debug!("Connecting to target: {}", addr);
-        let server = timeout(connect_timeout, TcpStream::connect(addr)).await??;
-
-        Ok(server)
+        match timeout(connect_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(server)) => Ok(server),
+            Ok(Err(e)) => Err(UpstreamError::Connect(target_addr.to_string(), e)),
+            Err(_) => Err(UpstreamError::Timeout(target_addr.to_string())),
+        }
     }
 
-    /// Return a connection to the pool if pooling is enabled
-    /// Reserved for future use with HTTP/1.1 keep-alive support
-    #[allow(dead_code)]
-    async fn return_to_pool(&self, target_addr: String, stream: TcpStream) {
-        if let Some(ref pool) = self.pool {
-            if pool.put(target_addr, stream) {
-                debug!("Connection returned to pool");
-            } else {
-                debug!("Connection not returned to pool (pool full or disabled)");
+    /// Checks out a backend connection for HTTP/1.x requests to `target_addr`
+    ///
+    /// Pooled connections are keyed by the resolved address plus, for routes
+    /// with `upstream_tls`, the route and the name the backend was verified
+    /// under. A new connection is opened if the pool has none.
+    async fn checkout_http_upstream(
+        &self,
+        target_addr: &str,
+        hostname: &str,
+        tls_route: Option<&Route>,
+        idle_timeout: Duration,
+    ) -> Result<HttpUpstream, UpstreamError> {
+        let upstream = |pool_key, server, lease, reused| HttpUpstream {
+            target_addr: target_addr.to_string(),
+            tls_route: tls_route.map(|route| route.host.clone()),
+            pool_key,
+            lease,
+            reusable: false,
+            reused,
+            server: http::MessageReader::new(server, idle_timeout),
+        };
+        let addr = self.resolve_upstream(target_addr).await?;
+        let pool_key = match tls_route {
+            Some(route) => format!(
+                "{} tls {} {}",
+                addr,
+                route.host,
+                upstream_server_name(route, hostname)
+            ),
+            None => addr.to_string(),
+        };
+
+        if let Some(ref pool) = self.pool
+            && let Some((server, lease)) = pool.get(&pool_key)
+        {
+            debug!(
+                upstream = target_addr,
+                pool_key,
+                request_count = lease.request_count(),
+                "Using pooled connection"
+            );
+            return Ok(upstream(pool_key, server, lease, true));
+        }
+
+        let server = self.connect_upstream(target_addr, addr).await?;
+        let server: Box<dyn PooledStream> = match tls_route {
+            Some(route) => {
+                let server = self.originate_tls(route, hostname, server).await?;
+                debug!(upstream = target_addr, "Originated TLS to backend");
+                Box::new(server)
             }
+            None => Box::new(server),
+        };
+        if let Some(ref pool) = self.pool {
+            pool.mark_active();
+        }
+        Ok(upstream(pool_key, server, ConnectionLease::new(), false))
+    }
+
+    /// Returns an HTTP/1.x backend connection to the pool if pooling is enabled
+    ///
+    /// Only a connection whose last response was read completely and that
+    /// both sides kept alive is pooled; any other connection is closed.
+    fn return_to_pool(&self, upstream: HttpUpstream) {
+        let Some(ref pool) = self.pool else {
+            return;
+        };
+        let (server, buffered) = upstream.server.into_parts();
+        if !upstream.reusable || !buffered.is_empty() {
+            pool.mark_inactive();
+            return;
+        }
+        if pool.put_with_http_info(
+            upstream.pool_key,
+            server,
+            upstream.lease,
+            HttpVersion::Http11,
+            true,
+        ) {
+            debug!(
+                upstream = upstream.target_addr,
+                "Connection returned to pool"
+            );
+        } else {
+            debug!(
+                upstream = upstream.target_addr,
+                "Connection not returned to pool (pool full)"
+            );
         }
     }
 
     /// Mark a connection as inactive in the pool (if pooling is enabled)
-    /// Reserved for future use with HTTP/1.1 keep-alive support
-    #[allow(dead_code)]
     fn mark_connection_inactive(&self) {
         if let Some(ref pool) = self.pool {
             pool.mark_inactive();
@@ -1059,25 +1738,93 @@ impl ConnectionHandler {
             "Extracted SNI from ClientHello"
         );
 
+        // Answer ACME tls-alpn-01 validation handshakes before any routing
+        if alpn == Some(acme::ACME_TLS_ALPN)
+            && let Some(ref acceptor) = self.acme_acceptor
+            && self
+                .acme_challenges
+                .as_ref()
+                .is_some_and(|challenges| challenges.has_tls_alpn01(&sni))
+        {
+            info!(sni, "Answering ACME tls-alpn-01 validation handshake");
+            let mut validation = timeout(
+                hello_timeout,
+                acceptor.accept(ReplayStream::new(record, reader)),
+            )
+            .await??;
+            validation.shutdown().await?;
+            return Ok(());
+        }
+
         // Check allowlist if configured
         if let Some(ref allowlist) = self.config.allowlist
             && !self.is_host_allowed(&sni, allowlist)
         {
             warn!(sni, "Host not in allowlist");
+            send_tls_alert(reader.get_mut(), tls::AlertDescription::AccessDenied).await;
             return Err(Box::new(SniError::InvalidSniFormat));
         }
 
-        // Resolve and connect to target
-        let target_addr = format!("{}:443", sni);
-        debug!("Resolving target address: {}", target_addr);
-        let addr = lookup_host(&target_addr)
-            .await?
-            .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Failed to resolve target"))?;
+        let route = self.config.route_for(&sni);
 
-        let connect_timeout = Duration::from_secs(self.config.timeouts.connect);
-        debug!("Connecting to target: {}", addr);
-        let mut server = timeout(connect_timeout, TcpStream::connect(addr)).await??;
+        // Enforce the route's TLS policy before any bytes reach the backend
+        if let Some(policy) = route.and_then(|route| route.tls_policy.as_ref()) {
+            let hello = tls::parse_client_hello(&record)?;
+            if let Err(violation) = tls::check_policy(policy, &hello) {
+                warn!(
+                    sni,
+                    reason = violation.as_str(),
+                    max_version = format!("{:#06x}", hello.max_version()),
+                    "ClientHello rejected by TLS policy"
+                );
+                self.record_policy_rejection(violation);
+
+                send_tls_alert(reader.get_mut(), violation.alert()).await;
+                return Err(
+                    format!("ClientHello rejected by TLS policy: {}", violation.as_str()).into(),
+                );
+            }
+        }
+
+        if let Some(route) = route
+            && route.mode == RouteMode::Terminate
+        {
+            return self
+                .terminate_tls(reader, record, &sni, route, protocol)
+                .await;
+        }
+
+        // Rewrite or strip the SNI the backend sees, if the route asks for it
+        let backend_sni = route
+            .and_then(|route| route.upstream_sni.as_deref())
+            .unwrap_or(&sni);
+        if let Some(route) = route
+            && (route.strip_sni || route.upstream_sni.is_some())
+        {
+            let server_name = (!route.strip_sni).then_some(backend_sni);
+            debug!(sni, upstream_sni = ?server_name, "Rewriting SNI in ClientHello");
+            record = match tls::rewrite_sni(&record, server_name) {
+                Ok(rewritten) => rewritten,
+                Err(e) => {
+                    warn!(sni, error = %e, "Failed to rewrite ClientHello SNI");
+                    send_tls_alert(reader.get_mut(), tls::AlertDescription::InternalError).await;
+                    return Err(Box::new(e));
+                }
+            };
+        }
+
+        // Resolve and connect to target, answering the client with an alert on failure
+        let target_addr = match route.and_then(|route| route.upstream.as_ref()) {
+            Some(upstream) => upstream.clone(),
+            None => format!("{}:443", backend_sni),
+        };
+        let mut server = match self.resolve_and_connect(&target_addr).await {
+            Ok(server) => server,
+            Err(e) => {
+                send_tls_alert(reader.get_mut(), e.tls_alert()).await;
+                return Err(Box::new(e));
+            }
+        };
 
         // Setup metrics if enabled
         let metrics = self.metrics.as_ref().map(|m| {
@@ -1095,6 +1842,12 @@ impl ConnectionHandler {
         debug!("Sending ClientHello to target");
         server.write_all(&record).await?;
 
+        // Hold the backend's ServerHello back until it satisfies the route's policy
+        if let Some(policy) = route.and_then(|route| route.tls_policy.as_ref()) {
+            self.relay_server_hello(&mut reader, &mut server, policy, &sni, hello_timeout)
+                .await?;
+        }
+
         // Get the underlying TcpStream back from the BufReader
         let client = reader.into_inner();
 
@@ -1107,6 +1860,278 @@ impl ConnectionHandler {
         Ok(())
     }
 
+    /// Relays the backend's ServerHello to the client once it satisfies the
+    /// route's TLS policy
+    ///
+    /// A HelloRetryRequest is checked and relayed like a ServerHello; the
+    /// client's second ClientHello is then checked and forwarded, and the real
+    /// ServerHello that follows is checked in turn.
+    async fn relay_server_hello(
+        &self,
+        reader: &mut BufReader<&mut TcpStream>,
+        server: &mut TcpStream,
+        policy: &TlsPolicy,
+        sni: &str,
+        hello_timeout: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut retried = false;
+        loop {
+            let server_hello = timeout(hello_timeout, read_tls_record(server)).await??;
+            // Alerts and other non-handshake records are relayed unchanged
+            if server_hello[0] != 0x16 {
+                reader.get_mut().write_all(&server_hello).await?;
+                return Ok(());
+            }
+
+            let hello = match tls::parse_server_hello(&server_hello) {
+                // A server may only ask for one retry (RFC 8446 §4.1.4)
+                Ok(hello) if !(retried && hello.hello_retry_request) => hello,
+                Ok(_) => {
+                    warn!(sni, "Backend sent a second HelloRetryRequest");
+                    send_tls_alert(reader.get_mut(), tls::AlertDescription::HandshakeFailure).await;
+                    return Err("Backend sent a second HelloRetryRequest".into());
+                }
+                Err(e) => {
+                    warn!(sni, error = %e, "Backend sent an unreadable ServerHello");
+                    send_tls_alert(reader.get_mut(), tls::AlertDescription::HandshakeFailure).await;
+                    return Err(Box::new(e));
+                }
+            };
+            if let Err(violation) = tls::check_server_policy(policy, &hello) {
+                warn!(
+                    sni,
+                    reason = violation.as_str(),
+                    version = format!("{:#06x}", hello.version()),
+                    cipher_suite = format!("{:#06x}", hello.cipher_suite),
+                    group = ?hello.key_share_group,
+                    hello_retry_request = hello.hello_retry_request,
+                    "Backend ServerHello rejected by TLS policy"
+                );
+                self.record_policy_rejection(violation);
+
+                let _ = server.shutdown().await;
+                send_tls_alert(reader.get_mut(), tls::AlertDescription::HandshakeFailure).await;
+                return Err(
+                    format!("ServerHello rejected by TLS policy: {}", violation.as_str()).into(),
+                );
+            }
+            reader.get_mut().write_all(&server_hello).await?;
+            if !hello.hello_retry_request {
+                return Ok(());
+            }
+            retried = true;
+
+            // Forward the client's answer to the retry: an optional
+            // ChangeCipherSpec, then the second ClientHello
+            loop {
+                let record = timeout(hello_timeout, read_tls_record(reader)).await??;
+                if record[0] == 0x16 {
+                    let client_hello = tls::parse_client_hello(&record)?;
+                    if let Err(violation) = tls::check_policy(policy, &client_hello) {
+                        warn!(
+                            sni,
+                            reason = violation.as_str(),
+                            max_version = format!("{:#06x}", client_hello.max_version()),
+                            "Second ClientHello rejected by TLS policy"
+                        );
+                        self.record_policy_rejection(violation);
+
+                        let _ = server.shutdown().await;
+                        send_tls_alert(reader.get_mut(), violation.alert()).await;
+                        return Err(format!(
+                            "ClientHello rejected by TLS policy: {}",
+                            violation.as_str()
+                        )
+                        .into());
+                    }
+                    server.write_all(&record).await?;
+                    break;
+                }
+                server.write_all(&record).await?;
+            }
+        }
+    }
+
+    fn record_policy_rejection(&self, violation: tls::PolicyViolation) {
+        if let Some(ref metrics) = self.metrics {
+            metrics
+                .tls_policy_rejections
+                .with_label_values(&[violation.as_str()])
+                .inc();
+        }
+    }
+
+    /// Completes the TLS handshake for a terminate-mode route and forwards the
+    /// decrypted stream to the route's backend
+    ///
+    /// For mutual TLS routes the verified client identity selects the backend
+    /// and is forwarded to it as a request header or PROXY v2 TLV.
+    async fn terminate_tls(
+        &self,
+        mut reader: BufReader<&mut TcpStream>,
+        record: Vec<u8>,
+        sni: &str,
+        route: &Route,
+        protocol: Protocol,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let acceptor = match route.client_auth {
+            // Never fall back to an acceptor without client authentication
+            Some(_) => self.client_auth_acceptors.get(&route.host),
+            None => self.tls_acceptor.as_ref(),
+        };
+        let Some(acceptor) = acceptor else {
+            warn!(
+                sni,
+                "Route terminates TLS but its TLS configuration is unavailable"
+            );
+            send_tls_alert(reader.get_mut(), tls::AlertDescription::InternalError).await;
+            return Err("TLS termination requested without usable tls_certificates".into());
+        };
+
+        let peer_addr = reader.get_ref().peer_addr().ok();
+        let local_addr = reader.get_ref().local_addr().ok();
+
+        // Replay the captured ClientHello into rustls and finish the handshake
+        let hello_timeout = Duration::from_secs(self.config.timeouts.client_hello);
+        let handshake = timeout(
+            hello_timeout,
+            acceptor.accept(ReplayStream::new(record, reader)),
+        )
+        .await;
+        let mut client = match handshake {
+            Ok(Ok(client)) => client,
+            Ok(Err(e)) => {
+                let client_auth_failure = e
+                    .get_ref()
+                    .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+                    .is_some_and(|e| {
+                        matches!(
+                            e,
+                            rustls::Error::InvalidCertificate(_)
+                                | rustls::Error::NoCertificatesPresented
+                        )
+                    });
+                if client_auth_failure {
+                    warn!(sni, peer = ?peer_addr, error = %e, "Client certificate rejected");
+                    self.count_termination("client_auth_failure");
+                } else {
+                    self.count_termination("handshake_failure");
+                }
+                return Err(format!("TLS termination handshake failed: {}", e).into());
+            }
+            Err(_) => {
+                self.count_termination("timeout");
+                return Err("TLS termination handshake timeout".into());
+            }
+        };
+
+        // Authorize the client identity and pick the backend
+        let session = client.get_ref().1;
+        let identity = session
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(termination::ClientIdentity::from_certificate);
+        let tls_version = match session.protocol_version() {
+            Some(rustls::ProtocolVersion::TLSv1_3) => "TLSv1.3",
+            Some(rustls::ProtocolVersion::TLSv1_2) => "TLSv1.2",
+            _ => "unknown",
+        };
+        let identity_id = identity.as_ref().map(|identity| identity.id());
+
+        let mut upstream = route.upstream.as_deref();
+        if let Some(ref auth) = route.client_auth {
+            match termination::authorize(auth, identity.as_ref()) {
+                termination::Authorization::Allowed(Some(rule_upstream)) => {
+                    upstream = Some(rule_upstream)
+                }
+                termination::Authorization::Allowed(None) => {}
+                termination::Authorization::Denied => {
+                    warn!(sni, peer = ?peer_addr, identity = ?identity_id, "Client identity not authorized");
+                    self.count_termination("access_denied");
+                    let _ = client.shutdown().await;
+                    return Err("Client identity not authorized for route".into());
+                }
+            }
+        }
+        self.count_termination("success");
+
+        let backend_name = route.upstream_sni.as_deref().unwrap_or(sni);
+        let target_addr = match upstream {
+            Some(upstream) => upstream.to_string(),
+            None if route.upstream_tls.is_some() => format!("{}:443", backend_name),
+            None => format!("{}:80", backend_name),
+        };
+
+        info!(
+            target: "sniproxy::access",
+            peer = ?peer_addr,
+            sni,
+            client_identity = identity_id.unwrap_or("-"),
+            client_subject = identity.as_ref().map_or("-", |identity| identity.subject.as_str()),
+            tls_version,
+            upstream = target_addr,
+            "TLS terminated"
+        );
+
+        let mut server = match self.resolve_and_connect(&target_addr).await {
+            Ok(server) => server,
+            Err(e) => {
+                let _ = client.shutdown().await;
+                return Err(Box::new(e));
+            }
+        };
+
+        // Pass the verified identity on to the backend
+        let forwarding = route.client_auth.as_ref().map(|auth| auth.forward_identity);
+        if forwarding == Some(IdentityForwarding::ProxyProtocol) {
+            let header = proxy_v2_header(
+                peer_addr.zip(local_addr),
+                sni,
+                tls_version,
+                identity.as_ref(),
+            );
+            server.write_all(&header).await?;
+        }
+
+        let client: Box<dyn ProxyStream> = match (forwarding, &route.client_auth) {
+            (Some(IdentityForwarding::Header), Some(auth)) => {
+                let rewriter = http::RequestHeaderRewriter::new(&auth.identity_header, identity_id);
+                Box::new(http::RequestRewriteStream::new(client, rewriter))
+            }
+            _ => Box::new(client),
+        };
+
+        let server: Box<dyn ProxyStream> = if route.upstream_tls.is_some() {
+            Box::new(self.originate_tls(route, backend_name, server).await?)
+        } else {
+            Box::new(server)
+        };
+
+        // Setup metrics if enabled
+        let metrics = self.metrics.as_ref().map(|m| {
+            let label = m.label_cache.get_or_insert(sni, protocol.as_str());
+            // Static string references for direction labels
+            const TX: &str = "tx";
+            const RX: &str = "rx";
+            (
+                m.bytes_transferred.with_label_values(&[label.as_ref(), TX]),
+                m.bytes_transferred.with_label_values(&[label.as_ref(), RX]),
+            )
+        });
+
+        let idle_timeout = Duration::from_secs(self.config.timeouts.idle);
+        copy_bidirectional_timeout(client, server, idle_timeout, metrics).await?;
+
+        debug!("Terminated TLS connection completed successfully");
+        Ok(())
+    }
+
+    fn count_termination(&self, result: &str) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.tls_terminations.with_label_values(&[result]).inc();
+        }
+    }
+
     fn is_host_allowed(&self, host: &str, allowlist: &[String]) -> bool {
         // Special case: "*" allows all hosts
         if allowlist.contains(&"*".to_string()) {
@@ -1120,6 +2145,312 @@ impl ConnectionHandler {
     }
 }
 
+/// The name sent as SNI and verified for a route with `upstream_tls`
+///
+/// This is `upstream_tls.server_name`, or `default_name` if unset.
+fn upstream_server_name<'a>(route: &'a Route, default_name: &'a str) -> &'a str {
+    route
+        .upstream_tls
+        .as_ref()
+        .and_then(|settings| settings.server_name.as_deref())
+        .unwrap_or(default_name)
+}
+
+/// Splits "host:port" as sent in a Host header or `:authority`
+///
+/// Without a valid port, the whole string is the host and `default_port` applies.
+fn split_host_port(host: &str, default_port: u16) -> (String, u16) {
+    if let Some(colon_pos) = host.rfind(':')
+        && let Ok(port) = host[colon_pos + 1..].parse::<u16>()
+    {
+        return (host[..colon_pos].to_string(), port);
+    }
+    (host.to_string(), default_port)
+}
+
+/// Routes the calls of a terminated h2c gRPC connection onto pooled channels
+struct PooledGrpcCalls {
+    pool: Arc<GrpcConnectionPool>,
+    rules: Arc<Vec<GrpcRule>>,
+    default_target: String,
+    grpc_requests: Option<IntCounterVec>,
+    byte_counters: Option<(IntCounter, IntCounter)>,
+}
+
+impl PooledGrpcCalls {
+    /// Accepts calls until the client closes the connection
+    ///
+    /// Each call is checked against the route's `grpc_rules` and forwarded on
+    /// its own task, so a slow backend does not hold up other calls.
+    async fn serve<S>(self, client: S) -> Result<(), Box<dyn std::error::Error>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut connection = h2::server::handshake(client).await?;
+
+        while let Some(call) = connection.accept().await {
+            let (request, mut respond) = call?;
+            let path = request.uri().path().to_string();
+
+            let Some(target) = grpc_call_target(&self.rules, &path, &self.default_target) else {
+                warn!(path, "gRPC call denied by route rule");
+                count_grpc_call(self.grpc_requests.as_ref(), &path, "denied");
+                let response = protocols::grpc::trailers_only_head(
+                    protocols::grpc::GRPC_STATUS_PERMISSION_DENIED,
+                    "Call denied by proxy policy",
+                );
+                let _ = respond.send_response(response, true);
+                continue;
+            };
+
+            let pool = self.pool.clone();
+            let grpc_requests = self.grpc_requests.clone();
+            let byte_counters = self.byte_counters.clone();
+            tokio::spawn(async move {
+                let stream = match pool.acquire(&target).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!(path, upstream = target, error = %e, "No gRPC channel for call");
+                        count_grpc_call(grpc_requests.as_ref(), &path, "unavailable");
+                        let status = match e {
+                            GrpcPoolError::Exhausted(_) => {
+                                protocols::grpc::GRPC_STATUS_RESOURCE_EXHAUSTED
+                            }
+                            _ => protocols::grpc::GRPC_STATUS_UNAVAILABLE,
+                        };
+                        let response = protocols::grpc::trailers_only_head(status, &e.to_string());
+                        let _ = respond.send_response(response, true);
+                        return;
+                    }
+                };
+
+                count_grpc_call(grpc_requests.as_ref(), &path, "forwarded");
+                debug!(
+                    path,
+                    upstream = target,
+                    channel = stream.channel_id(),
+                    "Forwarding gRPC call on pooled channel"
+                );
+                if let Err(e) = stream.forward(request, respond, byte_counters).await {
+                    debug!(path, upstream = target, error = %e, "gRPC call failed");
+                }
+            });
+        }
+
+        // Calls still in progress need the connection driven until they end
+        futures::future::poll_fn(|cx| connection.poll_closed(cx)).await?;
+        Ok(())
+    }
+}
+
+/// The backend connection serving a client's HTTP/1.x requests
+struct HttpUpstream {
+    target_addr: String,
+    /// Route host whose `upstream_tls` settings wrap the connection
+    tls_route: Option<String>,
+    /// Key of the connection in the connection pool
+    pool_key: String,
+    /// Age and request count of the connection, returned to the pool with it
+    lease: ConnectionLease,
+    /// Whether the last exchange completed with both sides keeping alive
+    reusable: bool,
+    /// Whether the connection carried an earlier exchange, so the backend may
+    /// have closed it since
+    reused: bool,
+    server: http::MessageReader<Box<dyn PooledStream>>,
+}
+
+/// What a client connection does after one request and response
+enum HttpExchange {
+    /// Both sides keep the connection open for another request
+    KeepAlive,
+    /// Either side asked to close, or the response ran until EOF
+    Close,
+    /// The protocol switched; the rest of the connection is opaque
+    Upgraded,
+}
+
+/// Forwards one request to the backend and its response to the client
+///
+/// With `Expect: 100-continue` the body is held back until the backend
+/// answers 100; a final response instead means the body is never sent and
+/// the connection closes afterwards. A 101 answering a WebSocket handshake
+/// must carry its accept value, or the exchange fails before it reaches the
+/// client. If the 101 accepts no extension, the proxy answers the client's
+/// permessage-deflate offer itself; otherwise the handshake's compression is
+/// dropped.
+async fn forward_http_exchange<C, S>(
+    client: &mut http::MessageReader<C>,
+    server: &mut http::MessageReader<S>,
+    request: &http::RequestHead,
+    head: &[u8],
+    websocket: Option<&mut websocket::Handshake>,
+    metrics: &Option<(IntCounter, IntCounter)>,
+) -> Result<HttpExchange, HttpError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (tx, rx) = metrics.as_ref().map(|(tx, rx)| (tx, rx)).unzip();
+
+    let mut body_sent = !request.expect_continue;
+    if body_sent {
+        client
+            .forward_message(head, request.framing, server.get_mut(), tx)
+            .await?;
+    } else {
+        client
+            .forward_message(head, http::BodyFraming::Empty, server.get_mut(), tx)
+            .await?;
+    }
+
+    let response = loop {
+        let Some(head) = server.read_head().await? else {
+            return Err(HttpError::UpstreamClosed);
+        };
+        let response = http::parse_response_head(&head, &request.method)?;
+
+        // Interim responses precede the final one (RFC 9110 §15.2)
+        if (100..200).contains(&response.status) && response.status != 101 {
+            server
+                .forward_message(&head, http::BodyFraming::Empty, client.get_mut(), rx)
+                .await?;
+            if response.status == 100 && !body_sent {
+                client
+                    .forward_message(b"", request.framing, server.get_mut(), tx)
+                    .await?;
+                body_sent = true;
+            }
+            continue;
+        }
+        break (head, response);
+    };
+    let (mut head, response) = response;
+
+    // A 101 must complete the WebSocket handshake the client started
+    if response.status == 101
+        && let Some(handshake) = websocket
+    {
+        if response.websocket_accept.as_deref() != Some(handshake.accept.as_str()) {
+            return Err(HttpError::WebSocketUpgradeFailed);
+        }
+        if response.websocket_extensions.is_some() {
+            handshake.compression = None;
+        } else if let Some((_, ref extension)) = handshake.compression {
+            head = http::append_header(&head, "Sec-WebSocket-Extensions", extension);
+        }
+    }
+
+    let switched = response.status == 101
+        || (request.method == "CONNECT" && (200..300).contains(&response.status));
+    let framing = if switched && request.upgrade {
+        http::BodyFraming::Empty
+    } else {
+        response.framing
+    };
+    server
+        .forward_message(&head, framing, client.get_mut(), rx)
+        .await?;
+
+    Ok(if switched && request.upgrade {
+        HttpExchange::Upgraded
+    } else if body_sent && request.keep_alive && response.keep_alive {
+        HttpExchange::KeepAlive
+    } else {
+        HttpExchange::Close
+    })
+}
+
+/// Backend for a gRPC call per the route's `grpc_rules`, or `None` if denied
+fn grpc_call_target(rules: &[GrpcRule], path: &str, default_target: &str) -> Option<String> {
+    match rules
+        .iter()
+        .find(|rule| sniproxy_config::matches_grpc_path(path, &rule.path))
+    {
+        Some(rule) if rule.deny => None,
+        Some(rule) => Some(
+            rule.upstream
+                .clone()
+                .unwrap_or_else(|| default_target.to_string()),
+        ),
+        None => Some(default_target.to_string()),
+    }
+}
+
+/// Counts a gRPC call under its service name
+fn count_grpc_call(counter: Option<&IntCounterVec>, path: &str, result: &str) {
+    if let Some(counter) = counter {
+        let service =
+            protocols::grpc::parse_grpc_path(path).map_or("unknown", |(service, _)| service);
+        counter.with_label_values(&[service, result]).inc();
+    }
+}
+
+/// A bidirectional byte stream, boxed so plain and TLS legs share one copy loop
+trait ProxyStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ProxyStream for T {}
+
+/// Builds the PROXY v2 header announcing a terminated TLS client to the backend
+fn proxy_v2_header(
+    addresses: Option<(SocketAddr, SocketAddr)>,
+    sni: &str,
+    tls_version: &str,
+    identity: Option<&termination::ClientIdentity>,
+) -> Vec<u8> {
+    use crate::proxy_protocol::{self, Tlv};
+
+    let mut ssl_tlvs = vec![Tlv::new(
+        proxy_protocol::PP2_SUBTYPE_SSL_VERSION,
+        tls_version,
+    )];
+    let mut client_flags = proxy_protocol::PP2_CLIENT_SSL;
+    let mut tlvs = vec![Tlv::new(proxy_protocol::PP2_TYPE_AUTHORITY, sni)];
+
+    if let Some(identity) = identity {
+        client_flags |= proxy_protocol::PP2_CLIENT_CERT_CONN;
+        if let Some(ref common_name) = identity.common_name {
+            ssl_tlvs.push(Tlv::new(
+                proxy_protocol::PP2_SUBTYPE_SSL_CN,
+                common_name.as_str(),
+            ));
+        }
+        tlvs.push(Tlv::new(
+            proxy_protocol::PP2_TYPE_CLIENT_IDENTITY,
+            identity.id(),
+        ));
+    }
+    tlvs.push(Tlv::ssl(client_flags, identity.is_some(), &ssl_tlvs));
+
+    proxy_protocol::encode_v2(addresses, &tlvs)
+}
+
+/// Reads one complete TLS record (header and fragment)
+async fn read_tls_record<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut record = vec![0u8; MIN_TLS_HEADER_SIZE];
+    stream.read_exact(&mut record).await?;
+    let length = ((record[3] as usize) << 8) | (record[4] as usize);
+    if length > MAX_TLS_HEADER_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid TLS record length",
+        ));
+    }
+    record.resize(MIN_TLS_HEADER_SIZE + length, 0);
+    stream
+        .read_exact(&mut record[MIN_TLS_HEADER_SIZE..])
+        .await?;
+    Ok(record)
+}
+
+/// Sends a fatal TLS alert to the client and closes the write side
+///
+/// Failures are ignored: the client may already have gone away.
+async fn send_tls_alert(client: &mut TcpStream, description: tls::AlertDescription) {
+    let _ = client.write_all(&tls::alert_record(description)).await;
+    let _ = client.shutdown().await;
+}
+
 async fn copy_bidirectional_timeout<T, U>(
     client: T,
     server: U,
@@ -1134,7 +2465,7 @@ where
     let (mut server_read, mut server_write) = io::split(server);
 
     let client_to_server = async {
-        let mut buf = [0u8; COPY_BUFFER_SIZE];
+        let mut buf = vec![0u8; COPY_BUFFER_SIZE];
         loop {
             let n = timeout(idle_timeout, client_read.read(&mut buf)).await??;
             if n == 0 {
@@ -1150,7 +2481,7 @@ where
     };
 
     let server_to_client = async {
-        let mut buf = [0u8; COPY_BUFFER_SIZE];
+        let mut buf = vec![0u8; COPY_BUFFER_SIZE];
         loop {
             let n = timeout(idle_timeout, server_read.read(&mut buf)).await??;
             if n == 0 {
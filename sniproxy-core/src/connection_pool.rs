@@ -2,10 +2,19 @@
 //!
 //! This module provides connection pooling functionality to reuse backend connections,
 //! reducing file descriptor usage and improving performance.
+//!
+//! Only idle connections live in the pool: a connection is checked out for one
+//! or more complete request/response exchanges and returned once its response
+//! has been fully read. Checkout skips connections the backend has closed.
+//!
+//! A checked-out connection travels with a `ConnectionLease` recording its age
+//! and request count, so `connection_ttl` and `max_requests_per_connection`
+//! apply over the connection's whole life rather than since its last return.
 
 use dashmap::DashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tracing::{debug, info};
 
@@ -52,9 +61,88 @@ impl Default for PoolConfig {
     }
 }
 
+/// A backend stream that can be kept idle in the pool
+///
+/// The underlying TCP socket is exposed so that checkout can tell whether the
+/// backend closed the connection while it sat in the pool.
+pub trait PooledStream: AsyncRead + AsyncWrite + Unpin + Send + Sync {
+    /// The TCP connection carrying this stream
+    fn tcp_stream(&self) -> &TcpStream;
+}
+
+impl PooledStream for TcpStream {
+    fn tcp_stream(&self) -> &TcpStream {
+        self
+    }
+}
+
+impl PooledStream for tokio_rustls::client::TlsStream<TcpStream> {
+    fn tcp_stream(&self) -> &TcpStream {
+        self.get_ref().0
+    }
+}
+
+impl<S: PooledStream + ?Sized> PooledStream for Box<S> {
+    fn tcp_stream(&self) -> &TcpStream {
+        (**self).tcp_stream()
+    }
+}
+
+/// Checks that an idle connection is still usable
+///
+/// An idle HTTP/1.1 connection has nothing to read: EOF means the backend
+/// closed it, and any bytes (an unsolicited response or a TLS close_notify)
+/// mean it can no longer carry a request.
+fn is_connection_alive(stream: &TcpStream) -> bool {
+    let mut byte = [std::mem::MaybeUninit::<u8>::uninit()];
+    // Tokio sockets are non-blocking, so an empty receive queue is WouldBlock
+    matches!(
+        socket2::SockRef::from(stream).peek(&mut byte),
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+    )
+}
+
+/// Age and use of a backend connection, kept across checkout and return
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLease {
+    created_at: Instant,
+    request_count: usize,
+}
+
+impl ConnectionLease {
+    /// Lease for a connection just opened to serve a request
+    pub fn new() -> Self {
+        Self {
+            created_at: Instant::now(),
+            request_count: 1,
+        }
+    }
+
+    /// Number of requests the connection has been checked out for
+    pub fn request_count(&self) -> usize {
+        self.request_count
+    }
+
+    /// Time since the connection was opened
+    pub fn age(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+
+    /// Counts another request carried without returning to the pool
+    pub fn mark_used(&mut self) {
+        self.request_count += 1;
+    }
+}
+
+impl Default for ConnectionLease {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A pooled connection with metadata
-struct PooledConnection {
-    stream: TcpStream,
+struct PooledConnection<S> {
+    stream: S,
     created_at: Instant,
     last_used: Instant,
     http_version: HttpVersion,
@@ -62,31 +150,43 @@ struct PooledConnection {
     request_count: usize,
 }
 
-impl PooledConnection {
-    fn new(stream: TcpStream) -> Self {
-        let now = Instant::now();
+impl<S> PooledConnection<S> {
+    fn new(stream: S, lease: ConnectionLease) -> Self {
         Self {
             stream,
-            created_at: now,
-            last_used: now,
+            created_at: lease.created_at,
+            last_used: Instant::now(),
             http_version: HttpVersion::Http11, // Default to HTTP/1.1
             keep_alive: true,                  // Default to Keep-Alive enabled
-            request_count: 0,
+            request_count: lease.request_count,
         }
     }
 
-    fn with_http_info(stream: TcpStream, http_version: HttpVersion, keep_alive: bool) -> Self {
-        let now = Instant::now();
+    fn with_http_info(
+        stream: S,
+        lease: ConnectionLease,
+        http_version: HttpVersion,
+        keep_alive: bool,
+    ) -> Self {
         Self {
             stream,
-            created_at: now,
-            last_used: now,
+            created_at: lease.created_at,
+            last_used: Instant::now(),
             http_version,
             keep_alive,
-            request_count: 0,
+            request_count: lease.request_count,
         }
     }
 
+    /// Splits the connection into its stream and the lease to return it with
+    fn into_checkout(self) -> (S, ConnectionLease) {
+        let lease = ConnectionLease {
+            created_at: self.created_at,
+            request_count: self.request_count,
+        };
+        (self.stream, lease)
+    }
+
     /// Check if connection has exceeded TTL
     fn is_expired(&self, ttl: Duration) -> bool {
         self.created_at.elapsed() > ttl
@@ -175,13 +275,17 @@ impl PoolMetrics {
 }
 
 /// Connection pool for backend connections
-pub struct ConnectionPool {
-    pools: Arc<DashMap<String, Vec<PooledConnection>>>,
+///
+/// Connections are keyed by the caller; a key must identify everything that
+/// makes two connections interchangeable, such as the resolved address and
+/// any TLS settings wrapping the stream.
+pub struct ConnectionPool<S = TcpStream> {
+    pools: Arc<DashMap<String, Vec<PooledConnection<S>>>>,
     config: PoolConfig,
     metrics: Option<PoolMetrics>,
 }
 
-impl ConnectionPool {
+impl<S: PooledStream> ConnectionPool<S> {
     /// Create a new connection pool
     pub fn new(config: PoolConfig) -> Self {
         Self {
@@ -206,8 +310,11 @@ impl ConnectionPool {
 
     /// Try to get a connection from the pool
     ///
-    /// Returns Some(TcpStream) if a valid connection is available, None otherwise
-    pub fn get(&self, host: &str) -> Option<TcpStream> {
+    /// Returns a connection that is within its TTL and idle timeout and that the
+    /// backend has not closed, or None if there is none. The connection counts
+    /// as active until it is handed back with `put` or `mark_inactive`; its
+    /// lease must be handed back with it.
+    pub fn get(&self, host: &str) -> Option<(S, ConnectionLease)> {
         if !self.config.enabled {
             return None;
         }
@@ -247,6 +354,17 @@ impl ConnectionPool {
                 continue;
             }
 
+            // The backend may have closed the connection while it was idle
+            if !is_connection_alive(conn.stream.tcp_stream()) {
+                debug!(host = host, "Evicting connection closed by backend");
+
+                if let Some(ref metrics) = self.metrics {
+                    metrics.pool_evictions.inc();
+                    metrics.pool_size.dec();
+                }
+                continue;
+            }
+
             // Connection is valid and can be reused
             conn.mark_used();
 
@@ -271,7 +389,7 @@ impl ConnectionPool {
                 }
             }
 
-            return Some(conn.stream);
+            return Some(conn.into_checkout());
         }
 
         // No valid connection found
@@ -286,11 +404,15 @@ impl ConnectionPool {
 
     /// Return a connection to the pool
     ///
+    /// `lease` is the one the connection was checked out with, or a new lease
+    /// for a connection that was just opened. The connection stops counting as
+    /// active whether or not it is pooled.
     /// Returns true if connection was added to pool, false if pool is full
-    pub fn put(&self, host: String, stream: TcpStream) -> bool {
+    pub fn put(&self, host: String, stream: S, lease: ConnectionLease) -> bool {
         if !self.config.enabled {
             return false;
         }
+        self.mark_inactive();
 
         let mut pool = self.pools.entry(host.clone()).or_default();
 
@@ -301,7 +423,7 @@ impl ConnectionPool {
         }
 
         // Add connection to pool
-        pool.push(PooledConnection::new(stream));
+        pool.push(PooledConnection::new(stream, lease));
 
         debug!(
             host = host,
@@ -311,7 +433,6 @@ impl ConnectionPool {
 
         if let Some(ref metrics) = self.metrics {
             metrics.pool_size.inc();
-            metrics.active_connections.dec();
         }
 
         true
@@ -322,17 +443,22 @@ impl ConnectionPool {
     /// This method allows specifying HTTP version and Keep-Alive status for better
     /// connection reuse decisions.
     ///
+    /// `lease` is the one the connection was checked out with, or a new lease
+    /// for a connection that was just opened. The connection stops counting as
+    /// active whether or not it is pooled.
     /// Returns true if connection was added to pool, false if pool is full or Keep-Alive disabled
     pub fn put_with_http_info(
         &self,
         host: String,
-        stream: TcpStream,
+        stream: S,
+        lease: ConnectionLease,
         http_version: HttpVersion,
         keep_alive: bool,
     ) -> bool {
         if !self.config.enabled {
             return false;
         }
+        self.mark_inactive();
 
         // Don't pool connections if Keep-Alive is disabled (e.g., Connection: close)
         if self.config.keep_alive_enabled && !keep_alive {
@@ -351,6 +477,7 @@ impl ConnectionPool {
         // Add connection to pool with HTTP info
         pool.push(PooledConnection::with_http_info(
             stream,
+            lease,
             http_version,
             keep_alive,
         ));
@@ -369,12 +496,27 @@ impl ConnectionPool {
 
         if let Some(ref metrics) = self.metrics {
             metrics.pool_size.inc();
-            metrics.active_connections.dec();
         }
 
         true
     }
 
+    /// Whether a checked-out connection may carry another request
+    ///
+    /// Applies the TTL and request limit that checkout from the pool applies.
+    pub fn can_reuse(&self, lease: &ConnectionLease) -> bool {
+        lease.age() <= Duration::from_secs(self.config.connection_ttl)
+            && (!self.config.keep_alive_enabled
+                || lease.request_count < self.config.max_requests_per_connection)
+    }
+
+    /// Mark a newly opened connection as active until it is returned
+    pub fn mark_active(&self) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.active_connections.inc();
+        }
+    }
+
     /// Mark a connection as no longer active (failed or closed)
     pub fn mark_inactive(&self) {
         if let Some(ref metrics) = self.metrics {
@@ -406,6 +548,9 @@ impl ConnectionPool {
             }
         }
 
+        // Forget hosts with no idle connections left
+        self.pools.retain(|_, pool| !pool.is_empty());
+
         if total_evicted > 0 {
             info!(evicted = total_evicted, "Connection pool cleanup complete");
 
@@ -431,7 +576,10 @@ impl ConnectionPool {
     /// Start background cleanup task
     ///
     /// Returns a JoinHandle that will run cleanup every interval
-    pub fn start_cleanup_task(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+    pub fn start_cleanup_task(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()>
+    where
+        S: 'static,
+    {
         tokio::spawn(async move {
             let mut ticker = tokio::time::interval(interval);
             loop {
@@ -538,10 +686,10 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         // Should not accept connections when disabled
-        assert!(!pool.put("test.com".to_string(), stream));
+        assert!(!pool.put("test.com".to_string(), stream, ConnectionLease::new()));
 
         // Should not return connections when disabled
         assert!(pool.get("test.com").is_none());
@@ -556,10 +704,10 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         // Put connection in pool
-        assert!(pool.put("test.com".to_string(), stream));
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
 
         // Get connection from pool
         let retrieved = pool.get("test.com");
@@ -578,16 +726,68 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
-        let (stream3, _) = create_test_connection().await;
+        let (stream1, _peer1) = create_test_connection().await;
+        let (stream2, _peer2) = create_test_connection().await;
+        let (stream3, _peer3) = create_test_connection().await;
 
         // Should accept first two
-        assert!(pool.put("test.com".to_string(), stream1));
-        assert!(pool.put("test.com".to_string(), stream2));
+        assert!(pool.put("test.com".to_string(), stream1, ConnectionLease::new()));
+        assert!(pool.put("test.com".to_string(), stream2, ConnectionLease::new()));
 
         // Should reject third (pool full)
-        assert!(!pool.put("test.com".to_string(), stream3));
+        assert!(!pool.put("test.com".to_string(), stream3, ConnectionLease::new()));
+    }
+
+    #[tokio::test]
+    async fn test_pool_evicts_closed_connection() {
+        let pool = ConnectionPool::new(PoolConfig::default());
+
+        let (stream, peer) = create_test_connection().await;
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
+
+        // The backend closes the idle connection
+        drop(peer);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(pool.get("test.com").is_none());
+        assert_eq!(pool.stats().total_connections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_evicts_connection_with_unread_data() {
+        use tokio::io::AsyncWriteExt;
+
+        let pool = ConnectionPool::new(PoolConfig::default());
+
+        let (stream, mut peer) = create_test_connection().await;
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
+
+        // Bytes arriving on an idle connection belong to no request
+        peer.write_all(b"HTTP/1.1 408 Request Timeout\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(pool.get("test.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pool_keeps_live_connection_usable() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let pool = ConnectionPool::new(PoolConfig::default());
+
+        let (stream, mut peer) = create_test_connection().await;
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
+
+        // The liveness check must not consume anything from the connection
+        let (mut stream, _) = pool.get("test.com").unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        peer.read_exact(&mut buf).await.unwrap();
+        peer.write_all(b"pong").await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
     }
 
     #[tokio::test]
@@ -600,10 +800,10 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         // Put connection in pool
-        assert!(pool.put("test.com".to_string(), stream));
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
 
         // Wait for TTL to expire
         tokio::time::sleep(Duration::from_secs(2)).await;
@@ -622,11 +822,11 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
+        let (stream1, _peer1) = create_test_connection().await;
+        let (stream2, _peer2) = create_test_connection().await;
 
-        pool.put("test1.com".to_string(), stream1);
-        pool.put("test2.com".to_string(), stream2);
+        pool.put("test1.com".to_string(), stream1, ConnectionLease::new());
+        pool.put("test2.com".to_string(), stream2, ConnectionLease::new());
 
         // Wait for expiration
         tokio::time::sleep(Duration::from_secs(2)).await;
@@ -636,6 +836,8 @@ mod tests {
 
         let stats = pool.stats();
         assert_eq!(stats.total_connections, 0);
+        // Hosts without idle connections are forgotten
+        assert_eq!(stats.hosts, 0);
     }
 
     #[tokio::test]
@@ -646,11 +848,11 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
+        let (stream1, _peer1) = create_test_connection().await;
+        let (stream2, _peer2) = create_test_connection().await;
 
-        pool.put("host1.com".to_string(), stream1);
-        pool.put("host2.com".to_string(), stream2);
+        pool.put("host1.com".to_string(), stream1, ConnectionLease::new());
+        pool.put("host2.com".to_string(), stream2, ConnectionLease::new());
 
         let stats = pool.stats();
         assert_eq!(stats.total_connections, 2);
@@ -719,10 +921,16 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         // Put connection with HTTP/1.1 and Keep-Alive enabled
-        assert!(pool.put_with_http_info("test.com".to_string(), stream, HttpVersion::Http11, true));
+        assert!(pool.put_with_http_info(
+            "test.com".to_string(),
+            stream,
+            ConnectionLease::new(),
+            HttpVersion::Http11,
+            true
+        ));
 
         // Should be able to retrieve it
         assert!(pool.get("test.com").is_some());
@@ -737,12 +945,13 @@ mod tests {
         };
         let pool = ConnectionPool::new(config);
 
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         // Put connection with Keep-Alive disabled (Connection: close)
         assert!(!pool.put_with_http_info(
             "test.com".to_string(),
             stream,
+            ConnectionLease::new(),
             HttpVersion::Http11,
             false
         ));
@@ -759,36 +968,74 @@ mod tests {
             max_requests_per_connection: 3, // Allow only 3 requests
             ..Default::default()
         };
-        let pool = ConnectionPool::new(config);
+        let registry = Registry::new();
+        let pool = ConnectionPool::with_metrics(config, &registry).unwrap();
+
+        // The connection was opened for request 1
+        let (stream, _peer) = create_test_connection().await;
+        pool.mark_active();
+        let mut checkout = (stream, ConnectionLease::new());
+
+        // Requests 2 and 3 reuse it
+        for request in 2..=3 {
+            let (stream, lease) = checkout;
+            assert!(pool.put_with_http_info(
+                "test.com".to_string(),
+                stream,
+                lease,
+                HttpVersion::Http11,
+                true
+            ));
+            checkout = pool.get("test.com").unwrap();
+            assert_eq!(checkout.1.request_count(), request);
+        }
 
-        let (stream, _) = create_test_connection().await;
+        // After 3 requests the connection is retired instead of reused
+        let (stream, lease) = checkout;
+        assert!(!pool.can_reuse(&lease));
+        assert!(pool.put_with_http_info(
+            "test.com".to_string(),
+            stream,
+            lease,
+            HttpVersion::Http11,
+            true
+        ));
+        assert!(pool.get("test.com").is_none());
+        assert_eq!(pool.stats().total_connections, 0);
 
-        // Put connection
-        pool.put_with_http_info("test.com".to_string(), stream, HttpVersion::Http11, true);
+        let metrics = pool.metrics.as_ref().unwrap();
+        assert_eq!(metrics.keep_alive_reuses.get(), 2);
+        assert_eq!(metrics.keep_alive_rejections.get(), 1);
+        assert_eq!(metrics.pool_size.get(), 0);
+    }
 
-        // First reuse (request 1)
-        assert!(pool.get("test.com").is_some());
+    #[tokio::test]
+    async fn test_pool_expiration_spans_reuse() {
+        let config = PoolConfig {
+            enabled: true,
+            connection_ttl: 1, // 1 second TTL
+            idle_timeout: 60,
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(config);
 
-        // Second reuse (request 2)
-        let (stream2, _) = create_test_connection().await;
-        pool.put_with_http_info("test.com".to_string(), stream2, HttpVersion::Http11, true);
-        assert!(pool.get("test.com").is_some());
+        let (stream, _peer) = create_test_connection().await;
+        assert!(pool.put("test.com".to_string(), stream, ConnectionLease::new()));
 
-        // Third reuse (request 3)
-        let (stream3, _) = create_test_connection().await;
-        pool.put_with_http_info("test.com".to_string(), stream3, HttpVersion::Http11, true);
-        assert!(pool.get("test.com").is_some());
+        // Returning a busy connection does not restart its TTL
+        let (stream, lease) = pool.get("test.com").unwrap();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(lease.age() > Duration::from_secs(1));
+        assert!(!pool.can_reuse(&lease));
+        assert!(pool.put("test.com".to_string(), stream, lease));
 
-        // Fourth reuse should fail (exceeded max_requests)
-        let (stream4, _) = create_test_connection().await;
-        pool.put_with_http_info("test.com".to_string(), stream4, HttpVersion::Http11, true);
-        // Pool has connection but it should be rejected due to max requests
-        // Note: Connection will be evicted on next get() attempt
+        assert!(pool.get("test.com").is_none());
+        assert_eq!(pool.stats().total_connections, 0);
     }
 
     #[tokio::test]
     async fn test_pooled_connection_can_keep_alive() {
-        let (stream, _) = create_test_connection().await;
+        let (stream, _peer) = create_test_connection().await;
 
         let mut conn = PooledConnection {
             stream,
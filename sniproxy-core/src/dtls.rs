@@ -0,0 +1,424 @@
+//! DTLS ClientHello parsing (RFC 6347 §4.1–4.2, RFC 9147 §4–5)
+//!
+//! DTLS runs TLS over datagrams. The ClientHello of DTLS 1.0, 1.2 and 1.3 is
+//! sent in plaintext epoch-0 records, so the proxy can read its SNI like on
+//! TCP, with three differences:
+//! 1. the record header carries an epoch and sequence number (13 bytes)
+//! 2. handshake messages may be fragmented across records and datagrams; each
+//!    fragment names its message sequence, offset and length (12-byte header)
+//! 3. the ClientHello body has a cookie field after the session ID
+//!
+//! Fragments are reassembled and the cookie is dropped, which turns the
+//! ClientHello into a TLS record the TCP parsers (`extract_sni`,
+//! `tls::parse_client_hello`) read unchanged.
+
+use std::fmt;
+
+/// Length of a DTLSPlaintext record header
+pub const RECORD_HEADER_LEN: usize = 13;
+/// Length of a DTLS handshake message header
+const HANDSHAKE_HEADER_LEN: usize = 12;
+
+const CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+const CONTENT_HANDSHAKE: u8 = 22;
+/// DTLS 1.3 ACK, the highest plaintext content type
+const CONTENT_ACK: u8 = 26;
+/// DTLS 1.3 ciphertext records start with 0b001xxxxx (RFC 9147 §4)
+const UNIFIED_HEADER_MIN: u8 = 0x20;
+const UNIFIED_HEADER_MAX: u8 = 0x3f;
+/// Major byte of every DTLS record version (1.0 is 0xfeff, 1.2 is 0xfefd)
+const DTLS_VERSION_MAJOR: u8 = 0xfe;
+
+const TLS_HANDSHAKE: u8 = 0x16;
+const CLIENT_HELLO: u8 = 0x01;
+
+/// Largest ClientHello that still fits a TLS record once reassembled
+pub const MAX_CLIENT_HELLO_LEN: usize = u16::MAX as usize - 4;
+
+/// Errors from DTLS record and ClientHello parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DtlsError {
+    /// The datagram ends before the record or message it announces
+    Truncated,
+    /// The datagram holds no epoch-0 handshake record
+    NotHandshake,
+    /// The handshake fragments belong to another message than a ClientHello
+    NotClientHello,
+    /// A header or field is out of range
+    Malformed(&'static str),
+    /// The fragments do not (yet) cover the whole ClientHello
+    IncompleteClientHello,
+}
+
+impl fmt::Display for DtlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DtlsError::Truncated => write!(f, "DTLS record too small or truncated"),
+            DtlsError::NotHandshake => write!(f, "No DTLS handshake record in datagram"),
+            DtlsError::NotClientHello => write!(f, "DTLS handshake message is not a ClientHello"),
+            DtlsError::Malformed(what) => write!(f, "Malformed DTLS record: {}", what),
+            DtlsError::IncompleteClientHello => {
+                write!(f, "Incomplete ClientHello in DTLS handshake fragments")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DtlsError {}
+
+/// A slice of a handshake message carried by one epoch-0 record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeFragment {
+    pub msg_type: u8,
+    /// Length of the whole message, repeated in every fragment
+    pub length: usize,
+    pub message_seq: u16,
+    pub offset: usize,
+    pub data: Vec<u8>,
+}
+
+/// Whether a datagram starts with a DTLS record
+///
+/// Follows the demultiplexing of RFC 7983 §7: plaintext records start with a
+/// content type from 20 to 26 and a 0xfe version byte, DTLS 1.3 ciphertext
+/// with the unified header bits 0b001. Neither overlaps with the fixed or
+/// long-header bits of QUIC.
+pub fn is_dtls(datagram: &[u8]) -> bool {
+    match datagram.first() {
+        Some(&(CONTENT_CHANGE_CIPHER_SPEC..=CONTENT_ACK)) => {
+            datagram.len() >= RECORD_HEADER_LEN && datagram[1] == DTLS_VERSION_MAJOR
+        }
+        // Unified header plus at least an 8-bit sequence number
+        Some(&(UNIFIED_HEADER_MIN..=UNIFIED_HEADER_MAX)) => datagram.len() >= 2,
+        _ => false,
+    }
+}
+
+/// Collects the handshake fragments of a datagram's epoch-0 records
+///
+/// A datagram may carry several records and a record several fragments.
+/// Records of other content types or epochs are skipped; parsing stops at a
+/// DTLS 1.3 ciphertext record, whose length cannot be read in plaintext.
+///
+/// # Errors
+///
+/// Fails when a record or fragment is truncated or inconsistent, or when the
+/// datagram holds no handshake fragment at all.
+pub fn handshake_fragments(datagram: &[u8]) -> Result<Vec<HandshakeFragment>, DtlsError> {
+    let mut fragments = Vec::new();
+    let mut pos = 0;
+
+    while pos < datagram.len() {
+        if datagram[pos] & 0xe0 == UNIFIED_HEADER_MIN {
+            break;
+        }
+        let header = datagram
+            .get(pos..pos + RECORD_HEADER_LEN)
+            .ok_or(DtlsError::Truncated)?;
+        if header[1] != DTLS_VERSION_MAJOR {
+            return Err(DtlsError::Malformed("record version is not DTLS"));
+        }
+        let epoch = u16::from_be_bytes([header[3], header[4]]);
+        let len = u16::from_be_bytes([header[11], header[12]]) as usize;
+        pos += RECORD_HEADER_LEN;
+        let body = datagram.get(pos..pos + len).ok_or(DtlsError::Truncated)?;
+        pos += len;
+
+        if header[0] == CONTENT_HANDSHAKE && epoch == 0 {
+            parse_fragments(body, &mut fragments)?;
+        }
+    }
+
+    if fragments.is_empty() {
+        return Err(DtlsError::NotHandshake);
+    }
+    Ok(fragments)
+}
+
+/// Parses the handshake fragments of one record body
+fn parse_fragments(mut body: &[u8], out: &mut Vec<HandshakeFragment>) -> Result<(), DtlsError> {
+    while !body.is_empty() {
+        let header = body
+            .get(..HANDSHAKE_HEADER_LEN)
+            .ok_or(DtlsError::Truncated)?;
+        let length = read_u24(&header[1..4]);
+        let message_seq = u16::from_be_bytes([header[4], header[5]]);
+        let offset = read_u24(&header[6..9]);
+        let fragment_len = read_u24(&header[9..12]);
+        if offset + fragment_len > length {
+            return Err(DtlsError::Malformed("fragment extends past its message"));
+        }
+        let data = body
+            .get(HANDSHAKE_HEADER_LEN..HANDSHAKE_HEADER_LEN + fragment_len)
+            .ok_or(DtlsError::Truncated)?;
+
+        out.push(HandshakeFragment {
+            msg_type: header[0],
+            length,
+            message_seq,
+            offset,
+            data: data.to_vec(),
+        });
+        body = &body[HANDSHAKE_HEADER_LEN + fragment_len..];
+    }
+    Ok(())
+}
+
+/// Reassembles the first ClientHello from handshake fragments
+///
+/// Fragments may be in any order, overlap and come from several datagrams.
+/// The ClientHello with the lowest message sequence is assembled; a client
+/// answering a HelloVerifyRequest repeats it with the next sequence number.
+/// Returns the message body once the fragments cover it.
+pub fn assemble_client_hello(fragments: &[HandshakeFragment]) -> Result<Vec<u8>, DtlsError> {
+    let mut hello: Vec<&HandshakeFragment> = fragments
+        .iter()
+        .filter(|fragment| fragment.msg_type == CLIENT_HELLO)
+        .collect();
+    let Some(first) = hello.iter().min_by_key(|fragment| fragment.message_seq) else {
+        return Err(DtlsError::NotClientHello);
+    };
+    let (message_seq, length) = (first.message_seq, first.length);
+    if length > MAX_CLIENT_HELLO_LEN {
+        return Err(DtlsError::Malformed(
+            "ClientHello too large for a TLS record",
+        ));
+    }
+    hello.retain(|fragment| fragment.message_seq == message_seq);
+    if hello.iter().any(|fragment| fragment.length != length) {
+        return Err(DtlsError::Malformed(
+            "ClientHello fragments disagree on length",
+        ));
+    }
+    hello.sort_by_key(|fragment| fragment.offset);
+
+    let mut body = Vec::with_capacity(length);
+    for fragment in hello {
+        if fragment.offset > body.len() {
+            break;
+        }
+        if fragment.offset + fragment.data.len() > body.len() {
+            body.extend_from_slice(&fragment.data[body.len() - fragment.offset..]);
+        }
+    }
+
+    if body.len() < length {
+        return Err(DtlsError::IncompleteClientHello);
+    }
+    Ok(body)
+}
+
+/// Wraps a DTLS ClientHello body in a TLS handshake record
+///
+/// The cookie is removed; everything else, including the DTLS version in the
+/// body, is kept.
+pub fn client_hello_record(body: &[u8]) -> Result<Vec<u8>, DtlsError> {
+    // legacy_version (2) and random (32)
+    let mut pos = 34;
+    let session_id_len = *body.get(pos).ok_or(DtlsError::Truncated)? as usize;
+    pos += 1 + session_id_len;
+    let cookie_start = pos;
+    let cookie_len = *body.get(pos).ok_or(DtlsError::Truncated)? as usize;
+    pos += 1 + cookie_len;
+    if pos > body.len() {
+        return Err(DtlsError::Truncated);
+    }
+
+    let hello_len = cookie_start + (body.len() - pos);
+    let record_len = u16::try_from(4 + hello_len)
+        .map_err(|_| DtlsError::Malformed("ClientHello too large for a TLS record"))?;
+
+    let mut record = Vec::with_capacity(5 + 4 + hello_len);
+    record.extend_from_slice(&[TLS_HANDSHAKE, 0x03, 0x01]);
+    record.extend_from_slice(&record_len.to_be_bytes());
+    record.push(CLIENT_HELLO);
+    record.extend_from_slice(&(hello_len as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&body[..cookie_start]);
+    record.extend_from_slice(&body[pos..]);
+    Ok(record)
+}
+
+fn read_u24(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | (bytes[2] as usize)
+}
+
+/// Builds the datagrams of a ClientHello split into fragments of at most
+/// `max_fragment` bytes, one record per datagram
+#[cfg(test)]
+pub(crate) fn client_hello_datagrams(
+    message_seq: u16,
+    body: &[u8],
+    max_fragment: usize,
+) -> Vec<Vec<u8>> {
+    body.chunks(max_fragment)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let offset = i * max_fragment;
+            let mut fragment = vec![CLIENT_HELLO];
+            fragment.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+            fragment.extend_from_slice(&message_seq.to_be_bytes());
+            fragment.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            fragment.extend_from_slice(&(chunk.len() as u32).to_be_bytes()[1..]);
+            fragment.extend_from_slice(chunk);
+
+            let mut record = vec![CONTENT_HANDSHAKE, 0xfe, 0xfd, 0x00, 0x00];
+            record.extend_from_slice(&(i as u64).to_be_bytes()[2..]);
+            record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            record.extend_from_slice(&fragment);
+            record
+        })
+        .collect()
+}
+
+/// DTLS 1.2 ClientHello body with an optional SNI and a cookie
+#[cfg(test)]
+pub(crate) fn client_hello_body(sni: Option<&str>, cookie: &[u8]) -> Vec<u8> {
+    let mut extensions = Vec::new();
+    if let Some(name) = sni.map(str::as_bytes) {
+        extensions.extend_from_slice(&[0x00, 0x00]);
+        extensions.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+        extensions.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+        extensions.push(0x00);
+        extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(name);
+    }
+
+    let mut body = vec![0xfe, 0xfd];
+    body.extend_from_slice(&[0x11; 32]);
+    body.push(0x00);
+    body.push(cookie.len() as u8);
+    body.extend_from_slice(cookie);
+    body.extend_from_slice(&[0x00, 0x02, 0xc0, 0x2b, 0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_dtls() {
+        let datagram = &client_hello_datagrams(0, &client_hello_body(None, &[]), 1000)[0];
+        assert!(is_dtls(datagram));
+        // DTLS 1.3 ciphertext with the unified header
+        assert!(is_dtls(&[0x2c, 0x01, 0x02, 0x03]));
+
+        // TLS record version, QUIC long and short headers, STUN
+        let mut tls = datagram.clone();
+        tls[1] = 0x03;
+        assert!(!is_dtls(&tls));
+        assert!(!is_dtls(&[0xc0, 0x00, 0x00, 0x00, 0x01]));
+        assert!(!is_dtls(&[0x40; 30]));
+        assert!(!is_dtls(&[0x00, 0x01, 0x00, 0x00]));
+        assert!(!is_dtls(&[]));
+        assert!(!is_dtls(&datagram[..RECORD_HEADER_LEN - 1]));
+    }
+
+    #[test]
+    fn test_client_hello_single_record() {
+        let body = client_hello_body(Some("dtls.example.com"), &[]);
+        let datagram = &client_hello_datagrams(0, &body, 1000)[0];
+
+        let fragments = handshake_fragments(datagram).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(assemble_client_hello(&fragments).unwrap(), body);
+
+        let record = client_hello_record(&body).unwrap();
+        assert_eq!(crate::extract_sni(&record).unwrap(), "dtls.example.com");
+    }
+
+    #[test]
+    fn test_client_hello_fragments_out_of_order() {
+        let body = client_hello_body(Some("iot.example.com"), &[]);
+        let mut datagrams = client_hello_datagrams(0, &body, 20);
+        assert!(datagrams.len() > 3);
+        datagrams.reverse();
+
+        let mut fragments = Vec::new();
+        for (i, datagram) in datagrams.iter().enumerate() {
+            let result = assemble_client_hello(&fragments);
+            if i > 0 {
+                assert_eq!(result, Err(DtlsError::IncompleteClientHello));
+            }
+            fragments.extend(handshake_fragments(datagram).unwrap());
+        }
+        let assembled = assemble_client_hello(&fragments).unwrap();
+        assert_eq!(assembled, body);
+        let record = client_hello_record(&assembled).unwrap();
+        assert_eq!(crate::extract_sni(&record).unwrap(), "iot.example.com");
+    }
+
+    #[test]
+    fn test_several_records_in_one_datagram() {
+        let body = client_hello_body(Some("turn.example.com"), &[]);
+        let datagram = client_hello_datagrams(0, &body, 40).concat();
+
+        let fragments = handshake_fragments(&datagram).unwrap();
+        assert!(fragments.len() > 1);
+        assert_eq!(assemble_client_hello(&fragments).unwrap(), body);
+    }
+
+    #[test]
+    fn test_cookie_is_removed() {
+        let cookie = [0xaa; 20];
+        let body = client_hello_body(Some("coap.example.com"), &cookie);
+        let record = client_hello_record(&body).unwrap();
+
+        // The cookie and its length byte are gone
+        assert_eq!(record.len(), 5 + 4 + body.len() - 1 - cookie.len());
+        assert_eq!(crate::extract_sni(&record).unwrap(), "coap.example.com");
+        let hello = crate::tls::parse_client_hello(&record).unwrap();
+        assert_eq!(hello.legacy_version, 0xfefd);
+        assert_eq!(hello.cipher_suites, vec![0xc02b]);
+    }
+
+    #[test]
+    fn test_lowest_message_sequence_wins() {
+        let first = client_hello_body(Some("first.example.com"), &[]);
+        let second = client_hello_body(Some("second.example.com"), &[0x01; 8]);
+        let mut fragments =
+            handshake_fragments(&client_hello_datagrams(1, &second, 1000)[0]).unwrap();
+        fragments.extend(handshake_fragments(&client_hello_datagrams(0, &first, 1000)[0]).unwrap());
+
+        assert_eq!(assemble_client_hello(&fragments).unwrap(), first);
+    }
+
+    #[test]
+    fn test_malformed_records() {
+        let body = client_hello_body(Some("dtls.example.com"), &[]);
+        let datagram = client_hello_datagrams(0, &body, 1000).remove(0);
+
+        assert_eq!(
+            handshake_fragments(&datagram[..datagram.len() - 1]),
+            Err(DtlsError::Truncated)
+        );
+
+        // A record of epoch 1 is encrypted
+        let mut encrypted = datagram.clone();
+        encrypted[4] = 0x01;
+        assert_eq!(
+            handshake_fragments(&encrypted),
+            Err(DtlsError::NotHandshake)
+        );
+
+        // Fragment offset past the message length
+        let mut past_end = datagram.clone();
+        past_end[RECORD_HEADER_LEN + 8] = 0xff;
+        assert!(matches!(
+            handshake_fragments(&past_end),
+            Err(DtlsError::Malformed(_))
+        ));
+
+        // A ServerHello is not a ClientHello
+        let mut server_hello = handshake_fragments(&datagram).unwrap();
+        server_hello[0].msg_type = 0x02;
+        assert_eq!(
+            assemble_client_hello(&server_hello),
+            Err(DtlsError::NotClientHello)
+        );
+
+        assert_eq!(client_hello_record(&body[..34]), Err(DtlsError::Truncated));
+    }
+}
@@ -6,26 +6,54 @@
 //!
 //! # Features
 //!
-//! - Channel pooling per backend host
-//! - Health checking for channels
+//! - HTTP/2 channel pooling per backend host
+//! - Per-channel stream accounting bounded by `max_concurrent_streams`
+//! - Health checking of channels with `grpc.health.v1.Health/Check`
 //! - Round-robin load balancing
-//! - Automatic cleanup of unhealthy channels
+//! - Automatic cleanup of expired and unhealthy channels
 //! - Prometheus metrics for monitoring
 //!
 //! # Architecture
 //!
 //! gRPC uses HTTP/2 as the transport protocol, which supports multiplexing multiple
-//! streams over a single connection. This pool maintains multiple channels per host
-//! to distribute load and provide resilience.
+//! streams over a single connection. Each pooled channel is an HTTP/2 client connection
+//! driven by its own task; [`GrpcConnectionPool::acquire`] hands out a [`GrpcStream`]
+//! for one call on a channel with a free stream slot, opening a new channel while the
+//! host has fewer than `max_channels_per_host`. Dropping the `GrpcStream` frees the slot.
+//! [`GrpcStream::forward`] proxies a call accepted on a terminated client connection.
+//!
+//! Unhealthy channels take no new calls and are closed once their last call ends.
 
+use bytes::Bytes;
 use dashmap::DashMap;
-use std::sync::Arc;
+use h2::client::{ResponseFuture, SendRequest};
+use h2::server::SendResponse;
+use h2::{Reason, RecvStream, SendStream};
+use hyper::{Request, Response, StatusCode};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tracing::{debug, info};
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
 
 use prometheus::{IntCounter, IntGauge, Registry};
 
+/// Path of the standard gRPC health checking method
+pub const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// Time allowed for a single health check
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest HealthCheckResponse accepted
+const MAX_HEALTH_RESPONSE_SIZE: usize = 1024;
+
+/// `HealthCheckResponse.ServingStatus.SERVING`
+const SERVING: u64 = 1;
+
+/// gRPC status of backends that do not implement the health service
+const GRPC_STATUS_UNIMPLEMENTED: &str = "12";
+
 /// Configuration for gRPC connection pooling
 #[derive(Debug, Clone)]
 pub struct GrpcPoolConfig {
@@ -41,6 +69,8 @@ pub struct GrpcPoolConfig {
     pub max_concurrent_streams: usize,
     /// Health check interval in seconds (default: 30)
     pub health_check_interval: u64,
+    /// Timeout for opening a new channel in seconds (default: 10)
+    pub connect_timeout: u64,
 }
 
 impl Default for GrpcPoolConfig {
@@ -52,15 +82,56 @@ impl Default for GrpcPoolConfig {
             enabled: true,
             max_concurrent_streams: 100,
             health_check_interval: 30,
+            connect_timeout: 10,
         }
     }
 }
 
+/// Errors returned when a call cannot be placed on a channel
+#[derive(Debug)]
+pub enum GrpcPoolError {
+    /// The pool is disabled
+    Disabled,
+    /// Every channel to the host is at `max_concurrent_streams` and no more may be opened
+    Exhausted(String),
+    /// The TCP connection to the host failed
+    Connect(String, std::io::Error),
+    /// Connecting or a health check did not complete in time
+    Timeout(String),
+    /// HTTP/2 error on a channel
+    Http2(h2::Error),
+    /// The health check did not report SERVING
+    HealthCheck(String),
+}
+
+impl std::fmt::Display for GrpcPoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrpcPoolError::Disabled => write!(f, "gRPC pool is disabled"),
+            GrpcPoolError::Exhausted(host) => {
+                write!(f, "All gRPC channels to {} are at their stream limit", host)
+            }
+            GrpcPoolError::Connect(host, e) => write!(f, "Failed to connect to {}: {}", host, e),
+            GrpcPoolError::Timeout(host) => write!(f, "gRPC channel to {} timed out", host),
+            GrpcPoolError::Http2(e) => write!(f, "HTTP/2 error: {}", e),
+            GrpcPoolError::HealthCheck(reason) => write!(f, "Health check failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GrpcPoolError {}
+
+impl From<h2::Error> for GrpcPoolError {
+    fn from(e: h2::Error) -> Self {
+        GrpcPoolError::Http2(e)
+    }
+}
+
 /// Represents a pooled gRPC channel
 #[derive(Debug)]
 struct GrpcChannel {
-    #[allow(dead_code)] // Used in full implementation
-    stream: TcpStream,
+    id: u64,
+    sender: SendRequest<Bytes>,
     created_at: Instant,
     last_used: Instant,
     rpc_count: usize,
@@ -69,10 +140,11 @@ struct GrpcChannel {
 }
 
 impl GrpcChannel {
-    fn new(stream: TcpStream) -> Self {
+    fn new(id: u64, sender: SendRequest<Bytes>) -> Self {
         let now = Instant::now();
         Self {
-            stream,
+            id,
+            sender,
             created_at: now,
             last_used: now,
             rpc_count: 0,
@@ -86,9 +158,9 @@ impl GrpcChannel {
         self.created_at.elapsed() > ttl
     }
 
-    /// Check if channel has been idle too long
+    /// Check if channel has been without calls too long
     fn is_idle(&self, idle_timeout: Duration) -> bool {
-        self.last_used.elapsed() > idle_timeout
+        self.active_streams == 0 && self.last_used.elapsed() > idle_timeout
     }
 
     /// Check if channel is still valid and healthy
@@ -102,7 +174,6 @@ impl GrpcChannel {
     }
 
     /// Mark channel as used and increment counters
-    #[allow(dead_code)]
     fn mark_used(&mut self) {
         self.rpc_count += 1;
         self.active_streams += 1;
@@ -110,20 +181,36 @@ impl GrpcChannel {
     }
 
     /// Decrement active stream count
-    #[allow(dead_code)]
     fn release_stream(&mut self) {
         if self.active_streams > 0 {
             self.active_streams -= 1;
         }
+        self.last_used = Instant::now();
     }
 
     /// Mark channel as unhealthy
-    #[allow(dead_code)]
     fn mark_unhealthy(&mut self) {
         self.healthy = false;
     }
 }
 
+/// Channels to one backend host
+#[derive(Debug, Default)]
+struct HostChannels {
+    channels: Vec<GrpcChannel>,
+    /// Channels being opened, counted against `max_channels_per_host`
+    connecting: usize,
+    /// Round-robin position
+    next: usize,
+}
+
+/// Outcome of looking for a free stream slot
+enum Reservation {
+    Channel(u64, SendRequest<Bytes>),
+    Connect,
+    Exhausted,
+}
+
 /// Metrics for gRPC connection pool
 struct GrpcPoolMetrics {
     pool_hits: IntCounter,
@@ -155,7 +242,7 @@ impl GrpcPoolMetrics {
         )?;
         let active_channels = IntGauge::new(
             "sniproxy_grpc_active_channels",
-            "Current number of active gRPC channels",
+            "Current number of gRPC channels with calls in progress",
         )?;
         let total_rpcs = IntCounter::new(
             "sniproxy_grpc_rpcs_total",
@@ -188,20 +275,20 @@ impl GrpcPoolMetrics {
 
 /// gRPC connection pool for channel reuse
 pub struct GrpcConnectionPool {
-    pools: Arc<DashMap<String, Vec<GrpcChannel>>>,
+    pools: DashMap<String, HostChannels>,
     config: GrpcPoolConfig,
     metrics: Option<GrpcPoolMetrics>,
-    next_channel_index: Arc<DashMap<String, usize>>, // For round-robin
+    next_channel_id: AtomicU64,
 }
 
 impl GrpcConnectionPool {
     /// Create a new gRPC connection pool
     pub fn new(config: GrpcPoolConfig) -> Self {
         Self {
-            pools: Arc::new(DashMap::new()),
+            pools: DashMap::new(),
             config,
             metrics: None,
-            next_channel_index: Arc::new(DashMap::new()),
+            next_channel_id: AtomicU64::new(0),
         }
     }
 
@@ -212,153 +299,223 @@ impl GrpcConnectionPool {
     ) -> Result<Self, prometheus::Error> {
         let metrics = GrpcPoolMetrics::new(registry)?;
         Ok(Self {
-            pools: Arc::new(DashMap::new()),
+            pools: DashMap::new(),
             config,
             metrics: Some(metrics),
-            next_channel_index: Arc::new(DashMap::new()),
+            next_channel_id: AtomicU64::new(0),
         })
     }
 
-    /// Try to get a channel from the pool using round-robin selection
+    /// Reserves a stream slot for one call to `host` ("host:port")
     ///
-    /// Returns Some(TcpStream) if a valid channel is available, None otherwise
-    pub fn get(&self, host: &str) -> Option<TcpStream> {
+    /// Channels are picked round-robin among those with a free slot. If all
+    /// are full, a new channel is opened unless the host already has
+    /// `max_channels_per_host`, in which case the call is refused with
+    /// [`GrpcPoolError::Exhausted`].
+    pub async fn acquire(self: &Arc<Self>, host: &str) -> Result<GrpcStream, GrpcPoolError> {
         if !self.config.enabled {
-            return None;
+            return Err(GrpcPoolError::Disabled);
         }
 
-        let mut pool = self.pools.get_mut(host)?;
+        match self.reserve(host) {
+            Reservation::Channel(channel_id, sender) => {
+                debug!(host, channel = channel_id, "gRPC pool hit");
+                if let Some(ref metrics) = self.metrics {
+                    metrics.pool_hits.inc();
+                    metrics.total_rpcs.inc();
+                }
+                return Ok(GrpcStream {
+                    pool: self.clone(),
+                    host: host.to_string(),
+                    channel_id,
+                    sender,
+                });
+            }
+            Reservation::Exhausted => {
+                debug!(host, "gRPC pool exhausted");
+                return Err(GrpcPoolError::Exhausted(host.to_string()));
+            }
+            Reservation::Connect => {}
+        }
 
-        let ttl = Duration::from_secs(self.config.channel_ttl);
-        let idle_timeout = Duration::from_secs(self.config.idle_timeout);
-        let max_streams = self.config.max_concurrent_streams;
+        debug!(host, "gRPC pool miss, opening channel");
+        if let Some(ref metrics) = self.metrics {
+            metrics.pool_misses.inc();
+        }
 
-        // Get next channel index for round-robin
-        let mut index_entry = self.next_channel_index.entry(host.to_string()).or_insert(0);
-        let start_index = *index_entry;
+        let result = self.connect(host).await;
+        let mut entry = self.pools.entry(host.to_string()).or_default();
+        entry.connecting -= 1;
+        let (channel_id, sender) = result?;
 
-        // Try to find a valid channel using round-robin
-        let pool_len = pool.len();
-        if pool_len == 0 {
-            drop(index_entry);
-            debug!(host = host, "gRPC pool miss (empty pool)");
-            if let Some(ref metrics) = self.metrics {
-                metrics.pool_misses.inc();
-            }
-            return None;
+        let mut channel = GrpcChannel::new(channel_id, sender.clone());
+        channel.mark_used();
+        entry.channels.push(channel);
+        debug!(
+            host,
+            channel = channel_id,
+            pool_size = entry.channels.len(),
+            "Opened gRPC channel"
+        );
+
+        if let Some(ref metrics) = self.metrics {
+            metrics.pool_size.inc();
+            metrics.active_channels.inc();
+            metrics.total_rpcs.inc();
         }
 
-        for attempt in 0..pool_len {
-            let idx = (start_index + attempt) % pool_len;
+        Ok(GrpcStream {
+            pool: self.clone(),
+            host: host.to_string(),
+            channel_id,
+            sender,
+        })
+    }
 
-            if let Some(channel) = pool.get_mut(idx) {
-                // Check if channel is valid and can accept streams
-                if !channel.is_valid(ttl, idle_timeout) {
-                    debug!(host = host, index = idx, "Skipping expired/idle channel");
-                    continue;
-                }
+    /// Takes a free slot on an existing channel, or a permit to open one
+    fn reserve(&self, host: &str) -> Reservation {
+        let ttl = Duration::from_secs(self.config.channel_ttl);
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout);
+        let max_streams = self.config.max_concurrent_streams;
 
-                if !channel.can_accept_stream(max_streams) {
-                    debug!(
-                        host = host,
-                        index = idx,
-                        active_streams = channel.active_streams,
-                        "Skipping saturated channel"
-                    );
-                    continue;
-                }
+        let mut entry = self.pools.entry(host.to_string()).or_default();
+        let host_channels = entry.value_mut();
+        let pool_len = host_channels.channels.len();
 
-                // Found a valid channel - extract it from the pool
-                // Remove the channel and extract its stream
-                // This provides connection reuse while maintaining compatibility
-                // with the current API that returns TcpStream
-                let channel = pool.remove(idx);
-
-                // Update round-robin index
-                *index_entry = idx % pool.len().max(1);
-
-                debug!(
-                    host = host,
-                    rpc_count = channel.rpc_count,
-                    active_streams = channel.active_streams,
-                    remaining_in_pool = pool.len(),
-                    "gRPC pool hit - extracted channel"
-                );
+        for attempt in 0..pool_len {
+            let idx = (host_channels.next + attempt) % pool_len;
+            let channel = &mut host_channels.channels[idx];
 
-                if let Some(ref metrics) = self.metrics {
-                    metrics.pool_hits.inc();
-                    metrics.total_rpcs.inc();
-                    metrics.pool_size.dec();
-                    metrics.active_channels.inc();
-                }
+            if !channel.is_valid(ttl, idle_timeout) || !channel.can_accept_stream(max_streams) {
+                continue;
+            }
 
-                // Return the stream - caller is responsible for returning it via put()
-                return Some(channel.stream);
+            if channel.active_streams == 0
+                && let Some(ref metrics) = self.metrics
+            {
+                metrics.active_channels.inc();
             }
+            channel.mark_used();
+            host_channels.next = (idx + 1) % pool_len;
+            return Reservation::Channel(channel.id, channel.sender.clone());
         }
 
-        drop(index_entry);
-
-        // No valid channel found
-        debug!(host = host, "gRPC pool miss (no valid channels)");
-
-        if let Some(ref metrics) = self.metrics {
-            metrics.pool_misses.inc();
+        let usable = host_channels
+            .channels
+            .iter()
+            .filter(|channel| channel.is_valid(ttl, idle_timeout))
+            .count();
+        if usable + host_channels.connecting < self.config.max_channels_per_host {
+            host_channels.connecting += 1;
+            Reservation::Connect
+        } else {
+            Reservation::Exhausted
         }
-
-        None
     }
 
-    /// Return a channel to the pool
-    ///
-    /// Returns true if channel was added to pool, false if pool is full
-    pub fn put(&self, host: String, stream: TcpStream) -> bool {
-        if !self.config.enabled {
-            return false;
-        }
+    /// Opens an HTTP/2 connection to `host` and starts the task driving it
+    async fn connect(
+        self: &Arc<Self>,
+        host: &str,
+    ) -> Result<(u64, SendRequest<Bytes>), GrpcPoolError> {
+        let connect_timeout = Duration::from_secs(self.config.connect_timeout);
+        let stream = match timeout(connect_timeout, TcpStream::connect(host)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => return Err(GrpcPoolError::Connect(host.to_string(), e)),
+            Err(_) => return Err(GrpcPoolError::Timeout(host.to_string())),
+        };
+        let _ = stream.set_nodelay(true);
 
-        let mut pool = self.pools.entry(host.clone()).or_default();
+        let (sender, connection) = timeout(connect_timeout, h2::client::handshake(stream))
+            .await
+            .map_err(|_| GrpcPoolError::Timeout(host.to_string()))??;
 
-        // Check if pool is full
-        if pool.len() >= self.config.max_channels_per_host {
-            debug!(host = host, "gRPC pool full, discarding channel");
-            return false;
-        }
+        let channel_id = self.next_channel_id.fetch_add(1, Ordering::Relaxed);
+        let pool = Arc::downgrade(self);
+        let host = host.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!(host, channel = channel_id, error = %e, "gRPC channel failed");
+            }
+            if let Some(pool) = Weak::upgrade(&pool) {
+                pool.retire(&host, channel_id, false);
+            }
+        });
 
-        // Add channel to pool
-        pool.push(GrpcChannel::new(stream));
+        Ok((channel_id, sender))
+    }
 
-        debug!(
-            host = host,
-            pool_size = pool.len(),
-            "Returned gRPC channel to pool"
-        );
+    /// Frees the stream slot held by a finished call on `channel_id`
+    pub fn release_stream(&self, host: &str, channel_id: u64) {
+        let Some(mut entry) = self.pools.get_mut(host) else {
+            return;
+        };
+        let channels = &mut entry.channels;
+        let Some(idx) = channels.iter().position(|c| c.id == channel_id) else {
+            return;
+        };
 
-        if let Some(ref metrics) = self.metrics {
-            metrics.pool_size.inc();
+        let channel = &mut channels[idx];
+        channel.release_stream();
+        if channel.active_streams == 0 {
+            if let Some(ref metrics) = self.metrics {
+                metrics.active_channels.dec();
+            }
+            if !channel.healthy {
+                self.evict(channels, idx);
+            }
         }
+    }
 
-        true
+    /// Marks `channel_id` unhealthy
+    ///
+    /// The channel takes no new calls and is closed once its last call ends.
+    pub fn mark_unhealthy(&self, host: &str, channel_id: u64) {
+        self.retire(host, channel_id, true);
     }
 
-    /// Mark a channel stream as released (RPC completed)
-    pub fn release_stream(&self, _host: &str, _stream_id: usize) {
-        // In a real implementation, we'd track which channel owns which stream
-        // For now, this is a placeholder
-        if let Some(ref metrics) = self.metrics {
-            metrics.active_channels.dec();
+    /// Stops new calls on a channel and evicts it if it has none in progress
+    fn retire(&self, host: &str, channel_id: u64, unhealthy: bool) {
+        let Some(mut entry) = self.pools.get_mut(host) else {
+            return;
+        };
+        let channels = &mut entry.channels;
+        let Some(idx) = channels.iter().position(|c| c.id == channel_id) else {
+            return;
+        };
+
+        let channel = &mut channels[idx];
+        if channel.healthy {
+            channel.mark_unhealthy();
+            if unhealthy {
+                warn!(host, channel = channel_id, "gRPC channel marked unhealthy");
+                if let Some(ref metrics) = self.metrics {
+                    metrics.unhealthy_channels.inc();
+                }
+            }
+        }
+        if channel.active_streams == 0 {
+            self.evict(channels, idx);
         }
     }
 
-    /// Mark a channel as unhealthy
-    pub fn mark_unhealthy(&self, _host: &str, _stream_id: usize) {
-        // In a real implementation, we'd identify and mark the specific channel
+    /// Removes the channel at `idx`; its connection closes once the last handle is gone
+    fn evict(&self, channels: &mut Vec<GrpcChannel>, idx: usize) {
+        let channel = channels.remove(idx);
+        debug!(
+            channel = channel.id,
+            rpc_count = channel.rpc_count,
+            "Evicted gRPC channel"
+        );
         if let Some(ref metrics) = self.metrics {
-            metrics.unhealthy_channels.inc();
+            metrics.pool_evictions.inc();
+            metrics.pool_size.dec();
         }
     }
 
     /// Cleanup expired and unhealthy channels from all pools
+    ///
+    /// Channels with calls in progress are kept until the calls end.
     pub fn cleanup(&self) {
         let ttl = Duration::from_secs(self.config.channel_ttl);
         let idle_timeout = Duration::from_secs(self.config.idle_timeout);
@@ -367,16 +524,21 @@ impl GrpcConnectionPool {
 
         for mut entry in self.pools.iter_mut() {
             let host = entry.key().to_string();
-            let pool = entry.value_mut();
-            let before = pool.len();
-            pool.retain(|channel| channel.is_valid(ttl, idle_timeout));
-            let evicted = before - pool.len();
+            let channels = &mut entry.value_mut().channels;
+            let before = channels.len();
+            channels.retain(|channel| {
+                channel.active_streams > 0 || channel.is_valid(ttl, idle_timeout)
+            });
+            let evicted = before - channels.len();
 
             if evicted > 0 {
                 debug!(host = host, evicted = evicted, "Cleaned up gRPC channels");
                 total_evicted += evicted;
             }
         }
+        self.pools.retain(|_, host_channels| {
+            !host_channels.channels.is_empty() || host_channels.connecting > 0
+        });
 
         if total_evicted > 0 {
             info!(evicted = total_evicted, "gRPC pool cleanup complete");
@@ -388,13 +550,59 @@ impl GrpcConnectionPool {
         }
     }
 
+    /// Runs a `grpc.health.v1` check on every healthy channel
+    ///
+    /// Channels whose check fails or times out are marked unhealthy. Backends
+    /// answering UNIMPLEMENTED have no health service and are assumed healthy.
+    pub async fn check_health(&self) {
+        let mut channels = Vec::new();
+        for entry in self.pools.iter() {
+            for channel in entry.channels.iter().filter(|channel| channel.healthy) {
+                channels.push((entry.key().clone(), channel.id, channel.sender.clone()));
+            }
+        }
+
+        let checks = channels
+            .into_iter()
+            .map(|(host, channel_id, sender)| async move {
+                let result = match timeout(HEALTH_CHECK_TIMEOUT, health_check(sender, &host)).await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(GrpcPoolError::Timeout(host.clone())),
+                };
+                (host, channel_id, result)
+            });
+        for (host, channel_id, result) in futures::future::join_all(checks).await {
+            if let Err(e) = result {
+                warn!(host, channel = channel_id, error = %e, "gRPC health check failed");
+                self.mark_unhealthy(&host, channel_id);
+            }
+        }
+    }
+
     /// Get statistics about the pool
     pub fn stats(&self) -> GrpcPoolStats {
-        let total_channels: usize = self.pools.iter().map(|entry| entry.value().len()).sum();
-        let hosts: usize = self.pools.len();
+        let total_channels: usize = self.pools.iter().map(|entry| entry.channels.len()).sum();
+        let active_streams: usize = self
+            .pools
+            .iter()
+            .map(|entry| {
+                entry
+                    .channels
+                    .iter()
+                    .map(|channel| channel.active_streams)
+                    .sum::<usize>()
+            })
+            .sum();
+        let hosts: usize = self
+            .pools
+            .iter()
+            .filter(|entry| !entry.channels.is_empty())
+            .count();
 
         GrpcPoolStats {
             total_channels,
+            active_streams,
             hosts,
             enabled: self.config.enabled,
         }
@@ -412,12 +620,298 @@ impl GrpcConnectionPool {
             }
         })
     }
+
+    /// Start background health check task
+    ///
+    /// Every `health_check_interval` seconds, checks all channels and then
+    /// runs cleanup.
+    pub fn start_health_check_task(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let interval = Duration::from_secs(self.config.health_check_interval.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.check_health().await;
+                self.cleanup();
+            }
+        })
+    }
+}
+
+/// A stream slot on a pooled channel, held for the duration of one call
+///
+/// Dropping it frees the slot.
+pub struct GrpcStream {
+    pool: Arc<GrpcConnectionPool>,
+    host: String,
+    channel_id: u64,
+    sender: SendRequest<Bytes>,
+}
+
+impl GrpcStream {
+    /// Channel carrying this call
+    pub fn channel_id(&self) -> u64 {
+        self.channel_id
+    }
+
+    /// Sends the request head on the channel
+    ///
+    /// Waits while the backend's own SETTINGS_MAX_CONCURRENT_STREAMS is reached.
+    pub async fn send_request(
+        &mut self,
+        request: Request<()>,
+        end_of_stream: bool,
+    ) -> Result<(ResponseFuture, SendStream<Bytes>), h2::Error> {
+        let mut sender = self.sender.clone().ready().await?;
+        sender.send_request(request, end_of_stream)
+    }
+
+    /// Marks the channel unhealthy, e.g. after a connection-level error
+    pub fn mark_unhealthy(&self) {
+        self.pool.mark_unhealthy(&self.host, self.channel_id);
+    }
+
+    /// Proxies one call from a terminated client connection over this channel
+    ///
+    /// The request head and body are sent to the backend and its response
+    /// relayed to `respond`, with flow control honoured on both sides. If the
+    /// backend fails before answering, the client gets a Trailers-Only
+    /// UNAVAILABLE response; later failures reset the client's stream.
+    /// `byte_counters` count request and response DATA bytes.
+    pub async fn forward(
+        mut self,
+        request: Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        byte_counters: Option<(IntCounter, IntCounter)>,
+    ) -> Result<(), GrpcPoolError> {
+        let (tx, rx) = byte_counters.unzip();
+        let (parts, request_body) = request.into_parts();
+        let end_of_stream = request_body.is_end_stream();
+
+        let (response, upstream_body) = match self
+            .send_request(Request::from_parts(parts, ()), end_of_stream)
+            .await
+        {
+            Ok(sent) => sent,
+            Err(e) => {
+                self.refuse(&mut respond, &e);
+                return Err(e.into());
+            }
+        };
+
+        // The request body streams up while the response streams down
+        let request_relay = tokio::spawn(async move {
+            if !end_of_stream {
+                let _ = relay_body(request_body, upstream_body, tx).await;
+            }
+        });
+
+        let result = match response.await {
+            Ok(response) => {
+                let (parts, response_body) = response.into_parts();
+                let end_of_stream = response_body.is_end_stream();
+                match respond.send_response(Response::from_parts(parts, ()), end_of_stream) {
+                    Ok(client_body) if !end_of_stream => {
+                        relay_body(response_body, client_body, rx).await
+                    }
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => {
+                self.refuse(&mut respond, &e);
+                Err(e)
+            }
+        };
+
+        request_relay.abort();
+        Ok(result?)
+    }
+
+    /// Answers the client after the backend failed a call before responding
+    fn refuse(&self, respond: &mut SendResponse<Bytes>, error: &h2::Error) {
+        if error.is_io() || error.is_go_away() {
+            self.mark_unhealthy();
+        }
+        match error.reason() {
+            // Pass through resets such as REFUSED_STREAM so clients may retry
+            Some(reason) if error.is_reset() && error.is_remote() => respond.send_reset(reason),
+            _ => {
+                let response = crate::protocols::grpc::trailers_only_head(
+                    crate::protocols::grpc::GRPC_STATUS_UNAVAILABLE,
+                    &error.to_string(),
+                );
+                let _ = respond.send_response(response, true);
+            }
+        }
+    }
+}
+
+impl Drop for GrpcStream {
+    fn drop(&mut self) {
+        self.pool.release_stream(&self.host, self.channel_id);
+    }
+}
+
+/// Copies DATA and trailers from `from` to `to`
+///
+/// Received data is acknowledged only once it has been handed to `to`, so a
+/// slow reader on one side applies backpressure to the other. A reset of
+/// `from` is passed on to `to`.
+async fn relay_body(
+    mut from: RecvStream,
+    mut to: SendStream<Bytes>,
+    counter: Option<IntCounter>,
+) -> Result<(), h2::Error> {
+    while let Some(chunk) = from.data().await {
+        let mut chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                to.send_reset(e.reason().unwrap_or(Reason::CANCEL));
+                return Err(e);
+            }
+        };
+        let len = chunk.len();
+
+        while !chunk.is_empty() {
+            to.reserve_capacity(chunk.len());
+            let capacity = futures::future::poll_fn(|cx| to.poll_capacity(cx))
+                .await
+                .ok_or(Reason::STREAM_CLOSED)??;
+            to.send_data(chunk.split_to(capacity.min(chunk.len())), false)?;
+        }
+
+        let _ = from.flow_control().release_capacity(len);
+        if let Some(ref counter) = counter {
+            counter.inc_by(len as u64);
+        }
+    }
+
+    match from.trailers().await {
+        Ok(Some(trailers)) => to.send_trailers(trailers),
+        Ok(None) => to.send_data(Bytes::new(), true),
+        Err(e) => {
+            to.send_reset(e.reason().unwrap_or(Reason::CANCEL));
+            Err(e)
+        }
+    }
+}
+
+/// Calls `grpc.health.v1.Health/Check` for the server as a whole
+async fn health_check(sender: SendRequest<Bytes>, host: &str) -> Result<(), GrpcPoolError> {
+    let request = Request::post(format!("http://{}{}", host, HEALTH_CHECK_PATH))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())
+        .map_err(|e| GrpcPoolError::HealthCheck(e.to_string()))?;
+
+    let mut sender = sender.ready().await?;
+    let (response, mut request_body) = sender.send_request(request, false)?;
+    // An empty HealthCheckRequest: uncompressed, zero length
+    request_body.send_data(Bytes::from_static(&[0; 5]), true)?;
+
+    let response = response.await?;
+    if response.status() != StatusCode::OK {
+        return Err(GrpcPoolError::HealthCheck(format!(
+            "HTTP status {}",
+            response.status()
+        )));
+    }
+
+    let (parts, mut body) = response.into_parts();
+    let mut message = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        message.extend_from_slice(&chunk);
+        if message.len() > MAX_HEALTH_RESPONSE_SIZE {
+            return Err(GrpcPoolError::HealthCheck("response too large".to_string()));
+        }
+    }
+    let trailers = body.trailers().await?;
+
+    // Trailers-Only responses carry grpc-status in the headers
+    let grpc_status = trailers
+        .as_ref()
+        .and_then(|trailers| trailers.get("grpc-status"))
+        .or_else(|| parts.headers.get("grpc-status"))
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    match grpc_status {
+        "0" => {}
+        GRPC_STATUS_UNIMPLEMENTED => return Ok(()),
+        status => {
+            return Err(GrpcPoolError::HealthCheck(format!(
+                "grpc-status {:?}",
+                status
+            )));
+        }
+    }
+
+    match serving_status(&message) {
+        Some(SERVING) => Ok(()),
+        Some(status) => Err(GrpcPoolError::HealthCheck(format!(
+            "serving status {}",
+            status
+        ))),
+        None => Err(GrpcPoolError::HealthCheck(
+            "malformed HealthCheckResponse".to_string(),
+        )),
+    }
+}
+
+/// Reads `status` (field 1) from a length-prefixed HealthCheckResponse
+///
+/// A missing field is the protobuf default, UNKNOWN (0).
+fn serving_status(message: &[u8]) -> Option<u64> {
+    let (&compressed, rest) = message.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let mut fields = rest.get(4..4 + len)?;
+
+    let mut status = 0;
+    while !fields.is_empty() {
+        let key = read_varint(&mut fields)?;
+        match key & 0x7 {
+            0 => {
+                let value = read_varint(&mut fields)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            }
+            1 => fields = fields.get(8..)?,
+            2 => {
+                let len = read_varint(&mut fields)? as usize;
+                fields = fields.get(len..)?;
+            }
+            5 => fields = fields.get(4..)?,
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+/// Reads a protobuf base-128 varint
+fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
 }
 
 /// Statistics about the gRPC connection pool
 #[derive(Debug, Clone)]
 pub struct GrpcPoolStats {
     pub total_channels: usize,
+    pub active_streams: usize,
     pub hosts: usize,
     pub enabled: bool,
 }
@@ -425,18 +919,52 @@ pub struct GrpcPoolStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hyper::{HeaderMap, Response};
+    use std::sync::atomic::AtomicBool;
     use tokio::net::TcpListener;
 
-    async fn create_test_connection() -> (TcpStream, TcpStream) {
+    /// Starts an HTTP/2 backend answering every call with a HealthCheckResponse
+    async fn start_backend(serving: Arc<AtomicBool>) -> String {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let serving = serving.clone();
+                tokio::spawn(async move {
+                    let Ok(mut connection) = h2::server::handshake(stream).await else {
+                        return;
+                    };
+                    while let Some(Ok((_request, mut respond))) = connection.accept().await {
+                        let status = if serving.load(Ordering::SeqCst) { 1 } else { 2 };
+                        let response = Response::builder()
+                            .header("content-type", "application/grpc")
+                            .body(())
+                            .unwrap();
+                        let mut body = respond.send_response(response, false).unwrap();
+                        body.send_data(Bytes::from(vec![0, 0, 0, 0, 2, 0x08, status]), false)
+                            .unwrap();
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert("grpc-status", "0".parse().unwrap());
+                        body.send_trailers(trailers).unwrap();
+                    }
+                });
+            }
+        });
 
-        let client_fut = TcpStream::connect(addr);
-        let server_fut = listener.accept();
+        addr
+    }
 
-        let (client_result, server_result) = tokio::join!(client_fut, server_fut);
+    async fn test_sender() -> SendRequest<Bytes> {
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (sender, connection) = h2::client::handshake(stream).await.unwrap();
+        tokio::spawn(connection);
+        sender
+    }
 
-        (client_result.unwrap(), server_result.unwrap().0)
+    fn test_pool(config: GrpcPoolConfig) -> Arc<GrpcConnectionPool> {
+        Arc::new(GrpcConnectionPool::new(config))
     }
 
     #[tokio::test]
@@ -445,68 +973,145 @@ mod tests {
             enabled: false,
             ..Default::default()
         };
-        let pool = GrpcConnectionPool::new(config);
+        let pool = test_pool(config);
 
-        let (stream, _) = create_test_connection().await;
-
-        // Should not accept channels when disabled
-        assert!(!pool.put("grpc.example.com".to_string(), stream));
-
-        // Should not return channels when disabled
-        assert!(pool.get("grpc.example.com").is_none());
+        // Should not hand out streams when disabled
+        assert!(matches!(
+            pool.acquire("127.0.0.1:1").await,
+            Err(GrpcPoolError::Disabled)
+        ));
     }
 
     #[tokio::test]
     async fn test_grpc_pool_basic() {
-        let config = GrpcPoolConfig {
-            enabled: true,
-            max_channels_per_host: 10,
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig {
+            max_channels_per_host: 1,
+            max_concurrent_streams: 2,
             ..Default::default()
-        };
-        let pool = GrpcConnectionPool::new(config);
+        });
+
+        // Both calls share one channel
+        let first = pool.acquire(&addr).await.unwrap();
+        let second = pool.acquire(&addr).await.unwrap();
+        assert_eq!(first.channel_id(), second.channel_id());
+        assert_eq!(pool.stats().active_streams, 2);
+
+        // The channel is full and no other may be opened
+        assert!(matches!(
+            pool.acquire(&addr).await,
+            Err(GrpcPoolError::Exhausted(_))
+        ));
+
+        // Finishing a call frees its slot
+        drop(first);
+        let third = pool.acquire(&addr).await.unwrap();
+        assert_eq!(third.channel_id(), second.channel_id());
+        assert_eq!(pool.stats().total_channels, 1);
+    }
 
-        let (stream, _) = create_test_connection().await;
+    #[tokio::test]
+    async fn test_grpc_pool_max_channels() {
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig {
+            max_channels_per_host: 2,
+            max_concurrent_streams: 1,
+            ..Default::default()
+        });
+
+        let first = pool.acquire(&addr).await.unwrap();
+        let second = pool.acquire(&addr).await.unwrap();
+        assert_ne!(first.channel_id(), second.channel_id());
+
+        // Should refuse a third channel (pool full)
+        assert!(matches!(
+            pool.acquire(&addr).await,
+            Err(GrpcPoolError::Exhausted(_))
+        ));
+        assert_eq!(pool.stats().total_channels, 2);
+    }
 
-        // Put channel in pool
-        assert!(pool.put("grpc.example.com".to_string(), stream));
+    #[tokio::test]
+    async fn test_grpc_pool_send_request() {
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig::default());
+
+        let mut stream = pool.acquire(&addr).await.unwrap();
+        let request = Request::post(format!("http://{}/helloworld.Greeter/SayHello", addr))
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        let (response, _) = stream.send_request(request, true).await.unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let mut message = Vec::new();
+        while let Some(chunk) = body.data().await {
+            message.extend_from_slice(&chunk.unwrap());
+        }
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(message, [0, 0, 0, 0, 2, 0x08, 1]);
+    }
 
-        // get() should return the channel stream for reuse
-        let extracted = pool.get("grpc.example.com");
-        assert!(extracted.is_some(), "Should extract channel from pool");
+    #[tokio::test]
+    async fn test_grpc_pool_connect_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
 
-        // After extraction, pool should be empty for this host
-        assert!(
-            pool.get("grpc.example.com").is_none(),
-            "Pool should be empty after extraction"
-        );
+        let pool = test_pool(GrpcPoolConfig {
+            max_channels_per_host: 1,
+            ..Default::default()
+        });
+        assert!(matches!(
+            pool.acquire(&addr).await,
+            Err(GrpcPoolError::Connect(..))
+        ));
+
+        // The failed attempt does not hold on to the channel permit
+        assert!(matches!(
+            pool.acquire(&addr).await,
+            Err(GrpcPoolError::Connect(..))
+        ));
     }
 
     #[tokio::test]
-    async fn test_grpc_pool_max_channels() {
-        let config = GrpcPoolConfig {
-            enabled: true,
-            max_channels_per_host: 2,
-            ..Default::default()
-        };
-        let pool = GrpcConnectionPool::new(config);
+    async fn test_grpc_pool_mark_unhealthy() {
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig::default());
 
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
-        let (stream3, _) = create_test_connection().await;
+        let first = pool.acquire(&addr).await.unwrap();
+        first.mark_unhealthy();
 
-        // Should accept first two
-        assert!(pool.put("grpc.example.com".to_string(), stream1));
-        assert!(pool.put("grpc.example.com".to_string(), stream2));
+        // New calls go to a fresh channel while the old one drains
+        let second = pool.acquire(&addr).await.unwrap();
+        assert_ne!(first.channel_id(), second.channel_id());
+        assert_eq!(pool.stats().total_channels, 2);
 
-        // Should reject third (pool full)
-        assert!(!pool.put("grpc.example.com".to_string(), stream3));
+        drop(first);
+        assert_eq!(pool.stats().total_channels, 1);
     }
 
     #[tokio::test]
-    async fn test_grpc_channel_expiration() {
-        let (stream, _) = create_test_connection().await;
+    async fn test_grpc_pool_health_check() {
+        let serving = Arc::new(AtomicBool::new(true));
+        let addr = start_backend(serving.clone()).await;
+        let pool = test_pool(GrpcPoolConfig::default());
+
+        drop(pool.acquire(&addr).await.unwrap());
+        pool.check_health().await;
+        assert_eq!(pool.stats().total_channels, 1);
+
+        serving.store(false, Ordering::SeqCst);
+        pool.check_health().await;
+        assert_eq!(pool.stats().total_channels, 0);
+    }
 
-        let mut channel = GrpcChannel::new(stream);
+    #[tokio::test]
+    async fn test_grpc_channel_expiration() {
+        let mut channel = GrpcChannel::new(0, test_sender().await);
 
         // Should not be expired initially
         assert!(!channel.is_expired(Duration::from_secs(10)));
@@ -520,9 +1125,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_grpc_channel_can_accept_stream() {
-        let (stream, _) = create_test_connection().await;
-
-        let mut channel = GrpcChannel::new(stream);
+        let mut channel = GrpcChannel::new(0, test_sender().await);
 
         // Should accept streams initially
         assert!(channel.can_accept_stream(10));
@@ -543,50 +1146,60 @@ mod tests {
 
     #[tokio::test]
     async fn test_grpc_pool_cleanup() {
-        let config = GrpcPoolConfig {
-            enabled: true,
-            channel_ttl: 1,
-            idle_timeout: 60,
+        let addr = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig {
+            channel_ttl: 10,
+            max_concurrent_streams: 1,
             ..Default::default()
-        };
-        let pool = GrpcConnectionPool::new(config);
-
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
-
-        pool.put("grpc1.example.com".to_string(), stream1);
-        pool.put("grpc2.example.com".to_string(), stream2);
+        });
 
-        // Wait for expiration
-        tokio::time::sleep(Duration::from_secs(2)).await;
+        let busy = pool.acquire(&addr).await.unwrap();
+        let idle = pool.acquire(&addr).await.unwrap();
+        drop(idle);
+        for channel in pool.pools.get_mut(&addr).unwrap().channels.iter_mut() {
+            channel.created_at = Instant::now() - Duration::from_secs(11);
+        }
 
-        // Cleanup should remove both
+        // Expired channels are kept while calls are in progress
         pool.cleanup();
+        assert_eq!(pool.stats().total_channels, 1);
 
+        drop(busy);
+        pool.cleanup();
         let stats = pool.stats();
         assert_eq!(stats.total_channels, 0);
+        assert_eq!(stats.hosts, 0);
     }
 
     #[tokio::test]
     async fn test_grpc_pool_stats() {
-        let config = GrpcPoolConfig {
-            enabled: true,
-            ..Default::default()
-        };
-        let pool = GrpcConnectionPool::new(config);
-
-        let (stream1, _) = create_test_connection().await;
-        let (stream2, _) = create_test_connection().await;
+        let addr1 = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let addr2 = start_backend(Arc::new(AtomicBool::new(true))).await;
+        let pool = test_pool(GrpcPoolConfig::default());
 
-        pool.put("grpc1.example.com".to_string(), stream1);
-        pool.put("grpc2.example.com".to_string(), stream2);
+        let _call1 = pool.acquire(&addr1).await.unwrap();
+        drop(pool.acquire(&addr2).await.unwrap());
 
         let stats = pool.stats();
         assert_eq!(stats.total_channels, 2);
+        assert_eq!(stats.active_streams, 1);
         assert_eq!(stats.hosts, 2);
         assert!(stats.enabled);
     }
 
+    #[test]
+    fn test_serving_status() {
+        assert_eq!(serving_status(&[0, 0, 0, 0, 2, 0x08, 1]), Some(SERVING));
+        assert_eq!(serving_status(&[0, 0, 0, 0, 2, 0x08, 2]), Some(2));
+        // Unknown fields are skipped; a missing status is UNKNOWN
+        assert_eq!(serving_status(&[0, 0, 0, 0, 3, 0x12, 1, b'x']), Some(0));
+        assert_eq!(serving_status(&[0, 0, 0, 0, 0]), Some(0));
+        // Compressed or truncated messages are rejected
+        assert_eq!(serving_status(&[1, 0, 0, 0, 2, 0x08, 1]), None);
+        assert_eq!(serving_status(&[0, 0, 0, 0, 3, 0x08, 1]), None);
+        assert_eq!(serving_status(&[0, 0, 0]), None);
+    }
+
     #[test]
     fn test_grpc_pool_config_default() {
         let config = GrpcPoolConfig::default();
@@ -596,5 +1209,6 @@ mod tests {
         assert!(config.enabled);
         assert_eq!(config.max_concurrent_streams, 100);
         assert_eq!(config.health_check_interval, 30);
+        assert_eq!(config.connect_timeout, 10);
     }
 }
@@ -0,0 +1,546 @@
+//! HPACK header decoding (RFC 7541)
+//!
+//! HTTP/2 header blocks are compressed against a static table of common
+//! fields and a per-connection dynamic table that both peers update as
+//! blocks are processed. The proxy decodes client requests to route h2c
+//! connections, so this module implements the decoder side, plus plain
+//! literals for the few responses the proxy generates itself.
+//!
+//! A decoder must see every header block of the connection in order, since
+//! literals with incremental indexing change the dynamic table.
+//!
+//! # Example
+//!
+//! ```
+//! use sniproxy_core::hpack::HpackDecoder;
+//!
+//! let mut decoder = HpackDecoder::new(4096);
+//! // :method GET, :scheme http, :path /, :authority www.example.com
+//! let block = b"\x82\x86\x84\x41\x0fwww.example.com";
+//! let headers = decoder.decode(block).unwrap();
+//! assert_eq!(headers[3], (":authority".to_string(), "www.example.com".to_string()));
+//! ```
+
+use crate::huffman::{self, HuffmanError};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Default SETTINGS_HEADER_TABLE_SIZE (RFC 9113 §6.5.2)
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// Upper bound on the decoded size of one header list, counted like
+/// SETTINGS_MAX_HEADER_LIST_SIZE (name + value + 32 per field)
+pub const MAX_HEADER_LIST_SIZE: usize = 65536;
+
+/// Overhead added to every entry when computing table size (RFC 7541 §4.1)
+const ENTRY_OVERHEAD: usize = 32;
+
+/// HPACK static table (RFC 7541 Appendix A), 1-based on the wire
+pub const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Why a header block could not be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HpackError {
+    /// The block ends in the middle of a representation
+    Truncated,
+    /// An integer does not fit in 64 bits
+    IntegerOverflow,
+    /// Index 0, or beyond the static and dynamic tables
+    InvalidIndex(u64),
+    /// A dynamic table size update above the allowed maximum, or after the
+    /// first field of the block
+    InvalidTableSizeUpdate(u64),
+    /// A Huffman-coded string is malformed
+    Huffman(HuffmanError),
+    /// The decoded header list exceeds `MAX_HEADER_LIST_SIZE`
+    HeaderListTooLarge,
+}
+
+impl fmt::Display for HpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HpackError::Truncated => write!(f, "truncated header block"),
+            HpackError::IntegerOverflow => write!(f, "integer too large"),
+            HpackError::InvalidIndex(index) => write!(f, "invalid table index {}", index),
+            HpackError::InvalidTableSizeUpdate(size) => {
+                write!(f, "invalid dynamic table size update to {}", size)
+            }
+            HpackError::Huffman(e) => write!(f, "{}", e),
+            HpackError::HeaderListTooLarge => write!(f, "header list too large"),
+        }
+    }
+}
+
+impl std::error::Error for HpackError {}
+
+impl From<HuffmanError> for HpackError {
+    fn from(err: HuffmanError) -> Self {
+        HpackError::Huffman(err)
+    }
+}
+
+/// HPACK decoder for one direction of an HTTP/2 connection
+#[derive(Debug)]
+pub struct HpackDecoder {
+    /// Dynamic table, newest entry first
+    entries: VecDeque<(String, String)>,
+    size: usize,
+    capacity: usize,
+    /// SETTINGS_HEADER_TABLE_SIZE advertised to the encoder
+    max_capacity: usize,
+}
+
+impl HpackDecoder {
+    /// Creates a decoder whose dynamic table may grow to `max_table_size`
+    /// octets, the SETTINGS_HEADER_TABLE_SIZE sent to the peer
+    pub fn new(max_table_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            capacity: max_table_size,
+            max_capacity: max_table_size,
+        }
+    }
+
+    /// Current dynamic table size in octets
+    pub fn table_size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of entries in the dynamic table
+    pub fn table_len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Decodes a complete header block (HEADERS plus any CONTINUATION
+    /// fragments), returning the fields in order
+    ///
+    /// Names and values that are not valid UTF-8 are converted lossily.
+    ///
+    /// # Errors
+    ///
+    /// Any error is a COMPRESSION_ERROR; the dynamic table is left in an
+    /// unspecified state and the connection cannot be decoded further.
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut reader = Reader {
+            data: block,
+            pos: 0,
+        };
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+
+        while let Some(&first) = block.get(reader.pos) {
+            let field = if first & 0x80 != 0 {
+                // Indexed Header Field
+                let index = reader.int(7)?;
+                self.lookup(index)?
+            } else if first & 0x40 != 0 {
+                // Literal Header Field with Incremental Indexing
+                let field = self.literal(&mut reader, 6)?;
+                self.insert(field.clone());
+                field
+            } else if first & 0x20 != 0 {
+                // Dynamic Table Size Update, only before the first field
+                let size = reader.int(5)?;
+                if !fields.is_empty() || size > self.max_capacity as u64 {
+                    return Err(HpackError::InvalidTableSizeUpdate(size));
+                }
+                self.capacity = size as usize;
+                self.evict(0);
+                continue;
+            } else {
+                // Literal Header Field without Indexing or Never Indexed
+                self.literal(&mut reader, 4)?
+            };
+
+            list_size += field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+            if list_size > MAX_HEADER_LIST_SIZE {
+                return Err(HpackError::HeaderListTooLarge);
+            }
+            fields.push(field);
+        }
+
+        Ok(fields)
+    }
+
+    /// Reads a literal whose name is indexed with an N-bit prefix, or
+    /// follows as a string when the index is 0
+    fn literal(
+        &self,
+        reader: &mut Reader<'_>,
+        prefix_bits: u8,
+    ) -> Result<(String, String), HpackError> {
+        let index = reader.int(prefix_bits)?;
+        let name = if index == 0 {
+            reader.string()?
+        } else {
+            self.lookup(index)?.0
+        };
+        Ok((name, reader.string()?))
+    }
+
+    fn lookup(&self, index: u64) -> Result<(String, String), HpackError> {
+        let position = usize::try_from(index).map_err(|_| HpackError::InvalidIndex(index))?;
+        if position == 0 {
+            return Err(HpackError::InvalidIndex(index));
+        }
+        if let Some(&(name, value)) = STATIC_TABLE.get(position - 1) {
+            return Ok((name.to_string(), value.to_string()));
+        }
+        self.entries
+            .get(position - 1 - STATIC_TABLE.len())
+            .cloned()
+            .ok_or(HpackError::InvalidIndex(index))
+    }
+
+    fn insert(&mut self, field: (String, String)) {
+        let size = field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+        // An entry larger than the table empties it and is not added
+        if size <= self.capacity {
+            self.entries.push_front(field);
+            self.size += size;
+        }
+    }
+
+    /// Evicts the oldest entries until `incoming` more octets fit
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.capacity {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for HpackDecoder {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+/// Appends a Literal Header Field without Indexing with a literal name and
+/// no Huffman coding, which leaves the peer's dynamic table untouched
+pub fn encode_literal(name: &str, value: &str, out: &mut Vec<u8>) {
+    out.push(0x00);
+    for string in [name, value] {
+        encode_int(out, 7, 0x00, string.len() as u64);
+        out.extend_from_slice(string.as_bytes());
+    }
+}
+
+/// Appends an integer with an N-bit prefix, `flags` filling the high bits
+fn encode_int(out: &mut Vec<u8>, prefix_bits: u8, flags: u8, value: u64) {
+    let max = (1u64 << prefix_bits) - 1;
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        out.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.push(rest as u8);
+}
+
+/// Cursor over a header block
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, HpackError> {
+        let byte = *self.data.get(self.pos).ok_or(HpackError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Reads an integer with an N-bit prefix (RFC 7541 §5.1)
+    fn int(&mut self, prefix_bits: u8) -> Result<u64, HpackError> {
+        let max = (1u64 << prefix_bits) - 1;
+        let mut value = (self.byte()? as u64) & max;
+        if value < max {
+            return Ok(value);
+        }
+
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 56 {
+                return Err(HpackError::IntegerOverflow);
+            }
+            value = value
+                .checked_add(((byte & 0x7f) as u64) << shift)
+                .ok_or(HpackError::IntegerOverflow)?;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Reads a string literal (RFC 7541 §5.2)
+    fn string(&mut self) -> Result<String, HpackError> {
+        let huffman_coded = *self.data.get(self.pos).ok_or(HpackError::Truncated)? & 0x80 != 0;
+        let len = self.int(7)?;
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(HpackError::Truncated);
+        }
+
+        let raw = &self.data[self.pos..self.pos + len as usize];
+        self.pos += len as usize;
+        Ok(if huffman_coded {
+            String::from_utf8_lossy(&huffman::decode(raw)?).into_owned()
+        } else {
+            String::from_utf8_lossy(raw).into_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// RFC 7541 C.3 and C.4 decode to the same requests
+    fn assert_request_sequence(blocks: [&str; 3]) {
+        let mut decoder = HpackDecoder::default();
+
+        let headers = decoder.decode(&hex(blocks[0])).unwrap();
+        assert_eq!(
+            headers,
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 57);
+
+        let headers = decoder.decode(&hex(blocks[1])).unwrap();
+        assert_eq!(
+            headers,
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 110);
+
+        let headers = decoder.decode(&hex(blocks[2])).unwrap();
+        assert_eq!(
+            headers,
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 164);
+        assert_eq!(decoder.table_len(), 3);
+    }
+
+    #[test]
+    fn test_rfc7541_requests_without_huffman() {
+        assert_request_sequence([
+            "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+            "8286 84be 5808 6e6f 2d63 6163 6865",
+            "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65",
+        ]);
+    }
+
+    #[test]
+    fn test_rfc7541_requests_with_huffman() {
+        assert_request_sequence([
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ]);
+    }
+
+    #[test]
+    fn test_rfc7541_eviction() {
+        // RFC 7541 C.5.1 and C.5.2 with a 256-octet table
+        let mut decoder = HpackDecoder::new(256);
+        decoder
+            .decode(&hex(
+                "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230
+                 3133 2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65
+                 7861 6d70 6c65 2e63 6f6d",
+            ))
+            .unwrap();
+        assert_eq!(decoder.table_size(), 222);
+
+        let headers = decoder.decode(&hex("4803 3330 37c1 c0bf")).unwrap();
+        assert_eq!(
+            headers,
+            fields(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 222);
+        assert_eq!(decoder.table_len(), 4);
+    }
+
+    #[test]
+    fn test_table_size_update() {
+        let mut decoder = HpackDecoder::default();
+        decoder.decode(&hex("4103 6162 63")).unwrap();
+        assert_eq!(decoder.table_len(), 1);
+
+        // Shrinking to 0 empties the table
+        assert_eq!(
+            decoder.decode(&hex("20 82")).unwrap(),
+            fields(&[(":method", "GET")])
+        );
+        assert_eq!(decoder.table_len(), 0);
+
+        // Updates after a field or above the advertised maximum are rejected
+        assert_eq!(
+            decoder.decode(&hex("82 20")),
+            Err(HpackError::InvalidTableSizeUpdate(0))
+        );
+        assert_eq!(
+            decoder.decode(&hex("3fe2 1f")),
+            Err(HpackError::InvalidTableSizeUpdate(4097))
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder = HpackDecoder::default();
+        assert_eq!(decoder.decode(&hex("80")), Err(HpackError::InvalidIndex(0)));
+        assert_eq!(
+            decoder.decode(&hex("be")),
+            Err(HpackError::InvalidIndex(62))
+        );
+        assert_eq!(
+            decoder.decode(&hex("410f 7777")),
+            Err(HpackError::Truncated)
+        );
+        assert_eq!(decoder.decode(&hex("ff")), Err(HpackError::Truncated));
+        assert_eq!(
+            decoder.decode(&hex("ffff ffff ffff ffff ffff 01")),
+            Err(HpackError::IntegerOverflow)
+        );
+        assert_eq!(
+            decoder.decode(&hex("0181 ff")),
+            Err(HpackError::Huffman(HuffmanError::InvalidPadding))
+        );
+    }
+
+    #[test]
+    fn test_encode_literal() {
+        let mut block = vec![0x88];
+        encode_literal("grpc-status", "7", &mut block);
+        encode_literal("x-long", &"v".repeat(200), &mut block);
+        assert_eq!(&block[1..5], &[0x00, 0x0b, b'g', b'r']);
+
+        let mut decoder = HpackDecoder::default();
+        let headers = decoder.decode(&block).unwrap();
+        assert_eq!(headers[1], ("grpc-status".to_string(), "7".to_string()));
+        assert_eq!(headers[2].1.len(), 200);
+        assert_eq!(decoder.table_len(), 0);
+    }
+
+    #[test]
+    fn test_header_list_size_limit() {
+        // One large entry referenced over and over
+        let mut block = vec![0x40, 0x01, b'x', 0x7f, 0xa1, 0x1e];
+        block.extend(std::iter::repeat_n(b'a', 4000));
+        block.extend(std::iter::repeat_n(0xbe, 20));
+
+        let mut decoder = HpackDecoder::default();
+        assert_eq!(decoder.decode(&block), Err(HpackError::HeaderListTooLarge));
+    }
+}
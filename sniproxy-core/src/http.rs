@@ -1,27 +1,38 @@
+use crate::hpack::HpackDecoder;
 use base64::{Engine as _, engine::general_purpose};
 use prometheus::IntCounter;
 use sha1::{Digest, Sha1};
 use std::error::Error;
 use std::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::time::{Duration, timeout};
 
 // Performance tuning constants
 const READ_BUFFER_SIZE: usize = 16384; // 16KB for better throughput
-const COPY_BUFFER_SIZE: usize = 32768; // 32KB for bidirectional copy
+const MAX_REQUEST_HEAD_SIZE: usize = 65536; // Request or response line + headers
+const MAX_CHUNK_LINE_SIZE: usize = 4096; // Chunk size line or trailer line
 
 // Constants for HTTP protocol detection
-const WEBSOCKET_UPGRADE: &str = "websocket";
-const SWITCHING_PROTOCOLS: &[u8] = b"HTTP/1.1 101";
-const CONTENT_TYPE_HEADER: &str = "content-type:";
 const GRPC_CONTENT_TYPE: &str = "application/grpc";
 
 // WebSocket handshake constant (RFC 6455)
 const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
-// HTTP/2 frame type constants
+// HTTP/2 frame constants (RFC 9113 §6)
+const HTTP2_FRAME_HEADER_LEN: usize = 9;
+const HTTP2_MAX_FRAME_SIZE: usize = 16384; // Default SETTINGS_MAX_FRAME_SIZE
+const MAX_HTTP2_HEAD_SIZE: usize = 65536; // Frames read before the first header block ends
 const HTTP2_FRAME_TYPE_HEADERS: u8 = 0x1;
+const HTTP2_FRAME_TYPE_PRIORITY: u8 = 0x2;
+const HTTP2_FRAME_TYPE_SETTINGS: u8 = 0x4;
+const HTTP2_FRAME_TYPE_PING: u8 = 0x6;
+const HTTP2_FRAME_TYPE_WINDOW_UPDATE: u8 = 0x8;
+const HTTP2_FRAME_TYPE_CONTINUATION: u8 = 0x9;
+const HTTP2_FLAG_END_HEADERS: u8 = 0x4;
+const HTTP2_FLAG_PADDED: u8 = 0x8;
+const HTTP2_FLAG_PRIORITY: u8 = 0x20;
 
 #[derive(Debug)]
 #[allow(dead_code)] // Some variants reserved for future protocol detection features
@@ -33,6 +44,9 @@ pub enum HttpError {
     Http2FrameError,
     GrpcDetectionFailed,
     Timeout,
+    RequestDenied(String),
+    /// The backend closed its connection without sending a response
+    UpstreamClosed,
 }
 
 impl std::fmt::Display for HttpError {
@@ -45,6 +59,10 @@ impl std::fmt::Display for HttpError {
             HttpError::Http2FrameError => write!(f, "HTTP/2 frame parsing error"),
             HttpError::GrpcDetectionFailed => write!(f, "gRPC detection failed"),
             HttpError::Timeout => write!(f, "Operation timed out"),
+            HttpError::RequestDenied(reason) => write!(f, "Request denied: {}", reason),
+            HttpError::UpstreamClosed => {
+                write!(f, "Backend closed the connection without a response")
+            }
         }
     }
 }
@@ -63,154 +81,791 @@ impl From<tokio::time::error::Elapsed> for HttpError {
     }
 }
 
+/// Builds a complete HTTP/1.1 error response for the proxy to send itself
+///
+/// The body is rendered from `template`, replacing `{status}`, `{reason}`,
+/// `{host}` and `{message}`. Substituted values are HTML-escaped when the
+/// content type is HTML, since `host` comes straight from the client.
+///
+/// # Arguments
+/// * `status` - HTTP status code (403, 421, 502 or 504)
+/// * `host` - The requested host, if known
+/// * `message` - Human-readable explanation
+/// * `template` - Body template
+/// * `content_type` - Content-Type of the rendered body
+pub fn error_response(
+    status: u16,
+    host: &str,
+    message: &str,
+    template: &str,
+    content_type: &str,
+) -> Vec<u8> {
+    let reason = status_reason(status);
+    let escape = content_type.to_ascii_lowercase().contains("html");
+    let render = |value: &str| {
+        if escape {
+            escape_html(value)
+        } else {
+            value.to_string()
+        }
+    };
+
+    let body = template
+        .replace("{status}", &status.to_string())
+        .replace("{reason}", reason)
+        .replace("{host}", &render(host))
+        .replace("{message}", &render(message));
+
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body.as_bytes());
+    response
+}
+
+/// Returns the reason phrase for the status codes the proxy generates
 #[inline]
-pub async fn extract_host(
-    stream: &mut TcpStream,
-    buffer: &mut Vec<u8>,
-) -> Result<(String, usize), HttpError> {
-    let mut total_read = 0;
-    loop {
-        let mut chunk = [0; READ_BUFFER_SIZE];
-        let n = stream.read(&mut chunk).await?;
-        if n == 0 {
+fn status_reason(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        403 => "Forbidden",
+        421 => "Misdirected Request",
+        502 => "Bad Gateway",
+        504 => "Gateway Timeout",
+        _ => "Error",
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Rewrites one header in every HTTP/1.x request of a client stream
+///
+/// Follows request framing (Content-Length and chunked bodies) so that each
+/// request's header block is rewritten, not only the first one on a keep-alive
+/// connection. Any client-supplied instance of the header is removed and the
+/// proxy's value, if any, is inserted after the request line. After an
+/// Upgrade or CONNECT request the rest of the stream passes through untouched.
+pub struct RequestHeaderRewriter {
+    header: String,
+    value: Option<String>,
+    state: RequestState,
+    buffer: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequestState {
+    Head,
+    Body(u64),
+    ChunkSize,
+    ChunkData(u64),
+    Trailers,
+    Raw,
+}
+
+impl RequestHeaderRewriter {
+    /// Creates a rewriter for `header`; control characters are removed from `value`
+    pub fn new(header: impl Into<String>, value: Option<&str>) -> Self {
+        Self {
+            header: header.into(),
+            value: value.map(|v| v.chars().filter(|c| !c.is_control()).collect()),
+            state: RequestState::Head,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Consumes client bytes and appends the rewritten stream to `out`
+    ///
+    /// Incomplete header blocks and chunk lines are buffered until the rest arrives.
+    pub fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), HttpError> {
+        if self.state == RequestState::Raw && self.buffer.is_empty() {
+            out.extend_from_slice(input);
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(input);
+        let mut pos = 0;
+
+        while pos < self.buffer.len() {
+            let available = &self.buffer[pos..];
+            match self.state {
+                RequestState::Head => {
+                    let Some(end) = find_headers_end(available) else {
+                        if available.len() > MAX_REQUEST_HEAD_SIZE {
+                            return Err(HttpError::InvalidRequest);
+                        }
+                        break;
+                    };
+                    self.state = rewrite_request_head(
+                        &available[..end],
+                        &self.header,
+                        self.value.as_deref(),
+                        out,
+                    )?;
+                    pos += end;
+                }
+                RequestState::Body(remaining) | RequestState::ChunkData(remaining) => {
+                    let take = remaining.min(available.len() as u64) as usize;
+                    out.extend_from_slice(&available[..take]);
+                    pos += take;
+
+                    let remaining = remaining - take as u64;
+                    self.state = match (self.state, remaining) {
+                        (RequestState::Body(_), 0) => RequestState::Head,
+                        (RequestState::Body(_), n) => RequestState::Body(n),
+                        (_, 0) => RequestState::ChunkSize,
+                        (_, n) => RequestState::ChunkData(n),
+                    };
+                }
+                RequestState::ChunkSize | RequestState::Trailers => {
+                    let Some(end) = available
+                        .windows(2)
+                        .position(|w| w == b"\r\n")
+                        .map(|p| p + 2)
+                    else {
+                        if available.len() > MAX_CHUNK_LINE_SIZE {
+                            return Err(HttpError::InvalidRequest);
+                        }
+                        break;
+                    };
+                    let line = &available[..end - 2];
+
+                    self.state = if self.state == RequestState::Trailers {
+                        // An empty line ends the trailer section and the request
+                        if line.is_empty() {
+                            RequestState::Head
+                        } else {
+                            RequestState::Trailers
+                        }
+                    } else {
+                        match parse_chunk_size(line)? {
+                            0 => RequestState::Trailers,
+                            size => RequestState::ChunkData(size + 2), // data + CRLF
+                        }
+                    };
+                    out.extend_from_slice(&available[..end]);
+                    pos += end;
+                }
+                RequestState::Raw => {
+                    out.extend_from_slice(available);
+                    pos = self.buffer.len();
+                }
+            }
+        }
+
+        self.buffer.drain(..pos);
+        Ok(())
+    }
+}
+
+/// Rewrites a complete request header block and returns the framing state of its body
+fn rewrite_request_head(
+    head: &[u8],
+    header: &str,
+    value: Option<&str>,
+    out: &mut Vec<u8>,
+) -> Result<RequestState, HttpError> {
+    let mut lines = head[..head.len() - 4].split(|&b| b == b'\n');
+    let request_line = lines.next().ok_or(HttpError::InvalidRequest)?;
+    let request_line = request_line.strip_suffix(b"\r").unwrap_or(request_line);
+    let is_connect = request_line.starts_with(b"CONNECT ");
+
+    out.extend_from_slice(request_line);
+    out.extend_from_slice(b"\r\n");
+    if let Some(value) = value {
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+
+    let mut content_length: Option<u64> = None;
+    let mut chunked = false;
+    let mut upgrade = false;
+
+    for line in lines {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let Some(colon) = line.iter().position(|&b| b == b':') else {
             return Err(HttpError::InvalidRequest);
+        };
+        let name = std::str::from_utf8(&line[..colon])
+            .map_err(|_| HttpError::InvalidRequest)?
+            .trim();
+        let field_value = String::from_utf8_lossy(&line[colon + 1..]);
+        let field_value = field_value.trim();
+
+        if name.eq_ignore_ascii_case(header) {
+            continue;
+        }
+
+        if name.eq_ignore_ascii_case("content-length") {
+            let length = field_value
+                .parse::<u64>()
+                .map_err(|_| HttpError::InvalidRequest)?;
+            // Conflicting lengths are a request smuggling vector (RFC 9112 §6.3)
+            if content_length.is_some_and(|existing| existing != length) {
+                return Err(HttpError::InvalidRequest);
+            }
+            content_length = Some(length);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = field_value
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+        } else if name.eq_ignore_ascii_case("upgrade") {
+            upgrade = true;
+        }
+
+        out.extend_from_slice(line);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+
+    // Transfer-Encoding overrides Content-Length (RFC 9112 §6.3)
+    Ok(if is_connect || upgrade {
+        RequestState::Raw
+    } else if chunked {
+        RequestState::ChunkSize
+    } else {
+        match content_length {
+            Some(length) if length > 0 => RequestState::Body(length),
+            _ => RequestState::Head,
         }
+    })
+}
+
+/// Parses a chunk size line, ignoring chunk extensions
+fn parse_chunk_size(line: &[u8]) -> Result<u64, HttpError> {
+    let line = std::str::from_utf8(line).map_err(|_| HttpError::InvalidRequest)?;
+    let size = line.split(';').next().unwrap_or_default().trim();
+    u64::from_str_radix(size, 16).map_err(|_| HttpError::InvalidRequest)
+}
+
+/// Transforms the request bytes a client sends, e.g. `RequestHeaderRewriter`
+pub trait RequestFilter {
+    /// Consumes client bytes and appends the bytes to forward to `out`
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), HttpError>;
+}
+
+impl RequestFilter for RequestHeaderRewriter {
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), HttpError> {
+        RequestHeaderRewriter::feed(self, input, out)
+    }
+}
+
+/// A client stream whose requests pass through a `RequestFilter`
+///
+/// Reads return the filtered request stream; writes go straight to the client.
+pub struct RequestRewriteStream<S, F = RequestHeaderRewriter> {
+    inner: S,
+    rewriter: F,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    output_pos: usize,
+}
+
+impl<S, F> RequestRewriteStream<S, F> {
+    pub fn new(inner: S, rewriter: F) -> Self {
+        Self {
+            inner,
+            rewriter,
+            input: vec![0u8; READ_BUFFER_SIZE],
+            output: Vec::new(),
+            output_pos: 0,
+        }
+    }
+}
 
-        buffer.extend_from_slice(&chunk[..n]);
-        total_read += n;
+impl<S: AsyncRead + Unpin, F: RequestFilter + Unpin> AsyncRead for RequestRewriteStream<S, F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.output_pos < this.output.len() {
+                let n = buf.remaining().min(this.output.len() - this.output_pos);
+                buf.put_slice(&this.output[this.output_pos..this.output_pos + n]);
+                this.output_pos += n;
+                return Poll::Ready(Ok(()));
+            }
 
-        if let Some(headers_end) = find_headers_end(buffer) {
-            if let Some(host) = extract_host_header(&buffer[..headers_end]) {
-                return Ok((host, total_read));
+            let mut read_buf = ReadBuf::new(&mut this.input);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            let filled = read_buf.filled();
+            if filled.is_empty() {
+                return Poll::Ready(Ok(()));
             }
-            return Err(HttpError::NoHostHeader);
+
+            this.output.clear();
+            this.output_pos = 0;
+            this.rewriter
+                .feed(filled, &mut this.output)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
         }
+    }
+}
+
+impl<S: AsyncWrite + Unpin, F: Unpin> AsyncWrite for RequestRewriteStream<S, F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
 
-        if total_read > READ_BUFFER_SIZE * 2 {
-            // Limit headers to prevent abuse
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// How the end of an HTTP/1.x message body is found (RFC 9112 §6.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    /// The message has no body
+    Empty,
+    /// The body is `Content-Length` bytes long
+    Length(u64),
+    /// Chunked transfer coding, ending with the trailer section
+    Chunked,
+    /// The body runs until the sender closes the connection (responses only)
+    UntilClose,
+}
+
+/// The parts of an HTTP/1.x request head the proxy acts on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHead {
+    pub method: String,
+    /// Authority of an absolute-form target, else the `Host` header
+    pub host: Option<String>,
+    pub framing: BodyFraming,
+    /// The client may send another request on the connection afterwards
+    pub keep_alive: bool,
+    /// An `Upgrade` or CONNECT request, after which the protocol may switch
+    pub upgrade: bool,
+    /// Asks to switch to WebSocket (`Upgrade: websocket`, `Connection: upgrade`)
+    pub websocket: bool,
+    /// `Sec-WebSocket-Extensions` offered by the client, all fields joined
+    pub websocket_extensions: Option<String>,
+    /// The client waits for `100 Continue` before sending the body
+    pub expect_continue: bool,
+}
+
+/// The parts of an HTTP/1.x response head the proxy acts on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHead {
+    pub status: u16,
+    pub framing: BodyFraming,
+    /// The backend keeps the connection open after this response
+    pub keep_alive: bool,
+    /// `Sec-WebSocket-Accept` of a WebSocket handshake response
+    pub websocket_accept: Option<String>,
+    /// `Sec-WebSocket-Extensions` the backend accepted, all fields joined
+    pub websocket_extensions: Option<String>,
+}
+
+/// Header fields relevant to message framing and connection reuse
+#[derive(Default)]
+struct FramingFields {
+    content_length: Option<u64>,
+    chunked: bool,
+    transfer_encoding: bool,
+    connection_close: bool,
+    connection_keep_alive: bool,
+    connection_upgrade: bool,
+    upgrade: bool,
+    upgrade_websocket: bool,
+    expect_continue: bool,
+    host: Option<String>,
+    websocket_accept: Option<String>,
+    websocket_extensions: Option<String>,
+}
+
+/// Splits a head into its start line and framing fields
+fn parse_head(head: &[u8]) -> Result<(&str, u8, FramingFields), HttpError> {
+    let head = std::str::from_utf8(head).map_err(|_| HttpError::InvalidRequest)?;
+    let mut lines = head.trim_end_matches("\r\n").split("\r\n");
+    let start_line = lines.next().ok_or(HttpError::InvalidRequest)?;
+
+    let mut fields = FramingFields::default();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(HttpError::InvalidRequest)?;
+        // Whitespace before the colon is a smuggling vector (RFC 9112 §5.1)
+        if name.is_empty() || name.ends_with([' ', '\t']) {
             return Err(HttpError::InvalidRequest);
         }
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("content-length") {
+            let length = value
+                .parse::<u64>()
+                .map_err(|_| HttpError::InvalidRequest)?;
+            if fields
+                .content_length
+                .is_some_and(|existing| existing != length)
+            {
+                return Err(HttpError::InvalidRequest);
+            }
+            fields.content_length = Some(length);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            fields.transfer_encoding = true;
+            fields.chunked = value
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+        } else if name.eq_ignore_ascii_case("connection") {
+            for token in value.split(',').map(str::trim) {
+                fields.connection_close |= token.eq_ignore_ascii_case("close");
+                fields.connection_keep_alive |= token.eq_ignore_ascii_case("keep-alive");
+                fields.connection_upgrade |= token.eq_ignore_ascii_case("upgrade");
+            }
+        } else if name.eq_ignore_ascii_case("upgrade") {
+            fields.upgrade = true;
+            fields.upgrade_websocket |= value
+                .split(',')
+                .any(|protocol| protocol.trim().eq_ignore_ascii_case("websocket"));
+        } else if name.eq_ignore_ascii_case("sec-websocket-accept") {
+            fields.websocket_accept = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("sec-websocket-extensions") {
+            // Repeated fields form one list (RFC 6455 §9.1)
+            fields.websocket_extensions = Some(match fields.websocket_extensions.take() {
+                Some(list) => format!("{}, {}", list, value),
+                None => value.to_string(),
+            });
+        } else if name.eq_ignore_ascii_case("expect") {
+            fields.expect_continue = value.eq_ignore_ascii_case("100-continue");
+        } else if name.eq_ignore_ascii_case("host") {
+            // More than one Host header is invalid (RFC 9112 §3.2)
+            if fields.host.is_some() {
+                return Err(HttpError::InvalidRequest);
+            }
+            fields.host = Some(value.to_string());
+        }
     }
+
+    let minor_version = match start_line
+        .split(' ')
+        .find(|part| part.starts_with("HTTP/1."))
+    {
+        Some("HTTP/1.0") => 0,
+        Some(_) => 1,
+        None => return Err(HttpError::InvalidRequest),
+    };
+    Ok((start_line, minor_version, fields))
 }
 
-/// Tunnels an HTTP connection with metrics tracking
-pub async fn tunnel_http(
-    client: &mut TcpStream,
-    initial_data: &[u8],
-    host: &str,
-    port: u16,
-    metrics: Option<(IntCounter, IntCounter)>,
-) -> Result<(), HttpError> {
-    let addr = format!("{}:{}", host, port);
-    let mut server = TcpStream::connect(addr).await?;
-
-    // Forward the initial request
-    server.write_all(initial_data).await?;
-
-    let (mut client_read, mut client_write) = tokio::io::split(client);
-    let (mut server_read, mut server_write) = tokio::io::split(&mut server);
-
-    // If metrics are enabled, use the tracking copy, otherwise use the standard copy
-    if let Some((tx_counter, rx_counter)) = metrics {
-        tokio::try_join!(
-            copy_with_metrics(&mut client_read, &mut server_write, tx_counter),
-            copy_with_metrics(&mut server_read, &mut client_write, rx_counter)
-        )?;
+/// Whether a connection persists after a message (RFC 9112 §9.3)
+fn persists(minor_version: u8, fields: &FramingFields) -> bool {
+    if minor_version == 0 {
+        fields.connection_keep_alive && !fields.connection_close
     } else {
-        tokio::try_join!(
-            tokio::io::copy(&mut client_read, &mut server_write),
-            tokio::io::copy(&mut server_read, &mut client_write)
-        )?;
+        !fields.connection_close
     }
+}
 
-    Ok(())
+/// Parses a complete request head as returned by [`MessageReader::read_head`]
+///
+/// Requests whose framing is ambiguous (conflicting lengths, a length next to
+/// a transfer coding, or a final coding other than chunked) are rejected as
+/// they could smuggle a second request past the proxy.
+pub fn parse_request_head(head: &[u8]) -> Result<RequestHead, HttpError> {
+    let (request_line, minor_version, fields) = parse_head(head)?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().ok_or(HttpError::InvalidRequest)?;
+
+    let framing = match (fields.transfer_encoding, fields.content_length) {
+        (true, Some(_)) => return Err(HttpError::InvalidRequest),
+        (true, None) if !fields.chunked => return Err(HttpError::InvalidRequest),
+        (true, None) => BodyFraming::Chunked,
+        (false, Some(length)) if length > 0 => BodyFraming::Length(length),
+        _ => BodyFraming::Empty,
+    };
+
+    // An absolute-form target overrides Host (RFC 9112 §3.2.2)
+    let host = target
+        .split_once("://")
+        .map(|(_, rest)| {
+            rest.split(['/', '?'])
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .filter(|authority| !authority.is_empty())
+        .or(fields.host.clone());
+
+    Ok(RequestHead {
+        upgrade: fields.upgrade || method == "CONNECT",
+        websocket: fields.upgrade_websocket && fields.connection_upgrade,
+        keep_alive: persists(minor_version, &fields),
+        websocket_extensions: fields.websocket_extensions,
+        expect_continue: fields.expect_continue,
+        method,
+        host,
+        framing,
+    })
 }
 
-/// Tunnels a WebSocket connection with upgrade detection
-pub async fn tunnel_websocket(
-    client: &mut TcpStream,
-    initial_data: &[u8],
-    host: &str,
-    port: u16,
-    metrics: Option<(IntCounter, IntCounter)>,
-) -> Result<(), HttpError> {
-    let addr = format!("{}:{}", host, port);
-    let mut server = TcpStream::connect(addr).await?;
-
-    // Forward the initial request
-    server.write_all(initial_data).await?;
-
-    // We need to inspect the response to detect WebSocket upgrade
-    // First, we'll read the response headers from the server
-    let mut response_buffer = [0u8; 4096]; // Enough for typical headers
-    let mut response_len = 0;
-    let mut _is_websocket = false; // Prefixed with underscore as it's used for debugging
-    let mut headers_complete = false;
-
-    // Read with timeout to prevent hanging
-    let response_timeout = Duration::from_secs(10);
-
-    while response_len < response_buffer.len() && !headers_complete {
-        let n = timeout(
-            response_timeout,
-            server.read(&mut response_buffer[response_len..]),
-        )
-        .await??;
+/// Parses a complete response head to a request made with `request_method`
+pub fn parse_response_head(head: &[u8], request_method: &str) -> Result<ResponseHead, HttpError> {
+    let (status_line, minor_version, fields) = parse_head(head)?;
+    let status = status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or(HttpError::InvalidRequest)?;
+
+    // RFC 9112 §6.3: these responses never carry a body
+    let framing = if request_method == "HEAD"
+        || (100..200).contains(&status)
+        || status == 204
+        || status == 304
+    {
+        BodyFraming::Empty
+    } else if fields.transfer_encoding {
+        if fields.chunked {
+            BodyFraming::Chunked
+        } else {
+            BodyFraming::UntilClose
+        }
+    } else {
+        match fields.content_length {
+            Some(0) => BodyFraming::Empty,
+            Some(length) => BodyFraming::Length(length),
+            None => BodyFraming::UntilClose,
+        }
+    };
+
+    Ok(ResponseHead {
+        status,
+        keep_alive: framing != BodyFraming::UntilClose && persists(minor_version, &fields),
+        framing,
+        websocket_accept: fields.websocket_accept,
+        websocket_extensions: fields.websocket_extensions,
+    })
+}
+
+/// Adds a header field to the end of a complete message head
+pub fn append_header(head: &[u8], name: &str, value: &str) -> Vec<u8> {
+    let mut appended = head[..head.len() - 2].to_vec();
+    appended.extend_from_slice(format!("{}: {}\r\n\r\n", name, value).as_bytes());
+    appended
+}
+
+/// Reads HTTP/1.x messages from a stream one at a time
+///
+/// Bytes read past the end of a message stay buffered for the next one, so
+/// pipelined requests are handled in order. Every read is bounded by the idle
+/// timeout.
+pub struct MessageReader<S> {
+    inner: S,
+    buffer: Vec<u8>,
+    idle_timeout: Duration,
+}
 
-        if n == 0 {
-            break; // End of stream
+impl<S: AsyncRead + Unpin> MessageReader<S> {
+    pub fn new(inner: S, idle_timeout: Duration) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            idle_timeout,
         }
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Bytes read from the stream but not yet consumed
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
 
-        response_len += n;
+    /// Returns the stream and any bytes read but not yet consumed
+    pub fn into_parts(self) -> (S, Vec<u8>) {
+        (self.inner, self.buffer)
+    }
+
+    /// Reads more of the stream into the buffer, returning 0 at EOF
+    async fn fill(&mut self) -> Result<usize, HttpError> {
+        // Read straight into the buffer's spare capacity
+        self.buffer.reserve(READ_BUFFER_SIZE);
+        let n = timeout(self.idle_timeout, self.inner.read_buf(&mut self.buffer)).await??;
+        Ok(n)
+    }
+
+    /// Reads the next message head, up to and including the blank line
+    ///
+    /// Returns `None` if the stream ends, or stays idle past the timeout,
+    /// before a new message starts.
+    pub async fn read_head(&mut self) -> Result<Option<Vec<u8>>, HttpError> {
+        loop {
+            // Empty lines before a request line are ignored (RFC 9112 §2.2)
+            let blank = self
+                .buffer
+                .iter()
+                .take_while(|&&b| b == b'\r' || b == b'\n')
+                .count();
+            self.buffer.drain(..blank);
+
+            if let Some(end) = find_headers_end(&self.buffer) {
+                return Ok(Some(self.buffer.drain(..end).collect()));
+            }
+            if self.buffer.len() > MAX_REQUEST_HEAD_SIZE {
+                return Err(HttpError::InvalidRequest);
+            }
 
-        // Check if we've read the end of headers (double CRLF)
-        if response_len >= 4 {
-            for i in 3..response_len {
-                if response_buffer[i - 3..=i] == [b'\r', b'\n', b'\r', b'\n'] {
-                    headers_complete = true;
+            match self.fill().await {
+                Ok(0) if self.buffer.is_empty() => return Ok(None),
+                Ok(0) => return Err(HttpError::InvalidRequest),
+                Ok(_) => {}
+                Err(HttpError::Timeout) if self.buffer.is_empty() => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Forwards a message head and its body framed by `framing` to `out`
+    ///
+    /// Chunk size lines and trailers are passed through unchanged. Bytes are
+    /// written as they arrive, the head together with any body bytes already
+    /// read, and added to `counter`.
+    pub async fn forward_message<W: AsyncWrite + Unpin>(
+        &mut self,
+        head: &[u8],
+        framing: BodyFraming,
+        out: &mut W,
+        counter: Option<&IntCounter>,
+    ) -> Result<(), HttpError> {
+        let mut out = MessageWriter {
+            out,
+            pending: head.to_vec(),
+            counter,
+        };
+        match framing {
+            BodyFraming::Empty => {}
+            BodyFraming::Length(length) => self.copy_exact(length, &mut out).await?,
+            BodyFraming::Chunked => loop {
+                let line = self.copy_line(&mut out).await?;
+                let size = parse_chunk_size(&line[..line.len() - 2])?;
+                if size == 0 {
+                    // Trailer fields, then the empty line ending the message
+                    while self.copy_line(&mut out).await?.len() > 2 {}
                     break;
                 }
-            }
+                self.copy_exact(size + 2, &mut out).await?; // data + CRLF
+            },
+            BodyFraming::UntilClose => loop {
+                if self.buffer.is_empty() && self.fill_after(&mut out).await? == 0 {
+                    break;
+                }
+                let len = self.buffer.len();
+                out.take(&mut self.buffer, len);
+            },
         }
+        out.send().await?;
+        out.out.flush().await?;
+        Ok(())
+    }
 
-        // Detect WebSocket upgrade (101 Switching Protocols)
-        if response_len >= SWITCHING_PROTOCOLS.len() {
-            let response_str =
-                std::str::from_utf8(&response_buffer[..response_len]).unwrap_or_default();
+    /// Sends what `out` holds, then reads more of the stream
+    async fn fill_after<W: AsyncWrite + Unpin>(
+        &mut self,
+        out: &mut MessageWriter<'_, W>,
+    ) -> Result<usize, HttpError> {
+        out.send().await?;
+        self.fill().await
+    }
 
-            if response_str
-                .starts_with(std::str::from_utf8(SWITCHING_PROTOCOLS).unwrap_or_default())
-                && response_str
-                    .to_lowercase()
-                    .contains(&format!("upgrade: {}", WEBSOCKET_UPGRADE))
-            {
-                _is_websocket = true;
-                println!("WebSocket upgrade detected");
+    async fn copy_exact<W: AsyncWrite + Unpin>(
+        &mut self,
+        mut remaining: u64,
+        out: &mut MessageWriter<'_, W>,
+    ) -> Result<(), HttpError> {
+        while remaining > 0 {
+            if self.buffer.is_empty() && self.fill_after(out).await? == 0 {
+                return Err(HttpError::Io(io::ErrorKind::UnexpectedEof.into()));
             }
+            let take = remaining.min(self.buffer.len() as u64) as usize;
+            out.take(&mut self.buffer, take);
+            remaining -= take as u64;
         }
+        Ok(())
     }
 
-    // Send the response back to the client
-    client.write_all(&response_buffer[..response_len]).await?;
+    /// Copies one CRLF-terminated line and returns it
+    async fn copy_line<W: AsyncWrite + Unpin>(
+        &mut self,
+        out: &mut MessageWriter<'_, W>,
+    ) -> Result<Vec<u8>, HttpError> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buffer[..end + 2].to_vec();
+                out.take(&mut self.buffer, end + 2);
+                return Ok(line);
+            }
+            if self.buffer.len() > MAX_CHUNK_LINE_SIZE {
+                return Err(HttpError::InvalidRequest);
+            }
+            if self.fill_after(out).await? == 0 {
+                return Err(HttpError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
+}
 
-    // Now continue with standard bidirectional copy
-    let (mut client_read, mut client_write) = tokio::io::split(client);
-    let (mut server_read, mut server_write) = tokio::io::split(&mut server);
+/// Output of `MessageReader::forward_message`, coalescing bytes between reads
+struct MessageWriter<'a, W> {
+    out: &'a mut W,
+    pending: Vec<u8>,
+    counter: Option<&'a IntCounter>,
+}
 
-    // If metrics are enabled, use the tracking copy, otherwise use the standard copy
-    if let Some((tx_counter, rx_counter)) = metrics {
-        tokio::try_join!(
-            copy_with_metrics(&mut client_read, &mut server_write, tx_counter),
-            copy_with_metrics(&mut server_read, &mut client_write, rx_counter)
-        )?;
-    } else {
-        tokio::try_join!(
-            tokio::io::copy(&mut client_read, &mut server_write),
-            tokio::io::copy(&mut server_read, &mut client_write)
-        )?;
+impl<W: AsyncWrite + Unpin> MessageWriter<'_, W> {
+    /// Moves the first `len` bytes of `buffer` to the pending output
+    fn take(&mut self, buffer: &mut Vec<u8>, len: usize) {
+        self.pending.extend(buffer.drain(..len));
     }
 
-    Ok(())
+    async fn send(&mut self) -> Result<(), HttpError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.out.write_all(&self.pending).await?;
+        if let Some(counter) = self.counter {
+            counter.inc_by(self.pending.len() as u64);
+        }
+        self.pending.clear();
+        Ok(())
+    }
 }
 
 /// Validate WebSocket upgrade and generate accept key
@@ -222,9 +877,13 @@ pub async fn tunnel_websocket(
 /// # Arguments
 /// * `headers` - The HTTP request headers as a string
 ///
+/// The key must be a base64-encoded 16-byte nonce and the version 13
+/// (RFC 6455 §4.1); the backend's 101 must echo the returned value.
+///
 /// # Returns
 /// * `Ok(String)` - The computed Sec-WebSocket-Accept value
-/// * `Err` - If the Sec-WebSocket-Key header is missing or invalid
+/// * `Err` - If the Sec-WebSocket-Key header is missing or invalid, or the
+///   version is not 13
 ///
 /// # Example
 /// ```ignore
@@ -232,10 +891,18 @@ pub async fn tunnel_websocket(
 /// let accept = validate_websocket_upgrade(headers).unwrap();
 /// assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
 /// ```
-#[allow(dead_code)] // Reserved for future WebSocket handshake validation
-pub fn validate_websocket_upgrade(headers: &str) -> Result<String, Box<dyn std::error::Error>> {
+pub fn validate_websocket_upgrade(headers: &str) -> Result<String, HttpError> {
     // Extract Sec-WebSocket-Key header
     let ws_key = extract_websocket_key(headers)?;
+    let nonce = general_purpose::STANDARD
+        .decode(&ws_key)
+        .map_err(|_| HttpError::WebSocketUpgradeFailed)?;
+    if nonce.len() != 16 {
+        return Err(HttpError::WebSocketUpgradeFailed);
+    }
+    if header_value(headers, "sec-websocket-version") != Some("13") {
+        return Err(HttpError::WebSocketUpgradeFailed);
+    }
 
     // Compute Sec-WebSocket-Accept
     let mut hasher = Sha1::new();
@@ -259,208 +926,313 @@ pub fn validate_websocket_upgrade(headers: &str) -> Result<String, Box<dyn std::
 /// # Returns
 /// * `Ok(String)` - The Sec-WebSocket-Key value
 /// * `Err` - If the header is not found
-#[allow(dead_code)] // Reserved for future WebSocket handshake validation
-fn extract_websocket_key(headers: &str) -> Result<String, Box<dyn std::error::Error>> {
-    for line in headers.lines() {
-        if line.to_lowercase().starts_with("sec-websocket-key:")
-            && let Some(key) = line.split(':').nth(1)
-        {
-            return Ok(key.trim().to_string());
-        }
-    }
-    Err("Missing Sec-WebSocket-Key header".into())
+fn extract_websocket_key(headers: &str) -> Result<String, HttpError> {
+    header_value(headers, "sec-websocket-key")
+        .map(str::to_string)
+        .ok_or(HttpError::WebSocketUpgradeFailed)
 }
 
-/// Detect gRPC from HTTP headers buffer
-///
-/// Checks if the HTTP request contains gRPC content-type header.
-/// This is a simpler version that works with buffers instead of streams.
-///
-/// # Arguments
-/// * `headers` - The HTTP headers as bytes
-///
-/// # Returns
-/// * `true` if gRPC content-type is detected
-/// * `false` otherwise
-#[inline]
-pub fn is_grpc_request(headers: &[u8]) -> bool {
-    let headers_str = String::from_utf8_lossy(headers).to_lowercase();
-    headers_str.contains(CONTENT_TYPE_HEADER) && headers_str.contains(GRPC_CONTENT_TYPE)
+/// Value of the first header named `name` (case-insensitive) in `headers`
+fn header_value<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines().find_map(|line| {
+        let (field, value) = line.split_once(':')?;
+        field.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// The first request of an h2c connection, as read by
+/// `read_http2_request_head`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2RequestHead {
+    /// `:authority`, or the `host` header when it is absent
+    pub authority: String,
+    /// `:path`, absent for CONNECT
+    pub path: Option<String>,
+    /// `content-type`, if sent
+    pub content_type: Option<String>,
+    /// Stream the request was sent on
+    pub stream_id: u32,
+    /// Every frame read after the preface, unchanged, to be forwarded to the
+    /// backend ahead of the rest of the connection
+    pub frames: Vec<u8>,
 }
 
-/// Parses HTTP/2 frames to detect gRPC traffic from a stream
+impl Http2RequestHead {
+    /// Whether the request carries a gRPC content type
+    pub fn is_grpc(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|ct| ct.to_ascii_lowercase().starts_with(GRPC_CONTENT_TYPE))
+    }
+}
+
+/// Reads frames after the HTTP/2 connection preface up to the end of the
+/// first request's header block, and decodes it with `decoder`
 ///
-/// This function reads HTTP/2 frames from a TCP stream to detect gRPC traffic.
-/// Note: This consumes data from the stream, so use carefully.
-/// For most cases, use `is_grpc_request()` instead which works with buffers.
+/// Clients open with SETTINGS and often WINDOW_UPDATE or PRIORITY frames
+/// before HEADERS; those are kept in `frames` along with the HEADERS and any
+/// CONTINUATION frames, so that the backend sees the exact same byte stream.
+/// Later header blocks of the connection must be decoded with the same
+/// `decoder`, see `Http2RequestFilter`.
 ///
-/// # Arguments
-/// * `stream` - The TCP stream to read from
+/// # Errors
 ///
-/// # Returns
-/// * `Ok(true)` if gRPC is detected
-/// * `Ok(false)` if not gRPC
-/// * `Err` on read errors or timeout
-#[allow(dead_code)] // Reserved for future stream-based gRPC detection
-pub async fn detect_grpc(stream: &mut TcpStream) -> Result<bool, HttpError> {
-    // Buffer for reading HTTP/2 frame header (9 bytes)
-    let mut frame_header = [0u8; 9];
-    let mut is_grpc = false;
+/// Returns `HttpError::Http2FrameError` for protocol violations, frames over
+/// the default SETTINGS_MAX_FRAME_SIZE, more than `MAX_HTTP2_HEAD_SIZE`
+/// bytes before the header block ends, an undecodable header block or a
+/// request without authority. Returns `HttpError::Timeout` if it does not
+/// complete within 5 seconds.
+pub async fn read_http2_request_head<S>(
+    stream: &mut S,
+    decoder: &mut HpackDecoder,
+) -> Result<Http2RequestHead, HttpError>
+where
+    S: AsyncRead + Unpin,
+{
     let detection_timeout = Duration::from_secs(5);
+    let (frames, block, stream_id) =
+        timeout(detection_timeout, read_http2_header_block(stream)).await??;
+
+    let fields = decoder
+        .decode(&block)
+        .map_err(|_| HttpError::Http2FrameError)?;
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    };
+    let authority = field(":authority")
+        .or_else(|| field("host"))
+        .filter(|authority| is_valid_hostname(authority))
+        .ok_or(HttpError::NoHostHeader)?;
+
+    Ok(Http2RequestHead {
+        authority,
+        path: field(":path"),
+        content_type: field("content-type"),
+        stream_id,
+        frames,
+    })
+}
 
-    // Read the HTTP/2 frame header
-    timeout(detection_timeout, stream.read_exact(&mut frame_header)).await??;
+/// Reads frames until a HEADERS frame and its CONTINUATION frames end,
+/// returning the raw frames, the reassembled header block and its stream
+async fn read_http2_header_block<S>(stream: &mut S) -> Result<(Vec<u8>, Vec<u8>, u32), HttpError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut frames = Vec::new();
+    let mut block = Vec::new();
+    let mut headers_stream = None;
 
-    // Parse HTTP/2 frame header
-    let frame_length = ((frame_header[0] as usize) << 16)
-        | ((frame_header[1] as usize) << 8)
-        | (frame_header[2] as usize);
-    let frame_type = frame_header[3];
+    loop {
+        let start = frames.len();
+        if start + HTTP2_FRAME_HEADER_LEN > MAX_HTTP2_HEAD_SIZE {
+            return Err(HttpError::Http2FrameError);
+        }
 
-    // If it's a HEADERS frame, we need to check for gRPC content-type
-    if frame_type == HTTP2_FRAME_TYPE_HEADERS && frame_length > 0 {
-        // Read the frame payload (limiting to a reasonable size to prevent abuse)
-        let max_payload = frame_length.min(4096);
-        let mut payload = vec![0u8; max_payload];
-        timeout(detection_timeout, stream.read_exact(&mut payload)).await??;
+        frames.resize(start + HTTP2_FRAME_HEADER_LEN, 0);
+        stream.read_exact(&mut frames[start..]).await?;
+        let header = &frames[start..];
+        let length =
+            ((header[0] as usize) << 16) | ((header[1] as usize) << 8) | header[2] as usize;
+        let frame_type = header[3];
+        let flags = header[4];
+        let stream_id =
+            u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+
+        if length > HTTP2_MAX_FRAME_SIZE || frames.len() + length > MAX_HTTP2_HEAD_SIZE {
+            return Err(HttpError::Http2FrameError);
+        }
+        let payload_start = frames.len();
+        frames.resize(payload_start + length, 0);
+        stream.read_exact(&mut frames[payload_start..]).await?;
+        let payload = &frames[payload_start..];
+
+        // Only CONTINUATION may follow a HEADERS frame without END_HEADERS
+        if let Some(expected) = headers_stream
+            && (frame_type != HTTP2_FRAME_TYPE_CONTINUATION || stream_id != expected)
+        {
+            return Err(HttpError::Http2FrameError);
+        }
 
-        // Convert to string (lossy) for header inspection
-        let payload_str = String::from_utf8_lossy(&payload);
+        match frame_type {
+            HTTP2_FRAME_TYPE_HEADERS => {
+                if stream_id.is_multiple_of(2) {
+                    return Err(HttpError::Http2FrameError);
+                }
+                block.extend_from_slice(headers_fragment(payload, flags)?);
+            }
+            HTTP2_FRAME_TYPE_CONTINUATION => {
+                if headers_stream.is_none() {
+                    return Err(HttpError::Http2FrameError);
+                }
+                block.extend_from_slice(payload);
+            }
+            HTTP2_FRAME_TYPE_SETTINGS => {
+                if stream_id != 0 || !length.is_multiple_of(6) {
+                    return Err(HttpError::Http2FrameError);
+                }
+                continue;
+            }
+            HTTP2_FRAME_TYPE_PRIORITY | HTTP2_FRAME_TYPE_PING | HTTP2_FRAME_TYPE_WINDOW_UPDATE => {
+                continue;
+            }
+            // DATA, RST_STREAM, PUSH_PROMISE and GOAWAY cannot precede the
+            // first request
+            0x0 | 0x3 | 0x5 | 0x7 => return Err(HttpError::Http2FrameError),
+            // Unknown frame types must be ignored (RFC 9113 §4.1)
+            _ => continue,
+        }
 
-        // Check for gRPC content-type
-        if payload_str.to_lowercase().contains(CONTENT_TYPE_HEADER)
-            && payload_str.to_lowercase().contains(GRPC_CONTENT_TYPE)
-        {
-            is_grpc = true;
+        if flags & HTTP2_FLAG_END_HEADERS != 0 {
+            return Ok((frames, block, stream_id));
         }
+        headers_stream = Some(stream_id);
     }
-
-    Ok(is_grpc)
 }
 
-/// Extracts :authority pseudo-header from HTTP/2 HEADERS frame
-///
-/// This function reads the HTTP/2 HEADERS frame and attempts to extract
-/// the :authority pseudo-header which contains the target hostname.
-///
-/// # Arguments
-///
-/// * `stream` - The TCP stream to read from
-///
-/// # Returns
-///
-/// Returns a tuple of (authority, frame_data) where frame_data contains
-/// the frame header and payload that was read, so it can be forwarded.
-///
-/// # Note
-///
-/// This is a simplified HTTP/2 frame parser. It searches for the :authority
-/// field in the HPACK-encoded headers using pattern matching rather than
-/// a full HPACK decoder. This works for most common cases.
-pub async fn extract_http2_authority(
-    stream: &mut TcpStream,
-) -> Result<(String, Vec<u8>), HttpError> {
-    let detection_timeout = Duration::from_secs(5);
-
-    // Read HTTP/2 frame header (9 bytes)
-    let mut frame_header = [0u8; 9];
-    timeout(detection_timeout, stream.read_exact(&mut frame_header)).await??;
-
-    // Parse frame header
-    let frame_length = ((frame_header[0] as usize) << 16)
-        | ((frame_header[1] as usize) << 8)
-        | (frame_header[2] as usize);
-    let frame_type = frame_header[3];
-
-    // Verify it's a HEADERS frame (type 0x1)
-    if frame_type != HTTP2_FRAME_TYPE_HEADERS {
-        return Err(HttpError::Http2FrameError);
+/// Strips padding and priority fields from a HEADERS frame payload
+fn headers_fragment(payload: &[u8], flags: u8) -> Result<&[u8], HttpError> {
+    let mut fragment = payload;
+    if flags & HTTP2_FLAG_PADDED != 0 {
+        let (&pad_length, rest) = fragment.split_first().ok_or(HttpError::Http2FrameError)?;
+        fragment = rest
+            .len()
+            .checked_sub(pad_length as usize)
+            .map(|end| &rest[..end])
+            .ok_or(HttpError::Http2FrameError)?;
     }
-
-    // Sanity check frame length (prevent abuse)
-    if frame_length == 0 || frame_length > 16384 {
-        return Err(HttpError::Http2FrameError);
+    if flags & HTTP2_FLAG_PRIORITY != 0 {
+        // Stream dependency (4 octets) and weight (1 octet)
+        fragment = fragment.get(5..).ok_or(HttpError::Http2FrameError)?;
     }
+    Ok(fragment)
+}
 
-    // Read the frame payload
-    let mut payload = vec![0u8; frame_length];
-    timeout(detection_timeout, stream.read_exact(&mut payload)).await??;
-
-    // Combine frame header and payload for forwarding
-    let mut frame_data = Vec::with_capacity(9 + frame_length);
-    frame_data.extend_from_slice(&frame_header);
-    frame_data.extend_from_slice(&payload);
-
-    // Search for :authority in the payload
-    // In HPACK encoding, :authority is a static table entry (index 1)
-    // or can be a literal header field
+/// Checks the fields of a request header block; an error closes the connection
+pub type Http2RequestCheck = Box<dyn FnMut(&[(String, String)]) -> Result<(), HttpError> + Send>;
 
-    // Try to find literal ":authority" string in payload
-    if let Some(pos) = payload.windows(10).position(|w| w == b":authority") {
-        // Found :authority field, now extract the value
-        // The value typically follows after the field name
-        let value_start = pos + 10;
+/// Decodes every header block an h2c client sends after its first request
+///
+/// Frames pass through unchanged. HEADERS and CONTINUATION frames are held
+/// until their block is complete and has passed the check, so a rejected
+/// request never reaches the backend. Trailers are checked too.
+pub struct Http2RequestFilter {
+    decoder: HpackDecoder,
+    check: Http2RequestCheck,
+    buffer: Vec<u8>,
+    /// Payload bytes of a forwarded frame still to pass through
+    forward: usize,
+    /// Raw frames and fragments of an incomplete header block
+    pending: Vec<u8>,
+    block: Vec<u8>,
+    block_stream: Option<u32>,
+}
 
-        if value_start < payload.len() {
-            // In HPACK, strings are length-prefixed
-            // For simplicity, we'll look for the next few bytes as the length
+impl Http2RequestFilter {
+    /// Creates a filter continuing with the decoder that read the first request
+    pub fn new(decoder: HpackDecoder, check: Http2RequestCheck) -> Self {
+        Self {
+            decoder,
+            check,
+            buffer: Vec::new(),
+            forward: 0,
+            pending: Vec::new(),
+            block: Vec::new(),
+            block_stream: None,
+        }
+    }
+}
 
-            // Try to find a reasonable hostname pattern after :authority
-            // Look for printable ASCII characters that form a hostname
-            let remaining = &payload[value_start..];
+impl RequestFilter for Http2RequestFilter {
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), HttpError> {
+        self.buffer.extend_from_slice(input);
+        let mut pos = 0;
 
-            // Skip potential padding/flags bytes and find the actual value
-            for offset in 0..remaining.len().min(10) {
-                if let Some(authority) = extract_authority_value(&remaining[offset..]) {
-                    return Ok((authority, frame_data));
+        loop {
+            let available = &self.buffer[pos..];
+            if self.forward > 0 {
+                let take = self.forward.min(available.len());
+                if take == 0 {
+                    break;
                 }
+                out.extend_from_slice(&available[..take]);
+                self.forward -= take;
+                pos += take;
+                continue;
+            }
+            if available.len() < HTTP2_FRAME_HEADER_LEN {
+                break;
             }
-        }
-    }
 
-    // Alternative: Look for indexed :authority (static table index 1)
-    // HPACK uses variable-length integers, index 1 could be encoded as 0x01 or 0x81
-    for i in 0..payload.len().saturating_sub(20) {
-        if payload[i] == 0x01 || payload[i] == 0x81 || payload[i] == 0x41 {
-            // Might be indexed :authority, check if followed by a hostname pattern
-            if let Some(authority) = extract_authority_value(&payload[i + 1..])
-                && (authority.contains('.') || authority.contains(':'))
+            let length = ((available[0] as usize) << 16)
+                | ((available[1] as usize) << 8)
+                | available[2] as usize;
+            let frame_type = available[3];
+            let flags = available[4];
+            let stream_id =
+                u32::from_be_bytes([available[5], available[6], available[7], available[8]])
+                    & 0x7fff_ffff;
+
+            if let Some(expected) = self.block_stream
+                && (frame_type != HTTP2_FRAME_TYPE_CONTINUATION || stream_id != expected)
             {
-                return Ok((authority, frame_data));
+                return Err(HttpError::Http2FrameError);
             }
-        }
-    }
 
-    Err(HttpError::Http2FrameError)
-}
-
-/// Helper function to extract authority value from HPACK-encoded data
-fn extract_authority_value(data: &[u8]) -> Option<String> {
-    if data.is_empty() {
-        return None;
-    }
-
-    // Check if first byte is a length indicator
-    let len = data[0] as usize;
-
-    // Sanity check: hostname should be between 3 and 255 characters
-    if !(3..=255).contains(&len) || len + 1 > data.len() {
-        return None;
-    }
-
-    // Extract the hostname
-    if let Ok(hostname) = std::str::from_utf8(&data[1..=len]) {
-        // Validate it looks like a hostname (contains at least one dot or colon for port)
-        // and only contains valid hostname characters
-        if is_valid_hostname(hostname) {
-            return Some(hostname.to_string());
+            match frame_type {
+                HTTP2_FRAME_TYPE_HEADERS | HTTP2_FRAME_TYPE_CONTINUATION => {
+                    if frame_type == HTTP2_FRAME_TYPE_CONTINUATION && self.block_stream.is_none() {
+                        return Err(HttpError::Http2FrameError);
+                    }
+                    let frame_len = HTTP2_FRAME_HEADER_LEN + length;
+                    if self.pending.len() + frame_len > MAX_HTTP2_HEAD_SIZE {
+                        return Err(HttpError::Http2FrameError);
+                    }
+                    if available.len() < frame_len {
+                        break;
+                    }
+
+                    let payload = &available[HTTP2_FRAME_HEADER_LEN..frame_len];
+                    let fragment = if frame_type == HTTP2_FRAME_TYPE_HEADERS {
+                        headers_fragment(payload, flags)?
+                    } else {
+                        payload
+                    };
+                    self.block.extend_from_slice(fragment);
+                    self.pending.extend_from_slice(&available[..frame_len]);
+                    pos += frame_len;
+
+                    if flags & HTTP2_FLAG_END_HEADERS == 0 {
+                        self.block_stream = Some(stream_id);
+                        continue;
+                    }
+                    let fields = self
+                        .decoder
+                        .decode(&self.block)
+                        .map_err(|_| HttpError::Http2FrameError)?;
+                    (self.check)(&fields)?;
+                    out.append(&mut self.pending);
+                    self.block.clear();
+                    self.block_stream = None;
+                }
+                _ => {
+                    out.extend_from_slice(&available[..HTTP2_FRAME_HEADER_LEN]);
+                    pos += HTTP2_FRAME_HEADER_LEN;
+                    self.forward = length;
+                }
+            }
         }
-    }
 
-    None
+        self.buffer.drain(..pos);
+        Ok(())
+    }
 }
 
-/// Validates if a string is a valid hostname
+/// Validates an authority as a hostname with an optional port
 #[inline]
 fn is_valid_hostname(s: &str) -> bool {
     if s.is_empty() || s.len() > 253 {
@@ -470,36 +1242,6 @@ fn is_valid_hostname(s: &str) -> bool {
     // Check for valid hostname characters
     s.chars()
         .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':' || c == '_')
-        && (s.contains('.') || s.contains(':'))
-}
-
-/// Copy data with metrics tracking
-#[inline]
-async fn copy_with_metrics<R, W>(
-    reader: &mut R,
-    writer: &mut W,
-    counter: IntCounter,
-) -> Result<u64, io::Error>
-where
-    R: AsyncReadExt + Unpin,
-    W: AsyncWriteExt + Unpin,
-{
-    let mut buffer = [0u8; COPY_BUFFER_SIZE];
-    let mut total = 0u64;
-
-    loop {
-        let n = reader.read(&mut buffer).await?;
-        if n == 0 {
-            break;
-        }
-        writer.write_all(&buffer[..n]).await?;
-
-        // Update the counter with the bytes transferred
-        counter.inc_by(n as u64);
-        total += n as u64;
-    }
-
-    Ok(total)
 }
 
 #[inline]
@@ -511,22 +1253,111 @@ fn find_headers_end(buffer: &[u8]) -> Option<usize> {
         .map(|pos| pos + 4)
 }
 
-#[inline]
-fn extract_host_header(headers: &[u8]) -> Option<String> {
-    let headers_str = std::str::from_utf8(headers).ok()?;
-    for line in headers_str.lines() {
-        // Case-insensitive comparison without allocating lowercase string
-        if line.len() > 5 && line[..5].eq_ignore_ascii_case("host:") {
-            return Some(line[5..].trim().to_string());
-        }
-    }
-    None
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn request_host(head: &[u8]) -> Option<String> {
+        parse_request_head(head).ok()?.host
+    }
+
+    fn rewrite_all(rewriter: &mut RequestHeaderRewriter, chunks: &[&[u8]]) -> String {
+        let mut out = Vec::new();
+        for chunk in chunks {
+            rewriter.feed(chunk, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_rewriter_replaces_spoofed_header() {
+        let mut rewriter = RequestHeaderRewriter::new("X-Client-Identity", Some("client-a"));
+        let out = rewrite_all(
+            &mut rewriter,
+            &[b"GET / HTTP/1.1\r\nHost: a\r\nx-client-identity: admin\r\n\r\n"],
+        );
+        assert_eq!(
+            out,
+            "GET / HTTP/1.1\r\nX-Client-Identity: client-a\r\nHost: a\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_rewriter_handles_keep_alive_and_bodies() {
+        let mut rewriter = RequestHeaderRewriter::new("X-Id", Some("c"));
+        let out = rewrite_all(
+            &mut rewriter,
+            &[
+                b"POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel",
+                b"lo",
+                b"POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nX-I\r\n",
+                b"0\r\nTrailer: x\r\n\r\n",
+                b"GET /c HTTP/1.1\r\nX-Id: spoofed\r\n\r\n",
+            ],
+        );
+        assert_eq!(
+            out,
+            "POST /a HTTP/1.1\r\nX-Id: c\r\nContent-Length: 5\r\n\r\nhello\
+             POST /b HTTP/1.1\r\nX-Id: c\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nX-I\r\n\
+             0\r\nTrailer: x\r\n\r\n\
+             GET /c HTTP/1.1\r\nX-Id: c\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_rewriter_split_head_and_upgrade() {
+        let mut rewriter = RequestHeaderRewriter::new("X-Id", None);
+        let out = rewrite_all(
+            &mut rewriter,
+            &[
+                b"GET /ws HTTP/1.1\r\nX-Id: spoof",
+                b"ed\r\nUpgrade: websocket\r\n\r\n",
+                b"X-Id: not a header\r\n\r\n",
+            ],
+        );
+        assert_eq!(
+            out,
+            "GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\nX-Id: not a header\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_rewriter_rejects_conflicting_lengths() {
+        let mut rewriter = RequestHeaderRewriter::new("X-Id", Some("c"));
+        let mut out = Vec::new();
+        assert!(
+            rewriter
+                .feed(
+                    b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                    &mut out
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rewriter_strips_control_characters_from_value() {
+        let mut rewriter = RequestHeaderRewriter::new("X-Id", Some("evil\r\nX-Admin: 1"));
+        let out = rewrite_all(&mut rewriter, &[b"GET / HTTP/1.1\r\n\r\n"]);
+        assert_eq!(out, "GET / HTTP/1.1\r\nX-Id: evilX-Admin: 1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_request_rewrite_stream() {
+        let (client, mut peer) = tokio::io::duplex(256);
+        let mut stream =
+            RequestRewriteStream::new(client, RequestHeaderRewriter::new("X-Id", Some("c")));
+
+        peer.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .await
+            .unwrap();
+        drop(peer);
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        assert_eq!(received, "GET / HTTP/1.1\r\nX-Id: c\r\nHost: a\r\n\r\n");
+    }
+
     #[test]
     fn test_find_headers_end_simple() {
         let buffer = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
@@ -556,67 +1387,208 @@ mod tests {
     }
 
     #[test]
-    fn test_extract_host_header_simple() {
+    fn test_request_host_simple() {
         let headers = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers),
-            Some("example.com".to_string())
-        );
+        assert_eq!(request_host(headers), Some("example.com".to_string()));
     }
 
     #[test]
-    fn test_extract_host_header_with_port() {
+    fn test_request_host_with_port() {
         let headers = b"GET / HTTP/1.1\r\nHost: example.com:8080\r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers),
-            Some("example.com:8080".to_string())
-        );
+        assert_eq!(request_host(headers), Some("example.com:8080".to_string()));
     }
 
     #[test]
-    fn test_extract_host_header_with_whitespace() {
+    fn test_request_host_with_whitespace() {
         let headers = b"GET / HTTP/1.1\r\nHost:   example.com   \r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers),
-            Some("example.com".to_string())
-        );
+        assert_eq!(request_host(headers), Some("example.com".to_string()));
     }
 
     #[test]
-    fn test_extract_host_header_case_insensitive() {
+    fn test_request_host_case_insensitive() {
         let headers = b"GET / HTTP/1.1\r\nHOST: example.com\r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers),
-            Some("example.com".to_string())
-        );
+        assert_eq!(request_host(headers), Some("example.com".to_string()));
 
         let headers2 = b"GET / HTTP/1.1\r\nhOsT: example.com\r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers2),
-            Some("example.com".to_string())
-        );
+        assert_eq!(request_host(headers2), Some("example.com".to_string()));
     }
 
     #[test]
-    fn test_extract_host_header_missing() {
+    fn test_request_host_missing() {
         let headers = b"GET / HTTP/1.1\r\nUser-Agent: Test\r\n\r\n";
-        assert_eq!(extract_host_header(headers), None);
+        assert_eq!(request_host(headers), None);
     }
 
     #[test]
-    fn test_extract_host_header_multiple_headers() {
+    fn test_request_host_multiple_headers() {
         let headers =
             b"GET / HTTP/1.1\r\nUser-Agent: Test\r\nHost: example.com\r\nAccept: */*\r\n\r\n";
-        assert_eq!(
-            extract_host_header(headers),
-            Some("example.com".to_string())
-        );
+        assert_eq!(request_host(headers), Some("example.com".to_string()));
     }
 
     #[test]
-    fn test_extract_host_header_invalid_utf8() {
+    fn test_request_host_invalid_utf8() {
         let headers = b"GET / HTTP/1.1\r\nHost: \xFF\xFE\r\n\r\n";
-        assert_eq!(extract_host_header(headers), None);
+        assert_eq!(request_host(headers), None);
+    }
+
+    #[test]
+    fn test_request_host_absolute_form() {
+        let head = b"GET http://origin.test:8080/a?b HTTP/1.1\r\nHost: other.test\r\n\r\n";
+        assert_eq!(request_host(head), Some("origin.test:8080".to_string()));
+        // Two Host headers are rejected outright
+        let head = b"GET / HTTP/1.1\r\nHost: a.test\r\nHost: b.test\r\n\r\n";
+        assert!(parse_request_head(head).is_err());
+    }
+
+    #[test]
+    fn test_parse_request_head_framing() {
+        let parse = |head: &str| parse_request_head(head.as_bytes());
+
+        let request = parse("POST /x HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\n").unwrap();
+        assert_eq!(request.framing, BodyFraming::Length(5));
+        assert!(request.keep_alive && !request.upgrade && !request.expect_continue);
+
+        let request = parse(
+            "POST /x HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\nExpect: 100-continue\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.framing, BodyFraming::Chunked);
+        assert!(request.expect_continue);
+
+        let request = parse(
+            "GET / HTTP/1.1\r\nHost: a\r\nConnection: Upgrade, close\r\nUpgrade: websocket\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.framing, BodyFraming::Empty);
+        assert!(request.upgrade && !request.keep_alive);
+
+        // HTTP/1.0 closes unless keep-alive is asked for
+        assert!(
+            !parse("GET / HTTP/1.0\r\nHost: a\r\n\r\n")
+                .unwrap()
+                .keep_alive
+        );
+        assert!(
+            parse("GET / HTTP/1.0\r\nHost: a\r\nConnection: keep-alive\r\n\r\n")
+                .unwrap()
+                .keep_alive
+        );
+        assert!(
+            parse("CONNECT a:443 HTTP/1.1\r\nHost: a:443\r\n\r\n")
+                .unwrap()
+                .upgrade
+        );
+
+        // Ambiguous framing could smuggle a second request
+        for head in [
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length : 1\r\n\r\n",
+            "GET / HTTP/2.0\r\nHost: a\r\n\r\n",
+        ] {
+            assert!(parse(head).is_err(), "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn test_parse_response_head_framing() {
+        let parse =
+            |head: &str, method: &str| parse_response_head(head.as_bytes(), method).unwrap();
+
+        let response = parse("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n", "GET");
+        assert_eq!(
+            (response.status, response.framing),
+            (200, BodyFraming::Length(3))
+        );
+        assert!(response.keep_alive);
+
+        // HEAD responses and 204/304 have no body whatever their headers say
+        assert_eq!(
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n", "HEAD").framing,
+            BodyFraming::Empty
+        );
+        assert_eq!(
+            parse(
+                "HTTP/1.1 304 Not Modified\r\nTransfer-Encoding: chunked\r\n\r\n",
+                "GET"
+            )
+            .framing,
+            BodyFraming::Empty
+        );
+
+        let response = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "GET",
+        );
+        assert_eq!(response.framing, BodyFraming::Chunked);
+
+        // Without framing the body ends when the backend closes
+        let response = parse("HTTP/1.1 200 OK\r\n\r\n", "GET");
+        assert_eq!(response.framing, BodyFraming::UntilClose);
+        assert!(!response.keep_alive);
+        assert!(!parse("HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n", "GET").keep_alive);
+    }
+
+    #[tokio::test]
+    async fn test_message_reader_pipelined_requests() {
+        let (client, mut peer) = tokio::io::duplex(1024);
+        let mut reader = MessageReader::new(client, Duration::from_secs(5));
+        peer.write_all(
+            b"\r\nPOST /a HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3;ext\r\nabc\r\n0\r\nX-Trailer: 1\r\n\r\n\
+              POST /b HTTP/1.1\r\nHost: b\r\nContent-Length: 2\r\n\r\nxy",
+        )
+        .await
+        .unwrap();
+        drop(peer);
+
+        let head = reader.read_head().await.unwrap().unwrap();
+        let request = parse_request_head(&head).unwrap();
+        assert_eq!(request.host.as_deref(), Some("a"));
+        let mut body = Vec::new();
+        reader
+            .forward_message(b"", request.framing, &mut body, None)
+            .await
+            .unwrap();
+        assert_eq!(body, b"3;ext\r\nabc\r\n0\r\nX-Trailer: 1\r\n\r\n");
+
+        let head = reader.read_head().await.unwrap().unwrap();
+        let request = parse_request_head(&head).unwrap();
+        assert_eq!(request.host.as_deref(), Some("b"));
+        // The head goes out with the body and is counted with it
+        let counter = IntCounter::new("message_bytes", "test").unwrap();
+        let mut message = Vec::new();
+        reader
+            .forward_message(&head, request.framing, &mut message, Some(&counter))
+            .await
+            .unwrap();
+        assert_eq!(message, [head.as_slice(), b"xy"].concat());
+        assert_eq!(counter.get(), message.len() as u64);
+
+        assert!(reader.read_head().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_message_reader_truncated_body() {
+        let (client, mut peer) = tokio::io::duplex(1024);
+        let mut reader = MessageReader::new(client, Duration::from_secs(5));
+        peer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort")
+            .await
+            .unwrap();
+        drop(peer);
+
+        let head = reader.read_head().await.unwrap().unwrap();
+        let response = parse_response_head(&head, "GET").unwrap();
+        let mut message = Vec::new();
+        assert!(
+            reader
+                .forward_message(&head, response.framing, &mut message, None)
+                .await
+                .is_err()
+        );
+        assert_eq!(message, [head.as_slice(), b"short"].concat());
     }
 
     #[test]
@@ -655,6 +1627,7 @@ mod tests {
                        Host: example.com\r\n\
                        Upgrade: websocket\r\n\
                        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                       Sec-WebSocket-Version: 13\r\n\
                        \r\n";
 
         let accept = validate_websocket_upgrade(headers).unwrap();
@@ -694,6 +1667,81 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_websocket_handshake_rejected() {
+        // The key must decode to 16 bytes
+        let headers = "GET / HTTP/1.1\r\n\
+                       Sec-WebSocket-Key: dGVzdA==\r\n\
+                       Sec-WebSocket-Version: 13\r\n\
+                       \r\n";
+        assert!(validate_websocket_upgrade(headers).is_err());
+
+        // Only version 13 is defined
+        let headers = "GET / HTTP/1.1\r\n\
+                       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                       Sec-WebSocket-Version: 8\r\n\
+                       \r\n";
+        assert!(validate_websocket_upgrade(headers).is_err());
+    }
+
+    #[test]
+    fn test_parse_websocket_handshake() {
+        let request = parse_request_head(
+            b"GET /chat HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\r\n",
+        )
+        .unwrap();
+        assert!(request.websocket && request.upgrade);
+
+        // Other upgrades, or Upgrade without Connection: upgrade, are not WebSocket
+        for head in [
+            &b"GET / HTTP/1.1\r\nHost: a\r\nUpgrade: h2c\r\nConnection: Upgrade\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\n\r\n",
+        ] {
+            assert!(!parse_request_head(head).unwrap().websocket);
+        }
+
+        let response = parse_response_head(
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+            "GET",
+        )
+        .unwrap();
+        assert_eq!(
+            response.websocket_accept.as_deref(),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+        assert!(response.websocket_extensions.is_none());
+    }
+
+    #[test]
+    fn test_websocket_extensions_joined() {
+        let request = parse_request_head(
+            b"GET /chat HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+              Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\
+              Sec-WebSocket-Extensions: x-custom\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            request.websocket_extensions.as_deref(),
+            Some("permessage-deflate; client_max_window_bits, x-custom")
+        );
+
+        let head = append_header(
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n",
+            "Sec-WebSocket-Extensions",
+            "permessage-deflate",
+        );
+        assert_eq!(
+            head,
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+              Sec-WebSocket-Extensions: permessage-deflate\r\n\r\n"
+        );
+        let response = parse_response_head(&head, "GET").unwrap();
+        assert_eq!(
+            response.websocket_extensions.as_deref(),
+            Some("permessage-deflate")
+        );
+    }
+
     #[test]
     fn test_websocket_accept_rfc_example() {
         // This is the example from RFC 6455 Section 1.3
@@ -709,53 +1757,243 @@ mod tests {
         assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
     }
 
+    #[test]
+    fn test_error_response_default_template() {
+        let response = error_response(
+            403,
+            "blocked.example.com",
+            "Host not in allowlist",
+            "{status} {reason}\n{message}\n",
+            "text/plain; charset=utf-8",
+        );
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(response.contains("Content-Length: 36\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("\r\n\r\n403 Forbidden\nHost not in allowlist\n"));
+    }
+
+    #[test]
+    fn test_error_response_escapes_html() {
+        let response = error_response(
+            502,
+            "<script>",
+            "Backend unreachable",
+            "<h1>{status} {reason}</h1><p>{host}</p>",
+            "text/html",
+        );
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+        assert!(response.ends_with("<h1>502 Bad Gateway</h1><p>&lt;script&gt;</p>"));
+    }
+
+    fn http2_frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    // RFC 7541 C.4.1: GET http://www.example.com/ with Huffman-coded authority
+    const HPACK_GET_EXAMPLE: &[u8] = &[
+        0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90,
+        0xf4, 0xff,
+    ];
+
+    #[tokio::test]
+    async fn test_http2_head_after_settings_and_window_update() {
+        let mut input = http2_frame(0x4, 0, 0, &[0x00, 0x03, 0x00, 0x00, 0x00, 0x64]);
+        input.extend(http2_frame(0x8, 0, 0, &[0x00, 0xff, 0x00, 0x01]));
+        input.extend(http2_frame(0x1, 0x5, 1, HPACK_GET_EXAMPLE));
+        let consumed = input.len();
+        input.extend(http2_frame(0x0, 0x1, 1, b"body"));
+
+        let mut reader = input.as_slice();
+        let head = read_http2_request_head(&mut reader, &mut HpackDecoder::default())
+            .await
+            .unwrap();
+        assert_eq!(head.authority, "www.example.com");
+        assert_eq!(head.path.as_deref(), Some("/"));
+        assert_eq!(head.content_type, None);
+        assert_eq!(head.stream_id, 1);
+        assert!(!head.is_grpc());
+
+        // Exactly the frames up to the header block are consumed
+        assert_eq!(head.frames, &input[..consumed]);
+        assert_eq!(reader, &input[consumed..]);
+    }
+
+    #[tokio::test]
+    async fn test_http2_head_padded_priority_and_continuation() {
+        // :method POST, :path /pkg.Svc/Call, :authority grpc.local,
+        // content-type application/grpc
+        let mut block = vec![0x83, 0x44, 0x0d];
+        block.extend_from_slice(b"/pkg.Svc/Call");
+        block.extend_from_slice(&[0x41, 0x0a]);
+        block.extend_from_slice(b"grpc.local");
+        block.extend_from_slice(&[0x5f, 0x10]);
+        block.extend_from_slice(b"application/grpc");
+
+        // PADDED | PRIORITY without END_HEADERS, then two CONTINUATIONs
+        let mut payload = vec![3, 0, 0, 0, 0, 15];
+        payload.extend_from_slice(&block[..5]);
+        payload.extend_from_slice(&[0, 0, 0]);
+        let mut input = http2_frame(0x4, 0, 0, &[]);
+        input.extend(http2_frame(0x1, 0x28, 3, &payload));
+        input.extend(http2_frame(0x9, 0, 3, &block[5..20]));
+        input.extend(http2_frame(0x9, 0x4, 3, &block[20..]));
+
+        let head = read_http2_request_head(&mut input.as_slice(), &mut HpackDecoder::default())
+            .await
+            .unwrap();
+        assert_eq!(head.authority, "grpc.local");
+        assert_eq!(head.path.as_deref(), Some("/pkg.Svc/Call"));
+        assert_eq!(head.content_type.as_deref(), Some("application/grpc"));
+        assert!(head.is_grpc());
+        assert_eq!(head.stream_id, 3);
+        assert_eq!(head.frames, input);
+    }
+
+    #[tokio::test]
+    async fn test_http2_head_host_fallback() {
+        // :method GET, :path /, host backend:8080
+        let mut block = vec![0x82, 0x84, 0x0f, 0x17, 0x0c];
+        block.extend_from_slice(b"backend:8080");
+        let input = http2_frame(0x1, 0x4, 1, &block);
+        let head = read_http2_request_head(&mut input.as_slice(), &mut HpackDecoder::default())
+            .await
+            .unwrap();
+        assert_eq!(head.authority, "backend:8080");
+
+        let input = http2_frame(0x1, 0x4, 1, &[0x82, 0x84]);
+        assert!(matches!(
+            read_http2_request_head(&mut input.as_slice(), &mut HpackDecoder::default()).await,
+            Err(HttpError::NoHostHeader)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http2_head_rejects_invalid_frames() {
+        let headers_open = http2_frame(0x1, 0, 1, &HPACK_GET_EXAMPLE[..4]);
+        let cases = [
+            // DATA before any HEADERS
+            http2_frame(0x0, 0, 1, b"x"),
+            // SETTINGS on a stream
+            http2_frame(0x4, 0, 1, &[]),
+            // HEADERS on an even stream
+            http2_frame(0x1, 0x4, 2, HPACK_GET_EXAMPLE),
+            // Padding longer than the payload
+            http2_frame(0x1, 0xc, 1, &[200, 0x82]),
+            // A PING between HEADERS and CONTINUATION
+            [headers_open.clone(), http2_frame(0x6, 0, 0, &[0; 8])].concat(),
+            // CONTINUATION on another stream
+            [
+                headers_open,
+                http2_frame(0x9, 0x4, 3, &HPACK_GET_EXAMPLE[4..]),
+            ]
+            .concat(),
+            // Invalid HPACK index
+            http2_frame(0x1, 0x4, 1, &[0xff, 0x00]),
+            // Larger than the default SETTINGS_MAX_FRAME_SIZE
+            http2_frame(0x1, 0x4, 1, &[0x82; 16385]),
+        ];
+        for input in cases {
+            assert!(matches!(
+                read_http2_request_head(&mut input.as_slice(), &mut HpackDecoder::default()).await,
+                Err(HttpError::Http2FrameError)
+            ));
+        }
+
+        // A truncated frame is an I/O error
+        let input = http2_frame(0x1, 0x4, 1, HPACK_GET_EXAMPLE);
+        assert!(matches!(
+            read_http2_request_head(&mut &input[..12], &mut HpackDecoder::default()).await,
+            Err(HttpError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_http2_request_filter_checks_every_header_block() {
+        use std::sync::{Arc, Mutex};
+
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let seen = paths.clone();
+        let check: Http2RequestCheck = Box::new(move |fields| {
+            let path = fields
+                .iter()
+                .find(|(n, _)| n == ":path")
+                .map(|(_, v)| v.clone());
+            if path.as_deref() == Some("/index.html") {
+                return Err(HttpError::RequestDenied("/index.html".to_string()));
+            }
+            seen.lock().unwrap().push(path);
+            Ok(())
+        });
+
+        // The first request indexed :authority, later blocks reference it
+        let mut decoder = HpackDecoder::default();
+        decoder.decode(HPACK_GET_EXAMPLE).unwrap();
+        let mut filter = Http2RequestFilter::new(decoder, check);
+
+        let mut input = http2_frame(0x0, 0x0, 1, &[0u8; 100]);
+        // :method GET, :scheme http, :path /, then :authority from the table
+        input.extend(http2_frame(0x1, 0x0, 3, &[0x82, 0x86]));
+        input.extend(http2_frame(0x9, 0x4, 3, &[0x84, 0xbe]));
+        // Trailers without :path
+        input.extend(http2_frame(0x1, 0x5, 3, &[0x40, 0x01, b'x', 0x01, b'y']));
+
+        // Frames pass through unchanged, whatever the read boundaries
+        let mut out = Vec::new();
+        for chunk in input.chunks(7) {
+            filter.feed(chunk, &mut out).unwrap();
+        }
+        assert_eq!(out, input);
+        assert_eq!(*paths.lock().unwrap(), vec![Some("/".to_string()), None]);
+
+        // A rejected block is not forwarded
+        let mut out = Vec::new();
+        let denied = http2_frame(0x1, 0x5, 5, &[0x82, 0x85, 0xbe]);
+        assert!(matches!(
+            filter.feed(&denied, &mut out),
+            Err(HttpError::RequestDenied(_))
+        ));
+        assert!(out.is_empty());
+    }
+
     // gRPC detection tests
+    fn head_with_content_type(content_type: Option<&str>) -> Http2RequestHead {
+        Http2RequestHead {
+            authority: "example.com".to_string(),
+            path: Some("/grpc.Service/Method".to_string()),
+            content_type: content_type.map(str::to_string),
+            stream_id: 1,
+            frames: Vec::new(),
+        }
+    }
+
     #[test]
     fn test_grpc_detection_positive() {
-        let headers = b"POST /grpc.Service/Method HTTP/2.0\r\n\
-                        Content-Type: application/grpc\r\n\
-                        Host: example.com\r\n\
-                        \r\n";
-
-        assert!(is_grpc_request(headers));
+        assert!(head_with_content_type(Some("application/grpc")).is_grpc());
     }
 
     #[test]
     fn test_grpc_detection_with_charset() {
-        let headers = b"POST /grpc.Service/Method HTTP/2.0\r\n\
-                        Content-Type: application/grpc+proto\r\n\
-                        Host: example.com\r\n\
-                        \r\n";
-
-        assert!(is_grpc_request(headers));
+        assert!(head_with_content_type(Some("application/grpc+proto")).is_grpc());
     }
 
     #[test]
     fn test_grpc_detection_negative() {
-        let headers = b"GET / HTTP/2.0\r\n\
-                        Content-Type: text/html\r\n\
-                        Host: example.com\r\n\
-                        \r\n";
-
-        assert!(!is_grpc_request(headers));
+        assert!(!head_with_content_type(Some("text/html")).is_grpc());
     }
 
     #[test]
     fn test_grpc_detection_case_insensitive() {
-        let headers = b"POST /grpc.Service/Method HTTP/2.0\r\n\
-                        CONTENT-TYPE: APPLICATION/GRPC\r\n\
-                        Host: example.com\r\n\
-                        \r\n";
-
-        assert!(is_grpc_request(headers));
+        assert!(head_with_content_type(Some("APPLICATION/GRPC")).is_grpc());
     }
 
     #[test]
     fn test_grpc_detection_no_content_type() {
-        let headers = b"POST /grpc.Service/Method HTTP/2.0\r\n\
-                        Host: example.com\r\n\
-                        \r\n";
-
-        assert!(!is_grpc_request(headers));
+        assert!(!head_with_content_type(None).is_grpc());
     }
 }
@@ -1,21 +1,32 @@
+pub mod acme;
 pub mod connection;
 pub mod connection_pool;
+pub mod dtls;
 pub mod grpc_pool;
+pub mod hpack;
 mod http;
 pub mod http2_cache;
+pub mod huffman;
 pub mod metrics_cache;
 pub mod protocols;
+pub mod proxy_protocol;
 pub mod qpack;
+pub mod quic;
 pub mod quic_handler;
 pub mod ssh;
+pub mod termination;
+pub mod tls;
+pub mod udp_admission;
 pub mod udp_connection;
+pub mod websocket;
 pub mod websocket_compression;
 
+use acme::AcmeManager;
 use connection::ConnectionHandler;
 use futures::StreamExt;
 use futures::stream::FuturesUnordered;
 use prometheus::Registry;
-use sniproxy_config::Config;
+use sniproxy_config::{Config, RouteMode};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -26,6 +37,7 @@ use tokio::sync::{Semaphore, broadcast};
 use tokio::time::timeout;
 use tracing::{error, info, warn};
 
+use crate::quic_handler::QuicHandler;
 use crate::udp_connection::UdpConnectionHandler;
 
 /// Runs the SNI proxy server with the given configuration.
@@ -43,7 +55,8 @@ use crate::udp_connection::UdpConnectionHandler;
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` on clean shutdown (via Ctrl+C or shutdown signal), or an error if listener setup fails.
+/// Returns `Ok(())` on clean shutdown (via Ctrl+C or shutdown signal), or an error if listener
+/// setup fails or a route's TLS configuration (such as its `ca_file`) can't be loaded.
 ///
 /// # Examples
 ///
@@ -65,7 +78,53 @@ pub async fn run_proxy(
     mut shutdown_rx: broadcast::Receiver<()>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let config = Arc::new(config);
-    let handler = ConnectionHandler::new(config.clone(), registry.as_ref());
+    let handler = ConnectionHandler::new(config.clone(), registry.as_ref())?;
+
+    // Hot-reload certificates for TLS-terminating routes
+    let cert_reload_task = config
+        .tls_certificates
+        .as_ref()
+        .filter(|certs| certs.reload_interval > 0)
+        .zip(handler.certificate_store())
+        .map(|(certs, store)| {
+            tokio::spawn(store.watch(Duration::from_secs(certs.reload_interval)))
+        });
+
+    // Close pooled HTTP/1.x backend connections once expired or idle
+    let pool_cleanup_task = config
+        .connection_pool
+        .as_ref()
+        .zip(handler.connection_pool())
+        .map(|(settings, pool)| {
+            pool.start_cleanup_task(Duration::from_secs(settings.cleanup_interval.max(1)))
+        });
+
+    // Health-check pooled gRPC channels and close expired ones
+    let grpc_pool_task = handler
+        .grpc_pool()
+        .map(|pool| pool.start_health_check_task());
+
+    // Issue and renew certificates for ACME-managed routes
+    let acme_task = match (
+        config.acme.as_ref(),
+        handler.certificate_store(),
+        handler.acme_challenges(),
+    ) {
+        (Some(settings), Some(store), Some(challenges)) => {
+            let manager = AcmeManager::new(
+                settings.clone(),
+                acme::managed_domains(&config),
+                store,
+                challenges,
+            );
+            Some(tokio::spawn(manager.run()))
+        }
+        (Some(_), None, _) => {
+            warn!("acme requires tls_certificates, automatic certificates disabled");
+            None
+        }
+        _ => None,
+    };
 
     // Connection limit enforcement with semaphore
     let max_connections = config.max_connections.unwrap_or(10000);
@@ -86,15 +145,43 @@ pub async fn run_proxy(
 
     // UDP listeners for HTTP/3 and QUIC (if configured)
     let mut udp_tasks = Vec::new();
+    let mut udp_handler = None;
+    let mut http3_terminator = None;
     if let Some(ref udp_addrs) = config.udp_listen_addrs {
-        let udp_handler = UdpConnectionHandler::new((*config).clone(), registry.as_ref());
+        let mut udp = UdpConnectionHandler::new((*config).clone(), registry.as_ref());
+
+        // QUIC connections to terminate-mode routes are served locally
+        let terminates = config
+            .routes
+            .iter()
+            .flatten()
+            .any(|route| route.mode == RouteMode::Terminate);
+        let http3_enabled = config.http3_config.as_ref().is_none_or(|c| c.enabled);
+        if terminates
+            && http3_enabled
+            && let Some(store) = handler.certificate_store()
+        {
+            match QuicHandler::new(config.clone(), store) {
+                Ok(quic) => {
+                    let quic = Arc::new(quic);
+                    let addr = quic.local_addr()?;
+                    info!("Terminating HTTP/3 on {}", addr);
+                    udp = udp.with_http3_terminator(addr);
+                    let task = tokio::spawn(quic.clone().run());
+                    http3_terminator = Some((quic, task));
+                }
+                Err(e) => error!("HTTP/3 termination disabled: {}", e),
+            }
+        }
+
+        let handler = udp_handler.insert(udp);
 
         for addr_str in udp_addrs {
             let addr: SocketAddr = addr_str.parse()?;
             info!("Starting UDP listener on {}", addr);
 
             let socket = UdpSocket::bind(addr).await?;
-            let handler = udp_handler.clone();
+            let handler = handler.clone();
 
             let udp_task = tokio::spawn(async move {
                 if let Err(e) = handler.run(socket).await {
@@ -180,13 +267,21 @@ pub async fn run_proxy(
     let shutdown_timeout_secs = config.shutdown_timeout.unwrap_or(30);
     let shutdown_timeout_duration = Duration::from_secs(shutdown_timeout_secs);
 
+    // UDP sessions drain alongside TCP connections; the listeners keep
+    // forwarding for existing sessions but open no new ones
+    let udp_drain = async {
+        if let Some(ref handler) = udp_handler {
+            handler.drain(shutdown_timeout_duration).await;
+        }
+    };
+
     // Wait for all TCP connection tasks to complete with timeout
-    let tcp_shutdown_result = timeout(shutdown_timeout_duration, async {
+    let tcp_drain = timeout(shutdown_timeout_duration, async {
         for handle in connection_handles {
             let _ = handle.await;
         }
-    })
-    .await;
+    });
+    let (tcp_shutdown_result, ()) = tokio::join!(tcp_drain, udp_drain);
 
     match tcp_shutdown_result {
         Ok(_) => {
@@ -201,7 +296,11 @@ pub async fn run_proxy(
         }
     }
 
-    // Abort UDP tasks (they run indefinitely until stopped)
+    // Stop the UDP listeners once their sessions have drained
+    if let Some((quic, task)) = http3_terminator {
+        quic.close();
+        task.abort();
+    }
     if !udp_tasks.is_empty() {
         info!("Stopping {} UDP listener(s)", udp_tasks.len());
         for task in udp_tasks {
@@ -209,6 +308,19 @@ pub async fn run_proxy(
         }
     }
 
+    if let Some(task) = cert_reload_task {
+        task.abort();
+    }
+    if let Some(task) = pool_cleanup_task {
+        task.abort();
+    }
+    if let Some(task) = grpc_pool_task {
+        task.abort();
+    }
+    if let Some(task) = acme_task {
+        task.abort();
+    }
+
     info!("Proxy shutdown complete");
     Ok(())
 }
@@ -0,0 +1,198 @@
+//! gRPC over cleartext HTTP/2 (prior knowledge)
+//!
+//! A gRPC call is an HTTP/2 POST with `content-type: application/grpc` whose
+//! path names the method as `/package.Service/Method`. Calls are routed on
+//! `:authority` like any h2c request; a route's `grpc_rules` can send them to
+//! other backends or refuse them by path.
+
+use crate::hpack;
+
+/// gRPC status for calls refused by a `grpc_rules` deny rule
+pub const GRPC_STATUS_PERMISSION_DENIED: u32 = 7;
+
+/// gRPC status for calls refused because every pooled channel is busy
+pub const GRPC_STATUS_RESOURCE_EXHAUSTED: u32 = 8;
+
+/// gRPC status for calls whose backend cannot be reached
+pub const GRPC_STATUS_UNAVAILABLE: u32 = 14;
+
+/// Splits a gRPC method path into service and method
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_core::protocols::grpc::parse_grpc_path;
+///
+/// assert_eq!(
+///     parse_grpc_path("/helloworld.Greeter/SayHello"),
+///     Some(("helloworld.Greeter", "SayHello"))
+/// );
+/// assert_eq!(parse_grpc_path("/index.html"), None);
+/// ```
+pub fn parse_grpc_path(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains(['/', '?']) {
+        return None;
+    }
+    Some((service, method))
+}
+
+/// Builds a server reply that refuses the call on `stream_id` and closes
+/// the connection
+///
+/// The reply is the server's SETTINGS, an acknowledgment of the client's
+/// SETTINGS, a Trailers-Only response carrying `status` and `message`, and a
+/// GOAWAY with NO_ERROR.
+pub fn trailers_only_response(stream_id: u32, status: u32, message: &str) -> Vec<u8> {
+    let mut block = vec![0x88]; // :status 200
+    hpack::encode_literal("content-type", "application/grpc", &mut block);
+    hpack::encode_literal("grpc-status", &status.to_string(), &mut block);
+    hpack::encode_literal("grpc-message", &percent_encode(message), &mut block);
+    refusal(stream_id, &block)
+}
+
+/// Builds a server reply that answers the plain h2c request on `stream_id`
+/// with a bodyless `status` and closes the connection
+///
+/// The frames are the same as [`trailers_only_response`], with an HTTP
+/// status in place of the gRPC one.
+pub fn status_only_response(stream_id: u32, status: u16) -> Vec<u8> {
+    let mut block = Vec::new();
+    hpack::encode_literal(":status", &status.to_string(), &mut block);
+    hpack::encode_literal("content-length", "0", &mut block);
+    refusal(stream_id, &block)
+}
+
+/// SETTINGS, SETTINGS ACK, a final HEADERS frame carrying `block`, and GOAWAY
+fn refusal(stream_id: u32, block: &[u8]) -> Vec<u8> {
+    let mut goaway = stream_id.to_be_bytes().to_vec();
+    goaway.extend_from_slice(&[0, 0, 0, 0]);
+
+    let mut out = Vec::new();
+    push_frame(&mut out, 0x4, 0x0, 0, &[]); // SETTINGS
+    push_frame(&mut out, 0x4, 0x1, 0, &[]); // SETTINGS ACK
+    push_frame(&mut out, 0x1, 0x5, stream_id, block); // HEADERS, END_STREAM | END_HEADERS
+    push_frame(&mut out, 0x7, 0x0, 0, &goaway); // GOAWAY
+    out
+}
+
+/// Builds a Trailers-Only response head carrying `status` and `message`
+///
+/// Used when the proxy terminates the client's HTTP/2 connection and refuses
+/// a single call; the head is sent with END_STREAM.
+pub fn trailers_only_head(status: u32, message: &str) -> hyper::Response<()> {
+    let mut response = hyper::Response::new(());
+    let headers = response.headers_mut();
+    headers.insert(
+        "content-type",
+        hyper::header::HeaderValue::from_static("application/grpc"),
+    );
+    headers.insert("grpc-status", status.into());
+    if let Ok(message) = percent_encode(message).parse() {
+        headers.insert("grpc-message", message);
+    }
+    response
+}
+
+fn push_frame(out: &mut Vec<u8>, frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    out.extend_from_slice(&[frame_type, flags]);
+    out.extend_from_slice(&stream_id.to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// Percent-encodes a grpc-message value (printable ASCII except '%' is kept)
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..=0x7e).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hpack::HpackDecoder;
+
+    #[test]
+    fn test_parse_grpc_path() {
+        assert_eq!(
+            parse_grpc_path("/grpc.health.v1.Health/Check"),
+            Some(("grpc.health.v1.Health", "Check"))
+        );
+        assert_eq!(parse_grpc_path("/"), None);
+        assert_eq!(parse_grpc_path("//Method"), None);
+        assert_eq!(parse_grpc_path("/Service/"), None);
+        assert_eq!(parse_grpc_path("/a/b/c"), None);
+        assert_eq!(parse_grpc_path("/a/b?x=1"), None);
+        assert_eq!(parse_grpc_path("Service/Method"), None);
+    }
+
+    #[test]
+    fn test_trailers_only_response() {
+        let response = trailers_only_response(5, GRPC_STATUS_PERMISSION_DENIED, "denied 100%");
+
+        // SETTINGS, SETTINGS ACK, HEADERS, GOAWAY
+        let mut frames = Vec::new();
+        let mut rest = response.as_slice();
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([0, rest[0], rest[1], rest[2]]) as usize;
+            let stream_id = u32::from_be_bytes([rest[5], rest[6], rest[7], rest[8]]);
+            frames.push((rest[3], rest[4], stream_id, rest[9..9 + len].to_vec()));
+            rest = &rest[9 + len..];
+        }
+        assert_eq!(frames.len(), 4);
+        assert_eq!((frames[0].0, frames[0].1), (0x4, 0x0));
+        assert_eq!((frames[1].0, frames[1].1), (0x4, 0x1));
+        assert_eq!((frames[2].0, frames[2].1, frames[2].2), (0x1, 0x5, 5));
+        assert_eq!(
+            (frames[3].0, frames[3].3.as_slice()),
+            (0x7, &[0, 0, 0, 5, 0, 0, 0, 0][..])
+        );
+
+        let headers = HpackDecoder::default().decode(&frames[2].3).unwrap();
+        let field = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(field(":status"), Some("200"));
+        assert_eq!(field("content-type"), Some("application/grpc"));
+        assert_eq!(field("grpc-status"), Some("7"));
+        assert_eq!(field("grpc-message"), Some("denied 100%25"));
+    }
+
+    #[test]
+    fn test_status_only_response() {
+        let response = status_only_response(3, 403);
+        // SETTINGS (9) + SETTINGS ACK (9) put the HEADERS frame at offset 18
+        let headers = &response[18..];
+        let len = u32::from_be_bytes([0, headers[0], headers[1], headers[2]]) as usize;
+        assert_eq!((headers[3], headers[4]), (0x1, 0x5));
+        assert_eq!(
+            u32::from_be_bytes([headers[5], headers[6], headers[7], headers[8]]),
+            3
+        );
+        let fields = HpackDecoder::default()
+            .decode(&headers[9..9 + len])
+            .unwrap();
+        assert!(fields.contains(&(":status".to_string(), "403".to_string())));
+        assert!(fields.contains(&("content-length".to_string(), "0".to_string())));
+        assert_eq!(&headers[9 + len + 3..9 + len + 5], &[0x7, 0x0]); // GOAWAY
+    }
+
+    #[test]
+    fn test_trailers_only_head() {
+        let response = trailers_only_head(GRPC_STATUS_UNAVAILABLE, "no backend\n");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/grpc");
+        assert_eq!(response.headers()["grpc-status"], "14");
+        assert_eq!(response.headers()["grpc-message"], "no backend%0A");
+    }
+}
@@ -0,0 +1,215 @@
+//! PROXY protocol version 2 header encoding
+//!
+//! Backends that cannot see the client's TLS session (because the proxy
+//! terminated it) learn the original addresses and the verified client
+//! identity from a binary PROXY v2 header sent before any stream data.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>
+
+use std::net::SocketAddr;
+
+/// Fixed 12-byte signature opening every v2 header
+pub const SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+const VERSION_2_PROXY: u8 = 0x21;
+const VERSION_2_LOCAL: u8 = 0x20;
+const FAMILY_TCP4: u8 = 0x11;
+const FAMILY_TCP6: u8 = 0x21;
+const FAMILY_UNSPEC: u8 = 0x00;
+
+/// TLV carrying the server name the client asked for (SNI)
+pub const PP2_TYPE_AUTHORITY: u8 = 0x02;
+/// TLV carrying TLS session details as sub-TLVs
+pub const PP2_TYPE_SSL: u8 = 0x20;
+/// SSL sub-TLV: negotiated protocol version (e.g. "TLSv1.3")
+pub const PP2_SUBTYPE_SSL_VERSION: u8 = 0x21;
+/// SSL sub-TLV: client certificate subject common name
+pub const PP2_SUBTYPE_SSL_CN: u8 = 0x22;
+/// Client-defined TLV carrying the verified client identity (first URI/DNS SAN or CN)
+pub const PP2_TYPE_CLIENT_IDENTITY: u8 = 0xE0;
+
+/// `client` field flag: connection used TLS
+pub const PP2_CLIENT_SSL: u8 = 0x01;
+/// `client` field flag: client presented a certificate on this connection
+pub const PP2_CLIENT_CERT_CONN: u8 = 0x02;
+
+/// A type-length-value extension appended to the address block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+impl Tlv {
+    pub fn new(kind: u8, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind,
+            value: value.into(),
+        }
+    }
+
+    /// Builds a `PP2_TYPE_SSL` TLV
+    ///
+    /// `verified` reports a successfully verified client certificate; the
+    /// sub-TLVs follow the fixed `client` and `verify` fields.
+    pub fn ssl(client_flags: u8, verified: bool, sub_tlvs: &[Tlv]) -> Self {
+        let mut value = Vec::with_capacity(5);
+        value.push(client_flags);
+        value.extend_from_slice(&(if verified { 0u32 } else { 1u32 }).to_be_bytes());
+        for tlv in sub_tlvs {
+            tlv.encode_into(&mut value);
+        }
+        Self::new(PP2_TYPE_SSL, value)
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        out.push(self.kind);
+        out.extend_from_slice(&(self.value.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.value);
+    }
+}
+
+/// Encodes a PROXY v2 header for a proxied TCP connection
+///
+/// Mixed address families (IPv4 source, IPv6 destination or vice versa) are
+/// encoded with IPv4 addresses mapped into IPv6.
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_core::proxy_protocol::{encode_v2, SIGNATURE, Tlv, PP2_TYPE_AUTHORITY};
+///
+/// let header = encode_v2(
+///     Some(("192.0.2.1:50000".parse().unwrap(), "198.51.100.7:443".parse().unwrap())),
+///     &[Tlv::new(PP2_TYPE_AUTHORITY, "example.com")],
+/// );
+/// assert_eq!(&header[..12], &SIGNATURE);
+/// assert_eq!(header[13], 0x11); // TCP over IPv4
+/// ```
+pub fn encode_v2(addresses: Option<(SocketAddr, SocketAddr)>, tlvs: &[Tlv]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(36 + tlvs.iter().map(|t| 3 + t.value.len()).sum::<usize>());
+
+    let (command, family) = match addresses {
+        Some((SocketAddr::V4(source), SocketAddr::V4(destination))) => {
+            body.extend_from_slice(&source.ip().octets());
+            body.extend_from_slice(&destination.ip().octets());
+            body.extend_from_slice(&source.port().to_be_bytes());
+            body.extend_from_slice(&destination.port().to_be_bytes());
+            (VERSION_2_PROXY, FAMILY_TCP4)
+        }
+        Some((source, destination)) => {
+            body.extend_from_slice(&to_ipv6(source).octets());
+            body.extend_from_slice(&to_ipv6(destination).octets());
+            body.extend_from_slice(&source.port().to_be_bytes());
+            body.extend_from_slice(&destination.port().to_be_bytes());
+            (VERSION_2_PROXY, FAMILY_TCP6)
+        }
+        None => (VERSION_2_LOCAL, FAMILY_UNSPEC),
+    };
+
+    for tlv in tlvs {
+        tlv.encode_into(&mut body);
+    }
+
+    let mut header = Vec::with_capacity(16 + body.len());
+    header.extend_from_slice(&SIGNATURE);
+    header.push(command);
+    header.push(family);
+    header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    header.extend_from_slice(&body);
+    header
+}
+
+#[inline]
+fn to_ipv6(address: SocketAddr) -> std::net::Ipv6Addr {
+    match address {
+        SocketAddr::V4(v4) => v4.ip().to_ipv6_mapped(),
+        SocketAddr::V6(v6) => *v6.ip(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_v2_ipv4() {
+        let header = encode_v2(
+            Some((
+                "10.0.0.1:40000".parse().unwrap(),
+                "10.0.0.2:443".parse().unwrap(),
+            )),
+            &[],
+        );
+
+        assert_eq!(&header[..12], &SIGNATURE);
+        assert_eq!(header[12], 0x21);
+        assert_eq!(header[13], 0x11);
+        assert_eq!(u16::from_be_bytes([header[14], header[15]]), 12);
+        assert_eq!(&header[16..20], &[10, 0, 0, 1]);
+        assert_eq!(&header[20..24], &[10, 0, 0, 2]);
+        assert_eq!(u16::from_be_bytes([header[24], header[25]]), 40000);
+        assert_eq!(u16::from_be_bytes([header[26], header[27]]), 443);
+    }
+
+    #[test]
+    fn test_encode_v2_mixed_families_use_ipv6() {
+        let header = encode_v2(
+            Some((
+                "10.0.0.1:40000".parse().unwrap(),
+                "[2001:db8::1]:443".parse().unwrap(),
+            )),
+            &[],
+        );
+
+        assert_eq!(header[13], 0x21);
+        assert_eq!(u16::from_be_bytes([header[14], header[15]]), 36);
+        assert_eq!(
+            &header[16..32],
+            &"::ffff:10.0.0.1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+    }
+
+    #[test]
+    fn test_encode_v2_local_without_addresses() {
+        let header = encode_v2(None, &[]);
+        assert_eq!(header.len(), 16);
+        assert_eq!(header[12], 0x20);
+        assert_eq!(header[13], 0x00);
+    }
+
+    #[test]
+    fn test_encode_v2_ssl_tlv() {
+        let ssl = Tlv::ssl(
+            PP2_CLIENT_SSL | PP2_CLIENT_CERT_CONN,
+            true,
+            &[
+                Tlv::new(PP2_SUBTYPE_SSL_VERSION, "TLSv1.3"),
+                Tlv::new(PP2_SUBTYPE_SSL_CN, "client.example.com"),
+            ],
+        );
+        let header = encode_v2(
+            Some((
+                "10.0.0.1:40000".parse().unwrap(),
+                "10.0.0.2:443".parse().unwrap(),
+            )),
+            std::slice::from_ref(&ssl),
+        );
+
+        let tlv = &header[28..];
+        assert_eq!(tlv[0], PP2_TYPE_SSL);
+        let length = u16::from_be_bytes([tlv[1], tlv[2]]) as usize;
+        assert_eq!(length, tlv.len() - 3);
+        assert_eq!(tlv[3], 0x03); // client flags
+        assert_eq!(&tlv[4..8], &[0, 0, 0, 0]); // verified
+        assert_eq!(tlv[8], PP2_SUBTYPE_SSL_VERSION);
+        assert_eq!(&tlv[11..18], b"TLSv1.3");
+        assert_eq!(tlv[18], PP2_SUBTYPE_SSL_CN);
+        assert_eq!(&tlv[21..], b"client.example.com");
+    }
+}
@@ -1,30 +1,61 @@
-//! QPACK Dynamic Table Optimization (RFC 9204)
+//! QPACK header compression (RFC 9204)
 //!
-//! This module implements QPACK header compression for HTTP/3, providing
-//! efficient header compression through a dynamic table of previously seen
-//! header field values.
+//! QPACK compresses HTTP/3 field sections against a static table of common
+//! header fields and a dynamic table that the encoder fills over its encoder
+//! stream. Because QUIC streams are delivered independently, a field section
+//! may reference entries the decoder has not received yet; such a section is
+//! blocked until the inserts arrive.
 //!
-//! # Features
+//! # Architecture
 //!
-//! - Dynamic table with configurable capacity
-//! - Header field indexing and lookup
-//! - 30% compression improvement over static tables
-//! - Memory-efficient eviction strategy
-//! - Thread-safe concurrent access
+//! - `QpackDynamicTable`: the dynamic table, addressed by absolute index
+//! - `QpackEncoder`: encodes field sections and emits encoder stream
+//!   instructions, consuming the peer's decoder stream
+//! - `QpackDecoder`: decodes field sections, holding blocked ones until the
+//!   encoder stream catches up, and emits decoder stream instructions
 //!
-//! # Architecture
+//! Field sections use relative indexing for entries inserted before the
+//! section started and post-base indexing for entries inserted while encoding
+//! it. Huffman coding (RFC 7541 Appendix B) is applied to string literals
+//! whenever it makes them shorter.
 //!
-//! QPACK is the header compression mechanism for HTTP/3, replacing HPACK
-//! from HTTP/2. It uses:
-//! - Static table: Predefined common headers
-//! - Dynamic table: Recently used headers (this module)
-//! - Encoder stream: Table updates
-//! - Decoder stream: Acknowledgments
-
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+//! # Example
+//!
+//! ```
+//! use sniproxy_core::qpack::{QpackConfig, QpackDecoder, QpackEncoder};
+//!
+//! let mut encoder = QpackEncoder::new(QpackConfig::default());
+//! let mut decoder = QpackDecoder::new(QpackConfig::default());
+//!
+//! let headers = vec![
+//!     (":method".to_string(), "GET".to_string()),
+//!     ("x-request-id".to_string(), "42".to_string()),
+//! ];
+//! let block = encoder.encode(0, &headers);
+//!
+//! // The block references an entry inserted on the encoder stream
+//! assert_eq!(decoder.decode(0, &block).unwrap(), None);
+//! let unblocked = decoder
+//!     .on_encoder_instructions(&encoder.encoder_instructions())
+//!     .unwrap();
+//! assert_eq!(unblocked, vec![(0, headers)]);
+//!
+//! encoder
+//!     .on_decoder_instructions(&decoder.decoder_instructions())
+//!     .unwrap();
+//! assert_eq!(encoder.blocked_streams(), 0);
+//! ```
+
+use crate::huffman;
+use sniproxy_config::Http3Config;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
 
 /// Configuration for QPACK dynamic table
+///
+/// For the encoder, the limits are those the peer decoder advertised in its
+/// SETTINGS; for the decoder, those it advertises.
 #[derive(Debug, Clone)]
 pub struct QpackConfig {
     /// Enable QPACK compression (default: true)
@@ -48,6 +79,304 @@ impl Default for QpackConfig {
     }
 }
 
+impl From<&Http3Config> for QpackConfig {
+    fn from(config: &Http3Config) -> Self {
+        Self {
+            max_table_capacity: config.qpack_max_table_capacity,
+            max_blocked_streams: config.qpack_blocked_streams,
+            ..Self::default()
+        }
+    }
+}
+
+/// QPACK errors, each mapping to an HTTP/3 error code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QpackError {
+    /// A field section could not be decoded
+    DecompressionFailed(String),
+    /// An encoder stream instruction could not be processed
+    EncoderStream(String),
+    /// A decoder stream instruction could not be processed
+    DecoderStream(String),
+}
+
+impl QpackError {
+    /// HTTP/3 error code to close the connection with (RFC 9204 §6)
+    pub fn code(&self) -> u64 {
+        match self {
+            QpackError::DecompressionFailed(_) => 0x0200,
+            QpackError::EncoderStream(_) => 0x0201,
+            QpackError::DecoderStream(_) => 0x0202,
+        }
+    }
+}
+
+impl fmt::Display for QpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QpackError::DecompressionFailed(msg) => {
+                write!(f, "QPACK decompression failed: {}", msg)
+            }
+            QpackError::EncoderStream(msg) => write!(f, "QPACK encoder stream error: {}", msg),
+            QpackError::DecoderStream(msg) => write!(f, "QPACK decoder stream error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for QpackError {}
+
+/// Static table (RFC 9204 Appendix A)
+pub const STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains",
+    ),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains; preload",
+    ),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    (
+        "content-security-policy",
+        "script-src 'none'; object-src 'none'; base-uri 'none'",
+    ),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+/// Headers carrying credentials: never inserted into the dynamic table and
+/// marked never-indexed, so intermediaries do not either
+const SENSITIVE_HEADERS: [&str; 2] = ["authorization", "proxy-authorization"];
+
+/// Index of an exact match in the static table
+fn static_index(name: &str, value: &str) -> Option<usize> {
+    STATIC_TABLE
+        .iter()
+        .position(|&(n, v)| n == name && v == value)
+}
+
+/// Index of the first static entry with this name
+fn static_name_index(name: &str) -> Option<usize> {
+    STATIC_TABLE.iter().position(|&(n, _)| n == name)
+}
+
+/// Appends an integer with an N-bit prefix (RFC 9204 §4.1.1), OR-ing `flags`
+/// into the first octet
+fn encode_int(out: &mut Vec<u8>, prefix_bits: u8, flags: u8, mut value: u64) {
+    let max = (1u64 << prefix_bits) - 1;
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | max as u8);
+    value -= max;
+    while value >= 0x80 {
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Appends a string literal whose length has an N-bit prefix; the Huffman
+/// flag is the bit above the prefix
+fn encode_string(out: &mut Vec<u8>, prefix_bits: u8, flags: u8, value: &str, huffman: bool) {
+    let value = value.as_bytes();
+    let coded_len = huffman::encoded_len(value);
+    if huffman && coded_len < value.len() {
+        encode_int(
+            out,
+            prefix_bits,
+            flags | (1 << prefix_bits),
+            coded_len as u64,
+        );
+        huffman::encode(value, out);
+    } else {
+        encode_int(out, prefix_bits, flags, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+}
+
+/// Why an instruction or field line could not be read
+enum ReadError {
+    /// More input is needed
+    Incomplete,
+    /// The input is malformed
+    Invalid(String),
+}
+
+/// Cursor over QPACK instructions or field lines
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn peek(&self) -> Result<u8, ReadError> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or(ReadError::Incomplete)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReadError> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Reads an integer with an N-bit prefix
+    fn int(&mut self, prefix_bits: u8) -> Result<u64, ReadError> {
+        let max = (1u64 << prefix_bits) - 1;
+        let mut value = (self.byte()? as u64) & max;
+        if value < max {
+            return Ok(value);
+        }
+
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 56 {
+                return Err(ReadError::Invalid("integer too large".into()));
+            }
+            value = value
+                .checked_add(((byte & 0x7f) as u64) << shift)
+                .ok_or_else(|| ReadError::Invalid("integer too large".into()))?;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Reads a string literal with an N-bit length prefix, at most `max_len`
+    /// octets on the wire
+    fn string(&mut self, prefix_bits: u8, max_len: usize) -> Result<String, ReadError> {
+        let huffman_coded = self.peek()? & (1 << prefix_bits) != 0;
+        let len = self.int(prefix_bits)?;
+        if len > max_len as u64 {
+            return Err(ReadError::Invalid(format!(
+                "string of {} octets too long",
+                len
+            )));
+        }
+        let len = len as usize;
+        if self.data.len() - self.pos < len {
+            return Err(ReadError::Incomplete);
+        }
+
+        let raw = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        let bytes = if huffman_coded {
+            huffman::decode(raw).map_err(|e| ReadError::Invalid(e.to_string()))?
+        } else {
+            raw.to_vec()
+        };
+        String::from_utf8(bytes).map_err(|_| ReadError::Invalid("field is not valid UTF-8".into()))
+    }
+}
+
 /// A header field in the dynamic table
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HeaderField {
@@ -72,38 +401,83 @@ impl HeaderField {
     }
 }
 
+/// Dynamic table contents
+#[derive(Debug, Default)]
+struct TableState {
+    /// Entries, newest first
+    entries: VecDeque<HeaderField>,
+    /// Sum of entry sizes
+    size: usize,
+    capacity: usize,
+    /// Entries ever inserted; the newest entry's absolute index is one less
+    insert_count: u64,
+}
+
+impl TableState {
+    /// Absolute index of the oldest entry still in the table
+    fn first_index(&self) -> u64 {
+        self.insert_count - self.entries.len() as u64
+    }
+
+    /// Evicts the oldest entries until `size` bytes fit; returns the count
+    fn evict_for(&mut self, size: usize) -> usize {
+        let mut evicted = 0;
+        while self.size + size > self.capacity {
+            match self.entries.pop_back() {
+                Some(field) => {
+                    self.size -= field.size();
+                    evicted += 1;
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
 /// QPACK Dynamic Table
 ///
-/// Maintains a FIFO queue of recently used header fields for compression.
+/// Entries have an absolute index, counting every insertion since the
+/// connection started. Methods without "absolute" in their name use the
+/// relative index, 0 being the most recent entry.
 pub struct QpackDynamicTable {
     config: QpackConfig,
-    /// Dynamic table entries (FIFO)
-    entries: Arc<Mutex<VecDeque<HeaderField>>>,
-    /// Current table size in bytes
-    current_size: Arc<Mutex<usize>>,
+    state: Mutex<TableState>,
     /// Statistics
-    stats: Arc<Mutex<QpackStats>>,
+    stats: Mutex<QpackStats>,
 }
 
 impl QpackDynamicTable {
     /// Create a new QPACK dynamic table
     ///
+    /// The capacity starts at `max_table_capacity`, or 0 when disabled.
+    ///
     /// # Arguments
     /// * `config` - QPACK configuration
     ///
     /// # Returns
     /// * `Self` - New dynamic table instance
     pub fn new(config: QpackConfig) -> Self {
+        let capacity = if config.enabled {
+            config.max_table_capacity
+        } else {
+            0
+        };
         Self {
             config,
-            entries: Arc::new(Mutex::new(VecDeque::new())),
-            current_size: Arc::new(Mutex::new(0)),
-            stats: Arc::new(Mutex::new(QpackStats::default())),
+            state: Mutex::new(TableState {
+                capacity,
+                ..TableState::default()
+            }),
+            stats: Mutex::new(QpackStats::default()),
         }
     }
 
     /// Insert a header field into the dynamic table
     ///
+    /// Older entries are evicted to make room. A field larger than the
+    /// capacity is not inserted.
+    ///
     /// # Arguments
     /// * `name` - Header name
     /// * `value` - Header value
@@ -118,26 +492,20 @@ impl QpackDynamicTable {
         let field = HeaderField::new(name, value);
         let field_size = field.size();
 
-        let mut entries = self.entries.lock().unwrap();
-        let mut current_size = self.current_size.lock().unwrap();
-
-        // Evict entries if needed to make space
-        while *current_size + field_size > self.config.max_table_capacity && !entries.is_empty() {
-            if let Some(evicted) = entries.pop_back() {
-                *current_size -= evicted.size();
-                self.stats.lock().unwrap().evictions += 1;
-            }
+        let mut state = self.state.lock().unwrap();
+        if field_size > state.capacity {
+            return 0;
         }
 
-        // Only insert if it fits
-        if field_size <= self.config.max_table_capacity {
-            entries.push_front(field);
-            *current_size += field_size;
-            self.stats.lock().unwrap().insertions += 1;
-            0 // Return index 0 (most recent)
-        } else {
-            0
-        }
+        let evicted = state.evict_for(field_size);
+        state.entries.push_front(field);
+        state.size += field_size;
+        state.insert_count += 1;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.evictions += evicted;
+        stats.insertions += 1;
+        0 // Return index 0 (most recent)
     }
 
     /// Look up a header field by index
@@ -152,8 +520,22 @@ impl QpackDynamicTable {
             return None;
         }
 
-        let entries = self.entries.lock().unwrap();
-        entries.get(index).cloned().inspect(|_field| {
+        let state = self.state.lock().unwrap();
+        state.entries.get(index).cloned().inspect(|_field| {
+            self.stats.lock().unwrap().lookups += 1;
+        })
+    }
+
+    /// Look up a header field by absolute index
+    ///
+    /// Returns `None` if the entry was evicted or not inserted yet.
+    pub fn get_absolute(&self, index: u64) -> Option<HeaderField> {
+        let state = self.state.lock().unwrap();
+        if index < state.first_index() || index >= state.insert_count {
+            return None;
+        }
+        let relative = (state.insert_count - 1 - index) as usize;
+        state.entries.get(relative).cloned().inspect(|_field| {
             self.stats.lock().unwrap().lookups += 1;
         })
     }
@@ -171,8 +553,8 @@ impl QpackDynamicTable {
             return None;
         }
 
-        let entries = self.entries.lock().unwrap();
-        for (index, field) in entries.iter().enumerate() {
+        let state = self.state.lock().unwrap();
+        for (index, field) in state.entries.iter().enumerate() {
             if field.name == name && field.value == value {
                 self.stats.lock().unwrap().hits += 1;
                 return Some(index);
@@ -195,29 +577,86 @@ impl QpackDynamicTable {
             return None;
         }
 
-        let entries = self.entries.lock().unwrap();
-        entries.iter().position(|field| field.name == name)
+        let state = self.state.lock().unwrap();
+        state.entries.iter().position(|field| field.name == name)
+    }
+
+    /// Number of entries inserted since the table was created
+    pub fn insert_count(&self) -> u64 {
+        self.state.lock().unwrap().insert_count
+    }
+
+    /// Current capacity in bytes
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().capacity
+    }
+
+    /// Changes the capacity, evicting entries that no longer fit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `capacity` exceeds `max_table_capacity`, or is not
+    /// 0 while the table is disabled.
+    pub fn set_capacity(&self, capacity: usize) -> Result<(), String> {
+        let max = if self.config.enabled {
+            self.config.max_table_capacity
+        } else {
+            0
+        };
+        if capacity > max {
+            return Err(format!("capacity {} exceeds maximum of {}", capacity, max));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.capacity = capacity;
+        let evicted = state.evict_for(0);
+        self.stats.lock().unwrap().evictions += evicted;
+        Ok(())
+    }
+
+    /// Whether an entry of `size` bytes can be inserted without evicting
+    /// any entry at or above the absolute index `pinned_from`
+    pub fn can_insert(&self, size: usize, pinned_from: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        if !self.config.enabled || size > state.capacity {
+            return false;
+        }
+
+        let mut free = state.capacity - state.size;
+        for (index, field) in (state.first_index()..).zip(state.entries.iter().rev()) {
+            if free >= size {
+                break;
+            }
+            if index >= pinned_from {
+                return false;
+            }
+            free += field.size();
+        }
+        free >= size
     }
 
     /// Get current table size in bytes
     pub fn size(&self) -> usize {
-        *self.current_size.lock().unwrap()
+        self.state.lock().unwrap().size
     }
 
     /// Get number of entries in the table
     pub fn len(&self) -> usize {
-        self.entries.lock().unwrap().len()
+        self.state.lock().unwrap().entries.len()
     }
 
     /// Check if table is empty
     pub fn is_empty(&self) -> bool {
-        self.entries.lock().unwrap().is_empty()
+        self.state.lock().unwrap().entries.is_empty()
     }
 
     /// Clear all entries from the table
+    ///
+    /// Absolute indices keep counting from the previous insert count.
     pub fn clear(&self) {
-        self.entries.lock().unwrap().clear();
-        *self.current_size.lock().unwrap() = 0;
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.size = 0;
     }
 
     /// Get compression ratio
@@ -273,60 +712,359 @@ impl QpackStats {
     }
 }
 
-/// QPACK Encoder (placeholder for future full implementation)
-///
-/// # Future Implementation
+/// Largest number of entries the dynamic table can hold (RFC 9204 §3.2.2)
+fn max_entries(config: &QpackConfig) -> u64 {
+    if config.enabled {
+        config.max_table_capacity as u64 / 32
+    } else {
+        0
+    }
+}
+
+/// How the encoder represents one field line
+enum FieldLine<'a> {
+    /// Static table entry
+    Static(usize),
+    /// Dynamic table entry, by absolute index
+    Dynamic(u64),
+    /// Literal value with a static table name
+    StaticName {
+        index: usize,
+        value: &'a str,
+        never_indexed: bool,
+    },
+    /// Literal value with a dynamic table name, by absolute index
+    DynamicName { index: u64, value: &'a str },
+    /// Literal name and value
+    Literal {
+        name: &'a str,
+        value: &'a str,
+        never_indexed: bool,
+    },
+}
+
+impl FieldLine<'_> {
+    /// Absolute dynamic table index this line references
+    fn dynamic_reference(&self) -> Option<u64> {
+        match *self {
+            FieldLine::Dynamic(index) | FieldLine::DynamicName { index, .. } => Some(index),
+            _ => None,
+        }
+    }
+}
+
+/// A field section the decoder has not acknowledged yet
+#[derive(Debug)]
+struct OutstandingSection {
+    required_insert_count: u64,
+    /// Smallest absolute index referenced, which must not be evicted
+    min_reference: u64,
+}
+
+/// QPACK Encoder
 ///
-/// A full QPACK encoder would:
-/// - Encode header fields using the dynamic table
-/// - Generate indexed representations for known headers
-/// - Use literal representations for new headers
-/// - Apply Huffman encoding to strings
-/// - Manage encoder stream for table updates
+/// Inserts fields into the dynamic table when they fit without evicting an
+/// entry an unacknowledged field section references. A field section may
+/// reference entries the decoder has not acknowledged only while fewer than
+/// `max_blocked_streams` streams are blocked; otherwise it uses the static
+/// table and literals.
 pub struct QpackEncoder {
     table: QpackDynamicTable,
+    max_entries: u64,
+    max_blocked_streams: usize,
+    huffman: bool,
+    /// Inserts the decoder is known to have processed
+    known_received_count: u64,
+    /// Unacknowledged field sections per stream, oldest first
+    outstanding: HashMap<u64, VecDeque<OutstandingSection>>,
+    /// Encoder stream bytes not yet sent
+    instructions: Vec<u8>,
+    /// Incomplete decoder stream instruction
+    decoder_stream: Vec<u8>,
 }
 
 impl QpackEncoder {
     /// Create a new QPACK encoder
+    ///
+    /// `config` holds the peer decoder's settings. With a nonzero capacity,
+    /// the first encoder stream instruction sets the table capacity.
     pub fn new(config: QpackConfig) -> Self {
+        let mut instructions = Vec::new();
+        let capacity = if config.enabled {
+            config.max_table_capacity
+        } else {
+            0
+        };
+        if capacity > 0 {
+            // Set Dynamic Table Capacity
+            encode_int(&mut instructions, 5, 0x20, capacity as u64);
+        }
+
         Self {
+            max_entries: max_entries(&config),
+            max_blocked_streams: config.max_blocked_streams as usize,
+            huffman: config.huffman_encoding,
             table: QpackDynamicTable::new(config),
+            known_received_count: 0,
+            outstanding: HashMap::new(),
+            instructions,
+            decoder_stream: Vec::new(),
         }
     }
 
-    /// Encode header fields (placeholder)
+    /// Encodes a field section for `stream_id`
+    ///
+    /// Entries inserted for it are written to the encoder stream, see
+    /// `encoder_instructions`.
     ///
     /// # Arguments
+    /// * `stream_id` - Stream the field section is sent on
     /// * `headers` - Header fields to encode
     ///
     /// # Returns
-    /// * `Vec<u8>` - Encoded header block
-    ///
-    /// # Implementation Note
-    ///
-    /// This is a placeholder. Full implementation would use the QPACK
-    /// encoding algorithm from RFC 9204 to generate compressed header blocks.
-    pub fn encode(&mut self, headers: &[(String, String)]) -> Vec<u8> {
-        let mut encoded = Vec::new();
+    /// * `Vec<u8>` - Encoded field section
+    pub fn encode(&mut self, stream_id: u64, headers: &[(String, String)]) -> Vec<u8> {
+        // Entries inserted from here on are referenced post-base
+        let base = self.table.insert_count();
+        let may_block =
+            self.is_blocked(stream_id) || self.blocked_streams() < self.max_blocked_streams;
+
+        let mut pinned_from = self
+            .outstanding
+            .values()
+            .flatten()
+            .map(|section| section.min_reference)
+            .min()
+            .unwrap_or(u64::MAX);
+        let mut required_insert_count = 0;
+        let mut lines = Vec::with_capacity(headers.len());
 
         for (name, value) in headers {
-            // Try to find in dynamic table
-            if let Some(index) = self.table.find(name, value) {
-                // Indexed header field: would encode as index
-                encoded.push(0x80 | (index as u8)); // Simplified
+            let line = self.field_line(name, value, may_block, pinned_from);
+            if let Some(index) = line.dynamic_reference() {
+                required_insert_count = required_insert_count.max(index + 1);
+                pinned_from = pinned_from.min(index);
+            }
+            lines.push(line);
+        }
+
+        let mut block = Vec::new();
+        if required_insert_count == 0 {
+            block.extend_from_slice(&[0, 0]);
+        } else {
+            let encoded = required_insert_count % (2 * self.max_entries) + 1;
+            encode_int(&mut block, 8, 0, encoded);
+            if base >= required_insert_count {
+                encode_int(&mut block, 7, 0, base - required_insert_count);
+            } else {
+                encode_int(&mut block, 7, 0x80, required_insert_count - base - 1);
+            }
+
+            self.outstanding
+                .entry(stream_id)
+                .or_default()
+                .push_back(OutstandingSection {
+                    required_insert_count,
+                    min_reference: pinned_from,
+                });
+        }
+
+        for line in &lines {
+            self.write_field_line(&mut block, line, base);
+        }
+        block
+    }
+
+    /// Picks the representation of one field, inserting it if worthwhile
+    fn field_line<'a>(
+        &mut self,
+        name: &'a str,
+        value: &'a str,
+        may_block: bool,
+        pinned_from: u64,
+    ) -> FieldLine<'a> {
+        if let Some(index) = static_index(name, value) {
+            return FieldLine::Static(index);
+        }
+
+        let sensitive = SENSITIVE_HEADERS.contains(&name);
+        let insert_count = self.table.insert_count();
+        let usable = |index: u64| index < self.known_received_count || may_block;
+        let static_name = static_name_index(name);
+        let dynamic_name = self
+            .table
+            .find_name(name)
+            .map(|relative| insert_count - 1 - relative as u64);
+
+        if !sensitive {
+            if let Some(relative) = self.table.find(name, value) {
+                let index = insert_count - 1 - relative as u64;
+                if usable(index) {
+                    return FieldLine::Dynamic(index);
+                }
+            }
+
+            let size = name.len() + value.len() + 32;
+            if may_block && self.table.can_insert(size, pinned_from) {
+                match (static_name, dynamic_name) {
+                    (Some(index), _) => {
+                        // Insert with Name Reference, static table
+                        encode_int(&mut self.instructions, 6, 0xc0, index as u64);
+                    }
+                    (None, Some(index)) => {
+                        // Insert with Name Reference, dynamic table
+                        encode_int(&mut self.instructions, 6, 0x80, insert_count - 1 - index);
+                    }
+                    (None, None) => {
+                        // Insert with Literal Name
+                        encode_string(&mut self.instructions, 5, 0x40, name, self.huffman);
+                    }
+                }
+                encode_string(&mut self.instructions, 7, 0x00, value, self.huffman);
+                self.table.insert(name.to_string(), value.to_string());
+                return FieldLine::Dynamic(insert_count);
+            }
+        }
+
+        if let Some(index) = static_name {
+            return FieldLine::StaticName {
+                index,
+                value,
+                never_indexed: sensitive,
+            };
+        }
+        if !sensitive
+            && let Some(index) = dynamic_name
+            && usable(index)
+        {
+            return FieldLine::DynamicName { index, value };
+        }
+        FieldLine::Literal {
+            name,
+            value,
+            never_indexed: sensitive,
+        }
+    }
+
+    /// Appends a field line representation relative to `base`
+    fn write_field_line(&self, out: &mut Vec<u8>, line: &FieldLine<'_>, base: u64) {
+        match *line {
+            FieldLine::Static(index) => encode_int(out, 6, 0xc0, index as u64),
+            FieldLine::Dynamic(index) if index < base => encode_int(out, 6, 0x80, base - 1 - index),
+            FieldLine::Dynamic(index) => encode_int(out, 4, 0x10, index - base),
+            FieldLine::StaticName {
+                index,
+                value,
+                never_indexed,
+            } => {
+                let flags = 0x50 | if never_indexed { 0x20 } else { 0 };
+                encode_int(out, 4, flags, index as u64);
+                encode_string(out, 7, 0, value, self.huffman);
+            }
+            FieldLine::DynamicName { index, value } => {
+                if index < base {
+                    encode_int(out, 4, 0x40, base - 1 - index);
+                } else {
+                    encode_int(out, 3, 0x00, index - base);
+                }
+                encode_string(out, 7, 0, value, self.huffman);
+            }
+            FieldLine::Literal {
+                name,
+                value,
+                never_indexed,
+            } => {
+                let flags = 0x20 | if never_indexed { 0x10 } else { 0 };
+                encode_string(out, 3, flags, name, self.huffman);
+                encode_string(out, 7, 0, value, self.huffman);
+            }
+        }
+    }
+
+    /// Takes the encoder stream bytes to send
+    pub fn encoder_instructions(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.instructions)
+    }
+
+    /// Processes bytes received on the peer's decoder stream
+    ///
+    /// An incomplete trailing instruction is kept until more bytes arrive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `QpackError::DecoderStream` for an acknowledgment of a section
+    /// that is not outstanding or an increment beyond the inserts sent.
+    pub fn on_decoder_instructions(&mut self, data: &[u8]) -> Result<(), QpackError> {
+        self.decoder_stream.extend_from_slice(data);
+        let buffered = std::mem::take(&mut self.decoder_stream);
+        let mut reader = Reader::new(&buffered);
+
+        while !reader.is_empty() {
+            let start = reader.pos;
+            let first = reader.peek().unwrap_or_default();
+            let value = match reader.int(if first & 0x80 != 0 { 7 } else { 6 }) {
+                Ok(value) => value,
+                Err(ReadError::Incomplete) => {
+                    self.decoder_stream = buffered[start..].to_vec();
+                    return Ok(());
+                }
+                Err(ReadError::Invalid(msg)) => return Err(QpackError::DecoderStream(msg)),
+            };
+
+            if first & 0x80 != 0 {
+                // Section Acknowledgment
+                let sections = self.outstanding.get_mut(&value);
+                let section = sections
+                    .and_then(|sections| sections.pop_front())
+                    .ok_or_else(|| {
+                        QpackError::DecoderStream(format!(
+                            "acknowledgment for stream {} without an outstanding section",
+                            value
+                        ))
+                    })?;
+                if self.outstanding.get(&value).is_some_and(VecDeque::is_empty) {
+                    self.outstanding.remove(&value);
+                }
+                self.known_received_count =
+                    self.known_received_count.max(section.required_insert_count);
+            } else if first & 0x40 != 0 {
+                // Stream Cancellation
+                self.outstanding.remove(&value);
             } else {
-                // Literal header field: add to table
-                self.table.insert(name.clone(), value.clone());
-                // Would encode as literal + index the name if known
-                encoded.extend_from_slice(name.as_bytes());
-                encoded.push(b':');
-                encoded.extend_from_slice(value.as_bytes());
-                encoded.push(b'\n');
+                // Insert Count Increment
+                let known = self.known_received_count.saturating_add(value);
+                if value == 0 || known > self.table.insert_count() {
+                    return Err(QpackError::DecoderStream(format!(
+                        "invalid Insert Count Increment of {}",
+                        value
+                    )));
+                }
+                self.known_received_count = known;
             }
         }
+        Ok(())
+    }
+
+    /// Whether `stream_id` has a section referencing unacknowledged inserts
+    fn is_blocked(&self, stream_id: u64) -> bool {
+        self.outstanding.get(&stream_id).is_some_and(|sections| {
+            sections
+                .iter()
+                .any(|section| section.required_insert_count > self.known_received_count)
+        })
+    }
+
+    /// Number of streams that may be blocked at the decoder
+    pub fn blocked_streams(&self) -> usize {
+        self.outstanding
+            .keys()
+            .filter(|&&stream_id| self.is_blocked(stream_id))
+            .count()
+    }
 
-        encoded
+    /// Inserts the decoder has acknowledged
+    pub fn known_received_count(&self) -> u64 {
+        self.known_received_count
     }
 
     /// Get reference to dynamic table
@@ -335,43 +1073,380 @@ impl QpackEncoder {
     }
 }
 
-/// QPACK Decoder (placeholder for future full implementation)
-///
-/// # Future Implementation
+/// A field section waiting for dynamic table inserts
+struct BlockedSection {
+    stream_id: u64,
+    required_insert_count: u64,
+    block: Vec<u8>,
+}
+
+/// Decoded field lines of a section
+type FieldSection = Vec<(String, String)>;
+
+/// QPACK Decoder
 ///
-/// A full QPACK decoder would:
-/// - Decode header blocks using the dynamic table
-/// - Process indexed representations
-/// - Process literal representations
-/// - Apply Huffman decoding to strings
-/// - Manage decoder stream for acknowledgments
+/// Field sections that reference entries not yet received are held until the
+/// encoder stream delivers them, for at most `max_blocked_streams` streams.
+/// The dynamic table capacity starts at 0 until the encoder sets it.
 pub struct QpackDecoder {
     table: QpackDynamicTable,
+    max_entries: u64,
+    max_blocked_streams: usize,
+    /// Blocked field sections in arrival order
+    blocked: Vec<BlockedSection>,
+    /// Inserts the encoder knows this decoder has processed
+    acknowledged_count: u64,
+    /// Incomplete encoder stream instruction
+    encoder_stream: Vec<u8>,
+    /// Decoder stream bytes not yet sent
+    instructions: Vec<u8>,
 }
 
 impl QpackDecoder {
     /// Create a new QPACK decoder
+    ///
+    /// `config` holds the settings this decoder advertises.
     pub fn new(config: QpackConfig) -> Self {
+        let table = QpackDynamicTable::new(config.clone());
+        // The capacity is 0 until the encoder sets it
+        let _ = table.set_capacity(0);
+
         Self {
-            table: QpackDynamicTable::new(config),
+            max_entries: max_entries(&config),
+            max_blocked_streams: config.max_blocked_streams as usize,
+            table,
+            blocked: Vec::new(),
+            acknowledged_count: 0,
+            encoder_stream: Vec::new(),
+            instructions: Vec::new(),
         }
     }
 
-    /// Decode header block (placeholder)
+    /// Decodes a field section received on `stream_id`
+    ///
+    /// Returns `Ok(None)` if the section is blocked on inserts that have not
+    /// arrived; it is decoded by `on_encoder_instructions` once they do.
     ///
     /// # Arguments
-    /// * `data` - Encoded header block
+    /// * `stream_id` - Stream the field section was received on
+    /// * `data` - Encoded field section
     ///
     /// # Returns
-    /// * `Result<Vec<(String, String)>, String>` - Decoded headers or error
+    /// * `Result<Option<Vec<(String, String)>>, QpackError>` - Decoded headers
+    ///
+    /// # Errors
+    ///
+    /// Returns `QpackError::DecompressionFailed` for a malformed section, a
+    /// reference to an evicted or unknown entry, or when blocking would exceed
+    /// `max_blocked_streams`.
+    pub fn decode(
+        &mut self,
+        stream_id: u64,
+        data: &[u8],
+    ) -> Result<Option<FieldSection>, QpackError> {
+        let mut reader = Reader::new(data);
+        let (required_insert_count, base) = self.section_prefix(&mut reader)?;
+
+        if required_insert_count > self.table.insert_count() {
+            let streams_blocked = self.blocked.iter().any(|s| s.stream_id == stream_id);
+            if !streams_blocked && self.blocked_streams() >= self.max_blocked_streams {
+                return Err(QpackError::DecompressionFailed(format!(
+                    "more than {} blocked streams",
+                    self.max_blocked_streams
+                )));
+            }
+            self.blocked.push(BlockedSection {
+                stream_id,
+                required_insert_count,
+                block: data.to_vec(),
+            });
+            return Ok(None);
+        }
+
+        let fields = self.field_lines(&mut reader, required_insert_count, base)?;
+        if required_insert_count > 0 {
+            // Section Acknowledgment
+            encode_int(&mut self.instructions, 7, 0x80, stream_id);
+            self.acknowledged_count = self.acknowledged_count.max(required_insert_count);
+        }
+        Ok(Some(fields))
+    }
+
+    /// Reads the Required Insert Count and Base (RFC 9204 §4.5.1)
+    fn section_prefix(&self, reader: &mut Reader<'_>) -> Result<(u64, u64), QpackError> {
+        let failed = |e: ReadError| match e {
+            ReadError::Incomplete => {
+                QpackError::DecompressionFailed("truncated field section prefix".into())
+            }
+            ReadError::Invalid(msg) => QpackError::DecompressionFailed(msg),
+        };
+
+        let encoded = reader.int(8).map_err(failed)?;
+        let required_insert_count = if encoded == 0 {
+            0
+        } else {
+            let full_range = 2 * self.max_entries;
+            if encoded > full_range {
+                return Err(QpackError::DecompressionFailed(format!(
+                    "invalid Required Insert Count encoding {}",
+                    encoded
+                )));
+            }
+            let max_value = self.table.insert_count() + self.max_entries;
+            let max_wrapped = (max_value / full_range) * full_range;
+            let mut count = max_wrapped + encoded - 1;
+            if count > max_value {
+                if count <= full_range {
+                    return Err(QpackError::DecompressionFailed(
+                        "invalid Required Insert Count".into(),
+                    ));
+                }
+                count -= full_range;
+            }
+            if count == 0 {
+                return Err(QpackError::DecompressionFailed(
+                    "invalid Required Insert Count".into(),
+                ));
+            }
+            count
+        };
+
+        let negative = reader.peek().map_err(failed)? & 0x80 != 0;
+        let delta = reader.int(7).map_err(failed)?;
+        let base = if !negative {
+            required_insert_count.checked_add(delta)
+        } else {
+            required_insert_count.checked_sub(delta + 1)
+        }
+        .ok_or_else(|| QpackError::DecompressionFailed("invalid Base".into()))?;
+
+        Ok((required_insert_count, base))
+    }
+
+    /// Decodes the field lines following the prefix
+    fn field_lines(
+        &self,
+        reader: &mut Reader<'_>,
+        required_insert_count: u64,
+        base: u64,
+    ) -> Result<FieldSection, QpackError> {
+        let max_len = reader.data.len();
+        let mut fields = Vec::new();
+
+        while !reader.is_empty() {
+            let line = self.field_line(reader, required_insert_count, base, max_len);
+            fields.push(line.map_err(|e| match e {
+                ReadError::Incomplete => {
+                    QpackError::DecompressionFailed("truncated field line".into())
+                }
+                ReadError::Invalid(msg) => QpackError::DecompressionFailed(msg),
+            })?);
+        }
+        Ok(fields)
+    }
+
+    fn field_line(
+        &self,
+        reader: &mut Reader<'_>,
+        required_insert_count: u64,
+        base: u64,
+        max_len: usize,
+    ) -> Result<(String, String), ReadError> {
+        let first = reader.peek()?;
+
+        if first & 0x80 != 0 {
+            // Indexed Field Line
+            let index = reader.int(6)?;
+            if first & 0x40 != 0 {
+                return static_entry(index).map(|(n, v)| (n.to_string(), v.to_string()));
+            }
+            let field = self.dynamic_entry(relative_index(base, index)?, required_insert_count)?;
+            Ok((field.name, field.value))
+        } else if first & 0x40 != 0 {
+            // Literal Field Line with Name Reference
+            let index = reader.int(4)?;
+            let name = if first & 0x10 != 0 {
+                static_entry(index)?.0.to_string()
+            } else {
+                self.dynamic_entry(relative_index(base, index)?, required_insert_count)?
+                    .name
+            };
+            Ok((name, reader.string(7, max_len)?))
+        } else if first & 0x20 != 0 {
+            // Literal Field Line with Literal Name
+            let name = reader.string(3, max_len)?;
+            Ok((name, reader.string(7, max_len)?))
+        } else if first & 0x10 != 0 {
+            // Indexed Field Line with Post-Base Index
+            let index = reader.int(4)?;
+            let field = self.dynamic_entry(post_base_index(base, index)?, required_insert_count)?;
+            Ok((field.name, field.value))
+        } else {
+            // Literal Field Line with Post-Base Name Reference
+            let index = reader.int(3)?;
+            let name = self
+                .dynamic_entry(post_base_index(base, index)?, required_insert_count)?
+                .name;
+            Ok((name, reader.string(7, max_len)?))
+        }
+    }
+
+    /// Dynamic table entry referenced by a field section
+    fn dynamic_entry(
+        &self,
+        index: u64,
+        required_insert_count: u64,
+    ) -> Result<HeaderField, ReadError> {
+        if index >= required_insert_count {
+            return Err(ReadError::Invalid(format!(
+                "reference to entry {} beyond Required Insert Count {}",
+                index, required_insert_count
+            )));
+        }
+        self.table
+            .get_absolute(index)
+            .ok_or_else(|| ReadError::Invalid(format!("reference to evicted entry {}", index)))
+    }
+
+    /// Processes bytes received on the peer's encoder stream
+    ///
+    /// Returns the field sections this unblocked, with their stream IDs. An
+    /// incomplete trailing instruction is kept until more bytes arrive.
     ///
-    /// # Implementation Note
+    /// # Errors
     ///
-    /// This is a placeholder. Full implementation would use the QPACK
-    /// decoding algorithm from RFC 9204 to parse compressed header blocks.
-    pub fn decode(&mut self, _data: &[u8]) -> Result<Vec<(String, String)>, String> {
-        // Placeholder: would parse the encoded data
-        Err("QPACK decoding not yet fully implemented".to_string())
+    /// Returns `QpackError::EncoderStream` for an invalid instruction, and
+    /// `QpackError::DecompressionFailed` if an unblocked section is invalid.
+    pub fn on_encoder_instructions(
+        &mut self,
+        data: &[u8],
+    ) -> Result<Vec<(u64, FieldSection)>, QpackError> {
+        self.encoder_stream.extend_from_slice(data);
+        let buffered = std::mem::take(&mut self.encoder_stream);
+        let mut reader = Reader::new(&buffered);
+
+        while !reader.is_empty() {
+            let start = reader.pos;
+            match self.encoder_instruction(&mut reader) {
+                Ok(()) => {}
+                Err(ReadError::Incomplete) => {
+                    self.encoder_stream = buffered[start..].to_vec();
+                    break;
+                }
+                Err(ReadError::Invalid(msg)) => return Err(QpackError::EncoderStream(msg)),
+            }
+        }
+
+        // Decode sections that no longer wait for inserts, in arrival order
+        let insert_count = self.table.insert_count();
+        let (ready, waiting) = std::mem::take(&mut self.blocked)
+            .into_iter()
+            .partition::<Vec<_>, _>(|section| section.required_insert_count <= insert_count);
+        self.blocked = waiting;
+
+        let mut unblocked = Vec::with_capacity(ready.len());
+        for section in ready {
+            if let Some(fields) = self.decode(section.stream_id, &section.block)? {
+                unblocked.push((section.stream_id, fields));
+            }
+        }
+
+        if insert_count > self.acknowledged_count {
+            // Insert Count Increment
+            encode_int(
+                &mut self.instructions,
+                6,
+                0x00,
+                insert_count - self.acknowledged_count,
+            );
+            self.acknowledged_count = insert_count;
+        }
+
+        Ok(unblocked)
+    }
+
+    /// Applies one encoder stream instruction
+    fn encoder_instruction(&mut self, reader: &mut Reader<'_>) -> Result<(), ReadError> {
+        let first = reader.peek()?;
+        let max_len = self.table.config().max_table_capacity;
+
+        if first & 0x80 != 0 {
+            // Insert with Name Reference
+            let index = reader.int(6)?;
+            let value = reader.string(7, max_len)?;
+            let name = if first & 0x40 != 0 {
+                static_entry(index)?.0.to_string()
+            } else {
+                self.relative_entry(index)?.name
+            };
+            self.insert(name, value)
+        } else if first & 0x40 != 0 {
+            // Insert with Literal Name
+            let name = reader.string(5, max_len)?;
+            let value = reader.string(7, max_len)?;
+            self.insert(name, value)
+        } else if first & 0x20 != 0 {
+            // Set Dynamic Table Capacity
+            let capacity = reader.int(5)?;
+            let capacity = usize::try_from(capacity).unwrap_or(usize::MAX);
+            self.table
+                .set_capacity(capacity)
+                .map_err(ReadError::Invalid)
+        } else {
+            // Duplicate
+            let index = reader.int(5)?;
+            let field = self.relative_entry(index)?;
+            self.insert(field.name, field.value)
+        }
+    }
+
+    /// Entry referenced relative to the insert count by the encoder stream
+    fn relative_entry(&self, index: u64) -> Result<HeaderField, ReadError> {
+        let absolute = self
+            .table
+            .insert_count()
+            .checked_sub(index + 1)
+            .ok_or_else(|| ReadError::Invalid(format!("invalid relative index {}", index)))?;
+        self.table
+            .get_absolute(absolute)
+            .ok_or_else(|| ReadError::Invalid(format!("reference to evicted entry {}", absolute)))
+    }
+
+    fn insert(&mut self, name: String, value: String) -> Result<(), ReadError> {
+        let size = name.len() + value.len() + 32;
+        if size > self.table.capacity() {
+            return Err(ReadError::Invalid(format!(
+                "entry of {} bytes exceeds table capacity {}",
+                size,
+                self.table.capacity()
+            )));
+        }
+        self.table.insert(name, value);
+        Ok(())
+    }
+
+    /// Abandons a stream's field section, e.g. after the stream was reset
+    ///
+    /// Emits a Stream Cancellation so the encoder releases its references.
+    pub fn cancel_stream(&mut self, stream_id: u64) {
+        self.blocked
+            .retain(|section| section.stream_id != stream_id);
+        if self.max_entries > 0 {
+            encode_int(&mut self.instructions, 6, 0x40, stream_id);
+        }
+    }
+
+    /// Takes the decoder stream bytes to send
+    pub fn decoder_instructions(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.instructions)
+    }
+
+    /// Number of streams with a blocked field section
+    pub fn blocked_streams(&self) -> usize {
+        let mut streams: Vec<u64> = self.blocked.iter().map(|s| s.stream_id).collect();
+        streams.sort_unstable();
+        streams.dedup();
+        streams.len()
     }
 
     /// Get reference to dynamic table
@@ -380,6 +1455,26 @@ impl QpackDecoder {
     }
 }
 
+/// Static table entry, or an error for an index beyond the table
+fn static_entry(index: u64) -> Result<(&'static str, &'static str), ReadError> {
+    STATIC_TABLE
+        .get(index as usize)
+        .copied()
+        .ok_or_else(|| ReadError::Invalid(format!("invalid static table index {}", index)))
+}
+
+/// Absolute index of a relative reference in a field section
+fn relative_index(base: u64, index: u64) -> Result<u64, ReadError> {
+    base.checked_sub(index + 1)
+        .ok_or_else(|| ReadError::Invalid(format!("invalid relative index {}", index)))
+}
+
+/// Absolute index of a post-base reference in a field section
+fn post_base_index(base: u64, index: u64) -> Result<u64, ReadError> {
+    base.checked_add(index)
+        .ok_or_else(|| ReadError::Invalid(format!("invalid post-base index {}", index)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -523,56 +1618,405 @@ mod tests {
     }
 
     #[test]
-    fn test_qpack_encoder_basic() {
+    fn test_dynamic_table_hit_rate() {
         let config = QpackConfig::default();
-        let mut encoder = QpackEncoder::new(config);
+        let table = QpackDynamicTable::new(config);
+
+        table.insert("header1".to_string(), "value1".to_string());
+
+        // First find: miss (not cached yet for stats)
+        table.find("header1", "value1"); // Hit
+
+        // Subsequent finds: hits
+        for _ in 0..9 {
+            table.find("header1", "value1");
+        }
 
-        let headers = vec![
-            ("content-type".to_string(), "application/json".to_string()),
-            ("content-length".to_string(), "1234".to_string()),
-        ];
+        // 10 hits, 0 misses (after first insert)
+        let hit_rate = table.hit_rate();
+        assert_eq!(hit_rate, 100.0);
+    }
 
-        let encoded = encoder.encode(&headers);
-        assert!(!encoded.is_empty());
+    /// Decodes hex with optional whitespace
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
 
-        // Second encoding should use table
-        let encoded2 = encoder.encode(&headers);
-        assert!(!encoded2.is_empty());
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
 
-        // Table should have entries
-        assert_eq!(encoder.table().len(), 2);
+    /// Config of the examples in RFC 9204 Appendix B
+    fn rfc_config() -> QpackConfig {
+        QpackConfig {
+            max_table_capacity: 220,
+            huffman_encoding: false,
+            ..Default::default()
+        }
     }
 
     #[test]
-    fn test_qpack_decoder_placeholder() {
-        let config = QpackConfig::default();
+    fn test_static_table() {
+        assert_eq!(STATIC_TABLE.len(), 99);
+        assert_eq!(STATIC_TABLE[0], (":authority", ""));
+        assert_eq!(STATIC_TABLE[17], (":method", "GET"));
+        assert_eq!(STATIC_TABLE[98], ("x-frame-options", "sameorigin"));
+        assert_eq!(static_index(":status", "200"), Some(25));
+        assert_eq!(static_name_index("content-type"), Some(44));
+    }
+
+    #[test]
+    fn test_prefix_integers() {
+        // RFC 7541 Appendix C.1
+        for (prefix, value, encoded) in [
+            (5, 10, vec![0x0a]),
+            (5, 1337, vec![0x1f, 0x9a, 0x0a]),
+            (8, 42, vec![0x2a]),
+        ] {
+            let mut out = Vec::new();
+            encode_int(&mut out, prefix, 0, value);
+            assert_eq!(out, encoded);
+            assert!(matches!(Reader::new(&out).int(prefix), Ok(v) if v == value));
+        }
+
+        let mut reader = Reader::new(&[0x1f, 0x9a]);
+        assert!(matches!(reader.int(5), Err(ReadError::Incomplete)));
+        let mut reader = Reader::new(&[0xff; 12]);
+        assert!(matches!(reader.int(8), Err(ReadError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_qpack_config_from_http3_config() {
+        let http3 = Http3Config {
+            qpack_max_table_capacity: 2048,
+            qpack_blocked_streams: 8,
+            ..Default::default()
+        };
+        let config = QpackConfig::from(&http3);
+        assert_eq!(config.max_table_capacity, 2048);
+        assert_eq!(config.max_blocked_streams, 8);
+        assert!(config.huffman_encoding);
+    }
+
+    /// RFC 9204 Appendix B, decoder side
+    #[test]
+    fn test_rfc9204_examples_decoder() {
+        let mut decoder = QpackDecoder::new(rfc_config());
+
+        // B.1: Literal Field Line with Name Reference
+        let headers = decoder
+            .decode(0, &hex("0000 510b 2f69 6e64 6578 2e68 746d 6c"))
+            .unwrap();
+        assert_eq!(headers, Some(fields(&[(":path", "/index.html")])));
+        assert!(decoder.decoder_instructions().is_empty());
+
+        // B.2: Dynamic Table; the section arrives before its inserts
+        assert_eq!(decoder.decode(4, &hex("0381 10 11")).unwrap(), None);
+        assert_eq!(decoder.blocked_streams(), 1);
+        let unblocked = decoder
+            .on_encoder_instructions(&hex("3fbd01 c00f 7777 772e 6578 616d 706c 652e 636f 6d
+                 c10c 2f73 616d 706c 652f 7061 7468"))
+            .unwrap();
+        assert_eq!(
+            unblocked,
+            vec![(
+                4,
+                fields(&[(":authority", "www.example.com"), (":path", "/sample/path")])
+            )]
+        );
+        assert_eq!(decoder.decoder_instructions(), hex("84"));
+        assert_eq!(decoder.table().size(), 106);
+
+        // B.3: Speculative Insert
+        let unblocked = decoder
+            .on_encoder_instructions(&hex(
+                "4a63 7573 746f 6d2d 6b65 790c 6375 7374 6f6d 2d76 616c 7565",
+            ))
+            .unwrap();
+        assert!(unblocked.is_empty());
+        assert_eq!(decoder.decoder_instructions(), hex("01"));
+        assert_eq!(decoder.table().size(), 160);
+
+        // B.4: Duplicate Instruction, Stream Cancellation
+        let block = hex("0500 80 c1 81");
+        assert_eq!(decoder.decode(8, &block).unwrap(), None);
+        decoder.cancel_stream(8);
+        assert_eq!(decoder.decoder_instructions(), hex("48"));
+        assert_eq!(decoder.blocked_streams(), 0);
+
+        assert!(
+            decoder
+                .on_encoder_instructions(&hex("02"))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(decoder.decoder_instructions(), hex("01"));
+        assert_eq!(decoder.table().size(), 217);
+        assert_eq!(
+            decoder.decode(12, &block).unwrap(),
+            Some(fields(&[
+                (":authority", "www.example.com"),
+                (":path", "/"),
+                ("custom-key", "custom-value"),
+            ]))
+        );
+        assert_eq!(decoder.decoder_instructions(), hex("8c"));
+
+        // B.5: Dynamic Table Insert, Eviction
+        decoder
+            .on_encoder_instructions(&hex("810d 6375 7374 6f6d 2d76 616c 7565 32"))
+            .unwrap();
+        assert_eq!(decoder.decoder_instructions(), hex("01"));
+        assert_eq!(decoder.table().size(), 215);
+        assert_eq!(decoder.table().get_absolute(0), None);
+        assert_eq!(
+            decoder.table().get_absolute(4),
+            Some(HeaderField::new(
+                "custom-key".to_string(),
+                "custom-value2".to_string()
+            ))
+        );
+    }
+
+    /// RFC 9204 Appendix B.2, encoder side
+    #[test]
+    fn test_rfc9204_example_encoder() {
+        let mut encoder = QpackEncoder::new(rfc_config());
+
+        let block = encoder.encode(
+            4,
+            &fields(&[(":authority", "www.example.com"), (":path", "/sample/path")]),
+        );
+        assert_eq!(block, hex("0381 10 11"));
+        assert_eq!(
+            encoder.encoder_instructions(),
+            hex("3fbd01 c00f 7777 772e 6578 616d 706c 652e 636f 6d
+                 c10c 2f73 616d 706c 652f 7061 7468")
+        );
+        assert_eq!(encoder.blocked_streams(), 1);
+
+        encoder.on_decoder_instructions(&hex("84")).unwrap();
+        assert_eq!(encoder.known_received_count(), 2);
+        assert_eq!(encoder.blocked_streams(), 0);
+
+        // The same fields are now referenced relative to the Base
+        let block = encoder.encode(
+            8,
+            &fields(&[(":authority", "www.example.com"), (":path", "/sample/path")]),
+        );
+        assert_eq!(block, hex("0300 81 80"));
+        assert!(encoder.encoder_instructions().is_empty());
+    }
+
+    #[test]
+    fn test_encoder_decoder_round_trip() {
+        let config = QpackConfig {
+            max_table_capacity: 256,
+            ..Default::default()
+        };
+        let mut encoder = QpackEncoder::new(config.clone());
         let mut decoder = QpackDecoder::new(config);
 
-        let result = decoder.decode(&[0x80, 0x01]);
-        assert!(result.is_err());
+        for request in 0..20u64 {
+            let stream_id = request * 4;
+            // Fields referenced earlier in a section pin their entries, so
+            // the ever-changing one comes first to force evictions
+            let headers = fields(&[
+                ("x-request-id", &format!("req-{}", request)),
+                (":method", if request % 3 == 0 { "POST" } else { "GET" }),
+                (":scheme", "https"),
+                (":authority", "api.example.com"),
+                (":path", &format!("/items/{}", request % 5)),
+                ("user-agent", "sniproxy-test/1.0"),
+                ("authorization", "Bearer secret"),
+            ]);
+
+            let block = encoder.encode(stream_id, &headers);
+            let mut decoded = decoder.decode(stream_id, &block).unwrap();
+            let unblocked = decoder
+                .on_encoder_instructions(&encoder.encoder_instructions())
+                .unwrap();
+            if decoded.is_none() {
+                assert_eq!(unblocked.len(), 1);
+                assert_eq!(unblocked[0].0, stream_id);
+                decoded = Some(unblocked[0].1.clone());
+            }
+            assert_eq!(decoded, Some(headers));
+
+            encoder
+                .on_decoder_instructions(&decoder.decoder_instructions())
+                .unwrap();
+            assert_eq!(encoder.blocked_streams(), 0);
+        }
+
+        // The table stayed within capacity by evicting, and credentials
+        // never entered it
+        assert!(encoder.table().stats().evictions > 0);
+        assert!(encoder.table().size() <= 256);
+        assert_eq!(encoder.table().find_name("authorization"), None);
         assert_eq!(
-            result.unwrap_err(),
-            "QPACK decoding not yet fully implemented"
+            decoder.table().insert_count(),
+            encoder.table().insert_count()
         );
     }
 
     #[test]
-    fn test_dynamic_table_hit_rate() {
-        let config = QpackConfig::default();
-        let table = QpackDynamicTable::new(config);
+    fn test_sensitive_headers_never_indexed() {
+        let mut encoder = QpackEncoder::new(QpackConfig {
+            huffman_encoding: false,
+            ..Default::default()
+        });
+        let block = encoder.encode(0, &fields(&[("authorization", "Bearer x")]));
 
-        table.insert("header1".to_string(), "value1".to_string());
+        // Literal with static name reference 84 and the N bit set
+        assert_eq!(&block[..4], &[0x00, 0x00, 0x7f, 0x45]);
+        assert!(encoder.table().is_empty());
+    }
 
-        // First find: miss (not cached yet for stats)
-        table.find("header1", "value1"); // Hit
+    #[test]
+    fn test_encoder_limits_blocked_streams() {
+        let config = QpackConfig {
+            max_blocked_streams: 1,
+            ..Default::default()
+        };
+        let mut encoder = QpackEncoder::new(config.clone());
+        let mut decoder = QpackDecoder::new(config);
 
-        // Subsequent finds: hits
-        for _ in 0..9 {
-            table.find("header1", "value1");
+        let first = fields(&[("x-trace", "a")]);
+        let block = encoder.encode(0, &first);
+        assert_eq!(encoder.blocked_streams(), 1);
+        assert_eq!(decoder.decode(0, &block).unwrap(), None);
+
+        // A second stream may not block, so it avoids unacknowledged entries
+        let second = fields(&[("x-trace", "a"), ("x-other", "b")]);
+        let block = encoder.encode(4, &second);
+        assert_eq!(encoder.blocked_streams(), 1);
+        assert_eq!(decoder.decode(4, &block).unwrap(), Some(second));
+
+        let unblocked = decoder
+            .on_encoder_instructions(&encoder.encoder_instructions())
+            .unwrap();
+        assert_eq!(unblocked, vec![(0, first)]);
+        encoder
+            .on_decoder_instructions(&decoder.decoder_instructions())
+            .unwrap();
+        assert_eq!(encoder.blocked_streams(), 0);
+    }
+
+    #[test]
+    fn test_encoder_keeps_referenced_entries() {
+        // Room for a single entry
+        let config = QpackConfig {
+            max_table_capacity: 64,
+            ..Default::default()
+        };
+        let mut encoder = QpackEncoder::new(config.clone());
+        let mut decoder = QpackDecoder::new(config);
+
+        let first = fields(&[("x-a", "1")]);
+        let first_block = encoder.encode(0, &first);
+
+        // Inserting would evict the entry the first section references
+        let second = fields(&[("x-b", "2")]);
+        let second_block = encoder.encode(4, &second);
+        assert_eq!(encoder.table().len(), 1);
+        assert_eq!(encoder.table().get(0).unwrap().name, "x-a");
+
+        decoder
+            .on_encoder_instructions(&encoder.encoder_instructions())
+            .unwrap();
+        assert_eq!(decoder.decode(0, &first_block).unwrap(), Some(first));
+        assert_eq!(decoder.decode(4, &second_block).unwrap(), Some(second));
+
+        // Once acknowledged, the entry may be evicted
+        encoder
+            .on_decoder_instructions(&decoder.decoder_instructions())
+            .unwrap();
+        encoder.encode(8, &fields(&[("x-b", "2")]));
+        assert_eq!(encoder.table().get(0).unwrap().name, "x-b");
+        assert_eq!(encoder.table().len(), 1);
+    }
+
+    #[test]
+    fn test_decoder_blocked_stream_limit() {
+        let mut encoder = QpackEncoder::new(QpackConfig::default());
+        let mut decoder = QpackDecoder::new(QpackConfig {
+            max_blocked_streams: 1,
+            ..Default::default()
+        });
+
+        let first = encoder.encode(0, &fields(&[("x-a", "1")]));
+        let second = encoder.encode(4, &fields(&[("x-b", "2")]));
+        assert_eq!(decoder.decode(0, &first).unwrap(), None);
+        let error = decoder.decode(4, &second).unwrap_err();
+        assert!(matches!(error, QpackError::DecompressionFailed(_)));
+        assert_eq!(error.code(), 0x0200);
+    }
+
+    #[test]
+    fn test_encoder_stream_split_across_reads() {
+        let mut encoder = QpackEncoder::new(QpackConfig::default());
+        let mut decoder = QpackDecoder::new(QpackConfig::default());
+
+        let headers = fields(&[("x-custom-header", "a fairly long header value")]);
+        let block = encoder.encode(0, &headers);
+        assert_eq!(decoder.decode(0, &block).unwrap(), None);
+
+        let instructions = encoder.encoder_instructions();
+        let (last, rest) = instructions.split_last().unwrap();
+        for byte in rest {
+            assert!(
+                decoder
+                    .on_encoder_instructions(&[*byte])
+                    .unwrap()
+                    .is_empty()
+            );
         }
+        assert_eq!(
+            decoder.on_encoder_instructions(&[*last]).unwrap(),
+            vec![(0, headers)]
+        );
+    }
 
-        // 10 hits, 0 misses (after first insert)
-        let hit_rate = table.hit_rate();
-        assert_eq!(hit_rate, 100.0);
+    #[test]
+    fn test_decoder_errors() {
+        let mut decoder = QpackDecoder::new(rfc_config());
+
+        // Static index beyond the table
+        let error = decoder.decode(0, &hex("0000 ff25")).unwrap_err();
+        assert!(error.to_string().contains("invalid static table index"));
+
+        // Truncated field line
+        assert!(decoder.decode(0, &hex("0000 510b 2f")).is_err());
+
+        // Capacity above the advertised maximum
+        let error = decoder.on_encoder_instructions(&hex("3fde01")).unwrap_err();
+        assert_eq!(error.code(), 0x0201);
+
+        // Entry larger than the capacity
+        let mut decoder = QpackDecoder::new(rfc_config());
+        let error =
+            decoder.on_encoder_instructions(&hex("c00f 7777 772e 6578 616d 706c 652e 636f 6d"));
+        assert!(matches!(error, Err(QpackError::EncoderStream(_))));
+
+        // Reference to an entry at or beyond the Required Insert Count
+        let mut decoder = QpackDecoder::new(rfc_config());
+        decoder
+            .on_encoder_instructions(&hex("3fbd01 c00f 7777 772e 6578 616d 706c 652e 636f 6d"))
+            .unwrap();
+        assert!(decoder.decode(0, &hex("0200 11")).is_err());
+
+        // Acknowledgment for a section never sent
+        let mut encoder = QpackEncoder::new(rfc_config());
+        let error = encoder.on_decoder_instructions(&hex("84")).unwrap_err();
+        assert_eq!(error.code(), 0x0202);
+        assert!(encoder.on_decoder_instructions(&hex("01")).is_err());
     }
 }
@@ -0,0 +1,1015 @@
+//! QUIC Initial packet decryption (RFC 9000 §17.2, RFC 9001 §5, RFC 9369)
+//!
+//! A QUIC client carries its TLS ClientHello in CRYPTO frames inside Initial
+//! packets. Their payload is encrypted, but with keys that anyone can derive
+//! from the Destination Connection ID the client chose. That lets the proxy read
+//! the ClientHello without taking part in the handshake:
+//! 1. parse the long header (token and length are variable-length integers)
+//! 2. derive the client Initial secret with the salt and labels of the version
+//! 3. remove header protection to recover the packet number
+//! 4. open the AES-128-GCM payload and collect its CRYPTO frames
+//!
+//! The ClientHello is wrapped in a TLS record, so the TCP parsers
+//! (`extract_sni`, `extract_alpn`, `tls::parse_client_hello`) apply unchanged.
+//!
+//! The module also builds the packets the proxy answers with itself, without
+//! involving a backend: Version Negotiation and Retry.
+
+use ring::aead::quic::{AES_128 as HP_AES_128, HeaderProtectionKey};
+use ring::aead::{AES_128_GCM, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::hkdf::{HKDF_SHA256, KeyType, Prk, Salt};
+use std::fmt;
+
+/// QUIC version 1 (RFC 9000)
+pub const QUIC_V1: u32 = 0x0000_0001;
+/// QUIC version 2 (RFC 9369)
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
+/// Initial salt for QUIC v1 (RFC 9001 §5.2)
+const V1_INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+/// Initial salt for QUIC v2 (RFC 9369 §3.3.1)
+const V2_INITIAL_SALT: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+/// Retry integrity key and nonce for QUIC v1 (RFC 9001 §5.8)
+const V1_RETRY_KEY: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+const V1_RETRY_NONCE: [u8; 12] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+/// Retry integrity key and nonce for QUIC v2 (RFC 9369 §3.3.3)
+const V2_RETRY_KEY: [u8; 16] = [
+    0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad, 0x7c, 0xcc, 0x92,
+];
+const V2_RETRY_NONCE: [u8; 12] = [
+    0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+];
+
+/// Client Initials must arrive in datagrams of at least 1200 bytes (RFC 9000 §14.1)
+pub const MIN_INITIAL_DATAGRAM: usize = 1200;
+
+/// Largest connection ID a v1 or v2 long header may carry
+pub const MAX_CID_LEN: usize = 20;
+const TAG_LEN: usize = 16;
+const SAMPLE_LEN: usize = 16;
+/// Header protection samples start 4 bytes past the packet number field
+const SAMPLE_OFFSET: usize = 4;
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CONNECTION_CLOSE: u64 = 0x1c;
+
+const TLS_HANDSHAKE: u8 = 0x16;
+const CLIENT_HELLO: u8 = 0x01;
+
+/// Errors from Initial packet parsing and decryption
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuicError {
+    /// The datagram ends before the packet it announces
+    Truncated,
+    /// Short-header (1-RTT) packets carry no ClientHello
+    NotLongHeader,
+    /// Initial keys are only defined for QUIC v1 and v2
+    UnsupportedVersion(u32),
+    /// A long-header packet of another type (0-RTT, Handshake, Retry)
+    NotInitial,
+    /// A header or frame field is out of range
+    Malformed(&'static str),
+    /// The payload failed AEAD authentication
+    DecryptionFailed,
+    /// A frame type that is not allowed in Initial packets
+    UnexpectedFrame(u64),
+    /// The CRYPTO frames do not (yet) hold the whole ClientHello
+    IncompleteClientHello,
+}
+
+impl fmt::Display for QuicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuicError::Truncated => write!(f, "QUIC packet too small or truncated"),
+            QuicError::NotLongHeader => write!(f, "Not a QUIC long header packet"),
+            QuicError::UnsupportedVersion(v) => write!(f, "Unsupported QUIC version {:#010x}", v),
+            QuicError::NotInitial => write!(f, "Not a QUIC Initial packet"),
+            QuicError::Malformed(what) => write!(f, "Malformed QUIC packet: {}", what),
+            QuicError::DecryptionFailed => write!(f, "QUIC Initial packet decryption failed"),
+            QuicError::UnexpectedFrame(t) => {
+                write!(f, "Frame type {:#x} not allowed in QUIC Initial packet", t)
+            }
+            QuicError::IncompleteClientHello => {
+                write!(f, "Incomplete ClientHello in QUIC CRYPTO frames")
+            }
+        }
+    }
+}
+
+impl std::error::Error for QuicError {}
+
+/// A CRYPTO frame: a slice of the TLS handshake stream at `offset`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CryptoFrame {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// A decrypted client Initial packet
+#[derive(Debug, Clone)]
+pub struct InitialPacket {
+    pub version: u32,
+    pub dcid: Vec<u8>,
+    pub scid: Vec<u8>,
+    pub token: Vec<u8>,
+    pub packet_number: u64,
+    pub crypto: Vec<CryptoFrame>,
+    /// Bytes of the datagram this packet occupies; coalesced packets follow
+    pub packet_len: usize,
+}
+
+/// Reads a variable-length integer (RFC 9000 §16) at `pos` and advances it
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, QuicError> {
+    let first = *buf.get(*pos).ok_or(QuicError::Truncated)?;
+    let len = 1usize << (first >> 6);
+    let bytes = buf.get(*pos..*pos + len).ok_or(QuicError::Truncated)?;
+
+    let mut value = u64::from(first & 0x3f);
+    for b in &bytes[1..] {
+        value = (value << 8) | u64::from(*b);
+    }
+    *pos += len;
+    Ok(value)
+}
+
+/// Appends `value` as a variable-length integer in its shortest encoding
+fn write_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..0x40 => out.push(value as u8),
+        0x40..0x4000 => out.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        0x4000..0x4000_0000 => out.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+/// Decrypts the client Initial packet at the start of `datagram`
+///
+/// # Errors
+///
+/// Fails for short headers, datagrams below `MIN_INITIAL_DATAGRAM`, other
+/// packet types, versions other than v1 and v2, truncated packets, payloads
+/// that do not authenticate and frames that are not allowed in Initial packets.
+pub fn decrypt_initial(datagram: &[u8]) -> Result<InitialPacket, QuicError> {
+    if datagram.first().is_some_and(|b| b & 0x80 == 0) {
+        return Err(QuicError::NotLongHeader);
+    }
+    if datagram.len() < MIN_INITIAL_DATAGRAM {
+        return Err(QuicError::Truncated);
+    }
+    open_initial(datagram, b"client in")
+}
+
+/// Reassembles the ClientHello from the CRYPTO frames of one or more Initials
+///
+/// Frames may be in any order and overlap. Returns the handshake message
+/// (type, length and body) once the stream from offset 0 covers it.
+pub fn assemble_client_hello(frames: &[CryptoFrame]) -> Result<Vec<u8>, QuicError> {
+    let mut sorted: Vec<&CryptoFrame> = frames.iter().collect();
+    sorted.sort_by_key(|frame| frame.offset);
+
+    let mut stream = Vec::new();
+    for frame in sorted {
+        let offset = usize::try_from(frame.offset)
+            .map_err(|_| QuicError::Malformed("CRYPTO offset out of range"))?;
+        if offset > stream.len() {
+            break;
+        }
+        if offset + frame.data.len() > stream.len() {
+            stream.extend_from_slice(&frame.data[stream.len() - offset..]);
+        }
+    }
+
+    if stream.len() < 4 {
+        return Err(QuicError::IncompleteClientHello);
+    }
+    if stream[0] != CLIENT_HELLO {
+        return Err(QuicError::Malformed(
+            "CRYPTO stream does not start with a ClientHello",
+        ));
+    }
+    let hello_len =
+        ((stream[1] as usize) << 16) | ((stream[2] as usize) << 8) | (stream[3] as usize);
+    if stream.len() < 4 + hello_len {
+        return Err(QuicError::IncompleteClientHello);
+    }
+    stream.truncate(4 + hello_len);
+    Ok(stream)
+}
+
+/// Destination Connection ID of any QUIC packet
+///
+/// Long headers encode the length; short headers do not, so the caller
+/// supplies the length its backends use for their connection IDs.
+pub fn destination_cid(packet: &[u8], short_cid_len: usize) -> Option<&[u8]> {
+    let first = *packet.first()?;
+    if first & 0x80 == 0 {
+        return packet.get(1..1 + short_cid_len);
+    }
+    let len = *packet.get(5)? as usize;
+    packet.get(6..6 + len)
+}
+
+/// Source Connection ID of a long-header packet
+///
+/// For packets from a server this is the connection ID the client uses as
+/// Destination Connection ID from then on.
+pub fn source_cid(packet: &[u8]) -> Option<&[u8]> {
+    if packet.first()? & 0x80 == 0 {
+        return None;
+    }
+    let mut pos = 5;
+    let dcid_len = *packet.get(pos)? as usize;
+    pos += 1 + dcid_len;
+    let scid_len = *packet.get(pos)? as usize;
+    packet.get(pos + 1..pos + 1 + scid_len)
+}
+
+/// Wraps a ClientHello handshake message in a TLS handshake record
+pub fn client_hello_record(hello: &[u8]) -> Result<Vec<u8>, QuicError> {
+    let len = u16::try_from(hello.len())
+        .map_err(|_| QuicError::Malformed("ClientHello too large for a TLS record"))?;
+
+    let mut record = Vec::with_capacity(5 + hello.len());
+    record.extend_from_slice(&[TLS_HANDSHAKE, 0x03, 0x01]);
+    record.extend_from_slice(&len.to_be_bytes());
+    record.extend_from_slice(hello);
+    Ok(record)
+}
+
+/// Version field of a long-header packet
+pub fn packet_version(packet: &[u8]) -> Option<u32> {
+    if packet.first()? & 0x80 == 0 {
+        return None;
+    }
+    let bytes = packet.get(1..5)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Whether the proxy can read Initial packets of `version`
+pub fn is_supported_version(version: u32) -> bool {
+    matches!(version, QUIC_V1 | QUIC_V2)
+}
+
+/// Builds a Version Negotiation packet (RFC 9000 §17.2.1) listing v1 and v2
+///
+/// `dcid` and `scid` are the connection IDs of the client's packet; they are
+/// echoed in swapped positions.
+pub fn version_negotiation(dcid: &[u8], scid: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0xc0, 0x00, 0x00, 0x00, 0x00];
+    packet.push(scid.len() as u8);
+    packet.extend_from_slice(scid);
+    packet.push(dcid.len() as u8);
+    packet.extend_from_slice(dcid);
+    packet.extend_from_slice(&QUIC_V1.to_be_bytes());
+    packet.extend_from_slice(&QUIC_V2.to_be_bytes());
+    packet
+}
+
+/// Builds a Retry packet (RFC 9000 §17.2.5) with its integrity tag
+///
+/// `odcid` is the Destination Connection ID of the client's Initial, `dcid`
+/// its Source Connection ID and `scid` the connection ID the client must use
+/// for its next Initial, which carries `token`.
+pub fn retry(
+    version: u32,
+    odcid: &[u8],
+    dcid: &[u8],
+    scid: &[u8],
+    token: &[u8],
+) -> Result<Vec<u8>, QuicError> {
+    // Retry is type 0b11 in v1 and 0b00 in v2 (RFC 9369 §3.2)
+    let (first, key, nonce) = match version {
+        QUIC_V1 => (0xff, &V1_RETRY_KEY, V1_RETRY_NONCE),
+        QUIC_V2 => (0xcf, &V2_RETRY_KEY, V2_RETRY_NONCE),
+        other => return Err(QuicError::UnsupportedVersion(other)),
+    };
+    if odcid.len() > MAX_CID_LEN || dcid.len() > MAX_CID_LEN || scid.len() > MAX_CID_LEN {
+        return Err(QuicError::Malformed("connection ID longer than 20 bytes"));
+    }
+
+    // The tag authenticates the Retry prefixed with the original DCID
+    let mut pseudo = vec![odcid.len() as u8];
+    pseudo.extend_from_slice(odcid);
+    let retry_start = pseudo.len();
+    pseudo.push(first);
+    pseudo.extend_from_slice(&version.to_be_bytes());
+    pseudo.push(dcid.len() as u8);
+    pseudo.extend_from_slice(dcid);
+    pseudo.push(scid.len() as u8);
+    pseudo.extend_from_slice(scid);
+    pseudo.extend_from_slice(token);
+
+    let tag = UnboundKey::new(&AES_128_GCM, key)
+        .map(LessSafeKey::new)
+        .and_then(|key| {
+            key.seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&pseudo[..]),
+                &mut [],
+            )
+        })
+        .map_err(|_| QuicError::DecryptionFailed)?;
+
+    let mut packet = pseudo.split_off(retry_start);
+    packet.extend_from_slice(tag.as_ref());
+    Ok(packet)
+}
+
+/// Long header fields up to the (still protected) packet number
+struct LongHeader<'a> {
+    version: u32,
+    dcid: &'a [u8],
+    scid: &'a [u8],
+    token: &'a [u8],
+    pn_offset: usize,
+    /// End of this packet within the datagram
+    end: usize,
+}
+
+fn parse_long_header(packet: &[u8]) -> Result<LongHeader<'_>, QuicError> {
+    let first = *packet.first().ok_or(QuicError::Truncated)?;
+    if first & 0x80 == 0 {
+        return Err(QuicError::NotLongHeader);
+    }
+    let version_bytes = packet.get(1..5).ok_or(QuicError::Truncated)?;
+    let version = u32::from_be_bytes([
+        version_bytes[0],
+        version_bytes[1],
+        version_bytes[2],
+        version_bytes[3],
+    ]);
+
+    // Initial is type 0b00 in v1 and 0b01 in v2 (RFC 9369 §3.2)
+    let initial_type = match version {
+        QUIC_V1 => 0b00,
+        QUIC_V2 => 0b01,
+        other => return Err(QuicError::UnsupportedVersion(other)),
+    };
+    if (first >> 4) & 0x03 != initial_type {
+        return Err(QuicError::NotInitial);
+    }
+
+    let mut pos = 5;
+    let dcid = read_cid(packet, &mut pos)?;
+    let scid = read_cid(packet, &mut pos)?;
+
+    let token_len = usize::try_from(read_varint(packet, &mut pos)?)
+        .map_err(|_| QuicError::Malformed("token length out of range"))?;
+    let token = packet
+        .get(pos..pos.saturating_add(token_len))
+        .ok_or(QuicError::Truncated)?;
+    pos += token_len;
+
+    let length = usize::try_from(read_varint(packet, &mut pos)?)
+        .map_err(|_| QuicError::Malformed("length out of range"))?;
+    let end = pos.checked_add(length).ok_or(QuicError::Truncated)?;
+    if end > packet.len() || length < SAMPLE_OFFSET + SAMPLE_LEN {
+        return Err(QuicError::Truncated);
+    }
+
+    Ok(LongHeader {
+        version,
+        dcid,
+        scid,
+        token,
+        pn_offset: pos,
+        end,
+    })
+}
+
+fn read_cid<'a>(packet: &'a [u8], pos: &mut usize) -> Result<&'a [u8], QuicError> {
+    let len = *packet.get(*pos).ok_or(QuicError::Truncated)? as usize;
+    if len > MAX_CID_LEN {
+        return Err(QuicError::Malformed("connection ID longer than 20 bytes"));
+    }
+    let cid = packet
+        .get(*pos + 1..*pos + 1 + len)
+        .ok_or(QuicError::Truncated)?;
+    *pos += 1 + len;
+    Ok(cid)
+}
+
+/// Removes header protection and opens the Initial packet at the start of
+/// `datagram` with the secret named by `secret_label` ("client in" or
+/// "server in")
+fn open_initial(datagram: &[u8], secret_label: &[u8]) -> Result<InitialPacket, QuicError> {
+    let header = parse_long_header(datagram)?;
+    let keys = InitialKeys::derive(header.version, header.dcid, secret_label)?;
+    let pn_offset = header.pn_offset;
+    let mut packet = datagram[..header.end].to_vec();
+
+    let sample_start = pn_offset + SAMPLE_OFFSET;
+    let mask = keys.header_mask(&packet[sample_start..sample_start + SAMPLE_LEN])?;
+    packet[0] ^= mask[0] & 0x0f;
+    let pn_len = (packet[0] & 0x03) as usize + 1;
+    if header.end < pn_offset + pn_len + TAG_LEN {
+        return Err(QuicError::Truncated);
+    }
+
+    // Initial packet numbers are small, so the truncated value is the full one
+    let mut packet_number = 0u64;
+    for i in 0..pn_len {
+        packet[pn_offset + i] ^= mask[1 + i];
+        packet_number = (packet_number << 8) | u64::from(packet[pn_offset + i]);
+    }
+
+    let (aad, payload) = packet.split_at_mut(pn_offset + pn_len);
+    let plaintext = keys.open(packet_number, aad, payload)?;
+    let crypto = parse_frames(plaintext)?;
+
+    Ok(InitialPacket {
+        version: header.version,
+        dcid: header.dcid.to_vec(),
+        scid: header.scid.to_vec(),
+        token: header.token.to_vec(),
+        packet_number,
+        crypto,
+        packet_len: header.end,
+    })
+}
+
+/// Collects the CRYPTO frames of a decrypted Initial payload
+///
+/// Only the frames RFC 9000 §12.4 permits in Initial packets are accepted.
+fn parse_frames(payload: &[u8]) -> Result<Vec<CryptoFrame>, QuicError> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+
+    while pos < payload.len() {
+        let frame_type = read_varint(payload, &mut pos)?;
+        match frame_type {
+            FRAME_PADDING | FRAME_PING => {}
+            FRAME_ACK | FRAME_ACK_ECN => {
+                // Largest acknowledged, delay, range count, first range
+                read_varint(payload, &mut pos)?;
+                read_varint(payload, &mut pos)?;
+                let ranges = read_varint(payload, &mut pos)?;
+                read_varint(payload, &mut pos)?;
+                for _ in 0..ranges {
+                    read_varint(payload, &mut pos)?;
+                    read_varint(payload, &mut pos)?;
+                }
+                if frame_type == FRAME_ACK_ECN {
+                    for _ in 0..3 {
+                        read_varint(payload, &mut pos)?;
+                    }
+                }
+            }
+            FRAME_CRYPTO => {
+                let offset = read_varint(payload, &mut pos)?;
+                let len = usize::try_from(read_varint(payload, &mut pos)?)
+                    .map_err(|_| QuicError::Malformed("CRYPTO length out of range"))?;
+                let data = payload
+                    .get(pos..pos.saturating_add(len))
+                    .ok_or(QuicError::Truncated)?;
+                pos += len;
+                frames.push(CryptoFrame {
+                    offset,
+                    data: data.to_vec(),
+                });
+            }
+            FRAME_CONNECTION_CLOSE => {
+                // Error code, offending frame type, reason phrase
+                read_varint(payload, &mut pos)?;
+                read_varint(payload, &mut pos)?;
+                let reason_len = usize::try_from(read_varint(payload, &mut pos)?)
+                    .map_err(|_| QuicError::Malformed("reason length out of range"))?;
+                pos = pos.saturating_add(reason_len);
+                if pos > payload.len() {
+                    return Err(QuicError::Truncated);
+                }
+            }
+            other => return Err(QuicError::UnexpectedFrame(other)),
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Packet protection keys for one direction of the Initial packet space
+struct InitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    hp: [u8; 16],
+}
+
+impl InitialKeys {
+    /// Derives the keys for `secret_label` from the client's first DCID
+    /// (RFC 9001 §5.2, RFC 9369 §3.3)
+    fn derive(version: u32, dcid: &[u8], secret_label: &[u8]) -> Result<Self, QuicError> {
+        let (salt, key_label, iv_label, hp_label): (&[u8], &[u8], &[u8], &[u8]) = match version {
+            QUIC_V1 => (&V1_INITIAL_SALT, b"quic key", b"quic iv", b"quic hp"),
+            QUIC_V2 => (&V2_INITIAL_SALT, b"quicv2 key", b"quicv2 iv", b"quicv2 hp"),
+            other => return Err(QuicError::UnsupportedVersion(other)),
+        };
+
+        let initial_secret = Salt::new(HKDF_SHA256, salt).extract(dcid);
+        let mut secret = [0u8; 32];
+        expand_label(&initial_secret, secret_label, &mut secret);
+        let secret = Prk::new_less_safe(HKDF_SHA256, &secret);
+
+        let mut keys = InitialKeys {
+            key: [0; 16],
+            iv: [0; 12],
+            hp: [0; 16],
+        };
+        expand_label(&secret, key_label, &mut keys.key);
+        expand_label(&secret, iv_label, &mut keys.iv);
+        expand_label(&secret, hp_label, &mut keys.hp);
+        Ok(keys)
+    }
+
+    /// AES-ECB header protection mask for `sample` (RFC 9001 §5.4.3)
+    fn header_mask(&self, sample: &[u8]) -> Result<[u8; 5], QuicError> {
+        HeaderProtectionKey::new(&HP_AES_128, &self.hp)
+            .and_then(|hp| hp.new_mask(sample))
+            .map_err(|_| QuicError::DecryptionFailed)
+    }
+
+    fn nonce(&self, packet_number: u64) -> Nonce {
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *n ^= p;
+        }
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey, QuicError> {
+        UnboundKey::new(&AES_128_GCM, &self.key)
+            .map(LessSafeKey::new)
+            .map_err(|_| QuicError::DecryptionFailed)
+    }
+
+    fn open<'a>(
+        &self,
+        packet_number: u64,
+        header: &[u8],
+        payload: &'a mut [u8],
+    ) -> Result<&'a mut [u8], QuicError> {
+        self.aead_key()?
+            .open_in_place(self.nonce(packet_number), Aad::from(header), payload)
+            .map_err(|_| QuicError::DecryptionFailed)
+    }
+}
+
+/// Output length for HKDF-Expand
+struct OutputLen(usize);
+
+impl KeyType for OutputLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// HKDF-Expand-Label from TLS 1.3 (RFC 8446 §7.1) with an empty context
+fn expand_label(secret: &Prk, label: &[u8], out: &mut [u8]) {
+    let out_len = (out.len() as u16).to_be_bytes();
+    let label_len = [(b"tls13 ".len() + label.len()) as u8];
+    let info: [&[u8]; 5] = [&out_len, &label_len, b"tls13 ", label, &[0]];
+    secret
+        .expand(&info, OutputLen(out.len()))
+        .and_then(|okm| okm.fill(out))
+        .expect("HKDF-Expand-Label output fits SHA-256");
+}
+
+/// Builds a protected client Initial carrying `frames`, padded to 1200 bytes
+///
+/// This is the inverse of `decrypt_initial`, for tests and load generators
+/// that need to open QUIC sessions through the proxy. `token` is empty unless
+/// the client is answering a Retry. The packet number is always encoded in 4
+/// bytes.
+pub fn seal_client_initial(
+    version: u32,
+    dcid: &[u8],
+    scid: &[u8],
+    token: &[u8],
+    packet_number: u32,
+    frames: &[u8],
+) -> Result<Vec<u8>, QuicError> {
+    let first = match version {
+        QUIC_V2 => 0xd3,
+        _ => 0xc3,
+    };
+    let keys = InitialKeys::derive(version, dcid, b"client in")?;
+    if dcid.len() > MAX_CID_LEN || scid.len() > MAX_CID_LEN {
+        return Err(QuicError::Malformed("connection ID longer than 20 bytes"));
+    }
+
+    let mut packet = vec![first];
+    packet.extend_from_slice(&version.to_be_bytes());
+    packet.push(dcid.len() as u8);
+    packet.extend_from_slice(dcid);
+    packet.push(scid.len() as u8);
+    packet.extend_from_slice(scid);
+    write_varint(&mut packet, token.len() as u64);
+    packet.extend_from_slice(token);
+
+    // Pad the datagram to MIN_INITIAL_DATAGRAM; the Length field and packet
+    // number add 6 bytes to the header
+    let mut payload = frames.to_vec();
+    let min_payload = MIN_INITIAL_DATAGRAM.saturating_sub(packet.len() + 6 + TAG_LEN);
+    if payload.len() < min_payload {
+        payload.resize(min_payload, 0);
+    }
+    let length = u16::try_from(4 + payload.len() + TAG_LEN)
+        .ok()
+        .filter(|len| *len < 0x4000)
+        .ok_or(QuicError::Malformed("frames too large for one Initial"))?;
+    packet.extend_from_slice(&(length | 0x4000).to_be_bytes());
+    let pn_offset = packet.len();
+    packet.extend_from_slice(&packet_number.to_be_bytes());
+
+    let tag = keys
+        .aead_key()?
+        .seal_in_place_separate_tag(
+            keys.nonce(u64::from(packet_number)),
+            Aad::from(&packet[..]),
+            &mut payload,
+        )
+        .map_err(|_| QuicError::DecryptionFailed)?;
+    packet.extend_from_slice(&payload);
+    packet.extend_from_slice(tag.as_ref());
+
+    let sample_start = pn_offset + SAMPLE_OFFSET;
+    let mask = keys.header_mask(&packet[sample_start..sample_start + SAMPLE_LEN])?;
+    packet[0] ^= mask[0] & 0x0f;
+    for i in 0..4 {
+        packet[pn_offset + i] ^= mask[1 + i];
+    }
+    Ok(packet)
+}
+
+/// `seal_client_initial` without a Source Connection ID
+#[cfg(test)]
+pub(crate) fn build_client_initial(
+    version: u32,
+    dcid: &[u8],
+    packet_number: u32,
+    frames: &[u8],
+) -> Vec<u8> {
+    seal_client_initial(version, dcid, &[], &[], packet_number, frames).unwrap()
+}
+
+/// Encodes `offset`/`data` as a CRYPTO frame with 8-byte varints
+pub fn crypto_frame(offset: u64, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![FRAME_CRYPTO as u8];
+    frame.extend_from_slice(&(offset | 0xc000_0000_0000_0000).to_be_bytes());
+    frame.extend_from_slice(&(data.len() as u64 | 0xc000_0000_0000_0000).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const RFC9001_DCID: &str = "8394c8f03e515708";
+
+    /// CRYPTO frame with the ClientHello of RFC 9001 Appendix A.2
+    const RFC9001_CLIENT_CRYPTO: &str = "
+        060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e868
+        04fe3a47f06a2b69484c00000413011302010000c000000010000e00000b6578
+        616d706c652e636f6dff01000100000a00080006001d00170018001000070005
+        04616c706e000500050100000000003300260024001d00209370b2c9caa47fba
+        baf4559fedba753de171fa71f50f1ce15d43e994ec74d748002b000302030400
+        0d0010000e0403050306030203080408050806002d00020101001c0002400100
+        3900320408ffffffffffffffff05048000ffff07048000ffff08011001048000
+        75300901100f088394c8f03e51570806048000ffff";
+
+    #[test]
+    fn test_read_varint() {
+        // RFC 9000 Appendix A.1 examples
+        for (encoded, value) in [
+            ("c2197c5eff14e88c", 151_288_809_941_952_652u64),
+            ("9d7f3e7d", 494_878_333),
+            ("7bbd", 15_293),
+            ("25", 37),
+            ("4025", 37),
+        ] {
+            let buf = hex(encoded);
+            let mut pos = 0;
+            assert_eq!(read_varint(&buf, &mut pos).unwrap(), value);
+            assert_eq!(pos, buf.len());
+        }
+
+        let mut pos = 0;
+        assert_eq!(
+            read_varint(&[0x80, 0x01], &mut pos),
+            Err(QuicError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_rfc9001_initial_keys() {
+        // RFC 9001 Appendix A.1
+        let dcid = hex(RFC9001_DCID);
+        let client = InitialKeys::derive(QUIC_V1, &dcid, b"client in").unwrap();
+        assert_eq!(client.key.to_vec(), hex("1f369613dd76d5467730efcbe3b1a22d"));
+        assert_eq!(client.iv.to_vec(), hex("fa044b2f42a3fd3b46fb255c"));
+        assert_eq!(client.hp.to_vec(), hex("9f50449e04a0e810283a1e9933adedd2"));
+
+        let server = InitialKeys::derive(QUIC_V1, &dcid, b"server in").unwrap();
+        assert_eq!(server.key.to_vec(), hex("cf3a5331653c364c88f0f379b6067e37"));
+        assert_eq!(server.iv.to_vec(), hex("0ac1493ca1905853b0bba03e"));
+        assert_eq!(server.hp.to_vec(), hex("c206b8d9b9f0f37644430b490eeaa314"));
+
+        // RFC 9001 Appendix A.2 header protection sample and mask
+        let mask = client
+            .header_mask(&hex("d1b1c98dd7689fb8ec11d242b123dc9b"))
+            .unwrap();
+        assert_eq!(mask.to_vec(), hex("437b9aec36"));
+    }
+
+    #[test]
+    fn test_rfc9001_client_initial() {
+        // RFC 9001 Appendix A.2: the ClientHello in packet number 2, padded
+        // to a 1200-byte datagram
+        let dcid = hex(RFC9001_DCID);
+        let packet = build_client_initial(QUIC_V1, &dcid, 2, &hex(RFC9001_CLIENT_CRYPTO));
+        assert_eq!(packet.len(), 1200);
+        assert_eq!(
+            packet[..38],
+            hex("c000000001088394c8f03e5157080000449e7b9aec34
+                 d1b1c98dd7689fb8ec11d242b123dc9b")[..]
+        );
+        assert_eq!(packet[1184..], hex("e221af44860018ab0856972e194cd934")[..]);
+
+        let initial = decrypt_initial(&packet).unwrap();
+        assert_eq!(initial.version, QUIC_V1);
+        assert_eq!(initial.dcid, dcid);
+        assert!(initial.scid.is_empty());
+        assert!(initial.token.is_empty());
+        assert_eq!(initial.packet_number, 2);
+        assert_eq!(initial.packet_len, 1200);
+        assert_eq!(initial.crypto.len(), 1);
+        assert_eq!(initial.crypto[0].offset, 0);
+
+        let hello = assemble_client_hello(&initial.crypto).unwrap();
+        assert_eq!(hello.len(), 241);
+        let record = client_hello_record(&hello).unwrap();
+        assert_eq!(crate::extract_sni(&record).unwrap(), "example.com");
+        assert_eq!(crate::extract_alpn(&record), Some("alpn"));
+    }
+
+    #[test]
+    fn test_rfc9369_server_initial_v2() {
+        // RFC 9369 Appendix A.3: server Initial protected with the v2 keys
+        // derived from the client's original DCID
+        let packet = hex("
+            dc6b3343cf0008f067a5502a4262b5004075d92faaf16f05d8a4398c47089698
+            baeea26b91eb761d9b89237bbf87263017915358230035f7fd3945d88965cf17
+            f9af6e16886c61bfc703106fbaf3cb4cfa52382dd16a393e42757507698075b2
+            c984c707f0a0812d8cd5a6881eaf21ceda98f4bd23f6fe1a3e2c43edd9ce7ca8
+            4bed8521e2e140");
+        let mut server_packet = packet.clone();
+        let header = parse_long_header(&packet).unwrap();
+        assert_eq!(header.version, QUIC_V2);
+        assert_eq!(header.scid, &hex("f067a5502a4262b5")[..]);
+
+        let dcid = hex(RFC9001_DCID);
+        let keys = InitialKeys::derive(QUIC_V2, &dcid, b"server in").unwrap();
+        let pn_offset = header.pn_offset;
+        let mask = keys
+            .header_mask(&server_packet[pn_offset + 4..pn_offset + 20])
+            .unwrap();
+        server_packet[0] ^= mask[0] & 0x0f;
+        let pn_len = (server_packet[0] & 0x03) as usize + 1;
+        for i in 0..pn_len {
+            server_packet[pn_offset + i] ^= mask[1 + i];
+        }
+        assert_eq!(server_packet[0], 0xd1);
+        assert_eq!(server_packet[pn_offset..pn_offset + pn_len], [0x00, 0x01]);
+
+        let (aad, payload) = server_packet.split_at_mut(pn_offset + pn_len);
+        let plaintext = keys.open(1, aad, payload).unwrap();
+        let frames = parse_frames(plaintext).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].offset, 0);
+        assert_eq!(frames[0].data.len(), 0x5a);
+        // ServerHello
+        assert_eq!(frames[0].data[0], 0x02);
+    }
+
+    #[test]
+    fn test_rfc9001_retry() {
+        // RFC 9001 Appendix A.4 and RFC 9369 Appendix A.4
+        let odcid = hex(RFC9001_DCID);
+        let scid = hex("f067a5502a4262b5");
+        assert_eq!(
+            retry(QUIC_V1, &odcid, &[], &scid, b"token").unwrap(),
+            hex("ff000000010008f067a5502a4262b5746f6b656e
+                 04a265ba2eff4d829058fb3f0f2496ba")
+        );
+        assert_eq!(
+            retry(QUIC_V2, &odcid, &[], &scid, b"token").unwrap(),
+            hex("cf6b3343cf0008f067a5502a4262b5746f6b656e
+                 c8646ce8bfe33952d955543665dcc7b6")
+        );
+        assert_eq!(
+            retry(0xff00_001d, &odcid, &[], &scid, b"token").unwrap_err(),
+            QuicError::UnsupportedVersion(0xff00_001d)
+        );
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        let packet = version_negotiation(&[0x01; 8], &[0x02; 4]);
+        assert_eq!(packet_version(&packet), Some(0));
+        assert_eq!(destination_cid(&packet, 0), Some(&[0x02; 4][..]));
+        assert_eq!(source_cid(&packet), Some(&[0x01; 8][..]));
+        assert_eq!(packet[packet.len() - 8..], hex("000000016b3343cf")[..]);
+
+        assert!(is_supported_version(QUIC_V1));
+        assert!(is_supported_version(QUIC_V2));
+        assert!(!is_supported_version(0x1a2a_3a4a));
+        assert_eq!(packet_version(&[0x40, 0x00, 0x00, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn test_initial_with_token() {
+        let dcid = hex(RFC9001_DCID);
+        let token = [0x7a; 100];
+        let packet = seal_client_initial(
+            QUIC_V1,
+            &dcid,
+            &[0x03; 8],
+            &token,
+            0,
+            &hex(RFC9001_CLIENT_CRYPTO),
+        )
+        .unwrap();
+        assert_eq!(packet.len(), MIN_INITIAL_DATAGRAM);
+
+        let initial = decrypt_initial(&packet).unwrap();
+        assert_eq!(initial.token, token);
+        assert_eq!(initial.scid, [0x03; 8]);
+    }
+
+    #[test]
+    fn test_v2_client_initial_roundtrip() {
+        let dcid = hex(RFC9001_DCID);
+        let packet = build_client_initial(QUIC_V2, &dcid, 0, &hex(RFC9001_CLIENT_CRYPTO));
+
+        let initial = decrypt_initial(&packet).unwrap();
+        assert_eq!(initial.version, QUIC_V2);
+        assert_eq!(initial.packet_number, 0);
+        let record = client_hello_record(&assemble_client_hello(&initial.crypto).unwrap()).unwrap();
+        assert_eq!(crate::extract_sni(&record).unwrap(), "example.com");
+
+        // v1 and v2 derive different keys from the same DCID
+        let mut relabeled = packet.clone();
+        relabeled[1..5].copy_from_slice(&QUIC_V1.to_be_bytes());
+        assert!(decrypt_initial(&relabeled).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_initial_rejects_tampering() {
+        let dcid = hex(RFC9001_DCID);
+        let mut packet = build_client_initial(QUIC_V1, &dcid, 2, &hex(RFC9001_CLIENT_CRYPTO));
+        packet[100] ^= 0x01;
+        assert_eq!(
+            decrypt_initial(&packet).unwrap_err(),
+            QuicError::DecryptionFailed
+        );
+    }
+
+    #[test]
+    fn test_decrypt_initial_header_errors() {
+        let dcid = hex(RFC9001_DCID);
+        let packet = build_client_initial(QUIC_V1, &dcid, 2, &hex(RFC9001_CLIENT_CRYPTO));
+
+        assert_eq!(
+            decrypt_initial(&[0x40; 50]).unwrap_err(),
+            QuicError::NotLongHeader
+        );
+        assert_eq!(
+            decrypt_initial(&packet[..600]).unwrap_err(),
+            QuicError::Truncated
+        );
+
+        let mut unknown = packet.clone();
+        unknown[1..5].copy_from_slice(&0x1a2a_3a4au32.to_be_bytes());
+        assert_eq!(
+            decrypt_initial(&unknown).unwrap_err(),
+            QuicError::UnsupportedVersion(0x1a2a_3a4a)
+        );
+
+        // Handshake packet type (0b10 in v1)
+        let mut handshake = packet.clone();
+        handshake[0] = 0xe0;
+        assert_eq!(
+            decrypt_initial(&handshake).unwrap_err(),
+            QuicError::NotInitial
+        );
+
+        let mut long_cid = packet;
+        long_cid[5] = 21;
+        assert!(matches!(
+            decrypt_initial(&long_cid).unwrap_err(),
+            QuicError::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn test_connection_ids() {
+        // RFC 9369 Appendix A.3 server Initial header
+        let server = hex("d16b3343cf0008f067a5502a4262b5004075");
+        assert_eq!(destination_cid(&server, 8), Some(&[][..]));
+        assert_eq!(source_cid(&server), Some(&hex("f067a5502a4262b5")[..]));
+
+        let client = build_client_initial(QUIC_V1, &hex(RFC9001_DCID), 0, &[0x01]);
+        assert_eq!(destination_cid(&client, 4), Some(&hex(RFC9001_DCID)[..]));
+        assert_eq!(source_cid(&client), Some(&[][..]));
+
+        // Short header: the configured length decides
+        let short = hex("41f067a5502a4262b5aabbcc");
+        assert_eq!(
+            destination_cid(&short, 8),
+            Some(&hex("f067a5502a4262b5")[..])
+        );
+        assert_eq!(destination_cid(&short, 0), Some(&[][..]));
+        assert_eq!(source_cid(&short), None);
+        assert_eq!(destination_cid(&short[..4], 8), None);
+    }
+
+    #[test]
+    fn test_parse_frames() {
+        let mut payload = vec![0x01]; // PING
+        payload.extend_from_slice(&[0x03, 0x05, 0x00, 0x01, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00]); // ACK_ECN
+        payload.extend_from_slice(&crypto_frame(7, b"world"));
+        payload.extend_from_slice(&[0x00, 0x00]); // PADDING
+        let frames = parse_frames(&payload).unwrap();
+        assert_eq!(
+            frames,
+            vec![CryptoFrame {
+                offset: 7,
+                data: b"world".to_vec()
+            }]
+        );
+
+        // STREAM frames are not allowed in Initial packets
+        assert_eq!(
+            parse_frames(&[0x08, 0x00]).unwrap_err(),
+            QuicError::UnexpectedFrame(0x08)
+        );
+        assert_eq!(
+            parse_frames(&[0x06, 0x00, 0x10, 0x01]).unwrap_err(),
+            QuicError::Truncated
+        );
+    }
+
+    #[test]
+    fn test_assemble_client_hello_out_of_order() {
+        let crypto = hex(RFC9001_CLIENT_CRYPTO);
+        // Skip the frame header (type, offset, 2-byte length)
+        let hello = &crypto[4..];
+        let frames = vec![
+            CryptoFrame {
+                offset: 150,
+                data: hello[150..].to_vec(),
+            },
+            CryptoFrame {
+                offset: 0,
+                data: hello[..100].to_vec(),
+            },
+            CryptoFrame {
+                offset: 80,
+                data: hello[80..160].to_vec(),
+            },
+        ];
+        assert_eq!(assemble_client_hello(&frames).unwrap(), hello);
+
+        // A gap leaves the ClientHello incomplete
+        assert_eq!(
+            assemble_client_hello(&[frames[0].clone(), frames[1].clone()]).unwrap_err(),
+            QuicError::IncompleteClientHello
+        );
+        assert_eq!(
+            assemble_client_hello(&[]).unwrap_err(),
+            QuicError::IncompleteClientHello
+        );
+    }
+}
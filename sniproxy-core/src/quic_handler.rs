@@ -1,192 +1,853 @@
-//! QUIC and HTTP/3 protocol handling (Future Implementation)
+//! QUIC termination and HTTP/3 proxying
 //!
-//! This module provides a placeholder for full QUIC/HTTP3 protocol handling.
-//! The current implementation focuses on UDP datagram forwarding with QUIC SNI extraction.
+//! `UdpConnectionHandler` forwards QUIC datagrams by SNI. For routes in
+//! `terminate` mode it forwards them to a local quinn endpoint run by
+//! `QuicHandler` instead: the endpoint completes the TLS 1.3 handshake with a
+//! certificate from `tls_certificates`, accepts HTTP/3 requests with h3 and
+//! sends each one to the route's backend over HTTP/1.1 or HTTP/2, so backends
+//! without HTTP/3 support can be served to HTTP/3 clients.
 //!
-//! # Architecture
-//!
-//! Full HTTP/3 support would require:
-//! - QUIC connection establishment using quinn
-//! - TLS 1.3 handshake handling
-//! - HTTP/3 request/response proxying using h3
-//! - 0-RTT resumption support
-//! - Connection migration handling
-//! - QPACK header compression
-//!
-//! # Current Status
+//! ```text
+//! Client ──QUIC──▶ UDP listener ──datagrams──▶ QuicHandler (loopback) ──HTTP/1.1 or h2──▶ Backend
+//! ```
 //!
-//! The UDP infrastructure is complete and handles QUIC datagrams transparently:
-//! - UDP listeners spawn in `run_proxy()`
-//! - `UdpConnectionHandler` manages sessions
-//! - QUIC SNI extraction from Initial packets
-//! - Bidirectional datagram forwarding
+//! # Configuration
 //!
-//! # Future Work
+//! - `quic_config` sets the transport: concurrent streams, idle timeout,
+//!   keep-alive, datagram size and 0-RTT
+//! - `http3_config.max_field_section_size` limits request headers; h3 only
+//!   uses the QPACK static table, so the `qpack_*` settings do not apply
+//! - `Route::upstream_http` selects the backend protocol; the backend address
+//!   and TLS settings are the same as for terminated TCP connections
 //!
-//! To implement full HTTP/3 proxy functionality:
-//! 1. Use quinn for QUIC connection handling
-//! 2. Implement h3 request/response proxying
-//! 3. Add connection pooling for QUIC connections
-//! 4. Implement 0-RTT resumption tickets
-//! 5. Handle connection migration events
-//! 6. Add QPACK compression support
+//! Routes with `client_auth` are not terminated over QUIC.
 //!
-//! # Example (Future Implementation)
+//! # Example
 //!
 //! ```no_run
+//! use sniproxy_config::Config;
 //! use sniproxy_core::quic_handler::QuicHandler;
+//! use sniproxy_core::termination::CertificateStore;
+//! use std::sync::Arc;
 //!
-//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-//! // Future: Full QUIC connection handling
-//! // let handler = QuicHandler::new(config)?;
-//! // handler.handle_connection(conn).await?;
+//! # async fn example(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+//! let certificates = Arc::new(CertificateStore::new("/etc/sniproxy/certs"));
+//! let handler = Arc::new(QuicHandler::new(Arc::new(config), certificates)?);
+//! println!("Terminating QUIC on {}", handler.local_addr()?);
+//! handler.run().await;
 //! # Ok(())
 //! # }
 //! ```
 
+use bytes::{Buf, Bytes};
+use futures::SinkExt;
+use futures::channel::mpsc;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Empty, StreamBody};
+use hyper::body::Frame;
+use hyper::client::conn::{http1, http2};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Request, Response, StatusCode, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use quinn::crypto::rustls::{HandshakeData, QuicServerConfig};
+use quinn::{IdleTimeout, MtuDiscoveryConfig, TransportConfig, VarInt};
+use rustls::pki_types::ServerName;
+use sniproxy_config::{Config, QuicConfig, RouteMode, UpstreamHttp};
+use std::collections::HashMap;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, info, warn};
+
+use crate::termination::{self, CertificateStore};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Body of a request forwarded to the backend
+type RequestBody = UnsyncBoxBody<Bytes, BoxError>;
+
+/// ALPN protocol of HTTP/3
+const ALPN_H3: &[u8] = b"h3";
+
+/// Smallest datagram size QUIC allows (RFC 9000 §14)
+const MIN_DATAGRAM_SIZE: usize = 1200;
+
+/// Request body chunks buffered between the client and the backend
+const BODY_CHANNEL_CAPACITY: usize = 8;
+
+/// H3_INTERNAL_ERROR, sent when a connection has no usable route
+const H3_INTERNAL_ERROR: u32 = 0x0102;
+
+/// Connection-specific headers, which are not forwarded in either direction
+const HOP_BY_HOP_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A stream to the backend, plain or TLS
+trait UpstreamStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> UpstreamStream for T {}
+
+/// Why a request could not be forwarded
+#[derive(Debug)]
+enum ForwardError {
+    /// Connecting to the backend timed out (504)
+    Timeout,
+    /// The backend could not be reached or failed the request (502)
+    Failed(BoxError),
+}
+
+impl ForwardError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ForwardError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ForwardError::Failed(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl std::fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForwardError::Timeout => write!(f, "connect timeout"),
+            ForwardError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How the requests of one HTTP/3 connection reach the route's backend
+struct Upstream {
+    /// Backend address as `host:port`
+    target: String,
+    /// Connector and verified server name for TLS to the backend
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    http: UpstreamHttp,
+    connect_timeout: Duration,
+    /// HTTP/2 connection shared by all requests, opened by the first one
+    http2: Mutex<Option<http2::SendRequest<RequestBody>>>,
+}
 
-/// QUIC connection handler (placeholder for future implementation)
+impl Upstream {
+    /// Opens a connection to the backend, with TLS if configured
+    async fn connect(&self) -> Result<Box<dyn UpstreamStream>, ForwardError> {
+        let stream = timeout(self.connect_timeout, TcpStream::connect(&self.target))
+            .await
+            .map_err(|_| ForwardError::Timeout)?
+            .map_err(|e| ForwardError::Failed(e.into()))?;
+        let _ = stream.set_nodelay(true);
+
+        match self.tls {
+            Some((ref connector, ref server_name)) => {
+                let stream = timeout(
+                    self.connect_timeout,
+                    connector.connect(server_name.clone(), stream),
+                )
+                .await
+                .map_err(|_| ForwardError::Timeout)?
+                .map_err(|e| ForwardError::Failed(e.into()))?;
+                Ok(Box::new(stream))
+            }
+            None => Ok(Box::new(stream)),
+        }
+    }
+
+    /// Sends a request to the backend and returns its response head
+    async fn send(
+        &self,
+        request: Request<RequestBody>,
+    ) -> Result<Response<hyper::body::Incoming>, ForwardError> {
+        let result = match self.http {
+            UpstreamHttp::Http1 => {
+                let stream = self.connect().await?;
+                let (mut sender, connection) = http1::handshake(TokioIo::new(stream))
+                    .await
+                    .map_err(|e| ForwardError::Failed(e.into()))?;
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        debug!("Upstream HTTP/1.1 connection error: {}", e);
+                    }
+                });
+                sender.send_request(request).await
+            }
+            UpstreamHttp::Http2 => self.http2_sender().await?.send_request(request).await,
+        };
+
+        result.map_err(|e| ForwardError::Failed(e.into()))
+    }
+
+    /// Returns the shared HTTP/2 connection, opening a new one if it closed
+    async fn http2_sender(&self) -> Result<http2::SendRequest<RequestBody>, ForwardError> {
+        let mut shared = self.http2.lock().await;
+        if let Some(ref sender) = *shared
+            && !sender.is_closed()
+        {
+            return Ok(sender.clone());
+        }
+
+        let stream = self.connect().await?;
+        let (sender, connection) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .map_err(|e| ForwardError::Failed(e.into()))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!("Upstream HTTP/2 connection error: {}", e);
+            }
+        });
+
+        *shared = Some(sender.clone());
+        Ok(sender)
+    }
+
+    /// Rewrites an HTTP/3 request for the backend's HTTP version
+    fn request(
+        &self,
+        request: Request<()>,
+        body: RequestBody,
+    ) -> Result<Request<RequestBody>, BoxError> {
+        let (mut parts, ()) = request.into_parts();
+        strip_hop_by_hop(&mut parts.headers);
+
+        let path = parts
+            .uri
+            .path_and_query()
+            .map_or("/", |path| path.as_str())
+            .to_string();
+        let authority = match parts.uri.authority() {
+            Some(authority) => authority.to_string(),
+            None => parts
+                .headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .ok_or("request without :authority or Host")?
+                .to_string(),
+        };
+
+        match self.http {
+            UpstreamHttp::Http1 => {
+                // Origin-form target with the authority in Host
+                parts.uri = path.parse()?;
+                parts.version = Version::HTTP_11;
+                parts
+                    .headers
+                    .insert(header::HOST, HeaderValue::from_str(&authority)?);
+            }
+            UpstreamHttp::Http2 => {
+                let scheme = if self.tls.is_some() { "https" } else { "http" };
+                parts.uri = Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(path)
+                    .build()?;
+                parts.version = Version::HTTP_2;
+                parts.headers.remove(header::HOST);
+            }
+        }
+
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+/// Removes connection-specific headers, including those named in `Connection`
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<String> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    for name in named.iter().map(String::as_str).chain(HOP_BY_HOP_HEADERS) {
+        headers.remove(name);
+    }
+}
+
+/// Builds the quinn transport configuration from `quic_config`
 ///
-/// Current implementation relies on transparent UDP datagram forwarding.
-/// Full HTTP/3 support will be implemented in a future phase.
-#[allow(dead_code)]
-pub struct QuicHandler {
-    /// Placeholder for future configuration
-    config: QuicConfig,
+/// An idle timeout or keep-alive interval of 0 disables it. `max_datagram_size`
+/// is both the initial datagram size and the upper bound of path MTU discovery.
+///
+/// # Errors
+///
+/// Returns an error if `max_datagram_size` is below the QUIC minimum of 1200
+/// bytes or the idle timeout is out of range.
+pub fn configure_quic_transport(config: &QuicConfig) -> Result<TransportConfig, Box<dyn Error>> {
+    if config.max_datagram_size < MIN_DATAGRAM_SIZE {
+        return Err(format!(
+            "max_datagram_size {} is below the QUIC minimum of {} bytes",
+            config.max_datagram_size, MIN_DATAGRAM_SIZE
+        )
+        .into());
+    }
+    let datagram_size = u16::try_from(config.max_datagram_size).unwrap_or(u16::MAX);
+
+    let mut transport = TransportConfig::default();
+    transport.max_concurrent_bidi_streams(VarInt::from_u32(config.max_concurrent_streams));
+    transport.max_idle_timeout(match config.max_idle_timeout {
+        0 => None,
+        secs => Some(IdleTimeout::try_from(Duration::from_secs(secs))?),
+    });
+    transport.keep_alive_interval(
+        (config.keep_alive_interval > 0).then(|| Duration::from_secs(config.keep_alive_interval)),
+    );
+    transport.initial_mtu(datagram_size);
+    let mut mtu_discovery = MtuDiscoveryConfig::default();
+    mtu_discovery.upper_bound(datagram_size);
+    transport.mtu_discovery_config(Some(mtu_discovery));
+
+    Ok(transport)
 }
 
-/// QUIC configuration (placeholder)
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub struct QuicConfig {
-    /// Maximum concurrent streams (future use)
-    pub max_concurrent_streams: u32,
-    /// Idle timeout in seconds (future use)
-    pub idle_timeout: u64,
-    /// Enable 0-RTT resumption (future use)
-    pub enable_0rtt: bool,
+/// Builds the quinn server configuration for HTTP/3 termination
+///
+/// Certificates are resolved per SNI from `certificates`, so reloads apply to
+/// new connections. 0-RTT is accepted when `enable_0rtt` is set.
+pub fn server_config(
+    config: &QuicConfig,
+    certificates: Arc<CertificateStore>,
+) -> Result<quinn::ServerConfig, Box<dyn Error>> {
+    let mut tls = rustls::ServerConfig::builder_with_provider(termination::crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates);
+    tls.alpn_protocols = vec![ALPN_H3.to_vec()];
+    if config.enable_0rtt {
+        tls.max_early_data_size = u32::MAX;
+    }
+
+    let crypto = QuicServerConfig::try_from(tls)?;
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    server_config.transport_config(Arc::new(configure_quic_transport(config)?));
+    Ok(server_config)
+}
+
+/// Terminates QUIC connections and proxies their HTTP/3 requests
+pub struct QuicHandler {
+    config: Arc<Config>,
+    endpoint: quinn::Endpoint,
+    /// TLS connectors of terminate-mode routes with `upstream_tls`, by route host
+    upstream_tls: HashMap<String, TlsConnector>,
+    max_field_section_size: u64,
 }
 
 impl QuicHandler {
-    /// Creates a new QUIC handler (placeholder)
+    /// Creates a handler listening on an ephemeral loopback port
     ///
-    /// # Note
+    /// # Errors
     ///
-    /// This is a placeholder for future full QUIC/HTTP3 implementation.
-    /// Current UDP/QUIC functionality works via `UdpConnectionHandler`.
-    #[allow(dead_code)]
-    pub fn new(config: QuicConfig) -> Self {
-        Self { config }
+    /// Returns an error if the QUIC or upstream TLS configuration is invalid
+    /// or the endpoint cannot be bound.
+    pub fn new(
+        config: Arc<Config>,
+        certificates: Arc<CertificateStore>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let quic_config = config.quic_config.clone().unwrap_or_default();
+        let http3_config = config.http3_config.clone().unwrap_or_default();
+
+        let mut upstream_tls = HashMap::new();
+        for route in config.routes.iter().flatten() {
+            if route.mode != RouteMode::Terminate {
+                continue;
+            }
+            if let Some(ref settings) = route.upstream_tls {
+                let mut client_config = (*termination::upstream_client_config(settings)?).clone();
+                if settings.alpn.is_empty() {
+                    client_config.alpn_protocols = match route.upstream_http {
+                        UpstreamHttp::Http1 => vec![b"http/1.1".to_vec()],
+                        UpstreamHttp::Http2 => vec![b"h2".to_vec()],
+                    };
+                }
+                client_config.enable_sni = !route.strip_sni;
+                upstream_tls.insert(
+                    route.host.clone(),
+                    TlsConnector::from(Arc::new(client_config)),
+                );
+            }
+        }
+
+        let endpoint = quinn::Endpoint::server(
+            server_config(&quic_config, certificates)?,
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+        )?;
+
+        Ok(Self {
+            config,
+            endpoint,
+            upstream_tls,
+            max_field_section_size: http3_config.max_field_section_size as u64,
+        })
     }
 
-    /// Handles a QUIC connection (future implementation)
-    ///
-    /// # Note
+    /// Address the UDP listener forwards terminated sessions to
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Accepts connections until the endpoint is closed
+    pub async fn run(self: Arc<Self>) {
+        while let Some(incoming) = self.endpoint.accept().await {
+            let handler = Arc::clone(&self);
+            tokio::spawn(async move {
+                match incoming.await {
+                    Ok(connection) => {
+                        if let Err(e) = handler.handle_connection(connection).await {
+                            debug!("HTTP/3 connection error: {}", e);
+                        }
+                    }
+                    Err(e) => debug!("QUIC handshake failed: {}", e),
+                }
+            });
+        }
+    }
+
+    /// Closes all connections and stops accepting new ones
+    pub fn close(&self) {
+        self.endpoint.close(VarInt::from_u32(0), b"shutdown");
+    }
+
+    /// Serves the HTTP/3 requests of one connection
     ///
-    /// Full implementation would use quinn::Connection and h3.
-    /// Current approach forwards raw UDP datagrams transparently.
-    #[allow(dead_code)]
-    pub async fn handle_connection(&self, _conn: ()) -> Result<(), Box<dyn Error>> {
-        // Placeholder for future quinn::Connection handling
-        Err("Full QUIC connection handling not yet implemented".into())
+    /// Each request is forwarded in its own task.
+    pub async fn handle_connection(&self, connection: quinn::Connection) -> Result<(), BoxError> {
+        let sni = connection
+            .handshake_data()
+            .and_then(|data| data.downcast::<HandshakeData>().ok())
+            .and_then(|data| data.server_name)
+            .ok_or("QUIC connection without SNI")?;
+
+        let upstream = match self.upstream_for(&sni) {
+            Ok(upstream) => Arc::new(upstream),
+            Err(e) => {
+                connection.close(VarInt::from_u32(H3_INTERNAL_ERROR), b"no route");
+                return Err(e);
+            }
+        };
+
+        let mut h3 = h3::server::builder()
+            .max_field_section_size(self.max_field_section_size)
+            .build::<_, Bytes>(h3_quinn::Connection::new(connection))
+            .await?;
+
+        loop {
+            match h3.accept().await {
+                Ok(Some(resolver)) => {
+                    let upstream = Arc::clone(&upstream);
+                    let sni = sni.clone();
+                    tokio::spawn(async move {
+                        let (request, stream) = match resolver.resolve_request().await {
+                            Ok(resolved) => resolved,
+                            Err(e) => {
+                                debug!(sni = %sni, "Failed to read HTTP/3 request: {}", e);
+                                return;
+                            }
+                        };
+                        if let Err(e) = forward_request(&sni, request, stream, &upstream).await {
+                            debug!(sni = %sni, "HTTP/3 stream error: {}", e);
+                        }
+                    });
+                }
+                Ok(None) => return Ok(()),
+                Err(e) if e.is_h3_no_error() => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Resolves the backend of a terminated connection from its SNI
+    fn upstream_for(&self, sni: &str) -> Result<Upstream, BoxError> {
+        let route = self
+            .config
+            .route_for(sni)
+            .filter(|route| route.mode == RouteMode::Terminate && route.client_auth.is_none())
+            .ok_or_else(|| format!("No HTTP/3 termination route for {}", sni))?;
+
+        let backend_name = route.upstream_sni.as_deref().unwrap_or(sni);
+        let target = match route.upstream {
+            Some(ref upstream) => upstream.clone(),
+            None if route.upstream_tls.is_some() => format!("{}:443", backend_name),
+            None => format!("{}:80", backend_name),
+        };
+
+        let tls = match route.upstream_tls {
+            Some(ref settings) => {
+                let connector = self
+                    .upstream_tls
+                    .get(&route.host)
+                    .ok_or("Upstream TLS configuration unavailable")?;
+                let name = settings.server_name.as_deref().unwrap_or(backend_name);
+                Some((connector.clone(), ServerName::try_from(name.to_string())?))
+            }
+            None => None,
+        };
+
+        Ok(Upstream {
+            target,
+            tls,
+            http: route.upstream_http,
+            connect_timeout: Duration::from_secs(self.config.timeouts.connect),
+            http2: Mutex::new(None),
+        })
     }
 }
 
-impl Default for QuicConfig {
-    fn default() -> Self {
-        Self {
-            max_concurrent_streams: 100,
-            idle_timeout: 60,
-            enable_0rtt: true,
+/// Forwards one HTTP/3 request and streams the backend's response back
+async fn forward_request<S>(
+    sni: &str,
+    request: Request<()>,
+    stream: h3::server::RequestStream<S, Bytes>,
+    upstream: &Upstream,
+) -> Result<(), BoxError>
+where
+    S: h3::quic::BidiStream<Bytes> + Send + 'static,
+    S::RecvStream: Send + 'static,
+{
+    let (mut send, mut recv) = stream.split();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    // A request that ends with its headers gets an empty body, so HTTP/1.1
+    // backends see no chunked encoding for it
+    let first = recv.recv_data().await?;
+    let body = match first {
+        None => Empty::new().map_err(|never| match never {}).boxed_unsync(),
+        Some(mut chunk) => {
+            let (mut tx, rx) = mpsc::channel(BODY_CHANNEL_CAPACITY);
+            let first = chunk.copy_to_bytes(chunk.remaining());
+            tokio::spawn(async move {
+                if tx.send(Ok(Frame::data(first))).await.is_err() {
+                    return;
+                }
+                loop {
+                    let frame = match recv.recv_data().await {
+                        Ok(Some(mut chunk)) => {
+                            Ok(Frame::data(chunk.copy_to_bytes(chunk.remaining())))
+                        }
+                        Ok(None) => match recv.recv_trailers().await {
+                            Ok(Some(trailers)) => Ok(Frame::trailers(trailers)),
+                            Ok(None) => return,
+                            Err(e) => Err(BoxError::from(e)),
+                        },
+                        Err(e) => Err(BoxError::from(e)),
+                    };
+                    let done = !matches!(frame, Ok(ref frame) if frame.is_data());
+                    if tx.send(frame).await.is_err() || done {
+                        return;
+                    }
+                }
+            });
+            StreamBody::new(rx).boxed_unsync()
+        }
+    };
+
+    let response = match upstream.request(request, body) {
+        Ok(request) => upstream.send(request).await,
+        Err(e) => Err(ForwardError::Failed(e)),
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            warn!(sni = %sni, upstream = %upstream.target, "HTTP/3 upstream request failed: {}", e);
+            let status = e.status();
+            let response = Response::builder().status(status).body(())?;
+            send.send_response(response).await?;
+            send.finish().await?;
+            log_request(sni, &method, &path, status, &upstream.target);
+            return Ok(());
+        }
+    };
+
+    let (mut parts, mut body) = response.into_parts();
+    strip_hop_by_hop(&mut parts.headers);
+    parts.version = Version::HTTP_3;
+    let status = parts.status;
+    send.send_response(Response::from_parts(parts, ())).await?;
+
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
         }
     }
-}
+    send.finish().await?;
 
-/// Configures QUIC transport parameters (placeholder)
-///
-/// This will be used when implementing full quinn-based QUIC handling.
-#[allow(dead_code)]
-pub fn configure_quic_transport(_config: &QuicConfig) -> Result<(), Box<dyn Error>> {
-    // Placeholder for quinn::TransportConfig setup
+    log_request(sni, &method, &path, status, &upstream.target);
     Ok(())
 }
 
-/// Implements 0-RTT resumption (future implementation)
-///
-/// # 0-RTT Overview
-///
-/// 0-RTT allows clients to send application data in the first flight:
-/// - Reduces connection establishment latency
-/// - Requires session ticket from previous connection
-/// - Data sent in 0-RTT is replay-safe
-///
-/// # Implementation Notes
+/// Writes the access log entry of a proxied HTTP/3 request
+fn log_request(sni: &str, method: &hyper::Method, path: &str, status: StatusCode, upstream: &str) {
+    info!(
+        target: "sniproxy::access",
+        sni,
+        method = %method,
+        path,
+        status = status.as_u16(),
+        upstream,
+        "HTTP/3 request"
+    );
+}
+
+/// Starts an HTTP/1.1 or h2c backend that echoes each request
 ///
-/// Full 0-RTT support requires:
-/// - Session ticket storage/retrieval
-/// - Replay attack mitigation
-/// - Integration with TLS 1.3 handshake
-#[allow(dead_code)]
-pub fn handle_0rtt_data(_data: &[u8]) -> Result<(), Box<dyn Error>> {
-    // Placeholder for 0-RTT data handling
-    Err("0-RTT resumption not yet implemented".into())
+/// The response body is `"<method> <uri> <version> host=<host>\n<body>"`.
+#[cfg(test)]
+pub(crate) async fn spawn_echo_backend(http2: bool) -> SocketAddr {
+    use hyper::body::Incoming;
+    use hyper::server::conn;
+    use hyper::service::service_fn;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|request: Request<Incoming>| async move {
+                    let head = format!(
+                        "{} {} {:?} host={}",
+                        request.method(),
+                        request.uri(),
+                        request.version(),
+                        request
+                            .headers()
+                            .get(header::HOST)
+                            .and_then(|host| host.to_str().ok())
+                            .unwrap_or("-")
+                    );
+                    let body = request.into_body().collect().await?.to_bytes();
+                    let echo = format!("{}\n{}", head, String::from_utf8_lossy(&body));
+                    Ok::<_, hyper::Error>(
+                        Response::builder()
+                            .header("x-backend", "echo")
+                            .header(header::CONNECTION, "keep-alive")
+                            .body(http_body_util::Full::new(Bytes::from(echo)))
+                            .unwrap(),
+                    )
+                });
+                let io = TokioIo::new(stream);
+                let _ = if http2 {
+                    conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(io, service)
+                        .await
+                } else {
+                    conn::http1::Builder::new()
+                        .serve_connection(io, service)
+                        .await
+                };
+            });
+        }
+    });
+    addr
+}
+
+/// Sends one HTTP/3 request and returns the response with its body
+#[cfg(test)]
+pub(crate) async fn http3_request(
+    addr: SocketAddr,
+    server_name: &str,
+    trusted: rustls::pki_types::CertificateDer<'static>,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<(Response<()>, String), BoxError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(trusted)?;
+    let mut tls = rustls::ClientConfig::builder_with_provider(termination::crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![ALPN_H3.to_vec()];
+    let client_config = quinn::ClientConfig::new(Arc::new(
+        quinn::crypto::rustls::QuicClientConfig::try_from(tls)?,
+    ));
+
+    let endpoint = quinn::Endpoint::client(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+    let connection = endpoint
+        .connect_with(client_config, addr, server_name)?
+        .await?;
+    let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(connection)).await?;
+    tokio::spawn(async move { driver.wait_idle().await });
+
+    let request = Request::builder()
+        .method(method)
+        .uri(format!("https://{}{}", server_name, path))
+        .body(())?;
+    let mut stream = sender.send_request(request).await?;
+    if !body.is_empty() {
+        stream.send_data(Bytes::copy_from_slice(body)).await?;
+    }
+    stream.finish().await?;
+
+    let response = stream.recv_response().await?;
+    let mut received = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await? {
+        received.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    endpoint.close(VarInt::from_u32(0), b"done");
+    Ok((response, String::from_utf8(received)?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustls::pki_types::CertificateDer;
+    use std::fs;
+    use std::path::PathBuf;
 
-    #[test]
-    fn test_quic_config_default() {
-        let config = QuicConfig::default();
-        assert_eq!(config.max_concurrent_streams, 100);
-        assert_eq!(config.idle_timeout, 60);
-        assert!(config.enable_0rtt);
+    /// Writes a self-signed certificate for `name` into a fresh scratch directory
+    fn certificate_dir(test: &str, name: &str) -> (PathBuf, CertificateDer<'static>) {
+        let dir = std::env::temp_dir().join(format!("sniproxy-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        fs::write(dir.join(format!("{}.crt", name)), cert.cert.pem()).unwrap();
+        fs::write(
+            dir.join(format!("{}.key", name)),
+            cert.signing_key.serialize_pem(),
+        )
+        .unwrap();
+        (dir, cert.cert.der().clone())
     }
 
-    #[test]
-    fn test_quic_handler_creation() {
-        let config = QuicConfig::default();
-        let _handler = QuicHandler::new(config);
-        // Handler created successfully (placeholder)
+    /// Starts a handler whose only route terminates `h3.example.com`
+    fn spawn_handler(dir: &PathBuf, route: &str) -> SocketAddr {
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 2, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+tls_certificates: {{ directory: "{}" }}
+routes:
+  - {{ host: "h3.example.com", mode: terminate, {} }}
+"#,
+            dir.display(),
+            route
+        ))
+        .unwrap();
+        let certificates = Arc::new(CertificateStore::new(dir));
+        let handler = Arc::new(QuicHandler::new(Arc::new(config), certificates).unwrap());
+        let addr = handler.local_addr().unwrap();
+        tokio::spawn(handler.run());
+        addr
     }
 
     #[test]
     fn test_configure_quic_transport() {
         let config = QuicConfig::default();
-        let result = configure_quic_transport(&config);
-        assert!(result.is_ok());
+        assert!(configure_quic_transport(&config).is_ok());
+
+        let disabled = QuicConfig {
+            max_idle_timeout: 0,
+            keep_alive_interval: 0,
+            ..QuicConfig::default()
+        };
+        assert!(configure_quic_transport(&disabled).is_ok());
+
+        let too_small = QuicConfig {
+            max_datagram_size: 1199,
+            ..QuicConfig::default()
+        };
+        let error = configure_quic_transport(&too_small).unwrap_err();
+        assert!(error.to_string().contains("below the QUIC minimum"));
     }
 
     #[test]
-    fn test_0rtt_placeholder() {
-        let data = b"test data";
-        let result = handle_0rtt_data(data);
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("0-RTT resumption not yet implemented")
+    fn test_strip_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("close, X-Trace"),
+        );
+        headers.insert("x-trace", HeaderValue::from_static("1"));
+        headers.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
         );
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+
+        strip_hop_by_hop(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::CONTENT_TYPE));
     }
 
-    #[test]
-    fn test_connection_handler_placeholder() {
-        let config = QuicConfig::default();
-        let handler = QuicHandler::new(config);
+    #[tokio::test]
+    async fn test_http3_request_to_http1_backend() {
+        let (dir, cert) = certificate_dir("h3-http1", "h3.example.com");
+        let backend = spawn_echo_backend(false).await;
+        let addr = spawn_handler(&dir, &format!("upstream: \"{}\"", backend));
 
-        let result = tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(handler.handle_connection(()));
-
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("Full QUIC connection handling not yet implemented")
+        let (response, body) = http3_request(
+            addr,
+            "h3.example.com",
+            cert,
+            "POST",
+            "/submit?x=1",
+            b"hello",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-backend"], "echo");
+        assert!(!response.headers().contains_key(header::CONNECTION));
+        assert_eq!(body, "POST /submit?x=1 HTTP/1.1 host=h3.example.com\nhello");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http3_request_to_http2_backend() {
+        let (dir, cert) = certificate_dir("h3-http2", "h3.example.com");
+        let backend = spawn_echo_backend(true).await;
+        let addr = spawn_handler(
+            &dir,
+            &format!("upstream: \"{}\", upstream_http: http2", backend),
         );
+
+        for path in ["/first", "/second"] {
+            let (response, body) =
+                http3_request(addr, "h3.example.com", cert.clone(), "GET", path, b"")
+                    .await
+                    .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                body,
+                format!("GET http://h3.example.com{} HTTP/2.0 host=-\n", path)
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_returns_502() {
+        let (dir, cert) = certificate_dir("h3-502", "h3.example.com");
+        // Bind and drop a listener to find a closed port
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let addr = spawn_handler(&dir, &format!("upstream: \"{}\"", closed));
+
+        let (response, body) = http3_request(addr, "h3.example.com", cert, "GET", "/", b"")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert!(body.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,1021 @@
+//! TLS termination for routes in `terminate` mode
+//!
+//! Instead of passing the encrypted stream through, the proxy completes the
+//! handshake itself with a certificate picked by SNI and forwards the decrypted
+//! stream to a plaintext or re-encrypted backend. This module provides:
+//! - `CertificateStore`: per-SNI certificates loaded from a directory, with
+//!   wildcard lookup and hot reload
+//! - `ReplayStream`: replays the already-captured ClientHello into rustls
+//! - `upstream_client_config`: rustls client configuration for backend TLS
+//! - `client_verifier`/`ClientIdentity`: mutual TLS with CRLs and identity rules
+//!
+//! # Certificate directory layout
+//!
+//! ```text
+//! certs/
+//!   wiki.internal.example.com.crt   # serves wiki.internal.example.com
+//!   wiki.internal.example.com.key
+//!   _wildcard.example.com.pem       # serves *.example.com
+//!   _wildcard.example.com.key
+//! ```
+
+use base64::Engine as _;
+use rustls::client::ClientConfig;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{
+    CertificateDer, CertificateRevocationListDer, PrivateKeyDer, ServerName, UnixTime,
+};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{
+    CertificateError, DigitallySignedStruct, DistinguishedName, ServerConfig, SignatureScheme,
+};
+use sniproxy_config::{ClientAuth, UpstreamTls, matches_allowlist_pattern};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::{debug, info, warn};
+
+/// File name prefix marking a wildcard certificate (`_wildcard.example.com` → `*.example.com`)
+const WILDCARD_PREFIX: &str = "_wildcard.";
+
+/// Returns the crypto provider used for all proxy-side TLS
+///
+/// Both the ring and aws-lc-rs backends are compiled in (quinn uses ring),
+/// so the provider is selected explicitly rather than via the process default.
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Per-SNI certificates loaded from a directory
+///
+/// Lookups try the exact server name first, then a wildcard for its parent
+/// domain (`a.example.com` → `*.example.com`). The store implements
+/// `ResolvesServerCert`, so a reload is picked up by the next handshake
+/// without rebuilding the `ServerConfig`.
+#[derive(Debug)]
+pub struct CertificateStore {
+    directory: PathBuf,
+    provider: Arc<CryptoProvider>,
+    certificates: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    fingerprint: Mutex<Vec<(PathBuf, Option<SystemTime>, u64)>>,
+}
+
+impl CertificateStore {
+    /// Creates a store and loads all certificates from `directory`
+    ///
+    /// A missing or unreadable directory yields an empty store; certificates
+    /// added later are picked up by `reload_if_changed`.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        let store = Self {
+            directory: directory.into(),
+            provider: crypto_provider(),
+            certificates: RwLock::new(HashMap::new()),
+            fingerprint: Mutex::new(Vec::new()),
+        };
+
+        if let Err(e) = store.reload() {
+            warn!(
+                directory = %store.directory.display(),
+                error = %e,
+                "Failed to load TLS certificates"
+            );
+        }
+
+        store
+    }
+
+    /// Directory certificates are loaded from
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Number of server names with a loaded certificate
+    pub fn len(&self) -> usize {
+        self.certificates.read().unwrap().len()
+    }
+
+    /// Returns true if no certificate is loaded
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Finds the certificate for a server name, falling back to a wildcard
+    pub fn lookup(&self, server_name: &str) -> Option<Arc<CertifiedKey>> {
+        let name = server_name.to_ascii_lowercase();
+        let certificates = self.certificates.read().unwrap();
+
+        if let Some(key) = certificates.get(&name) {
+            return Some(key.clone());
+        }
+
+        let (_, parent) = name.split_once('.')?;
+        certificates.get(&format!("*.{}", parent)).cloned()
+    }
+
+    /// Reloads the directory if any certificate or key file changed
+    ///
+    /// Returns `Ok(true)` if a reload happened.
+    pub fn reload_if_changed(&self) -> io::Result<bool> {
+        let fingerprint = self.scan()?;
+        if *self.fingerprint.lock().unwrap() == fingerprint {
+            return Ok(false);
+        }
+
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Loads every certificate/key pair from the directory
+    ///
+    /// A pair that fails to load keeps its previously loaded certificate, so a
+    /// file caught mid-write does not take a hostname offline. Returns the
+    /// number of server names served after the reload.
+    pub fn reload(&self) -> io::Result<usize> {
+        let fingerprint = self.scan()?;
+        let previous = self.certificates.read().unwrap().clone();
+        let mut certificates = HashMap::new();
+
+        for (path, _, _) in &fingerprint {
+            let extension = path.extension().and_then(|e| e.to_str());
+            if !matches!(extension, Some("crt") | Some("pem")) {
+                continue;
+            }
+            let Some(name) = server_name_for(path) else {
+                continue;
+            };
+
+            match self.load_pair(path) {
+                Ok(key) => {
+                    debug!(name, path = %path.display(), "Loaded TLS certificate");
+                    certificates.insert(name, Arc::new(key));
+                }
+                Err(e) => {
+                    warn!(name, path = %path.display(), error = %e, "Failed to load TLS certificate");
+                    if let Some(key) = previous.get(&name) {
+                        certificates.insert(name, key.clone());
+                    }
+                }
+            }
+        }
+
+        let count = certificates.len();
+        *self.certificates.write().unwrap() = certificates;
+        *self.fingerprint.lock().unwrap() = fingerprint;
+
+        info!(
+            directory = %self.directory.display(),
+            certificates = count,
+            "TLS certificates loaded"
+        );
+        Ok(count)
+    }
+
+    /// Polls the directory for changes until the store is dropped elsewhere
+    ///
+    /// Holds only a weak reference, so the task ends once the owning handler
+    /// goes away.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let store = Arc::downgrade(&self);
+        drop(self);
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // The first tick completes immediately
+
+        loop {
+            ticker.tick().await;
+            let Some(store) = store.upgrade() else {
+                break;
+            };
+
+            if let Err(e) = store.reload_if_changed() {
+                warn!(
+                    directory = %store.directory.display(),
+                    error = %e,
+                    "Failed to check TLS certificates for changes"
+                );
+            }
+        }
+    }
+
+    /// Builds a rustls server configuration that resolves certificates from this store
+    ///
+    /// Pass `WebPkiClientVerifier::no_client_auth()` for routes without mutual TLS.
+    pub fn server_config(
+        self: &Arc<Self>,
+        client_verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<Arc<ServerConfig>, rustls::Error> {
+        let config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(client_verifier)
+            .with_cert_resolver(self.clone());
+
+        Ok(Arc::new(config))
+    }
+
+    fn load_pair(&self, cert_path: &Path) -> Result<CertifiedKey, Box<dyn std::error::Error>> {
+        let key_path = cert_path.with_extension("key");
+
+        let chain = CertificateDer::pem_file_iter(cert_path)?.collect::<Result<Vec<_>, _>>()?;
+        if chain.is_empty() {
+            return Err("no certificate in file".into());
+        }
+        let key = PrivateKeyDer::from_pem_file(&key_path)?;
+
+        Ok(CertifiedKey::from_der(chain, key, &self.provider)?)
+    }
+
+    /// Lists certificate and key files with their modification time and size
+    fn scan(&self) -> io::Result<Vec<(PathBuf, Option<SystemTime>, u64)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|e| e.to_str());
+            if !matches!(extension, Some("crt") | Some("pem") | Some("key")) {
+                continue;
+            }
+
+            // Follow symlinks so renewals that swap a link target are noticed
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_file() {
+                files.push((path, metadata.modified().ok(), metadata.len()));
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name()?;
+        let key = self.lookup(server_name);
+        if key.is_none() {
+            debug!(server_name, "No TLS certificate for server name");
+        }
+        key
+    }
+}
+
+/// Maps a certificate file name to the server name it serves
+fn server_name_for(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?.to_ascii_lowercase();
+    match stem.strip_prefix(WILDCARD_PREFIX) {
+        Some(parent) if !parent.is_empty() => Some(format!("*.{}", parent)),
+        Some(_) => None,
+        None if !stem.is_empty() => Some(stem),
+        None => None,
+    }
+}
+
+/// Builds a rustls client configuration for TLS to a backend
+///
+/// Backend certificates are verified against `ca_file` if configured,
+/// otherwise against the system trust store. With `pins`, the verified leaf
+/// certificate's public key must also match one of the pinned hashes.
+pub fn upstream_client_config(
+    settings: &UpstreamTls,
+) -> Result<Arc<ClientConfig>, Box<dyn std::error::Error>> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    match settings.ca_file {
+        Some(ref ca_file) => {
+            for cert in CertificateDer::pem_file_iter(ca_file)? {
+                roots.add(cert?)?;
+            }
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for error in &native.errors {
+                warn!(error = %error, "Failed to load system trust root");
+            }
+            roots.add_parsable_certificates(native.certs);
+        }
+    }
+
+    if roots.is_empty() {
+        return Err("no trust roots available for upstream TLS".into());
+    }
+
+    let provider = crypto_provider();
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let mut config = if settings.pins.is_empty() {
+        builder.with_root_certificates(roots).with_no_client_auth()
+    } else {
+        let pins = settings
+            .pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inner =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedServerVerifier { inner, pins }))
+            .with_no_client_auth()
+    };
+    config.alpn_protocols = settings
+        .alpn
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+
+    Ok(Arc::new(config))
+}
+
+/// Decodes a "sha256/<base64>" public key pin
+fn parse_pin(pin: &str) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let encoded = pin
+        .strip_prefix("sha256/")
+        .ok_or_else(|| format!("pin must start with \"sha256/\": {}", pin))?;
+    let hash = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("invalid pin {}: {}", pin, e))?;
+    hash.try_into()
+        .map_err(|_| format!("pin is not a SHA-256 hash: {}", pin).into())
+}
+
+/// Applies public key pins on top of regular certificate verification
+#[derive(Debug)]
+struct PinnedServerVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let (_, certificate) = x509_parser::parse_x509_certificate(end_entity)
+            .map_err(|_| CertificateError::BadEncoding)?;
+        let hash = ring::digest::digest(&ring::digest::SHA256, certificate.public_key().raw);
+        if !self.pins.iter().any(|pin| pin.as_slice() == hash.as_ref()) {
+            warn!(server_name = ?server_name, "Backend certificate matches no pin");
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Builds the client certificate verifier for a mutual TLS route
+///
+/// Certificates must chain to `ca_file` and must not be revoked by any CRL in
+/// `crl_files`. When identity rules are configured, certificates matching no
+/// rule are refused during the handshake with an access_denied alert.
+pub fn client_verifier(
+    auth: &ClientAuth,
+) -> Result<Arc<dyn ClientCertVerifier>, Box<dyn std::error::Error>> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(&auth.ca_file)? {
+        roots.add(cert?)?;
+    }
+    if roots.is_empty() {
+        return Err(format!("no CA certificates in {}", auth.ca_file).into());
+    }
+
+    let mut crls = Vec::new();
+    for crl_file in &auth.crl_files {
+        crls.extend(load_crls(Path::new(crl_file))?);
+    }
+
+    let mut builder =
+        WebPkiClientVerifier::builder_with_provider(Arc::new(roots), crypto_provider())
+            .with_crls(crls);
+    if auth.optional {
+        builder = builder.allow_unauthenticated();
+    }
+    let verifier = builder.build()?;
+
+    if auth.rules.is_empty() {
+        return Ok(verifier);
+    }
+
+    Ok(Arc::new(IdentityVerifier {
+        inner: verifier,
+        patterns: auth
+            .rules
+            .iter()
+            .map(|rule| rule.identity.to_ascii_lowercase())
+            .collect(),
+    }))
+}
+
+/// Loads certificate revocation lists from a PEM or DER file
+fn load_crls(
+    path: &Path,
+) -> Result<Vec<CertificateRevocationListDer<'static>>, Box<dyn std::error::Error>> {
+    let contents = fs::read(path)?;
+    if contents.starts_with(b"-----BEGIN") {
+        let crls = CertificateRevocationListDer::pem_slice_iter(&contents)
+            .collect::<Result<Vec<_>, _>>()?;
+        if crls.is_empty() {
+            return Err(format!("no CRL in {}", path.display()).into());
+        }
+        Ok(crls)
+    } else {
+        Ok(vec![CertificateRevocationListDer::from(contents)])
+    }
+}
+
+/// Applies the route's identity rules on top of chain and revocation checks
+#[derive(Debug)]
+struct IdentityVerifier {
+    inner: Arc<dyn ClientCertVerifier>,
+    patterns: Vec<String>,
+}
+
+impl ClientCertVerifier for IdentityVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_client_cert(end_entity, intermediates, now)?;
+
+        let identity =
+            ClientIdentity::from_certificate(end_entity).ok_or(CertificateError::BadEncoding)?;
+        if !self
+            .patterns
+            .iter()
+            .any(|pattern| identity.matches(pattern))
+        {
+            warn!(
+                identity = identity.id(),
+                "Client certificate matches no identity rule"
+            );
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Identity of a verified client certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// Full subject distinguished name (e.g. "CN=billing,O=Example")
+    pub subject: String,
+    /// Subject common name, if present
+    pub common_name: Option<String>,
+    /// URI subject alternative names (e.g. SPIFFE IDs)
+    pub uris: Vec<String>,
+    /// DNS subject alternative names
+    pub dns_names: Vec<String>,
+    /// Email subject alternative names
+    pub emails: Vec<String>,
+}
+
+impl ClientIdentity {
+    /// Extracts the identity from a DER certificate
+    pub fn from_certificate(cert: &CertificateDer<'_>) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+
+        let mut identity = Self {
+            subject: cert.subject().to_string(),
+            common_name,
+            uris: Vec::new(),
+            dns_names: Vec::new(),
+            emails: Vec::new(),
+        };
+
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                match name {
+                    x509_parser::extensions::GeneralName::URI(uri) => {
+                        identity.uris.push(uri.to_string())
+                    }
+                    x509_parser::extensions::GeneralName::DNSName(dns) => {
+                        identity.dns_names.push(dns.to_string())
+                    }
+                    x509_parser::extensions::GeneralName::RFC822Name(email) => {
+                        identity.emails.push(email.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Some(identity)
+    }
+
+    /// The identity forwarded upstream: first URI SAN, else first DNS SAN,
+    /// else subject CN, else the full subject
+    pub fn id(&self) -> &str {
+        self.uris
+            .first()
+            .or(self.dns_names.first())
+            .or(self.common_name.as_ref())
+            .unwrap_or(&self.subject)
+    }
+
+    /// Checks the subject CN and every SAN against an allowlist-style pattern
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_ascii_lowercase();
+        self.common_name
+            .iter()
+            .chain(&self.uris)
+            .chain(&self.dns_names)
+            .chain(&self.emails)
+            .any(|name| matches_allowlist_pattern(&name.to_ascii_lowercase(), &pattern))
+    }
+}
+
+/// Outcome of applying a route's identity rules to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Authorization<'a> {
+    /// Admitted; carries the backend override of the matching rule, if any
+    Allowed(Option<&'a str>),
+    /// No rule matches the client (or it presented no certificate)
+    Denied,
+}
+
+/// Applies a route's identity rules to a client
+///
+/// The first matching rule wins. Without rules every client admitted by the
+/// verifier is authorized for the route's backend.
+pub fn authorize<'a>(auth: &'a ClientAuth, identity: Option<&ClientIdentity>) -> Authorization<'a> {
+    if auth.rules.is_empty() {
+        return Authorization::Allowed(None);
+    }
+
+    let Some(identity) = identity else {
+        return Authorization::Denied;
+    };
+    auth.rules
+        .iter()
+        .find(|rule| identity.matches(&rule.identity))
+        .map_or(Authorization::Denied, |rule| {
+            Authorization::Allowed(rule.upstream.as_deref())
+        })
+}
+
+/// A stream that yields previously captured bytes before reading from the inner stream
+///
+/// The proxy reads the ClientHello to route the connection before deciding to
+/// terminate TLS; `ReplayStream` hands those bytes to rustls as if they had not
+/// been consumed yet. Writes go straight to the inner stream.
+pub struct ReplayStream<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> ReplayStream<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ReplayStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.position < this.prefix.len() {
+            let n = buf.remaining().min(this.prefix.len() - this.position);
+            buf.put_slice(&this.prefix[this.position..this.position + n]);
+            this.position += n;
+
+            if this.position == this.prefix.len() {
+                this.prefix = Vec::new();
+                this.position = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ReplayStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Creates an empty scratch directory unique to this process and test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sniproxy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a self-signed certificate for `names` as `<file_name>.crt`/`.key`
+    fn write_cert(dir: &Path, file_name: &str, names: &[&str]) -> CertificateDer<'static> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let cert = rcgen::generate_simple_self_signed(names).unwrap();
+        fs::write(dir.join(format!("{}.crt", file_name)), cert.cert.pem()).unwrap();
+        fs::write(
+            dir.join(format!("{}.key", file_name)),
+            cert.signing_key.serialize_pem(),
+        )
+        .unwrap();
+        cert.cert.der().clone()
+    }
+
+    #[test]
+    fn test_server_name_for() {
+        assert_eq!(
+            server_name_for(Path::new("/certs/Example.COM.crt")).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            server_name_for(Path::new("/certs/_wildcard.example.com.pem")).as_deref(),
+            Some("*.example.com")
+        );
+        assert_eq!(server_name_for(Path::new("/certs/_wildcard..crt")), None);
+    }
+
+    #[test]
+    fn test_certificate_store_lookup_with_wildcard() {
+        let dir = scratch_dir("store-lookup");
+        let exact = write_cert(&dir, "api.example.com", &["api.example.com"]);
+        let wildcard = write_cert(&dir, "_wildcard.example.com", &["*.example.com"]);
+
+        let store = CertificateStore::new(&dir);
+        assert_eq!(store.len(), 2);
+
+        assert_eq!(store.lookup("api.example.com").unwrap().cert[0], exact);
+        assert_eq!(store.lookup("API.Example.com").unwrap().cert[0], exact);
+        assert_eq!(store.lookup("web.example.com").unwrap().cert[0], wildcard);
+
+        // Wildcards cover exactly one label
+        assert!(store.lookup("a.b.example.com").is_none());
+        assert!(store.lookup("example.com").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_certificate_store_reload() {
+        let dir = scratch_dir("store-reload");
+        let first = write_cert(&dir, "app.example.com", &["app.example.com"]);
+
+        let store = CertificateStore::new(&dir);
+        assert_eq!(store.lookup("app.example.com").unwrap().cert[0], first);
+        assert!(!store.reload_if_changed().unwrap());
+
+        // Replace the certificate and add a new one
+        let second = write_cert(&dir, "app.example.com", &["app.example.com"]);
+        write_cert(&dir, "new.example.com", &["new.example.com"]);
+        assert!(store.reload_if_changed().unwrap());
+        assert_eq!(store.lookup("app.example.com").unwrap().cert[0], second);
+        assert!(store.lookup("new.example.com").is_some());
+
+        // A broken key keeps the previously loaded certificate
+        fs::write(dir.join("app.example.com.key"), "not a key").unwrap();
+        assert!(store.reload_if_changed().unwrap());
+        assert_eq!(store.lookup("app.example.com").unwrap().cert[0], second);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_certificate_store_missing_directory() {
+        let store = CertificateStore::new("/nonexistent/sniproxy/certs");
+        assert!(store.is_empty());
+        assert!(store.reload_if_changed().is_err());
+    }
+
+    #[test]
+    fn test_upstream_client_config_with_ca_file() {
+        let dir = scratch_dir("upstream-ca");
+        write_cert(&dir, "backend", &["backend.internal"]);
+
+        let settings = UpstreamTls {
+            ca_file: Some(dir.join("backend.crt").to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        assert!(upstream_client_config(&settings).is_ok());
+
+        let missing = UpstreamTls {
+            ca_file: Some(dir.join("missing.crt").to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        assert!(upstream_client_config(&missing).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_upstream_client_config_alpn_and_pins() {
+        let dir = scratch_dir("upstream-pins");
+        write_cert(&dir, "backend", &["backend.internal"]);
+        let ca_file = Some(dir.join("backend.crt").to_string_lossy().into_owned());
+
+        let settings = UpstreamTls {
+            ca_file: ca_file.clone(),
+            alpn: vec!["http/1.1".to_string()],
+            pins: vec!["sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string()],
+            ..Default::default()
+        };
+        let config = upstream_client_config(&settings).unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"http/1.1".to_vec()]);
+
+        for pin in [
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+            "sha256/AAAA",
+            "sha256/!",
+        ] {
+            let settings = UpstreamTls {
+                ca_file: ca_file.clone(),
+                pins: vec![pin.to_string()],
+                ..Default::default()
+            };
+            assert!(upstream_client_config(&settings).is_err(), "{}", pin);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test PKI: a CA, a client certificate it issued, and a CRL revoking a second one
+    struct TestPki {
+        ca_pem: String,
+        client_der: CertificateDer<'static>,
+        revoked_der: CertificateDer<'static>,
+        crl_pem: String,
+    }
+
+    fn test_pki() -> TestPki {
+        use rcgen::{
+            BasicConstraints, CertificateParams, CertificateRevocationListParams, IsCa, KeyPair,
+            KeyUsagePurpose, RevokedCertParams, SanType, SerialNumber,
+        };
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = rcgen::Issuer::new(ca_params, ca_key);
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params =
+            CertificateParams::new(vec!["billing.payments.internal".to_string()]).unwrap();
+        client_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "billing");
+        client_params.subject_alt_names.push(SanType::URI(
+            "spiffe://example.org/billing".try_into().unwrap(),
+        ));
+        client_params.serial_number = Some(SerialNumber::from(1u64));
+        let client = client_params.signed_by(&client_key, &issuer).unwrap();
+
+        let mut revoked_params = client_params.clone();
+        revoked_params.serial_number = Some(SerialNumber::from(2u64));
+        let revoked = revoked_params.signed_by(&client_key, &issuer).unwrap();
+
+        let crl = CertificateRevocationListParams {
+            this_update: rcgen::date_time_ymd(2024, 1, 1),
+            next_update: rcgen::date_time_ymd(2099, 1, 1),
+            crl_number: SerialNumber::from(1u64),
+            issuing_distribution_point: None,
+            revoked_certs: vec![RevokedCertParams {
+                serial_number: SerialNumber::from(2u64),
+                revocation_time: rcgen::date_time_ymd(2024, 1, 1),
+                reason_code: None,
+                invalidity_date: None,
+            }],
+            key_identifier_method: rcgen::KeyIdMethod::Sha256,
+        }
+        .signed_by(&issuer)
+        .unwrap();
+
+        TestPki {
+            ca_pem: ca.pem(),
+            client_der: client.der().clone(),
+            revoked_der: revoked.der().clone(),
+            crl_pem: crl.pem().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_client_identity_from_certificate() {
+        let pki = test_pki();
+        let identity = ClientIdentity::from_certificate(&pki.client_der).unwrap();
+
+        assert_eq!(identity.common_name.as_deref(), Some("billing"));
+        assert_eq!(identity.dns_names, vec!["billing.payments.internal"]);
+        assert_eq!(identity.id(), "spiffe://example.org/billing");
+        assert!(identity.matches("*.payments.internal"));
+        assert!(identity.matches("spiffe://example.org/billing"));
+        assert!(identity.matches("BILLING"));
+        assert!(!identity.matches("*.ledger.internal"));
+    }
+
+    #[test]
+    fn test_authorize_rules() {
+        let pki = test_pki();
+        let identity = ClientIdentity::from_certificate(&pki.client_der).unwrap();
+
+        let open = ClientAuth::default();
+        assert_eq!(authorize(&open, None), Authorization::Allowed(None));
+
+        let auth = ClientAuth {
+            rules: vec![
+                sniproxy_config::IdentityRule {
+                    identity: "*.ledger.internal".to_string(),
+                    upstream: Some("10.0.0.1:80".to_string()),
+                },
+                sniproxy_config::IdentityRule {
+                    identity: "*.payments.internal".to_string(),
+                    upstream: Some("10.0.0.2:80".to_string()),
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            authorize(&auth, Some(&identity)),
+            Authorization::Allowed(Some("10.0.0.2:80"))
+        );
+        assert_eq!(authorize(&auth, None), Authorization::Denied);
+    }
+
+    #[test]
+    fn test_client_verifier_with_crl() {
+        let pki = test_pki();
+        let dir = scratch_dir("client-verifier");
+        fs::write(dir.join("ca.pem"), &pki.ca_pem).unwrap();
+        fs::write(dir.join("clients.crl"), &pki.crl_pem).unwrap();
+
+        let auth = ClientAuth {
+            ca_file: dir.join("ca.pem").to_string_lossy().into_owned(),
+            crl_files: vec![dir.join("clients.crl").to_string_lossy().into_owned()],
+            rules: vec![sniproxy_config::IdentityRule {
+                identity: "*.payments.internal".to_string(),
+                upstream: None,
+            }],
+            ..Default::default()
+        };
+        let verifier = client_verifier(&auth).unwrap();
+        assert!(verifier.client_auth_mandatory());
+        assert!(
+            verifier
+                .verify_client_cert(&pki.client_der, &[], UnixTime::now())
+                .is_ok()
+        );
+
+        assert!(matches!(
+            verifier.verify_client_cert(&pki.revoked_der, &[], UnixTime::now()),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Revoked))
+        ));
+
+        // The same certificate is refused once no rule matches it
+        let auth = ClientAuth {
+            rules: vec![sniproxy_config::IdentityRule {
+                identity: "*.ledger.internal".to_string(),
+                upstream: None,
+            }],
+            ..auth
+        };
+        let verifier = client_verifier(&auth).unwrap();
+        assert!(matches!(
+            verifier.verify_client_cert(&pki.client_der, &[], UnixTime::now()),
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure
+            ))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut stream = ReplayStream::new(b"hello ".to_vec(), client);
+
+        server.write_all(b"world").await.unwrap();
+        drop(server);
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        assert_eq!(received, "hello world");
+    }
+}
@@ -0,0 +1,1081 @@
+//! TLS ClientHello inspection, policy enforcement and alert generation
+//!
+//! This module complements `extract_sni`/`extract_alpn` with a structured view of
+//! the ClientHello that is needed to enforce per-route TLS policies:
+//! - Offered protocol versions (legacy_version and supported_versions)
+//! - Offered cipher suites
+//! - Offered named groups (supported_groups)
+//!
+//! The backend's ServerHello is checked against the same policy, so a mixed
+//! offer cannot be negotiated down to a forbidden version, suite or group.
+//!
+//! When a policy is violated or the connection cannot be routed, the proxy
+//! answers with a fatal TLS alert record instead of silently closing the connection.
+//!
+//! It can also rewrite or strip the server_name extension of a captured
+//! ClientHello record so the backend sees a different SNI than the client sent.
+
+use crate::SniError;
+use sniproxy_config::TlsPolicy;
+
+const TLS_HANDSHAKE: u8 = 0x16;
+const TLS_ALERT: u8 = 0x15;
+const CLIENT_HELLO: u8 = 0x01;
+const SERVER_HELLO: u8 = 0x02;
+const SNI_EXTENSION: u16 = 0x0000;
+const SUPPORTED_GROUPS_EXTENSION: u16 = 0x000a;
+const PRE_SHARED_KEY_EXTENSION: u16 = 0x0029;
+const EARLY_DATA_EXTENSION: u16 = 0x002a;
+const SUPPORTED_VERSIONS_EXTENSION: u16 = 0x002b;
+const KEY_SHARE_EXTENSION: u16 = 0x0033;
+
+/// ServerHello.random value that marks a HelloRetryRequest (RFC 8446 §4.1.3)
+const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// Maximum TLSPlaintext fragment length (RFC 8446 §5.1)
+const MAX_RECORD_LENGTH: usize = 16384;
+
+/// Alert level "fatal" (RFC 8446 §6)
+const ALERT_LEVEL_FATAL: u8 = 2;
+
+/// TLS alert descriptions sent by the proxy (RFC 8446 §6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertDescription {
+    /// No acceptable set of parameters could be negotiated (40)
+    HandshakeFailure,
+    /// The requested server name is refused by access control (49)
+    AccessDenied,
+    /// The offered protocol versions are not supported (70)
+    ProtocolVersion,
+    /// The proxy failed for reasons unrelated to the peer, e.g. backend down (80)
+    InternalError,
+    /// The requested server name cannot be resolved (112)
+    UnrecognizedName,
+}
+
+impl AlertDescription {
+    /// Returns the alert description code
+    #[inline]
+    pub fn code(&self) -> u8 {
+        match self {
+            AlertDescription::HandshakeFailure => 40,
+            AlertDescription::AccessDenied => 49,
+            AlertDescription::ProtocolVersion => 70,
+            AlertDescription::InternalError => 80,
+            AlertDescription::UnrecognizedName => 112,
+        }
+    }
+}
+
+/// Builds a fatal TLS alert record ready to be written to the client.
+///
+/// The record uses the TLS 1.2 record version, which is what TLS 1.3 peers
+/// expect for plaintext records sent before the handshake completes.
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_core::tls::{AlertDescription, alert_record};
+///
+/// let record = alert_record(AlertDescription::ProtocolVersion);
+/// assert_eq!(record, [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 70]);
+/// ```
+pub fn alert_record(description: AlertDescription) -> [u8; 7] {
+    [
+        TLS_ALERT,
+        0x03,
+        0x03,
+        0x00,
+        0x02,
+        ALERT_LEVEL_FATAL,
+        description.code(),
+    ]
+}
+
+/// Parameters offered by a client in its ClientHello
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// The legacy_version field of the ClientHello (e.g. 0x0303)
+    pub legacy_version: u16,
+    /// Offered cipher suites, in client preference order
+    pub cipher_suites: Vec<u16>,
+    /// Versions from the supported_versions extension (empty if absent)
+    pub supported_versions: Vec<u16>,
+    /// Groups from the supported_groups extension (empty if absent)
+    pub supported_groups: Vec<u16>,
+}
+
+impl ClientHelloInfo {
+    /// Returns the highest protocol version the client offers.
+    ///
+    /// Uses supported_versions when present (TLS 1.3 clients), otherwise
+    /// the legacy_version field. GREASE values are ignored.
+    pub fn max_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .copied()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(self.legacy_version)
+    }
+}
+
+/// Returns true for GREASE code points (RFC 8701), e.g. 0x0a0a, 0x1a1a
+#[inline]
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && (value >> 8) == (value & 0xff)
+}
+
+/// Parses the version, cipher suite and group offers from a TLS ClientHello record.
+///
+/// # Arguments
+///
+/// * `record` - A complete TLS record containing the ClientHello
+///
+/// # Errors
+///
+/// Returns an `SniError` if the record is not a ClientHello or is truncated.
+pub fn parse_client_hello(record: &[u8]) -> Result<ClientHelloInfo, SniError> {
+    if record.len() < 9 {
+        return Err(SniError::MessageTruncated);
+    }
+    if record[0] != TLS_HANDSHAKE {
+        return Err(SniError::InvalidHandshakeType);
+    }
+    if record[5] != CLIENT_HELLO {
+        return Err(SniError::InvalidClientHello);
+    }
+
+    let handshake_length =
+        ((record[6] as usize) << 16) | ((record[7] as usize) << 8) | (record[8] as usize);
+    let end = 9 + handshake_length;
+    if record.len() < end {
+        return Err(SniError::MessageTruncated);
+    }
+    let body = &record[..end];
+
+    let mut pos = 9;
+    let legacy_version = read_u16(body, pos)?;
+    pos += 2 + 32; // version + random
+
+    // Session ID
+    let session_id_length = *body.get(pos).ok_or(SniError::MessageTruncated)? as usize;
+    pos += 1 + session_id_length;
+
+    // Cipher suites
+    let cipher_suites_length = read_u16(body, pos)? as usize;
+    pos += 2;
+    let cipher_suites = read_u16_list(body, pos, cipher_suites_length)?;
+    pos += cipher_suites_length;
+
+    // Compression methods
+    let compression_methods_length = *body.get(pos).ok_or(SniError::MessageTruncated)? as usize;
+    pos += 1 + compression_methods_length;
+
+    let mut info = ClientHelloInfo {
+        legacy_version,
+        cipher_suites,
+        supported_versions: Vec::new(),
+        supported_groups: Vec::new(),
+    };
+
+    // Extensions are optional in pre-TLS 1.3 ClientHellos
+    if pos == body.len() {
+        return Ok(info);
+    }
+
+    let extensions_length = read_u16(body, pos)? as usize;
+    pos += 2;
+    let extensions_end = pos + extensions_length;
+    if body.len() < extensions_end {
+        return Err(SniError::MessageTruncated);
+    }
+
+    while pos + 4 <= extensions_end {
+        let extension_type = read_u16(body, pos)?;
+        let extension_length = read_u16(body, pos + 2)? as usize;
+        pos += 4;
+        if pos + extension_length > extensions_end {
+            return Err(SniError::MessageTruncated);
+        }
+
+        match extension_type {
+            SUPPORTED_VERSIONS_EXTENSION if extension_length >= 1 => {
+                let list_length = body[pos] as usize;
+                if list_length + 1 > extension_length {
+                    return Err(SniError::MessageTruncated);
+                }
+                info.supported_versions = read_u16_list(body, pos + 1, list_length)?;
+            }
+            SUPPORTED_GROUPS_EXTENSION if extension_length >= 2 => {
+                let list_length = read_u16(body, pos)? as usize;
+                if list_length + 2 > extension_length {
+                    return Err(SniError::MessageTruncated);
+                }
+                info.supported_groups = read_u16_list(body, pos + 2, list_length)?;
+            }
+            _ => {}
+        }
+
+        pos += extension_length;
+    }
+
+    Ok(info)
+}
+
+/// Parameters a server selected in its ServerHello (or HelloRetryRequest)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerHelloInfo {
+    /// The legacy_version field of the ServerHello (e.g. 0x0303)
+    pub legacy_version: u16,
+    /// The selected cipher suite
+    pub cipher_suite: u16,
+    /// Version from the supported_versions extension (TLS 1.3 servers)
+    pub selected_version: Option<u16>,
+    /// Group from the key_share extension (TLS 1.3 servers)
+    pub key_share_group: Option<u16>,
+    /// Whether this is a HelloRetryRequest rather than the real ServerHello
+    pub hello_retry_request: bool,
+}
+
+impl ServerHelloInfo {
+    /// Returns the negotiated protocol version.
+    ///
+    /// Uses supported_versions when present, otherwise the legacy_version field.
+    pub fn version(&self) -> u16 {
+        self.selected_version.unwrap_or(self.legacy_version)
+    }
+}
+
+/// Parses the version, cipher suite and key share group selected in a TLS
+/// ServerHello record.
+///
+/// A HelloRetryRequest is parsed the same way and flagged in
+/// `hello_retry_request`; its key_share carries the group the server asks the
+/// client to use.
+///
+/// # Errors
+///
+/// Returns an `SniError` if the record does not start with a ServerHello or is truncated.
+pub fn parse_server_hello(record: &[u8]) -> Result<ServerHelloInfo, SniError> {
+    if record.len() < 9 {
+        return Err(SniError::MessageTruncated);
+    }
+    if record[0] != TLS_HANDSHAKE || record[5] != SERVER_HELLO {
+        return Err(SniError::InvalidHandshakeType);
+    }
+
+    let handshake_length =
+        ((record[6] as usize) << 16) | ((record[7] as usize) << 8) | (record[8] as usize);
+    let end = 9 + handshake_length;
+    if record.len() < end {
+        return Err(SniError::MessageTruncated);
+    }
+    let body = &record[..end];
+
+    let mut pos = 9;
+    let legacy_version = read_u16(body, pos)?;
+    let hello_retry_request = body
+        .get(pos + 2..pos + 34)
+        .ok_or(SniError::MessageTruncated)?
+        == HELLO_RETRY_REQUEST_RANDOM;
+    pos += 2 + 32; // version + random
+
+    // Session ID echo
+    let session_id_length = *body.get(pos).ok_or(SniError::MessageTruncated)? as usize;
+    pos += 1 + session_id_length;
+
+    let cipher_suite = read_u16(body, pos)?;
+    pos += 2 + 1; // cipher suite + compression method
+
+    let mut info = ServerHelloInfo {
+        legacy_version,
+        cipher_suite,
+        selected_version: None,
+        key_share_group: None,
+        hello_retry_request,
+    };
+
+    // Extensions are optional in pre-TLS 1.3 ServerHellos
+    if pos >= body.len() {
+        return Ok(info);
+    }
+
+    let extensions_length = read_u16(body, pos)? as usize;
+    pos += 2;
+    let extensions_end = pos + extensions_length;
+    if body.len() < extensions_end {
+        return Err(SniError::MessageTruncated);
+    }
+
+    while pos + 4 <= extensions_end {
+        let extension_type = read_u16(body, pos)?;
+        let extension_length = read_u16(body, pos + 2)? as usize;
+        pos += 4;
+        if pos + extension_length > extensions_end {
+            return Err(SniError::MessageTruncated);
+        }
+
+        match extension_type {
+            SUPPORTED_VERSIONS_EXTENSION if extension_length >= 2 => {
+                info.selected_version = Some(read_u16(body, pos)?);
+            }
+            // KeyShareEntry (ServerHello) and selected_group (HelloRetryRequest) both start with the group
+            KEY_SHARE_EXTENSION if extension_length >= 2 => {
+                info.key_share_group = Some(read_u16(body, pos)?);
+            }
+            _ => {}
+        }
+
+        pos += extension_length;
+    }
+
+    Ok(info)
+}
+
+#[inline]
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, SniError> {
+    match data.get(pos..pos + 2) {
+        Some(bytes) => Ok(((bytes[0] as u16) << 8) | (bytes[1] as u16)),
+        None => Err(SniError::MessageTruncated),
+    }
+}
+
+fn read_u16_list(data: &[u8], pos: usize, length: usize) -> Result<Vec<u16>, SniError> {
+    let bytes = data
+        .get(pos..pos + length)
+        .ok_or(SniError::MessageTruncated)?;
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| ((pair[0] as u16) << 8) | (pair[1] as u16))
+        .collect())
+}
+
+/// Rewrites the server_name extension of a ClientHello record.
+///
+/// With `Some(name)` the SNI is replaced by `name` (or added if the client sent
+/// none); with `None` the extension is removed. The extensions, handshake and
+/// record length fields are recomputed; all other extensions keep their order.
+///
+/// The pre_shared_key and early_data extensions are dropped as well: PSK binders
+/// are computed over the original ClientHello, so a resumption attempt would
+/// fail at the backend. Dropping them makes the client fall back to a full
+/// handshake instead.
+///
+/// The backend's handshake transcript then covers the rewritten ClientHello
+/// while the client's covers the original, so a backend that verifies the
+/// client's Finished message will reject the handshake. Terminate routes with
+/// `upstream_tls` present a different SNI without this limitation.
+///
+/// # Errors
+///
+/// Returns an `SniError` if the record is not a complete ClientHello, if `name`
+/// is not a valid DNS name, or if the rewritten record would exceed the maximum
+/// TLS record size.
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_core::extract_sni;
+/// use sniproxy_core::tls::rewrite_sni;
+///
+/// let mut record = vec![
+///     0x16, 0x03, 0x01, 0x00, 0x3F,  // TLS Record
+///     0x01, 0x00, 0x00, 0x3B,        // ClientHello
+///     0x03, 0x03,                    // Version
+/// ];
+/// record.extend_from_slice(&[0; 32]);  // Random
+/// record.extend_from_slice(&[
+///     0x00,                          // Session ID
+///     0x00, 0x02, 0x00, 0x00,        // Cipher suites
+///     0x01, 0x00,                    // Compression
+///     0x00, 0x10,                    // Extensions length
+///     0x00, 0x00, 0x00, 0x0C,        // SNI extension
+///     0x00, 0x0A, 0x00, 0x00, 0x07,  // SNI list, host_name, length
+///     0x65, 0x78, 0x61, 0x6D, 0x70, 0x6C, 0x65,  // "example"
+/// ]);
+///
+/// let rewritten = rewrite_sni(&record, Some("new.example.net")).unwrap();
+/// assert_eq!(extract_sni(&rewritten).unwrap(), "new.example.net");
+/// ```
+pub fn rewrite_sni(record: &[u8], server_name: Option<&str>) -> Result<Vec<u8>, SniError> {
+    if let Some(name) = server_name
+        && !is_valid_server_name(name)
+    {
+        return Err(SniError::InvalidSniFormat);
+    }
+
+    if record.len() < 9 {
+        return Err(SniError::MessageTruncated);
+    }
+    if record[0] != TLS_HANDSHAKE {
+        return Err(SniError::InvalidHandshakeType);
+    }
+    if record[5] != CLIENT_HELLO {
+        return Err(SniError::InvalidClientHello);
+    }
+
+    let record_length = ((record[3] as usize) << 8) | (record[4] as usize);
+    let record_end = 5 + record_length;
+    let handshake_length =
+        ((record[6] as usize) << 16) | ((record[7] as usize) << 8) | (record[8] as usize);
+    let handshake_end = 9 + handshake_length;
+    if record.len() < record_end || record_end < handshake_end {
+        return Err(SniError::MessageTruncated);
+    }
+    let body = &record[..handshake_end];
+
+    // Locate the extensions block
+    let mut pos = 9 + 2 + 32; // header + version + random
+    let session_id_length = *body.get(pos).ok_or(SniError::MessageTruncated)? as usize;
+    pos += 1 + session_id_length;
+    let cipher_suites_length = read_u16(body, pos)? as usize;
+    pos += 2 + cipher_suites_length;
+    let compression_methods_length = *body.get(pos).ok_or(SniError::MessageTruncated)? as usize;
+    pos += 1 + compression_methods_length;
+    if pos > body.len() {
+        return Err(SniError::MessageTruncated);
+    }
+    let extensions_start = pos;
+
+    let mut extensions = Vec::with_capacity(body.len() - extensions_start + 64);
+    let mut sni_written = false;
+
+    if pos < body.len() {
+        let extensions_length = read_u16(body, pos)? as usize;
+        pos += 2;
+        let extensions_end = pos + extensions_length;
+        if extensions_end != body.len() {
+            return Err(SniError::MessageTruncated);
+        }
+
+        while pos < extensions_end {
+            let extension_type = read_u16(body, pos)?;
+            let extension_length = read_u16(body, pos + 2)? as usize;
+            let extension_end = pos + 4 + extension_length;
+            if extension_end > extensions_end {
+                return Err(SniError::MessageTruncated);
+            }
+
+            match extension_type {
+                SNI_EXTENSION => {
+                    if let Some(name) = server_name {
+                        write_sni_extension(&mut extensions, name);
+                        sni_written = true;
+                    }
+                }
+                PRE_SHARED_KEY_EXTENSION | EARLY_DATA_EXTENSION => {}
+                _ => extensions.extend_from_slice(&body[pos..extension_end]),
+            }
+
+            pos = extension_end;
+        }
+    }
+
+    // The client sent no SNI: add it in front of the other extensions
+    if let Some(name) = server_name
+        && !sni_written
+    {
+        let mut with_sni = Vec::with_capacity(extensions.len() + name.len() + 9);
+        write_sni_extension(&mut with_sni, name);
+        with_sni.extend_from_slice(&extensions);
+        extensions = with_sni;
+    }
+
+    let new_handshake_length = (extensions_start - 9) + 2 + extensions.len();
+    let trailing = &record[handshake_end..record_end];
+    let new_record_length = 4 + new_handshake_length + trailing.len();
+    if new_record_length > MAX_RECORD_LENGTH || extensions.len() > u16::MAX as usize {
+        return Err(SniError::InvalidSniFormat);
+    }
+
+    let mut rewritten = Vec::with_capacity(5 + new_record_length);
+    rewritten.extend_from_slice(&record[..3]);
+    rewritten.extend_from_slice(&(new_record_length as u16).to_be_bytes());
+    rewritten.push(CLIENT_HELLO);
+    rewritten.extend_from_slice(&(new_handshake_length as u32).to_be_bytes()[1..]);
+    rewritten.extend_from_slice(&record[9..extensions_start]);
+    rewritten.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    rewritten.extend_from_slice(&extensions);
+    rewritten.extend_from_slice(trailing);
+
+    Ok(rewritten)
+}
+
+/// Appends a server_name extension carrying a single host_name entry
+fn write_sni_extension(out: &mut Vec<u8>, name: &str) {
+    let name_length = name.len() as u16;
+    out.extend_from_slice(&SNI_EXTENSION.to_be_bytes());
+    out.extend_from_slice(&(name_length + 5).to_be_bytes()); // extension length
+    out.extend_from_slice(&(name_length + 3).to_be_bytes()); // server name list length
+    out.push(0x00); // host_name
+    out.extend_from_slice(&name_length.to_be_bytes());
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// Validates a name for use as SNI host_name (RFC 6066 §3: DNS name, no IP literals)
+#[inline]
+fn is_valid_server_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.parse::<std::net::IpAddr>().is_err()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'_')
+}
+
+/// Reason a ClientHello was rejected by a TLS policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The highest offered version is below the configured minimum
+    MinVersion,
+    /// Every offered cipher suite is forbidden
+    CipherSuite,
+    /// Every offered named group is forbidden
+    Group,
+}
+
+impl PolicyViolation {
+    /// Returns a string representation of the violation for metrics and logging
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyViolation::MinVersion => "min_version",
+            PolicyViolation::CipherSuite => "cipher_suite",
+            PolicyViolation::Group => "group",
+        }
+    }
+
+    /// Returns the TLS alert that should be sent to the client
+    #[inline]
+    pub fn alert(&self) -> AlertDescription {
+        match self {
+            PolicyViolation::MinVersion => AlertDescription::ProtocolVersion,
+            PolicyViolation::CipherSuite | PolicyViolation::Group => {
+                AlertDescription::HandshakeFailure
+            }
+        }
+    }
+}
+
+/// Evaluates a route's TLS policy against the parsed ClientHello.
+///
+/// Cipher suites and groups are rejected the way a TLS server would reject
+/// them: only when nothing acceptable remains in the client's offer.
+/// GREASE values never count as acceptable.
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_config::{TlsPolicy, TlsVersion};
+/// use sniproxy_core::tls::{ClientHelloInfo, PolicyViolation, check_policy};
+///
+/// let policy = TlsPolicy {
+///     min_version: Some(TlsVersion::Tls12),
+///     ..Default::default()
+/// };
+/// let hello = ClientHelloInfo {
+///     legacy_version: 0x0301,
+///     cipher_suites: vec![0x002f],
+///     supported_versions: vec![],
+///     supported_groups: vec![],
+/// };
+///
+/// assert_eq!(check_policy(&policy, &hello), Err(PolicyViolation::MinVersion));
+/// ```
+pub fn check_policy(policy: &TlsPolicy, hello: &ClientHelloInfo) -> Result<(), PolicyViolation> {
+    if let Some(min_version) = policy.min_version
+        && hello.max_version() < min_version.wire_version()
+    {
+        return Err(PolicyViolation::MinVersion);
+    }
+
+    if !policy.forbidden_cipher_suites.is_empty()
+        && !offers_acceptable(&hello.cipher_suites, &policy.forbidden_cipher_suites)
+    {
+        return Err(PolicyViolation::CipherSuite);
+    }
+
+    // Clients without supported_groups (pre-ECC TLS) are not subject to group policy
+    if !policy.forbidden_groups.is_empty()
+        && !hello.supported_groups.is_empty()
+        && !offers_acceptable(&hello.supported_groups, &policy.forbidden_groups)
+    {
+        return Err(PolicyViolation::Group);
+    }
+
+    Ok(())
+}
+
+/// Evaluates a route's TLS policy against the backend's ServerHello.
+///
+/// This catches a backend that negotiates a mixed offer down to a forbidden
+/// version, cipher suite or key share group. The group is only known here for
+/// TLS 1.3; TLS 1.2 selects it in the ServerKeyExchange.
+///
+/// # Examples
+///
+/// ```
+/// use sniproxy_config::TlsPolicy;
+/// use sniproxy_core::tls::{PolicyViolation, ServerHelloInfo, check_server_policy};
+///
+/// let policy = TlsPolicy {
+///     forbidden_cipher_suites: vec![0x1302],
+///     ..Default::default()
+/// };
+/// let hello = ServerHelloInfo {
+///     legacy_version: 0x0303,
+///     cipher_suite: 0x1302,
+///     selected_version: Some(0x0304),
+///     key_share_group: Some(0x001d),
+///     hello_retry_request: false,
+/// };
+///
+/// assert_eq!(check_server_policy(&policy, &hello), Err(PolicyViolation::CipherSuite));
+/// ```
+pub fn check_server_policy(
+    policy: &TlsPolicy,
+    hello: &ServerHelloInfo,
+) -> Result<(), PolicyViolation> {
+    if let Some(min_version) = policy.min_version
+        && hello.version() < min_version.wire_version()
+    {
+        return Err(PolicyViolation::MinVersion);
+    }
+
+    if policy.forbidden_cipher_suites.contains(&hello.cipher_suite) {
+        return Err(PolicyViolation::CipherSuite);
+    }
+
+    if let Some(group) = hello.key_share_group
+        && policy.forbidden_groups.contains(&group)
+    {
+        return Err(PolicyViolation::Group);
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn offers_acceptable(offered: &[u16], forbidden: &[u16]) -> bool {
+    offered
+        .iter()
+        .any(|value| !is_grease(*value) && !forbidden.contains(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sniproxy_config::TlsVersion;
+
+    /// Builds a ClientHello record with the given offers
+    fn build_client_hello(
+        legacy_version: u16,
+        cipher_suites: &[u16],
+        supported_versions: Option<&[u16]>,
+        supported_groups: Option<&[u16]>,
+    ) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(versions) = supported_versions {
+            extensions.extend_from_slice(&SUPPORTED_VERSIONS_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&((1 + versions.len() * 2) as u16).to_be_bytes());
+            extensions.push((versions.len() * 2) as u8);
+            for v in versions {
+                extensions.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        if let Some(groups) = supported_groups {
+            extensions.extend_from_slice(&SUPPORTED_GROUPS_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&((2 + groups.len() * 2) as u16).to_be_bytes());
+            extensions.extend_from_slice(&((groups.len() * 2) as u16).to_be_bytes());
+            for g in groups {
+                extensions.extend_from_slice(&g.to_be_bytes());
+            }
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&legacy_version.to_be_bytes());
+        body.extend_from_slice(&[0; 32]); // Random
+        body.push(0x00); // Session ID length
+        body.extend_from_slice(&((cipher_suites.len() * 2) as u16).to_be_bytes());
+        for suite in cipher_suites {
+            body.extend_from_slice(&suite.to_be_bytes());
+        }
+        body.extend_from_slice(&[0x01, 0x00]); // Compression methods
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        record.push(CLIENT_HELLO);
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        record.extend_from_slice(&body);
+        record
+    }
+
+    #[test]
+    fn test_parse_client_hello_tls13() {
+        let record = build_client_hello(
+            0x0303,
+            &[0x1301, 0xc02f],
+            Some(&[0x3a3a, 0x0304, 0x0303]),
+            Some(&[0x001d, 0x0017]),
+        );
+        let info = parse_client_hello(&record).unwrap();
+        assert_eq!(info.legacy_version, 0x0303);
+        assert_eq!(info.cipher_suites, vec![0x1301, 0xc02f]);
+        assert_eq!(info.supported_versions, vec![0x3a3a, 0x0304, 0x0303]);
+        assert_eq!(info.supported_groups, vec![0x001d, 0x0017]);
+        assert_eq!(info.max_version(), 0x0304);
+    }
+
+    #[test]
+    fn test_parse_client_hello_legacy_without_extensions() {
+        let record = build_client_hello(0x0301, &[0x002f], None, None);
+        let info = parse_client_hello(&record).unwrap();
+        assert!(info.supported_versions.is_empty());
+        assert_eq!(info.max_version(), 0x0301);
+    }
+
+    #[test]
+    fn test_parse_client_hello_truncated() {
+        let record = build_client_hello(0x0303, &[0x1301], Some(&[0x0304]), None);
+        assert!(matches!(
+            parse_client_hello(&record[..record.len() - 3]),
+            Err(SniError::MessageTruncated)
+        ));
+        assert!(matches!(
+            parse_client_hello(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28, 0x00, 0x00]),
+            Err(SniError::InvalidHandshakeType)
+        ));
+    }
+
+    #[test]
+    fn test_grease_detection() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+    }
+
+    #[test]
+    fn test_policy_min_version() {
+        let policy = TlsPolicy {
+            min_version: Some(TlsVersion::Tls13),
+            ..Default::default()
+        };
+        let tls12 = parse_client_hello(&build_client_hello(0x0303, &[0xc02f], None, None)).unwrap();
+        let tls13 = parse_client_hello(&build_client_hello(
+            0x0303,
+            &[0x1301],
+            Some(&[0x0304, 0x0303]),
+            None,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            check_policy(&policy, &tls12),
+            Err(PolicyViolation::MinVersion)
+        );
+        assert_eq!(check_policy(&policy, &tls13), Ok(()));
+    }
+
+    #[test]
+    fn test_policy_forbidden_cipher_suites() {
+        let policy = TlsPolicy {
+            forbidden_cipher_suites: vec![0x000a, 0x0005],
+            ..Default::default()
+        };
+        let legacy_only = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: vec![0x0a0a, 0x000a, 0x0005],
+            supported_versions: vec![],
+            supported_groups: vec![],
+        };
+        let mixed = ClientHelloInfo {
+            cipher_suites: vec![0x000a, 0xc02f],
+            ..legacy_only.clone()
+        };
+
+        assert_eq!(
+            check_policy(&policy, &legacy_only),
+            Err(PolicyViolation::CipherSuite)
+        );
+        assert_eq!(check_policy(&policy, &mixed), Ok(()));
+    }
+
+    #[test]
+    fn test_policy_forbidden_groups() {
+        let policy = TlsPolicy {
+            forbidden_groups: vec![0x0017],
+            ..Default::default()
+        };
+        let p256_only = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: vec![0x1301],
+            supported_versions: vec![0x0304],
+            supported_groups: vec![0x0017],
+        };
+        let no_groups = ClientHelloInfo {
+            supported_groups: vec![],
+            ..p256_only.clone()
+        };
+
+        assert_eq!(
+            check_policy(&policy, &p256_only),
+            Err(PolicyViolation::Group)
+        );
+        assert_eq!(check_policy(&policy, &no_groups), Ok(()));
+    }
+
+    /// Builds a ServerHello record with the given selections
+    fn build_server_hello(
+        cipher_suite: u16,
+        selected_version: Option<u16>,
+        key_share: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(version) = selected_version {
+            extensions.extend_from_slice(&SUPPORTED_VERSIONS_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&[0x00, 0x02]);
+            extensions.extend_from_slice(&version.to_be_bytes());
+        }
+        if let Some(key_share) = key_share {
+            extensions.extend_from_slice(&KEY_SHARE_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&(key_share.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(key_share);
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&[0x03, 0x03]);
+        // A HelloRetryRequest is a ServerHello that selects a group without a key share
+        if key_share.is_some_and(|key_share| key_share.len() == 2) {
+            body.extend_from_slice(&HELLO_RETRY_REQUEST_RANDOM);
+        } else {
+            body.extend_from_slice(&[0; 32]); // Random
+        }
+        body.push(0x00); // Session ID length
+        body.extend_from_slice(&cipher_suite.to_be_bytes());
+        body.push(0x00); // Compression method
+        if !extensions.is_empty() {
+            body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+            body.extend_from_slice(&extensions);
+        }
+
+        let mut record = vec![0x16, 0x03, 0x03];
+        record.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        record.push(SERVER_HELLO);
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        record.extend_from_slice(&body);
+        record
+    }
+
+    /// Appends an extension with the given type and payload to a ClientHello record
+    fn append_extension(record: &mut Vec<u8>, extension_type: u16, data: &[u8]) {
+        record.extend_from_slice(&extension_type.to_be_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+
+        // Patch extensions, handshake and record lengths
+        let record_length = record.len() - 5;
+        record[3..5].copy_from_slice(&(record_length as u16).to_be_bytes());
+        record[6..9].copy_from_slice(&((record_length - 4) as u32).to_be_bytes()[1..]);
+        let extensions_start =
+            9 + 2 + 32 + 1 + 2 + (record[44] as usize) * 256 + record[45] as usize + 1 + 1;
+        let extensions_length = record.len() - extensions_start - 2;
+        record[extensions_start..extensions_start + 2]
+            .copy_from_slice(&(extensions_length as u16).to_be_bytes());
+    }
+
+    fn client_hello_with_sni(name: &str) -> Vec<u8> {
+        let mut record = build_client_hello(0x0303, &[0x1301], Some(&[0x0304]), Some(&[0x001d]));
+        let mut sni = Vec::new();
+        write_sni_extension(&mut sni, name);
+        append_extension(&mut record, SNI_EXTENSION, &sni[4..]);
+        record
+    }
+
+    #[test]
+    fn test_parse_server_hello() {
+        // TLS 1.3 ServerHello: x25519 key share with a 32-byte public key
+        let mut key_share = vec![0x00, 0x1d, 0x00, 0x20];
+        key_share.extend_from_slice(&[7; 32]);
+        let info = parse_server_hello(&build_server_hello(0x1301, Some(0x0304), Some(&key_share)))
+            .unwrap();
+        assert_eq!(info.cipher_suite, 0x1301);
+        assert_eq!(info.version(), 0x0304);
+        assert_eq!(info.key_share_group, Some(0x001d));
+        assert!(!info.hello_retry_request);
+
+        // HelloRetryRequest: key_share holds only the selected group
+        let info = parse_server_hello(&build_server_hello(
+            0x1301,
+            Some(0x0304),
+            Some(&[0x00, 0x17]),
+        ))
+        .unwrap();
+        assert_eq!(info.key_share_group, Some(0x0017));
+        assert!(info.hello_retry_request);
+
+        // TLS 1.2 without extensions
+        let info = parse_server_hello(&build_server_hello(0xc02f, None, None)).unwrap();
+        assert_eq!(info.version(), 0x0303);
+        assert_eq!(info.key_share_group, None);
+
+        let record = build_server_hello(0x1301, Some(0x0304), None);
+        assert!(matches!(
+            parse_server_hello(&record[..record.len() - 1]),
+            Err(SniError::MessageTruncated)
+        ));
+        assert!(matches!(
+            parse_server_hello(&build_client_hello(0x0303, &[0x1301], None, None)),
+            Err(SniError::InvalidHandshakeType)
+        ));
+    }
+
+    #[test]
+    fn test_server_policy_catches_negotiated_down_offer() {
+        let policy = TlsPolicy {
+            min_version: Some(TlsVersion::Tls13),
+            forbidden_cipher_suites: vec![0x1302],
+            forbidden_groups: vec![0x0017],
+        };
+
+        // The mixed offer passes the ClientHello check
+        let offer = build_client_hello(
+            0x0303,
+            &[0x1301, 0x1302],
+            Some(&[0x0304, 0x0303]),
+            Some(&[0x001d, 0x0017]),
+        );
+        assert_eq!(
+            check_policy(&policy, &parse_client_hello(&offer).unwrap()),
+            Ok(())
+        );
+
+        let selected = |suite, version, key_share: Option<&[u8]>| {
+            parse_server_hello(&build_server_hello(suite, version, key_share)).unwrap()
+        };
+        assert_eq!(
+            check_server_policy(
+                &policy,
+                &selected(0x1302, Some(0x0304), Some(&[0x00, 0x1d]))
+            ),
+            Err(PolicyViolation::CipherSuite)
+        );
+        assert_eq!(
+            check_server_policy(
+                &policy,
+                &selected(0x1301, Some(0x0304), Some(&[0x00, 0x17]))
+            ),
+            Err(PolicyViolation::Group)
+        );
+        assert_eq!(
+            check_server_policy(&policy, &selected(0xc02f, None, None)),
+            Err(PolicyViolation::MinVersion)
+        );
+        assert_eq!(
+            check_server_policy(
+                &policy,
+                &selected(0x1301, Some(0x0304), Some(&[0x00, 0x1d]))
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sni_longer_and_shorter() {
+        let record = client_hello_with_sni("legacy.example.com");
+        assert_eq!(crate::extract_sni(&record).unwrap(), "legacy.example.com");
+
+        for name in ["a.io", "a-much-longer-backend-name.internal.example.net"] {
+            let rewritten = rewrite_sni(&record, Some(name)).unwrap();
+            assert_eq!(crate::extract_sni(&rewritten).unwrap(), name);
+
+            // Other offers survive the rewrite untouched
+            let before = parse_client_hello(&record).unwrap();
+            let after = parse_client_hello(&rewritten).unwrap();
+            assert_eq!(before, after);
+            assert_eq!(
+                rewritten.len() as isize - record.len() as isize,
+                name.len() as isize - "legacy.example.com".len() as isize
+            );
+        }
+    }
+
+    #[test]
+    fn test_rewrite_sni_strip() {
+        let record = client_hello_with_sni("legacy.example.com");
+        let stripped = rewrite_sni(&record, None).unwrap();
+
+        assert!(matches!(
+            crate::extract_sni(&stripped),
+            Err(SniError::InvalidSniFormat)
+        ));
+        assert_eq!(
+            parse_client_hello(&stripped).unwrap(),
+            parse_client_hello(&record).unwrap()
+        );
+        assert_eq!(
+            stripped.len(),
+            record.len() - 4 - 5 - "legacy.example.com".len()
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sni_adds_missing_extension() {
+        let record = build_client_hello(0x0303, &[0xc02f], None, None);
+        let rewritten = rewrite_sni(&record, Some("backend.example.net")).unwrap();
+        assert_eq!(
+            crate::extract_sni(&rewritten).unwrap(),
+            "backend.example.net"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sni_drops_psk() {
+        let mut record = client_hello_with_sni("legacy.example.com");
+        append_extension(&mut record, EARLY_DATA_EXTENSION, &[]);
+        append_extension(
+            &mut record,
+            PRE_SHARED_KEY_EXTENSION,
+            &[0x00, 0x00, 0x00, 0x00],
+        );
+
+        let rewritten = rewrite_sni(&record, Some("new.example.net")).unwrap();
+        assert_eq!(crate::extract_sni(&rewritten).unwrap(), "new.example.net");
+        assert!(
+            !rewritten
+                .windows(2)
+                .any(|w| w == PRE_SHARED_KEY_EXTENSION.to_be_bytes())
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sni_rejects_invalid_names() {
+        let record = client_hello_with_sni("legacy.example.com");
+        assert!(rewrite_sni(&record, Some("")).is_err());
+        assert!(rewrite_sni(&record, Some("10.0.0.1")).is_err());
+        assert!(rewrite_sni(&record, Some("bad name")).is_err());
+        assert!(rewrite_sni(&record[..record.len() - 1], Some("ok.example")).is_err());
+    }
+
+    #[test]
+    fn test_policy_violation_alerts() {
+        assert_eq!(
+            PolicyViolation::MinVersion.alert(),
+            AlertDescription::ProtocolVersion
+        );
+        assert_eq!(
+            PolicyViolation::CipherSuite.alert(),
+            AlertDescription::HandshakeFailure
+        );
+        assert_eq!(
+            alert_record(AlertDescription::HandshakeFailure),
+            [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 40]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Admission control for new UDP sessions
+//!
+//! A QUIC Initial comes from an address the client only claims to own, and
+//! its ClientHello picks the backend. Without checks the UDP listener can be
+//! used to reflect traffic at spoofed victims and to pin session state. Two
+//! guards are applied before a session is created:
+//! - stateless address validation: the proxy answers an Initial with a Retry
+//!   carrying a token bound to the client's IP address, and only a retried
+//!   Initial that returns a valid token may open a session (RFC 9000 §8.1)
+//! - a token bucket per source prefix limiting how fast new sessions start
+//!
+//! # Retry token format
+//!
+//! ```text
+//! issued at (u64, Unix seconds) | ODCID length (u8) | ODCID | MAC (32 bytes)
+//! ```
+//!
+//! The MAC is HMAC-SHA256 over the fields before it, the client's IP address
+//! and the connection ID the Retry assigned. The original DCID is readable so
+//! that backends behind a Retry-issuing proxy can send it in their
+//! `original_destination_connection_id` transport parameter.
+
+use dashmap::DashMap;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::quic;
+
+/// Length of the connection ID a Retry assigns to the client's next Initial
+pub const RETRY_CID_LEN: usize = 8;
+
+/// Length of the HMAC-SHA256 token MAC
+const MAC_LEN: usize = 32;
+
+/// Tokens issued this far in the future are accepted, for clock adjustments
+const CLOCK_SKEW_SECS: u64 = 2;
+
+/// Why a Retry token was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    /// The token does not have the proxy's layout
+    Malformed,
+    /// The MAC does not match this address and connection ID
+    Invalid,
+    /// The token is older than the configured lifetime
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "Malformed QUIC Retry token"),
+            TokenError::Invalid => write!(f, "QUIC Retry token not valid for this client"),
+            TokenError::Expired => write!(f, "QUIC Retry token expired"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Issues and validates address-bound Retry tokens
+///
+/// The key is random per process, so validation needs no shared state but
+/// tokens are only accepted by the proxy instance that issued them.
+pub struct RetryTokens {
+    key: hmac::Key,
+    lifetime: Duration,
+    rng: SystemRandom,
+}
+
+impl RetryTokens {
+    /// Creates a token issuer with a fresh random key
+    pub fn new(lifetime: Duration) -> Self {
+        let rng = SystemRandom::new();
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &rng)
+            .expect("system random number generator failed");
+        Self { key, lifetime, rng }
+    }
+
+    /// Picks a random connection ID for the client's retried Initial
+    pub fn connection_id(&self) -> [u8; RETRY_CID_LEN] {
+        let mut cid = [0u8; RETRY_CID_LEN];
+        self.rng
+            .fill(&mut cid)
+            .expect("system random number generator failed");
+        cid
+    }
+
+    /// Issues a token for `client`, whose Initial had destination `odcid`,
+    /// to return with connection ID `retry_cid`
+    pub fn issue(&self, client: IpAddr, odcid: &[u8], retry_cid: &[u8]) -> Vec<u8> {
+        let mut token = Vec::with_capacity(9 + odcid.len() + MAC_LEN);
+        token.extend_from_slice(&unix_time().to_be_bytes());
+        token.push(odcid.len() as u8);
+        token.extend_from_slice(odcid);
+        let mac = hmac::sign(&self.key, &mac_input(&token, client, retry_cid));
+        token.extend_from_slice(mac.as_ref());
+        token
+    }
+
+    /// Checks a token returned by `client` in an Initial sent to `dcid`
+    ///
+    /// Returns the client's original Destination Connection ID.
+    ///
+    /// # Errors
+    ///
+    /// Fails for tokens of another layout, tokens issued to another address or
+    /// connection ID, and tokens older than the lifetime.
+    pub fn validate(
+        &self,
+        token: &[u8],
+        client: IpAddr,
+        dcid: &[u8],
+    ) -> Result<Vec<u8>, TokenError> {
+        let odcid_len = *token.get(8).ok_or(TokenError::Malformed)? as usize;
+        if odcid_len > quic::MAX_CID_LEN || token.len() != 9 + odcid_len + MAC_LEN {
+            return Err(TokenError::Malformed);
+        }
+        let (fields, mac) = token.split_at(9 + odcid_len);
+
+        hmac::verify(&self.key, &mac_input(fields, client, dcid), mac)
+            .map_err(|_| TokenError::Invalid)?;
+
+        let mut issued = [0u8; 8];
+        issued.copy_from_slice(&fields[..8]);
+        let issued = u64::from_be_bytes(issued);
+        let now = unix_time();
+        if issued > now + CLOCK_SKEW_SECS || now.saturating_sub(issued) > self.lifetime.as_secs() {
+            return Err(TokenError::Expired);
+        }
+
+        Ok(fields[9..].to_vec())
+    }
+}
+
+/// Data a token's MAC covers: its fields, the client address and the
+/// connection ID of the retried Initial
+fn mac_input(fields: &[u8], client: IpAddr, retry_cid: &[u8]) -> Vec<u8> {
+    let mut input = fields.to_vec();
+    match client.to_canonical() {
+        IpAddr::V4(ip) => input.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => input.extend_from_slice(&ip.octets()),
+    }
+    input.extend_from_slice(retry_cid);
+    input
+}
+
+/// Token bucket limiting new sessions per source prefix
+///
+/// Clients are grouped by their IPv4 or IPv6 prefix so an attacker holding a
+/// whole network cannot multiply its budget by rotating addresses. Each
+/// prefix may start `per_second` sessions per second, in bursts of up to one
+/// second's worth.
+pub struct PrefixRateLimiter {
+    per_second: f64,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    buckets: DashMap<IpAddr, Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl PrefixRateLimiter {
+    /// Creates a limiter; prefix lengths are capped at the address width
+    pub fn new(per_second: u32, ipv4_prefix: u8, ipv6_prefix: u8) -> Self {
+        Self {
+            per_second: f64::from(per_second),
+            ipv4_prefix: ipv4_prefix.min(32),
+            ipv6_prefix: ipv6_prefix.min(128),
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Takes a token from the bucket of `ip`'s prefix, if one is left
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(self.prefix(ip)).or_insert(Bucket {
+            tokens: self.per_second,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.per_second);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Forgets prefixes whose bucket has refilled, returning how many
+    pub fn prune(&self) -> usize {
+        let before = self.buckets.len();
+        let refill = Duration::from_secs(1);
+        self.buckets
+            .retain(|_, bucket| bucket.updated.elapsed() < refill);
+        before - self.buckets.len()
+    }
+
+    /// Number of prefixes currently tracked
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Whether no prefix is tracked
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    fn prefix(&self, ip: IpAddr) -> IpAddr {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.ipv4_prefix))
+                    .unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.ipv6_prefix))
+                    .unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_token_roundtrip() {
+        let tokens = RetryTokens::new(Duration::from_secs(10));
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let retry_cid = tokens.connection_id();
+        let token = tokens.issue(client, &[0x83; 8], &retry_cid);
+
+        assert_eq!(
+            tokens.validate(&token, client, &retry_cid).unwrap(),
+            vec![0x83; 8]
+        );
+        // The same client over an IPv4-mapped IPv6 socket
+        assert!(
+            tokens
+                .validate(&token, "::ffff:192.0.2.1".parse().unwrap(), &retry_cid)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_retry_token_rejections() {
+        let tokens = RetryTokens::new(Duration::from_secs(10));
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let token = tokens.issue(client, &[0x83; 8], &[0x01; 8]);
+
+        assert_eq!(
+            tokens.validate(&token, "192.0.2.2".parse().unwrap(), &[0x01; 8]),
+            Err(TokenError::Invalid)
+        );
+        assert_eq!(
+            tokens.validate(&token, client, &[0x02; 8]),
+            Err(TokenError::Invalid)
+        );
+        assert_eq!(
+            tokens.validate(&token[..20], client, &[0x01; 8]),
+            Err(TokenError::Malformed)
+        );
+        assert_eq!(
+            tokens.validate(b"new-token", client, &[0x01; 8]),
+            Err(TokenError::Malformed)
+        );
+
+        // Another proxy instance has another key
+        let other = RetryTokens::new(Duration::from_secs(10));
+        assert_eq!(
+            other.validate(&token, client, &[0x01; 8]),
+            Err(TokenError::Invalid)
+        );
+
+        // Tampering with the issue time breaks the MAC
+        let mut tampered = token.clone();
+        tampered[7] ^= 0x01;
+        assert_eq!(
+            tokens.validate(&tampered, client, &[0x01; 8]),
+            Err(TokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_retry_token_expiry() {
+        let tokens = RetryTokens::new(Duration::from_secs(10));
+        let client: IpAddr = "2001:db8::1".parse().unwrap();
+
+        let mut old = (unix_time() - 11).to_be_bytes().to_vec();
+        old.push(4);
+        old.extend_from_slice(&[0x83; 4]);
+        let mac = hmac::sign(&tokens.key, &mac_input(&old, client, &[0x01; 8]));
+        old.extend_from_slice(mac.as_ref());
+        assert_eq!(
+            tokens.validate(&old, client, &[0x01; 8]),
+            Err(TokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_prefix_rate_limiter() {
+        let limiter = PrefixRateLimiter::new(2, 24, 56);
+        assert!(limiter.allow("198.51.100.1".parse().unwrap()));
+        assert!(limiter.allow("198.51.100.2".parse().unwrap()));
+        // Same /24, bucket empty
+        assert!(!limiter.allow("198.51.100.3".parse().unwrap()));
+        assert!(!limiter.allow("::ffff:198.51.100.4".parse().unwrap()));
+        // Other prefixes have their own buckets
+        assert!(limiter.allow("198.51.101.1".parse().unwrap()));
+        assert!(limiter.allow("2001:db8:0:1::1".parse().unwrap()));
+        assert!(limiter.allow("2001:db8:0:1:ffff::1".parse().unwrap()));
+        assert!(!limiter.allow("2001:db8:0:1::2".parse().unwrap()));
+        assert!(limiter.allow("2001:db8:0:100::1".parse().unwrap()));
+        assert_eq!(limiter.len(), 4);
+
+        // Buckets used within the last second are kept
+        assert_eq!(limiter.prune(), 0);
+    }
+
+    #[test]
+    fn test_prefix_rate_limiter_refills() {
+        let limiter = PrefixRateLimiter::new(1, 32, 128);
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        assert!(limiter.allow(ip));
+        assert!(!limiter.allow(ip));
+
+        limiter.buckets.get_mut(&ip).unwrap().updated -= Duration::from_secs(1);
+        assert!(limiter.allow(ip));
+
+        limiter.buckets.get_mut(&ip).unwrap().updated -= Duration::from_secs(2);
+        assert_eq!(limiter.prune(), 1);
+        assert!(limiter.is_empty());
+    }
+}
@@ -1,11 +1,28 @@
-//! UDP connection handling for QUIC/HTTP3
+//! UDP connection handling for QUIC/HTTP3 and DTLS
 //!
-//! This module provides UDP datagram handling for QUIC-based protocols including HTTP/3.
+//! This module provides UDP datagram handling for QUIC-based protocols including HTTP/3,
+//! and for DTLS 1.0–1.3 (WebRTC TURN, CoAP/DTLS) on the same port.
 //! It manages:
-//! - UDP session tracking with automatic cleanup
-//! - QUIC protocol detection
+//! - UDP session tracking with automatic cleanup, keyed by QUIC connection ID
+//!   so sessions survive client address changes
+//! - QUIC protocol detection, with Version Negotiation for unsupported versions
+//! - Optional stateless Retry address validation and per-prefix limits on new
+//!   sessions (see `udp_admission`)
+//! - ClientHello reassembly across Initial packets, buffering the client's
+//!   datagrams until the SNI is known
+//! - DTLS record detection and handshake-fragment reassembly; DTLS sessions
+//!   are keyed by client address and routed by SNI through the same route
+//!   table and allowlist as QUIC (see `dtls`)
+//! - Static port forwarding (`udp_routes`) for traffic without SNI, such as
+//!   DNS or WireGuard: every datagram on a routed port goes to a fixed
+//!   upstream, one flow per client address with its own idle timeout
 //! - Bidirectional datagram forwarding between client and backend
 //! - Session expiration and resource management
+//! - Graceful draining on shutdown: no new sessions, existing ones are
+//!   forwarded until idle or the shutdown timeout
+//! - Prometheus metrics: active sessions, sessions created, closed and rejected
+//!   by reason, bytes per SNI and direction, SNI extraction failures and
+//!   session lifetimes
 //!
 //! # Architecture
 //!
@@ -17,6 +34,13 @@
 //! Packet
 //! ```
 //!
+//! Sessions to the same backend share a small pool of sockets, each served by
+//! one receive task. Backend responses are matched to their session by the
+//! client's connection ID, which the backend uses as Destination Connection
+//! ID. Clients with zero-length or clashing connection IDs get a dedicated
+//! socket and are matched by 4-tuple, as are all sessions to a backend seen
+//! switching to connection IDs the proxy cannot know (see `BackendPool`).
+//!
 //! # Example
 //!
 //! ```no_run
@@ -39,58 +63,637 @@
 //! ```
 
 use dashmap::DashMap;
-use prometheus::Registry;
+use prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+};
+use sniproxy_config::{RouteMode, UdpRoute, matches_allowlist_pattern};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
+use tokio::sync::Notify;
 use tracing::{debug, error, info, warn};
 
 use crate::Config;
+use crate::dtls;
+use crate::metrics_cache::MetricLabelCache;
+use crate::quic;
+use crate::udp_admission::{PrefixRateLimiter, RetryTokens};
+
+/// Maximum UDP datagram size (Ethernet MTU)
+///
+/// Longer datagrams are truncated by `recv_from`, which breaks Initial packet
+/// authentication, so this must cover the largest Initial a client may send.
+const MAX_DATAGRAM_SIZE: usize = 1500;
+
+/// Shared sockets opened per backend before sessions start sharing them
+const BACKEND_SOCKETS_PER_UPSTREAM: usize = 4;
 
-/// Maximum UDP datagram size (MTU-safe)
-const MAX_DATAGRAM_SIZE: usize = 1350;
+/// Maximum number of clients whose ClientHello is still being assembled
+const MAX_PENDING_HANDSHAKES: usize = 1_000;
 
-/// Default session timeout in seconds
-const SESSION_TIMEOUT_SECS: u64 = 30;
+/// Maximum datagrams buffered per pending handshake
+const MAX_PENDING_DATAGRAMS: usize = 8;
 
-/// Maximum number of concurrent UDP sessions
-const MAX_SESSIONS: usize = 10_000;
+/// Maximum bytes buffered per pending handshake
+const MAX_PENDING_BYTES: usize = 16 * 1024;
+
+/// Maximum connection IDs remembered per session
+const MAX_CONNECTION_IDS: usize = 8;
+
+/// Short-header bytes after the DCID: packet number and header protection sample
+const MIN_SHORT_HEADER_PAYLOAD: usize = 20;
+
+/// How often `drain` checks for sessions that went idle
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 /// UDP connection handler managing QUIC/HTTP3 sessions
 #[derive(Clone)]
 pub struct UdpConnectionHandler {
-    #[allow(dead_code)]
     config: Arc<Config>,
-    sessions: Arc<DashMap<SocketAddr, UdpSession>>,
-    #[allow(dead_code)]
+    sessions: Arc<SessionTable>,
+    backends: Arc<BackendPool>,
+    pending: Arc<DashMap<SocketAddr, PendingHandshake>>,
+    pending_dtls: Arc<DashMap<SocketAddr, PendingDtlsHandshake>>,
     metrics: Option<Arc<UdpMetrics>>,
+    /// Connection ID length of short-header packets
+    short_cid_len: usize,
+    session_timeout: Duration,
+    max_sessions: usize,
+    /// Issues Retry tokens when address validation is enabled
+    retry_tokens: Option<Arc<RetryTokens>>,
+    rate_limiter: Option<Arc<PrefixRateLimiter>>,
+    /// Set once shutdown begins; no new sessions are created
+    draining: Arc<AtomicBool>,
+    /// `QuicHandler` endpoint that QUIC sessions of terminate-mode routes go to
+    http3_terminator: Option<SocketAddr>,
 }
 
+/// Identifier of a session in the `SessionTable`
+type SessionId = u64;
+
 /// UDP session state
 struct UdpSession {
-    backend_socket: Arc<UdpSocket>,
-    backend_addr: SocketAddr,
+    backend: Arc<BackendSocket>,
+    /// Key of this session in `backend`'s routes
+    route_key: Vec<u8>,
+    /// Listener socket the client talks to
+    client_socket: Arc<UdpSocket>,
+    /// Address the client last sent from; responses go here
+    client_addr: SocketAddr,
+    /// The client's original DCID and the connection IDs the backend issued
+    connection_ids: Vec<Vec<u8>>,
+    created: Instant,
     last_activity: Instant,
-    #[allow(dead_code)]
+    /// Silence after which the session is closed
+    idle_timeout: Duration,
     protocol: UdpProtocol,
-    #[allow(dead_code)]
     bytes_tx: u64,
-    #[allow(dead_code)]
     bytes_rx: u64,
+    /// Per-SNI metric handles, if metrics are enabled
+    metrics: Option<SessionMetrics>,
+}
+
+/// Client whose ClientHello spans Initial packets that have not all arrived
+///
+/// Datagrams are kept in arrival order and replayed to the backend once the
+/// CRYPTO frames cover the whole ClientHello.
+struct PendingHandshake {
+    /// Original Destination Connection ID shared by the client's Initials
+    dcid: Vec<u8>,
+    /// The client's own connection ID, which the backend sends packets to
+    scid: Vec<u8>,
+    datagrams: Vec<Vec<u8>>,
+    crypto: Vec<quic::CryptoFrame>,
+    buffered_bytes: usize,
+    started: Instant,
+}
+
+/// DTLS client whose ClientHello fragments have not all arrived
+struct PendingDtlsHandshake {
+    datagrams: Vec<Vec<u8>>,
+    fragments: Vec<dtls::HandshakeFragment>,
+    buffered_bytes: usize,
+    started: Instant,
+}
+
+/// A completed ClientHello and the datagrams that carried it
+///
+/// DTLS handshakes have no connection IDs; `dcid` and `scid` are empty.
+/// Port-forwarded flows use it too, with their destination host as `sni`.
+#[derive(Debug)]
+struct BufferedHandshake {
+    sni: String,
+    protocol: UdpProtocol,
+    dcid: Vec<u8>,
+    scid: Vec<u8>,
+    datagrams: Vec<Vec<u8>>,
+}
+
+/// A socket towards one backend and the sessions whose responses it carries
+struct BackendSocket {
+    socket: UdpSocket,
+    backend_addr: SocketAddr,
+    /// Client connection ID → session; the empty ID claims every datagram
+    routes: DashMap<Vec<u8>, SessionId>,
+    /// Bit n is set once a client connection ID of length n was routed here
+    cid_lengths: AtomicU32,
+    /// Signalled when the last route is removed, so the receive task can end
+    idle: Notify,
+}
+
+impl BackendSocket {
+    async fn bind(backend_addr: SocketAddr) -> std::io::Result<Self> {
+        let local: SocketAddr = if backend_addr.is_ipv4() {
+            "0.0.0.0:0".parse().expect("valid address")
+        } else {
+            "[::]:0".parse().expect("valid address")
+        };
+        Ok(Self {
+            socket: UdpSocket::bind(local).await?,
+            backend_addr,
+            routes: DashMap::new(),
+            cid_lengths: AtomicU32::new(0),
+            idle: Notify::new(),
+        })
+    }
+
+    fn add_route(&self, key: &[u8], id: SessionId) {
+        self.cid_lengths.fetch_or(1 << key.len(), Ordering::Relaxed);
+        self.routes.insert(key.to_vec(), id);
+    }
+
+    fn remove_route(&self, key: &[u8], id: SessionId) {
+        self.routes.remove_if(key, |_, owner| *owner == id);
+        if self.routes.is_empty() {
+            self.idle.notify_one();
+        }
+    }
+
+    /// Session a backend datagram belongs to
+    ///
+    /// Backends address packets to the client's connection ID. Short headers
+    /// do not encode its length, so each length routed here is tried.
+    fn route(&self, packet: &[u8]) -> Option<SessionId> {
+        if let Some(id) = self.routes.get(&[][..]) {
+            return Some(*id);
+        }
+        let first = *packet.first()?;
+        if first & 0x80 != 0 {
+            let cid = quic::destination_cid(packet, 0)?;
+            return self.routes.get(cid).map(|id| *id);
+        }
+
+        let lengths = self.cid_lengths.load(Ordering::Relaxed);
+        (1..=quic::MAX_CID_LEN)
+            .filter(|len| lengths & (1 << len) != 0)
+            .find_map(|len| {
+                let cid = quic::destination_cid(packet, len)?;
+                self.routes.get(cid).map(|id| *id)
+            })
+    }
+}
+
+/// Backend sockets shared between sessions, per backend address
+///
+/// A backend may stop addressing the client's initial connection ID: quinn,
+/// for one, switches to an ID the client issued in an encrypted
+/// NEW_CONNECTION_ID frame right after the handshake. Such datagrams can only
+/// be attributed on a socket that carries a single session. The first one
+/// marks the backend as switching, and its later sessions get dedicated
+/// sockets matched by 4-tuple, so sockets it already has gain no new sessions.
+#[derive(Default)]
+struct BackendPool {
+    sockets: DashMap<SocketAddr, Vec<Arc<BackendSocket>>>,
+    /// Backends seen addressing connection IDs no session was routed by
+    switching: DashMap<SocketAddr, ()>,
+}
+
+impl BackendPool {
+    /// Assigns a backend socket to a new session
+    ///
+    /// Sessions with a client connection ID share one of
+    /// `BACKEND_SOCKETS_PER_UPSTREAM` sockets, picking the least loaded one
+    /// where the ID is not taken. Others get a dedicated socket. Returns the
+    /// socket, the session's route key, and whether the socket is new and
+    /// needs a receive task.
+    async fn assign(
+        &self,
+        backend_addr: SocketAddr,
+        client_cid: &[u8],
+        id: SessionId,
+    ) -> std::io::Result<(Arc<BackendSocket>, Vec<u8>, bool)> {
+        let pooled = !client_cid.is_empty() && !self.switching.contains_key(&backend_addr);
+        if pooled && let Some(sockets) = self.sockets.get_mut(&backend_addr) {
+            // Routes are added under the entry lock, so `release_idle` never
+            // closes a socket a session was just assigned to
+            let shared = sockets
+                .iter()
+                .filter(|socket| !socket.routes.contains_key(client_cid))
+                .min_by_key(|socket| socket.routes.len())
+                .filter(|_| sockets.len() >= BACKEND_SOCKETS_PER_UPSTREAM);
+            if let Some(socket) = shared {
+                socket.add_route(client_cid, id);
+                return Ok((Arc::clone(socket), client_cid.to_vec(), false));
+            }
+        }
+
+        let socket = Arc::new(BackendSocket::bind(backend_addr).await?);
+        if pooled {
+            let mut sockets = self.sockets.entry(backend_addr).or_default();
+            if sockets.len() < BACKEND_SOCKETS_PER_UPSTREAM {
+                socket.add_route(client_cid, id);
+                sockets.push(Arc::clone(&socket));
+                return Ok((socket, client_cid.to_vec(), true));
+            }
+        }
+        socket.add_route(&[], id);
+        Ok((socket, Vec::new(), true))
+    }
+
+    /// Session of a backend datagram `socket.route` could not match
+    ///
+    /// Attributes it to the socket's only session, if it has one, and marks
+    /// the backend as switching connection IDs either way.
+    fn route_unknown(&self, socket: &BackendSocket) -> Option<SessionId> {
+        self.switching.insert(socket.backend_addr, ());
+        let _sockets = self.sockets.get(&socket.backend_addr);
+        if socket.routes.len() != 1 {
+            return None;
+        }
+        socket.routes.iter().next().map(|route| *route.value())
+    }
+
+    /// Forgets a socket once it carries no session, so its receive task can end
+    ///
+    /// Returns false if a session was assigned to it in the meantime.
+    fn release_idle(&self, socket: &Arc<BackendSocket>) -> bool {
+        let Some(mut sockets) = self.sockets.get_mut(&socket.backend_addr) else {
+            return socket.routes.is_empty();
+        };
+        if !socket.routes.is_empty() {
+            return false;
+        }
+        sockets.retain(|s| !Arc::ptr_eq(s, socket));
+        let empty = sockets.is_empty();
+        drop(sockets);
+        if empty {
+            self.sockets
+                .remove_if(&socket.backend_addr, |_, sockets| sockets.is_empty());
+        }
+        true
+    }
+
+    /// Forgets a socket whose receive task failed
+    fn release(&self, socket: &Arc<BackendSocket>) {
+        if let Some(mut sockets) = self.sockets.get_mut(&socket.backend_addr) {
+            sockets.retain(|s| !Arc::ptr_eq(s, socket));
+        }
+        self.sockets
+            .remove_if(&socket.backend_addr, |_, sockets| sockets.is_empty());
+    }
+}
+
+/// UDP sessions indexed by connection ID and by client address
+///
+/// QUIC identifies a connection by its Destination Connection ID rather than
+/// by the 4-tuple: after NAT rebinding or migration the client's packets come
+/// from a new address but carry a connection ID the proxy already knows. Each
+/// session records the client's original DCID and every Source Connection ID
+/// its backend announced in long-header packets. The address index covers
+/// connection IDs the proxy cannot recognize, such as zero-length ones or
+/// short headers with a different length than configured.
+#[derive(Default)]
+struct SessionTable {
+    sessions: DashMap<SessionId, UdpSession>,
+    connection_ids: DashMap<Vec<u8>, SessionId>,
+    addresses: DashMap<SocketAddr, SessionId>,
+    next_id: AtomicU64,
+    /// Live sessions plus slots reserved for sessions being created
+    slots: AtomicUsize,
+}
+
+/// Room for one session, claimed by `SessionTable::reserve`
+///
+/// Dropping the reservation without passing it to `SessionTable::insert`
+/// gives the slot back.
+struct SlotReservation<'a> {
+    slots: &'a AtomicUsize,
+    inserted: bool,
+}
+
+impl Drop for SlotReservation<'_> {
+    fn drop(&mut self) {
+        if !self.inserted {
+            self.slots.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl SessionTable {
+    fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Claims a slot for a session about to be created, unless `max` are taken
+    ///
+    /// The check and the claim are one atomic step, so concurrent handshakes
+    /// cannot overshoot `max` while they resolve their backends.
+    fn reserve(&self, max: usize) -> Option<SlotReservation<'_>> {
+        self.slots
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |slots| {
+                (slots < max).then_some(slots + 1)
+            })
+            .ok()?;
+        Some(SlotReservation {
+            slots: &self.slots,
+            inserted: false,
+        })
+    }
+
+    /// Reserves the identifier of a session about to be inserted
+    fn next_id(&self) -> SessionId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Inserts a session into the slot `slot` reserved; removing it frees the slot
+    fn insert(&self, mut slot: SlotReservation<'_>, id: SessionId, session: UdpSession) {
+        slot.inserted = true;
+        self.addresses.insert(session.client_addr, id);
+        for cid in session.connection_ids.iter().filter(|cid| !cid.is_empty()) {
+            self.connection_ids.insert(cid.clone(), id);
+        }
+        self.sessions.insert(id, session);
+    }
+
+    /// Finds the session of a client packet, by connection ID first
+    fn find(&self, dcid: Option<&[u8]>, client_addr: SocketAddr) -> Option<SessionId> {
+        dcid.filter(|cid| !cid.is_empty())
+            .and_then(|cid| self.find_connection_id(cid))
+            .or_else(|| self.addresses.get(&client_addr).map(|id| *id))
+    }
+
+    fn find_connection_id(&self, cid: &[u8]) -> Option<SessionId> {
+        self.connection_ids.get(cid).map(|id| *id)
+    }
+
+    /// Records a connection ID the session's backend announced
+    fn add_connection_id(&self, id: SessionId, cid: &[u8]) {
+        if cid.is_empty() || self.connection_ids.contains_key(cid) {
+            return;
+        }
+        let Some(mut session) = self.sessions.get_mut(&id) else {
+            return;
+        };
+        if session.connection_ids.len() >= MAX_CONNECTION_IDS {
+            return;
+        }
+        session.connection_ids.push(cid.to_vec());
+        drop(session);
+        self.connection_ids.insert(cid.to_vec(), id);
+    }
+
+    /// Points the session at the address the client now sends from
+    fn rebind(&self, id: SessionId, client_addr: SocketAddr) {
+        let old_addr = match self.sessions.get_mut(&id) {
+            Some(mut session) if session.client_addr != client_addr => {
+                std::mem::replace(&mut session.client_addr, client_addr)
+            }
+            _ => return,
+        };
+        self.addresses.remove_if(&old_addr, |_, owner| *owner == id);
+        self.addresses.insert(client_addr, id);
+        info!(
+            "UDP session moved from {} to {} (NAT rebinding or migration)",
+            old_addr, client_addr
+        );
+    }
+
+    fn remove(&self, id: SessionId) -> Option<UdpSession> {
+        let (_, session) = self.sessions.remove(&id)?;
+        self.slots.fetch_sub(1, Ordering::AcqRel);
+        session.backend.remove_route(&session.route_key, id);
+        for cid in &session.connection_ids {
+            self.connection_ids.remove_if(cid, |_, owner| *owner == id);
+        }
+        self.addresses
+            .remove_if(&session.client_addr, |_, owner| *owner == id);
+        Some(session)
+    }
+
+    /// Removes and returns sessions idle for at least their idle timeout
+    fn remove_expired(&self) -> Vec<UdpSession> {
+        let now = Instant::now();
+        let expired: Vec<SessionId> = self
+            .sessions
+            .iter()
+            .filter(|session| now.duration_since(session.last_activity) >= session.idle_timeout)
+            .map(|session| *session.key())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
 }
 
 /// UDP protocol type
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum UdpProtocol {
     Quic,
+    Dtls,
+    /// Port-forwarded by a `udp_routes` entry
+    Forward,
     Unknown,
 }
 
-/// UDP metrics (placeholder for future implementation)
+impl UdpProtocol {
+    /// Protocol part of metric labels
+    fn as_str(self) -> &'static str {
+        match self {
+            UdpProtocol::Quic => "quic",
+            UdpProtocol::Dtls => "dtls",
+            UdpProtocol::Forward => "udp",
+            UdpProtocol::Unknown => "unknown",
+        }
+    }
+}
+
+/// Prometheus metrics for the UDP/QUIC path
 struct UdpMetrics {
-    #[allow(dead_code)]
-    registry: Registry,
+    sessions_active: IntGauge,
+    sessions_created: IntCounter,
+    sessions_closed: IntCounterVec,
+    sessions_rejected: IntCounterVec,
+    sni_failures: IntCounterVec,
+    bytes_transferred: IntCounterVec,
+    session_duration: HistogramVec,
+    label_cache: MetricLabelCache,
+}
+
+/// Metric handles resolved once when a session starts
+struct SessionMetrics {
+    bytes_tx: IntCounter,
+    bytes_rx: IntCounter,
+    duration: Histogram,
+}
+
+impl UdpMetrics {
+    fn new(registry: &Registry) -> Self {
+        let sessions_active = IntGauge::new(
+            "sniproxy_udp_sessions_active",
+            "Number of currently active UDP sessions",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(sessions_active.clone()))
+            .unwrap();
+
+        let sessions_created = IntCounter::new(
+            "sniproxy_udp_sessions_created_total",
+            "Total number of UDP sessions created",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(sessions_created.clone()))
+            .unwrap();
+
+        let sessions_closed = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_udp_sessions_closed_total",
+                "Total number of UDP sessions closed by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(sessions_closed.clone()))
+            .unwrap();
+
+        let sessions_rejected = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_udp_sessions_rejected_total",
+                "Clients refused a UDP session by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(sessions_rejected.clone()))
+            .unwrap();
+
+        let sni_failures = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_udp_sni_failures_total",
+                "QUIC packets from which no SNI could be extracted, by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        registry.register(Box::new(sni_failures.clone())).unwrap();
+
+        let bytes_transferred = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_udp_bytes_transferred_total",
+                "Total UDP bytes forwarded per host and direction",
+            ),
+            &["host", "direction"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(bytes_transferred.clone()))
+            .unwrap();
+
+        let session_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "sniproxy_udp_session_duration_seconds",
+                "UDP session lifetime in seconds",
+            )
+            .buckets(vec![
+                0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0,
+            ]),
+            &["host"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(session_duration.clone()))
+            .unwrap();
+
+        Self {
+            sessions_active,
+            sessions_created,
+            sessions_closed,
+            sessions_rejected,
+            sni_failures,
+            bytes_transferred,
+            session_duration,
+            label_cache: MetricLabelCache::new(),
+        }
+    }
+
+    /// Counts a new session and resolves its per-SNI handles
+    fn session_started(&self, sni: &str, protocol: UdpProtocol) -> SessionMetrics {
+        self.sessions_created.inc();
+        self.sessions_active.inc();
+
+        let label = self.label_cache.get_or_insert(sni, protocol.as_str());
+        SessionMetrics {
+            bytes_tx: self
+                .bytes_transferred
+                .with_label_values(&[label.as_ref(), "tx"]),
+            bytes_rx: self
+                .bytes_transferred
+                .with_label_values(&[label.as_ref(), "rx"]),
+            duration: self.session_duration.with_label_values(&[label.as_ref()]),
+        }
+    }
+}
+
+/// Records the end of a session removed from the `SessionTable`
+fn session_closed(metrics: Option<&UdpMetrics>, session: &UdpSession, reason: &'static str) {
+    let lifetime = session.created.elapsed();
+    info!(
+        "Closed UDP session for {} ({}) after {:.1}s: {} bytes sent, {} received",
+        session.client_addr,
+        reason,
+        lifetime.as_secs_f64(),
+        session.bytes_tx,
+        session.bytes_rx
+    );
+
+    if let Some(metrics) = metrics {
+        metrics.sessions_active.dec();
+        metrics.sessions_closed.with_label_values(&[reason]).inc();
+        if let Some(session_metrics) = &session.metrics {
+            session_metrics.duration.observe(lifetime.as_secs_f64());
+        }
+    }
+}
+
+/// Metric label for a failed QUIC Initial
+fn sni_failure_reason(error: &quic::QuicError) -> &'static str {
+    match error {
+        quic::QuicError::Truncated => "truncated",
+        quic::QuicError::NotLongHeader => "not_long_header",
+        quic::QuicError::UnsupportedVersion(_) => "unsupported_version",
+        quic::QuicError::NotInitial => "not_initial",
+        quic::QuicError::Malformed(_) => "malformed",
+        quic::QuicError::DecryptionFailed => "decryption_failed",
+        quic::QuicError::UnexpectedFrame(_) => "unexpected_frame",
+        quic::QuicError::IncompleteClientHello => "incomplete",
+    }
+}
+
+/// Metric label for a failed DTLS ClientHello
+fn dtls_failure_reason(error: &dtls::DtlsError) -> &'static str {
+    match error {
+        dtls::DtlsError::Truncated => "truncated",
+        dtls::DtlsError::NotHandshake => "not_handshake",
+        dtls::DtlsError::NotClientHello => "not_client_hello",
+        dtls::DtlsError::Malformed(_) => "malformed",
+        dtls::DtlsError::IncompleteClientHello => "incomplete",
+    }
 }
 
 impl UdpConnectionHandler {
@@ -119,17 +722,44 @@ impl UdpConnectionHandler {
     /// # }
     /// ```
     pub fn new(config: Config, registry: Option<&Registry>) -> Self {
+        let quic_config = config.quic_config.clone().unwrap_or_default();
+
         Self {
             config: Arc::new(config),
-            sessions: Arc::new(DashMap::new()),
-            metrics: registry.map(|r| {
-                Arc::new(UdpMetrics {
-                    registry: r.clone(),
-                })
+            sessions: Arc::new(SessionTable::default()),
+            backends: Arc::new(BackendPool::default()),
+            pending: Arc::new(DashMap::new()),
+            pending_dtls: Arc::new(DashMap::new()),
+            metrics: registry.map(|r| Arc::new(UdpMetrics::new(r))),
+            short_cid_len: quic_config.connection_id_length.min(quic::MAX_CID_LEN),
+            session_timeout: Duration::from_secs(quic_config.session_timeout),
+            max_sessions: quic_config.max_sessions,
+            retry_tokens: quic_config.retry.then(|| {
+                Arc::new(RetryTokens::new(Duration::from_secs(
+                    quic_config.retry_token_lifetime,
+                )))
             }),
+            rate_limiter: (quic_config.new_sessions_per_prefix > 0).then(|| {
+                Arc::new(PrefixRateLimiter::new(
+                    quic_config.new_sessions_per_prefix,
+                    quic_config.rate_limit_ipv4_prefix,
+                    quic_config.rate_limit_ipv6_prefix,
+                ))
+            }),
+            draining: Arc::new(AtomicBool::new(false)),
+            http3_terminator: None,
         }
     }
 
+    /// Sends QUIC sessions of terminate-mode routes to a `QuicHandler`
+    ///
+    /// Without a terminator, such sessions are rejected rather than passed
+    /// through, and so are those of routes with `client_auth`.
+    pub fn with_http3_terminator(mut self, addr: SocketAddr) -> Self {
+        self.http3_terminator = Some(addr);
+        self
+    }
+
     /// Main UDP handling loop
     ///
     /// Receives datagrams from clients, manages sessions, and forwards traffic to backends.
@@ -145,20 +775,57 @@ impl UdpConnectionHandler {
         let socket = Arc::new(socket);
         let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
 
-        info!("UDP handler started");
+        // Sessions no longer own a receive task, so expiry is swept here,
+        // often enough for the shortest idle timeout
+        let shortest_timeout = self
+            .config
+            .udp_routes
+            .iter()
+            .flatten()
+            .filter_map(|route| route.idle_timeout)
+            .map(Duration::from_secs)
+            .fold(self.session_timeout, Duration::min);
+        let mut cleanup = tokio::time::interval((shortest_timeout / 4).max(Duration::from_secs(1)));
+        cleanup.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        // A port with a UDP route forwards every datagram, without detection
+        let forward = self.udp_route(socket.local_addr()?.port()).cloned();
+        match &forward {
+            Some(route) => info!(
+                "UDP handler started, forwarding port {} to {}:{}",
+                route.listen_port, route.destination_host, route.destination_port
+            ),
+            None => info!("UDP handler started"),
+        }
 
         loop {
             // Receive datagram from client
-            let (len, src_addr) = match socket.recv_from(&mut buf).await {
-                Ok(result) => result,
-                Err(e) => {
-                    error!("Failed to receive UDP datagram: {}", e);
+            let (len, src_addr) = tokio::select! {
+                result = socket.recv_from(&mut buf) => match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        error!("Failed to receive UDP datagram: {}", e);
+                        continue;
+                    }
+                },
+                _ = cleanup.tick() => {
+                    self.cleanup_sessions();
                     continue;
                 }
             };
 
             let data = &buf[..len];
 
+            if let Some(route) = &forward {
+                if let Err(e) = self
+                    .handle_forwarded_packet(data, src_addr, route, &socket)
+                    .await
+                {
+                    warn!("Failed to forward UDP datagram from {}: {}", src_addr, e);
+                }
+                continue;
+            }
+
             // Detect protocol
             let protocol = match self.detect_protocol(data) {
                 Ok(p) => p,
@@ -175,16 +842,71 @@ impl UdpConnectionHandler {
                         warn!("Failed to handle QUIC packet from {}: {}", src_addr, e);
                     }
                 }
-                UdpProtocol::Unknown => {
+                UdpProtocol::Dtls => {
+                    if let Err(e) = self.handle_dtls_packet(data, src_addr, &socket).await {
+                        warn!("Failed to handle DTLS packet from {}: {}", src_addr, e);
+                    }
+                }
+                UdpProtocol::Forward | UdpProtocol::Unknown => {
                     debug!("Unknown UDP protocol from {}", src_addr);
                 }
             }
+        }
+    }
+
+    /// Stops creating sessions, so that `drain` can wait for the existing
+    /// ones to finish
+    ///
+    /// Datagrams of existing sessions keep being forwarded by `run`. Every
+    /// clone of the handler shares the draining state.
+    pub fn begin_drain(&self) {
+        if !self.draining.swap(true, Ordering::Relaxed) {
+            self.pending.clear();
+            self.pending_dtls.clear();
+            info!(
+                "Draining {} UDP session(s), no new sessions accepted",
+                self.sessions.len()
+            );
+        }
+    }
+
+    /// Waits for every session to go idle, for at most `timeout`
+    ///
+    /// Calls `begin_drain` first. Sessions still active when the timeout
+    /// passes are closed; their number is returned.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        self.begin_drain();
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            self.cleanup_sessions();
+            if self.sessions.len() == 0 || tokio::time::Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep_until(
+                deadline.min(tokio::time::Instant::now() + DRAIN_POLL_INTERVAL),
+            )
+            .await;
+        }
 
-            // Periodic cleanup
-            if self.sessions.len().is_multiple_of(100) {
-                self.cleanup_sessions();
+        let remaining: Vec<SessionId> = self.sessions.sessions.iter().map(|s| *s.key()).collect();
+        let mut cut_off = 0;
+        for id in remaining {
+            if let Some(session) = self.sessions.remove(id) {
+                session_closed(self.metrics.as_deref(), &session, "shutdown");
+                cut_off += 1;
             }
         }
+        if cut_off > 0 {
+            warn!(
+                "UDP drain timeout ({}s) reached, closed {} active session(s)",
+                timeout.as_secs(),
+                cut_off
+            );
+        } else {
+            info!("All UDP sessions drained");
+        }
+        cut_off
     }
 
     /// Detects protocol from UDP datagram
@@ -194,6 +916,12 @@ impl UdpConnectionHandler {
             return Ok(UdpProtocol::Unknown);
         }
 
+        // DTLS: content types 20-26 or the DTLS 1.3 unified header, neither
+        // of which has the QUIC fixed or long-header bit
+        if dtls::is_dtls(data) {
+            return Ok(UdpProtocol::Dtls);
+        }
+
         // QUIC: Long header has bit 7 set (0x80)
         // QUIC packets start with a header byte where:
         // - Long header: bit 7 = 1 (initial, 0-RTT, handshake, retry)
@@ -202,249 +930,841 @@ impl UdpConnectionHandler {
             return Ok(UdpProtocol::Quic);
         }
 
+        // Short header: fixed bit (0x40) set and room for the DCID, a packet
+        // number and the header protection sample
+        if (data[0] & 0x40) != 0 && data.len() >= 1 + self.short_cid_len + MIN_SHORT_HEADER_PAYLOAD
+        {
+            return Ok(UdpProtocol::Quic);
+        }
+
         Ok(UdpProtocol::Unknown)
     }
 
     /// Handles QUIC packet forwarding
+    ///
+    /// Packets are matched to sessions by Destination Connection ID, then by
+    /// client address. Long-header packets without a session start one once
+    /// their ClientHello is complete; short-header packets need a session.
+    /// Unsupported versions are answered with Version Negotiation and, with
+    /// Retry enabled, completed handshakes without a valid token with a Retry.
+    /// Sessions of terminate-mode routes skip the Retry: the HTTP/3
+    /// terminator did not issue it, so its transport parameters would lack the
+    /// Retry connection IDs the client checks.
     async fn handle_quic_packet(
         &self,
         data: &[u8],
         src_addr: SocketAddr,
         client_socket: &Arc<UdpSocket>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // Get or create session
-        let session_created = !self.sessions.contains_key(&src_addr);
+        let dcid = quic::destination_cid(data, self.short_cid_len);
+        let session = match dcid {
+            // Long headers carry the exact DCID, so an unknown one is a new
+            // connection even when it reuses an existing session's address
+            Some(cid) if (data[0] & 0x80) != 0 && !cid.is_empty() => {
+                self.sessions.find_connection_id(cid)
+            }
+            _ => self.sessions.find(dcid, src_addr),
+        };
+        if let Some(id) = session {
+            return self.forward_to_backend(id, data, src_addr).await;
+        }
 
-        if session_created {
-            self.create_session(src_addr, data, client_socket).await?;
+        if self.draining.load(Ordering::Relaxed) {
+            self.record_rejection("draining");
+            debug!("Shutting down, no new UDP session for {}", src_addr);
+            return Ok(());
         }
 
-        // Forward packet to backend
-        if let Some(mut session) = self.sessions.get_mut(&src_addr) {
-            session
-                .backend_socket
-                .send_to(data, session.backend_addr)
-                .await?;
-            session.bytes_tx += data.len() as u64;
-            session.last_activity = Instant::now();
+        if (data[0] & 0x80) == 0 {
             debug!(
-                "Forwarded {} bytes from {} to backend {}",
-                data.len(),
-                src_addr,
-                session.backend_addr
+                "Dropping short-header QUIC packet from {} without a session",
+                src_addr
             );
+            return Ok(());
+        }
+
+        if let Some(version) = quic::packet_version(data)
+            && !quic::is_supported_version(version)
+        {
+            return self
+                .negotiate_version(data, version, src_addr, client_socket)
+                .await;
+        }
+
+        let Some(handshake) = self.buffer_initial(src_addr, data)? else {
+            debug!("Waiting for more QUIC Initial packets from {}", src_addr);
+            return Ok(());
+        };
+        debug!("Extracted SNI from QUIC: {}", handshake.sni);
+
+        // Buffered handshakes always start with an Initial
+        if let Some(tokens) = &self.retry_tokens
+            && !self.is_terminated(&handshake.sni)
+            && !self
+                .validate_address(tokens, &handshake.datagrams[0], src_addr, client_socket)
+                .await?
+        {
+            return Ok(());
         }
+        let id = self
+            .create_session(src_addr, &handshake, client_socket)
+            .await?;
 
+        // Replay everything the client sent while the ClientHello was incomplete
+        for datagram in &handshake.datagrams {
+            self.forward_to_backend(id, datagram, src_addr).await?;
+        }
         Ok(())
     }
 
-    /// Creates a new UDP session
-    async fn create_session(
+    /// Handles DTLS datagram forwarding
+    ///
+    /// DTLS records carry no connection ID the proxy can read, so sessions
+    /// are matched by client address. A client without a session opens one
+    /// once its ClientHello fragments are complete; HelloVerifyRequest cookie
+    /// exchanges then run between client and backend over that session.
+    async fn handle_dtls_packet(
         &self,
+        data: &[u8],
         src_addr: SocketAddr,
-        initial_packet: &[u8],
         client_socket: &Arc<UdpSocket>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // Enforce session limit
-        if self.sessions.len() >= MAX_SESSIONS {
-            return Err("Max UDP sessions reached".into());
+        if let Some(id) = self.sessions.find(None, src_addr) {
+            return self.forward_to_backend(id, data, src_addr).await;
         }
 
-        // Extract SNI from QUIC Initial packet
-        let sni = extract_quic_sni(initial_packet)?;
-        debug!("Extracted SNI from QUIC: {}", sni);
-
-        // Resolve backend address
-        let backend_addr = self.resolve_backend(&sni).await?;
-
-        // Create backend socket
-        let backend_socket = Arc::new(UdpSocket::bind("0.0.0.0:0").await?);
+        if self.draining.load(Ordering::Relaxed) {
+            self.record_rejection("draining");
+            debug!("Shutting down, no new UDP session for {}", src_addr);
+            return Ok(());
+        }
 
-        let session = UdpSession {
-            backend_socket: Arc::clone(&backend_socket),
-            backend_addr,
-            last_activity: Instant::now(),
-            protocol: UdpProtocol::Quic,
-            bytes_tx: 0,
-            bytes_rx: 0,
+        let Some(handshake) = self.buffer_dtls_hello(src_addr, data)? else {
+            debug!(
+                "Waiting for more DTLS ClientHello fragments from {}",
+                src_addr
+            );
+            return Ok(());
         };
+        debug!("Extracted SNI from DTLS: {}", handshake.sni);
+        let id = self
+            .create_session(src_addr, &handshake, client_socket)
+            .await?;
 
-        self.sessions.insert(src_addr, session);
-
-        // Spawn response handler
-        self.spawn_response_handler(src_addr, backend_socket, Arc::clone(client_socket))
-            .await;
-
-        info!("Created UDP session for {} → {}", src_addr, backend_addr);
-
+        for datagram in &handshake.datagrams {
+            self.forward_to_backend(id, datagram, src_addr).await?;
+        }
         Ok(())
     }
 
-    /// Resolves backend address from SNI
-    async fn resolve_backend(&self, sni: &str) -> Result<SocketAddr, Box<dyn std::error::Error>> {
-        // Use default HTTPS port for QUIC/HTTP3
-        let port = 443;
-        let addr_str = format!("{}:{}", sni, port);
+    /// Handles a datagram on a port with a UDP route
+    ///
+    /// Each client address is one flow. The first datagram of a flow opens a
+    /// session with a dedicated backend socket, so replies map back to it.
+    async fn handle_forwarded_packet(
+        &self,
+        data: &[u8],
+        src_addr: SocketAddr,
+        route: &UdpRoute,
+        client_socket: &Arc<UdpSocket>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(id) = self.sessions.find(None, src_addr) {
+            return self.forward_to_backend(id, data, src_addr).await;
+        }
 
-        let addr = tokio::net::lookup_host(&addr_str)
-            .await?
-            .next()
-            .ok_or_else(|| format!("Failed to resolve {}", addr_str))?;
+        if self.draining.load(Ordering::Relaxed) {
+            self.record_rejection("draining");
+            debug!("Shutting down, no new UDP session for {}", src_addr);
+            return Ok(());
+        }
 
-        Ok(addr)
+        let flow = BufferedHandshake {
+            sni: route.destination_host.clone(),
+            protocol: UdpProtocol::Forward,
+            dcid: Vec::new(),
+            scid: Vec::new(),
+            datagrams: vec![data.to_vec()],
+        };
+        let id = self.create_session(src_addr, &flow, client_socket).await?;
+        for datagram in &flow.datagrams {
+            self.forward_to_backend(id, datagram, src_addr).await?;
+        }
+        Ok(())
     }
 
-    /// Spawns background task to handle responses from backend
-    async fn spawn_response_handler(
+    /// Answers a packet of an unsupported version with the versions the
+    /// proxy can route
+    ///
+    /// Only datagrams large enough to be a client's first packet are
+    /// answered, so the reply is never larger than what triggered it (RFC
+    /// 9000 §6.1). Version Negotiation packets themselves are dropped.
+    async fn negotiate_version(
         &self,
-        client_addr: SocketAddr,
-        backend_socket: Arc<UdpSocket>,
-        client_socket: Arc<UdpSocket>,
-    ) {
-        let sessions = Arc::clone(&self.sessions);
-
-        tokio::spawn(async move {
-            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
-            let timeout_duration = Duration::from_secs(SESSION_TIMEOUT_SECS);
+        data: &[u8],
+        version: u32,
+        src_addr: SocketAddr,
+        client_socket: &UdpSocket,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if version == 0 || data.len() < quic::MIN_INITIAL_DATAGRAM {
+            debug!(
+                "Dropping QUIC packet of version {:#010x} from {}",
+                version, src_addr
+            );
+            return Ok(());
+        }
+        let (Some(dcid), Some(scid)) = (quic::destination_cid(data, 0), quic::source_cid(data))
+        else {
+            return Err(quic::QuicError::Truncated.into());
+        };
 
-            loop {
-                match tokio::time::timeout(timeout_duration, backend_socket.recv(&mut buf)).await {
-                    Ok(Ok(len)) => {
-                        // Forward response to client
-                        if let Err(e) = client_socket.send_to(&buf[..len], client_addr).await {
-                            error!("Failed to send to client {}: {}", client_addr, e);
-                            break;
-                        }
+        let packet = quic::version_negotiation(dcid, scid);
+        client_socket.send_to(&packet, src_addr).await?;
+        debug!(
+            "Sent QUIC Version Negotiation to {} for version {:#010x}",
+            src_addr, version
+        );
+        Ok(())
+    }
 
-                        // Update session stats
-                        if let Some(mut session) = sessions.get_mut(&client_addr) {
-                            session.bytes_rx += len as u64;
-                            session.last_activity = Instant::now();
-                        }
+    /// Validates the client's address before its Initial may open a session
+    ///
+    /// An Initial whose token was issued to this address and connection ID
+    /// passes. Any other Initial, including one carrying a token from a
+    /// backend's NEW_TOKEN frame, is answered with a Retry and dropped; the
+    /// Retry is smaller than the Initial, so it cannot amplify a spoofed
+    /// source. Unvalidated clients only hold pending handshake state, which
+    /// `MAX_PENDING_HANDSHAKES` bounds, never a session.
+    async fn validate_address(
+        &self,
+        tokens: &RetryTokens,
+        data: &[u8],
+        src_addr: SocketAddr,
+        client_socket: &UdpSocket,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Ok(initial) = quic::decrypt_initial(data) else {
+            return Ok(true);
+        };
 
-                        debug!("Forwarded {} bytes from backend to {}", len, client_addr);
-                    }
-                    Ok(Err(e)) => {
-                        error!("Backend recv error: {}", e);
-                        break;
-                    }
-                    Err(_) => {
-                        // Timeout - session expired
-                        debug!("UDP session timeout for {}", client_addr);
-                        break;
-                    }
-                }
+        match tokens.validate(&initial.token, src_addr.ip(), &initial.dcid) {
+            Ok(_) => return Ok(true),
+            Err(e) if !initial.token.is_empty() => {
+                debug!("Ignoring QUIC token from {}: {}", src_addr, e);
             }
+            Err(_) => {}
+        }
 
-            // Remove session on exit
-            sessions.remove(&client_addr);
-            info!("Closed UDP session for {}", client_addr);
-        });
+        let retry_cid = tokens.connection_id();
+        let token = tokens.issue(src_addr.ip(), &initial.dcid, &retry_cid);
+        let packet = quic::retry(
+            initial.version,
+            &initial.dcid,
+            &initial.scid,
+            &retry_cid,
+            &token,
+        )?;
+        client_socket.send_to(&packet, src_addr).await?;
+        debug!("Sent QUIC Retry to {}", src_addr);
+        Ok(false)
     }
 
-    /// Cleans up expired sessions
-    fn cleanup_sessions(&self) {
-        let now = Instant::now();
-        let timeout = Duration::from_secs(SESSION_TIMEOUT_SECS);
+    /// Forwards a client datagram to the session's backend
+    async fn forward_to_backend(
+        &self,
+        id: SessionId,
+        data: &[u8],
+        src_addr: SocketAddr,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.sessions.rebind(id, src_addr);
+
+        let backend = match self.sessions.sessions.get_mut(&id) {
+            Some(mut session) => {
+                session.bytes_tx += data.len() as u64;
+                session.last_activity = Instant::now();
+                if let Some(metrics) = &session.metrics {
+                    metrics.bytes_tx.inc_by(data.len() as u64);
+                }
+                Arc::clone(&session.backend)
+            }
+            None => return Ok(()),
+        };
 
-        let expired_count = self.sessions.len();
-        self.sessions
-            .retain(|_, session| now.duration_since(session.last_activity) < timeout);
+        backend.socket.send_to(data, backend.backend_addr).await?;
+        debug!(
+            "Forwarded {} bytes from {} to backend {}",
+            data.len(),
+            src_addr,
+            backend.backend_addr
+        );
 
-        let remaining = self.sessions.len();
-        if expired_count > remaining {
-            debug!(
-                "Cleaned up {} expired UDP sessions",
-                expired_count - remaining
-            );
+        Ok(())
+    }
+
+    /// Buffers a datagram from a client without a session until its
+    /// ClientHello is complete
+    ///
+    /// Returns the SNI and all buffered datagrams in arrival order once the
+    /// CRYPTO frames received so far cover the ClientHello, or `None` while
+    /// more Initial packets are needed. Datagrams that are not Initials (such
+    /// as 0-RTT) are kept for replay if a handshake is already pending.
+    ///
+    /// # Errors
+    ///
+    /// Fails, dropping the pending state, when the datagram is not a usable
+    /// Initial, the per-client buffer limits are exceeded or the assembled
+    /// ClientHello carries no SNI.
+    fn buffer_initial(
+        &self,
+        src_addr: SocketAddr,
+        data: &[u8],
+    ) -> Result<Option<BufferedHandshake>, Box<dyn std::error::Error>> {
+        let timeout = Duration::from_secs(self.config.timeouts.client_hello);
+        let initial = quic::decrypt_initial(data);
+
+        let mut pending = match (self.pending.get_mut(&src_addr), initial) {
+            // A new DCID or a stale entry means the client started over
+            (Some(pending), Ok(initial))
+                if pending.dcid != initial.dcid || pending.started.elapsed() >= timeout =>
+            {
+                drop(pending);
+                self.pending.remove(&src_addr);
+                self.start_pending(src_addr, initial)?
+            }
+            (Some(mut pending), Ok(initial)) => {
+                pending.crypto.extend(initial.crypto);
+                pending
+            }
+            (Some(pending), Err(_)) if pending.started.elapsed() < timeout => pending,
+            (Some(pending), Err(e)) => {
+                drop(pending);
+                self.pending.remove(&src_addr);
+                self.record_sni_failure(sni_failure_reason(&e));
+                return Err(e.into());
+            }
+            (None, Ok(initial)) => self.start_pending(src_addr, initial)?,
+            (None, Err(e)) => {
+                self.record_sni_failure(sni_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+
+        pending.buffered_bytes += data.len();
+        pending.datagrams.push(data.to_vec());
+        if pending.datagrams.len() > MAX_PENDING_DATAGRAMS
+            || pending.buffered_bytes > MAX_PENDING_BYTES
+        {
+            drop(pending);
+            self.pending.remove(&src_addr);
+            self.record_rejection("pending_limit");
+            return Err("QUIC ClientHello exceeds pending handshake limits".into());
         }
+
+        let hello = match quic::assemble_client_hello(&pending.crypto) {
+            Ok(hello) => hello,
+            Err(quic::QuicError::IncompleteClientHello) => return Ok(None),
+            Err(e) => {
+                drop(pending);
+                self.pending.remove(&src_addr);
+                self.record_sni_failure(sni_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+        drop(pending);
+
+        let (_, pending) = self
+            .pending
+            .remove(&src_addr)
+            .ok_or("Pending QUIC handshake vanished")?;
+        let sni = match quic::client_hello_record(&hello) {
+            Ok(record) => crate::extract_sni(&record).map_err(|e| {
+                self.record_sni_failure("no_sni");
+                format!("No valid SNI found in QUIC packet: {}", e)
+            })?,
+            Err(e) => {
+                self.record_sni_failure(sni_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+
+        Ok(Some(BufferedHandshake {
+            sni,
+            protocol: UdpProtocol::Quic,
+            dcid: pending.dcid,
+            scid: pending.scid,
+            datagrams: pending.datagrams,
+        }))
     }
-}
 
-/// Extracts SNI from QUIC Initial packet
-///
-/// # Arguments
-///
-/// * `packet` - Raw QUIC packet data
-///
-/// # Returns
-///
-/// Returns the extracted SNI hostname or an error if parsing fails.
-///
-/// # Implementation
-///
-/// QUIC Initial packets have the following structure:
-/// ```text
-/// +--------+--------+--------+--------+--------+
-/// | Header | DCID   | SCID   | Token  | Payload|
-/// |  Form  | Len    | Len    | Len    |        |
-/// +--------+--------+--------+--------+--------+
-/// ```
-///
-/// The payload contains CRYPTO frames with TLS ClientHello.
-/// We search for the TLS handshake (0x16) byte and attempt SNI extraction.
-pub fn extract_quic_sni(packet: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
-    // Minimum QUIC Initial packet size check
-    if packet.len() < 20 {
-        return Err("Packet too small to be QUIC Initial".into());
+    /// Starts tracking a client's handshake from its first Initial packet
+    fn start_pending(
+        &self,
+        src_addr: SocketAddr,
+        initial: quic::InitialPacket,
+    ) -> Result<
+        dashmap::mapref::one::RefMut<'_, SocketAddr, PendingHandshake>,
+        Box<dyn std::error::Error>,
+    > {
+        if self.pending.len() + self.pending_dtls.len() >= MAX_PENDING_HANDSHAKES {
+            self.record_rejection("pending_limit");
+            return Err("Max pending QUIC handshakes reached".into());
+        }
+
+        Ok(self.pending.entry(src_addr).or_insert(PendingHandshake {
+            dcid: initial.dcid,
+            scid: initial.scid,
+            datagrams: Vec::new(),
+            crypto: initial.crypto,
+            buffered_bytes: 0,
+            started: Instant::now(),
+        }))
     }
 
-    // Verify this is a QUIC long header (bit 7 = 1)
-    if (packet[0] & 0x80) == 0 {
-        return Err("Not a QUIC long header packet".into());
+    /// Buffers a DTLS datagram from a client without a session until its
+    /// ClientHello is complete
+    ///
+    /// Works like `buffer_initial`: fragments are collected per client
+    /// address, under the same per-client limits, and the SNI is returned
+    /// with every buffered datagram once the ClientHello is covered.
+    ///
+    /// # Errors
+    ///
+    /// Fails, dropping the pending state, when the first datagram holds no
+    /// ClientHello fragment, a record is malformed, the limits are exceeded
+    /// or the ClientHello carries no SNI.
+    fn buffer_dtls_hello(
+        &self,
+        src_addr: SocketAddr,
+        data: &[u8],
+    ) -> Result<Option<BufferedHandshake>, Box<dyn std::error::Error>> {
+        let timeout = Duration::from_secs(self.config.timeouts.client_hello);
+        self.pending_dtls
+            .remove_if(&src_addr, |_, pending| pending.started.elapsed() >= timeout);
+
+        let fragments = match dtls::handshake_fragments(data) {
+            Ok(fragments) => fragments,
+            // Later flights, such as 1.3 ciphertext, wait with the handshake
+            Err(dtls::DtlsError::NotHandshake) if self.pending_dtls.contains_key(&src_addr) => {
+                Vec::new()
+            }
+            Err(e) => {
+                self.pending_dtls.remove(&src_addr);
+                self.record_sni_failure(dtls_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+
+        let mut pending = match self.pending_dtls.get_mut(&src_addr) {
+            Some(pending) => pending,
+            None => {
+                if self.pending.len() + self.pending_dtls.len() >= MAX_PENDING_HANDSHAKES {
+                    self.record_rejection("pending_limit");
+                    return Err("Max pending DTLS handshakes reached".into());
+                }
+                self.pending_dtls
+                    .entry(src_addr)
+                    .or_insert(PendingDtlsHandshake {
+                        datagrams: Vec::new(),
+                        fragments: Vec::new(),
+                        buffered_bytes: 0,
+                        started: Instant::now(),
+                    })
+            }
+        };
+
+        pending.fragments.extend(fragments);
+        pending.buffered_bytes += data.len();
+        pending.datagrams.push(data.to_vec());
+        if pending.datagrams.len() > MAX_PENDING_DATAGRAMS
+            || pending.buffered_bytes > MAX_PENDING_BYTES
+        {
+            drop(pending);
+            self.pending_dtls.remove(&src_addr);
+            self.record_rejection("pending_limit");
+            return Err("DTLS ClientHello exceeds pending handshake limits".into());
+        }
+
+        let hello = match dtls::assemble_client_hello(&pending.fragments) {
+            Ok(hello) => hello,
+            Err(dtls::DtlsError::IncompleteClientHello) => return Ok(None),
+            Err(e) => {
+                drop(pending);
+                self.pending_dtls.remove(&src_addr);
+                self.record_sni_failure(dtls_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+        drop(pending);
+
+        let (_, pending) = self
+            .pending_dtls
+            .remove(&src_addr)
+            .ok_or("Pending DTLS handshake vanished")?;
+        let sni = match dtls::client_hello_record(&hello) {
+            Ok(record) => crate::extract_sni(&record).map_err(|e| {
+                self.record_sni_failure("no_sni");
+                format!("No valid SNI found in DTLS ClientHello: {}", e)
+            })?,
+            Err(e) => {
+                self.record_sni_failure(dtls_failure_reason(&e));
+                return Err(e.into());
+            }
+        };
+
+        Ok(Some(BufferedHandshake {
+            sni,
+            protocol: UdpProtocol::Dtls,
+            dcid: Vec::new(),
+            scid: Vec::new(),
+            datagrams: pending.datagrams,
+        }))
     }
 
-    // Parse QUIC long header to find payload
-    // Byte 0: Header form and flags
-    // Bytes 1-4: Version
-    // Byte 5: DCID Length
-    if packet.len() < 6 {
-        return Err("Packet truncated at DCID length".into());
+    /// Creates a new UDP session
+    async fn create_session(
+        &self,
+        src_addr: SocketAddr,
+        handshake: &BufferedHandshake,
+        client_socket: &Arc<UdpSocket>,
+    ) -> Result<SessionId, Box<dyn std::error::Error>> {
+        // Claim a session slot up front; any rejection below gives it back
+        let Some(slot) = self.sessions.reserve(self.max_sessions) else {
+            self.record_rejection("session_limit");
+            return Err("Max UDP sessions reached".into());
+        };
+        // Port-forwarded flows have no hostname to check
+        if handshake.protocol != UdpProtocol::Forward
+            && let Some(allowlist) = &self.config.allowlist
+            && !is_host_allowed(&handshake.sni, allowlist)
+        {
+            self.record_rejection("not_allowed");
+            return Err(format!("Host {} not in allowlist", handshake.sni).into());
+        }
+        if let Some(limiter) = &self.rate_limiter
+            && !limiter.allow(src_addr.ip())
+        {
+            self.record_rejection("rate_limit");
+            return Err("New UDP session rate limit reached for client prefix".into());
+        }
+
+        // Resolve backend address
+        let listen_port = client_socket.local_addr()?.port();
+        let backend_addr = match self.resolve_backend(handshake, listen_port).await {
+            Ok(addr) => addr,
+            Err(e) => {
+                self.record_rejection("backend_resolution");
+                return Err(e);
+            }
+        };
+
+        // Share a backend socket, or open one. quinn switches to a new
+        // client connection ID after the handshake, so terminated sessions
+        // get a dedicated socket instead of being routed by the initial ID.
+        let id = self.sessions.next_id();
+        let client_cid = if Some(backend_addr) == self.http3_terminator {
+            &[][..]
+        } else {
+            &handshake.scid[..]
+        };
+        let (backend, route_key, opened) =
+            match self.backends.assign(backend_addr, client_cid, id).await {
+                Ok(assigned) => assigned,
+                Err(e) => {
+                    self.record_rejection("backend_socket");
+                    return Err(e.into());
+                }
+            };
+
+        let session = UdpSession {
+            backend: Arc::clone(&backend),
+            route_key,
+            client_socket: Arc::clone(client_socket),
+            client_addr: src_addr,
+            connection_ids: vec![handshake.dcid.clone()],
+            created: Instant::now(),
+            last_activity: Instant::now(),
+            idle_timeout: self.idle_timeout(handshake.protocol, listen_port),
+            protocol: handshake.protocol,
+            bytes_tx: 0,
+            bytes_rx: 0,
+            metrics: self
+                .metrics
+                .as_ref()
+                .map(|m| m.session_started(&handshake.sni, handshake.protocol)),
+        };
+
+        self.sessions.insert(slot, id, session);
+        if opened {
+            self.spawn_response_handler(backend);
+        }
+
+        info!("Created UDP session for {} → {}", src_addr, backend_addr);
+
+        Ok(id)
     }
 
-    let dcid_len = packet[5] as usize;
-    let mut offset = 6 + dcid_len;
+    /// Resolves backend address from SNI
+    ///
+    /// A matching route's `upstream` wins; otherwise the SNI is used with the
+    /// default HTTPS port for QUIC/HTTP3, and with the listener's port for
+    /// DTLS, which has no well-known port. Port-forwarded flows go to the
+    /// destination of the listener's UDP route, and QUIC sessions of
+    /// terminate-mode routes to the HTTP/3 terminator.
+    async fn resolve_backend(
+        &self,
+        handshake: &BufferedHandshake,
+        listen_port: u16,
+    ) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+        let addr_str = match handshake.protocol {
+            UdpProtocol::Forward => {
+                let route = self
+                    .udp_route(listen_port)
+                    .ok_or_else(|| format!("No UDP route for port {}", listen_port))?;
+                format!("{}:{}", route.destination_host, route.destination_port)
+            }
+            protocol => {
+                let route = self.config.route_for(&handshake.sni);
+                if protocol == UdpProtocol::Quic
+                    && let Some(route) = route
+                    && route.mode == RouteMode::Terminate
+                {
+                    return match self.http3_terminator {
+                        Some(addr) if route.client_auth.is_none() => Ok(addr),
+                        _ => Err(
+                            format!("HTTP/3 termination unavailable for {}", handshake.sni).into(),
+                        ),
+                    };
+                }
 
-    // Skip DCID
-    if packet.len() < offset + 1 {
-        return Err("Packet truncated at SCID length".into());
+                let default_port = if protocol == UdpProtocol::Dtls {
+                    listen_port
+                } else {
+                    443
+                };
+                route
+                    .and_then(|route| route.upstream.clone())
+                    .unwrap_or_else(|| format!("{}:{}", handshake.sni, default_port))
+            }
+        };
+
+        let addr = tokio::net::lookup_host(&addr_str)
+            .await?
+            .next()
+            .ok_or_else(|| format!("Failed to resolve {}", addr_str))?;
+
+        Ok(addr)
     }
 
-    // SCID Length
-    let scid_len = packet[offset] as usize;
-    offset += 1 + scid_len;
+    /// Spawns background task to handle responses from backend
+    ///
+    /// One task serves every session on the socket. Responses go to each
+    /// client's current address, and connection IDs the backend announces are
+    /// added to the session so later client packets can be matched from any
+    /// address. The task ends as soon as the socket's last session is removed.
+    fn spawn_response_handler(&self, backend: Arc<BackendSocket>) {
+        let sessions = Arc::clone(&self.sessions);
+        let backends = Arc::clone(&self.backends);
+        let metrics = self.metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+
+            loop {
+                let received = tokio::select! {
+                    received = backend.socket.recv_from(&mut buf) => received,
+                    _ = backend.idle.notified() => {
+                        if backends.release_idle(&backend) {
+                            debug!("Closed idle backend socket for {}", backend.backend_addr);
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                let (len, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        error!("Backend recv error: {}", e);
+                        break;
+                    }
+                };
+                if from != backend.backend_addr {
+                    debug!("Ignoring datagram from {} on backend socket", from);
+                    continue;
+                }
+                let data = &buf[..len];
+                let Some(id) = backend
+                    .route(data)
+                    .or_else(|| backends.route_unknown(&backend))
+                else {
+                    debug!(
+                        "No UDP session for {} byte datagram from backend {}",
+                        len, from
+                    );
+                    continue;
+                };
+
+                // Update session stats
+                let (client_socket, client_addr, protocol) = match sessions.sessions.get_mut(&id)
+                {
+                    Some(mut session) => {
+                        session.bytes_rx += len as u64;
+                        session.last_activity = Instant::now();
+                        if let Some(metrics) = &session.metrics {
+                            metrics.bytes_rx.inc_by(len as u64);
+                        }
+                        (
+                            Arc::clone(&session.client_socket),
+                            session.client_addr,
+                            session.protocol,
+                        )
+                    }
+                    None => continue,
+                };
+                if protocol == UdpProtocol::Quic
+                    && let Some(cid) = quic::source_cid(data)
+                {
+                    sessions.add_connection_id(id, cid);
+                }
+
+                // Forward response to client
+                if let Err(e) = client_socket.send_to(data, client_addr).await {
+                    error!("Failed to send to client {}: {}", client_addr, e);
+                    continue;
+                }
 
-    // Skip Token Length (VarInt)
-    if packet.len() < offset + 1 {
-        return Err("Packet truncated at token".into());
+                debug!("Forwarded {} bytes from backend to {}", len, client_addr);
+            }
+
+            backends.release(&backend);
+            for id in backend
+                .routes
+                .iter()
+                .map(|route| *route.value())
+                .collect::<Vec<_>>()
+            {
+                if let Some(session) = sessions.remove(id) {
+                    session_closed(metrics.as_deref(), &session, "backend_error");
+                }
+            }
+            debug!("Closed backend socket for {}", backend.backend_addr);
+        });
+    }
+
+    /// Whether QUIC sessions for `sni` go to the HTTP/3 terminator
+    fn is_terminated(&self, sni: &str) -> bool {
+        self.config
+            .route_for(sni)
+            .is_some_and(|route| route.mode == RouteMode::Terminate)
     }
 
-    let token_len = packet[offset] as usize;
-    offset += 1 + token_len;
+    /// The UDP route forwarding `listen_port`, if any
+    fn udp_route(&self, listen_port: u16) -> Option<&UdpRoute> {
+        self.config
+            .udp_routes
+            .as_ref()?
+            .iter()
+            .find(|route| route.listen_port == listen_port)
+    }
+
+    /// Idle timeout of a new session: the UDP route's for port-forwarded
+    /// flows, the QUIC session timeout otherwise
+    fn idle_timeout(&self, protocol: UdpProtocol, listen_port: u16) -> Duration {
+        match protocol {
+            UdpProtocol::Forward => self
+                .udp_route(listen_port)
+                .and_then(|route| route.idle_timeout)
+                .map_or(self.session_timeout, Duration::from_secs),
+            _ => self.session_timeout,
+        }
+    }
+
+    /// Counts a client refused a session
+    fn record_rejection(&self, reason: &'static str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.sessions_rejected.with_label_values(&[reason]).inc();
+        }
+    }
 
-    // Skip Length field (VarInt encoding, simplified)
-    if packet.len() < offset + 2 {
-        return Err("Packet truncated at length".into());
+    /// Counts a client whose Initial packets yielded no SNI
+    fn record_sni_failure(&self, reason: &'static str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.sni_failures.with_label_values(&[reason]).inc();
+        }
     }
-    offset += 2;
 
-    // Now we're at the payload, which contains CRYPTO frames with TLS ClientHello
-    // Search for TLS ClientHello (0x16 = Handshake)
-    let payload = &packet[offset..];
+    /// Cleans up expired sessions
+    fn cleanup_sessions(&self) {
+        let expired = self.sessions.remove_expired();
+        for session in &expired {
+            session_closed(self.metrics.as_deref(), session, "idle");
+        }
+        if !expired.is_empty() {
+            debug!("Cleaned up {} expired UDP sessions", expired.len());
+        }
 
-    // Try to find TLS record in payload
-    // Look for 0x16 (TLS Handshake) byte
-    for i in 0..payload.len().saturating_sub(5) {
-        if payload[i] == 0x16 {
-            // Found potential TLS handshake
-            // Try to extract SNI from this position
-            if let Ok(sni) = crate::extract_sni(&payload[i..]) {
-                return Ok(sni);
+        // Drop handshakes whose ClientHello never completed
+        let handshake_timeout = Duration::from_secs(self.config.timeouts.client_hello);
+        self.pending.retain(|_, pending| {
+            let alive = pending.started.elapsed() < handshake_timeout;
+            if !alive {
+                self.record_sni_failure("timeout");
+            }
+            alive
+        });
+        self.pending_dtls.retain(|_, pending| {
+            let alive = pending.started.elapsed() < handshake_timeout;
+            if !alive {
+                self.record_sni_failure("timeout");
             }
+            alive
+        });
+
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.prune();
         }
     }
+}
 
-    Err("No valid SNI found in QUIC packet".into())
+/// Whether the allowlist admits `host`; "*" admits every host
+fn is_host_allowed(host: &str, allowlist: &[String]) -> bool {
+    if allowlist.iter().any(|pattern| pattern == "*") {
+        return true;
+    }
+
+    let host_lower = host.to_lowercase();
+    allowlist
+        .iter()
+        .any(|pattern| matches_allowlist_pattern(&host_lower, &pattern.to_lowercase()))
+}
+
+/// Extracts SNI from QUIC Initial packet
+///
+/// # Arguments
+///
+/// * `packet` - Raw QUIC packet data
+///
+/// # Returns
+///
+/// Returns the extracted SNI hostname or an error if parsing fails.
+///
+/// # Implementation
+///
+/// The ClientHello travels in CRYPTO frames inside the encrypted Initial
+/// payload. `quic::decrypt_initial` derives the Initial keys from the
+/// Destination Connection ID (RFC 9001 §5), removes header protection and
+/// decrypts the payload; the reassembled ClientHello is then parsed like a
+/// TLS record:
+/// ```text
+/// +--------+---------+------+------+-------+--------+-----+------------------+
+/// | Header | Version | DCID | SCID | Token | Length | PN  | Protected payload|
+/// |  Byte  |         |      |      |varint | varint |     | (CRYPTO frames)  |
+/// +--------+---------+------+------+-------+--------+-----+------------------+
+/// ```
+pub fn extract_quic_sni(packet: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let initial = quic::decrypt_initial(packet)?;
+    let hello = quic::assemble_client_hello(&initial.crypto)?;
+    let record = quic::client_hello_record(&hello)?;
+
+    crate::extract_sni(&record)
+        .map_err(|e| format!("No valid SNI found in QUIC packet: {}", e).into())
 }
 
 #[cfg(test)]
@@ -489,6 +1809,1195 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_protocol_detection_short_header() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+
+        // Fixed bit, 8-byte DCID, packet number and sample
+        let mut short_header = vec![0x40; 29];
+        assert_eq!(
+            handler.detect_protocol(&short_header).unwrap(),
+            UdpProtocol::Quic
+        );
+
+        short_header.truncate(28);
+        assert_eq!(
+            handler.detect_protocol(&short_header).unwrap(),
+            UdpProtocol::Unknown
+        );
+    }
+
+    #[test]
+    fn test_protocol_detection_dtls() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+
+        let body = dtls::client_hello_body(Some("dtls.example.com"), &[]);
+        let hello = &dtls::client_hello_datagrams(0, &body, 1000)[0];
+        assert_eq!(handler.detect_protocol(hello).unwrap(), UdpProtocol::Dtls);
+
+        // DTLS 1.3 ciphertext record with the unified header
+        let ciphertext = [0x2c, 0x00, 0x01, 0x00, 0x10];
+        assert_eq!(
+            handler.detect_protocol(&ciphertext).unwrap(),
+            UdpProtocol::Dtls
+        );
+    }
+
+    #[test]
+    fn test_quic_config_settings() {
+        let config = Config::parse(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+quic_config:
+  connection_id_length: 4
+  session_timeout: 90
+  max_sessions: 250
+"#,
+        )
+        .unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        assert_eq!(handler.short_cid_len, 4);
+        assert_eq!(handler.session_timeout, Duration::from_secs(90));
+        assert_eq!(handler.max_sessions, 250);
+
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        assert_eq!(handler.short_cid_len, 8);
+        assert_eq!(handler.session_timeout, Duration::from_secs(30));
+        assert_eq!(handler.max_sessions, 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_session_follows_connection_id_across_addresses() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr_a = client_a.local_addr().unwrap();
+        let addr_b = client_b.local_addr().unwrap();
+
+        let (id, backend_socket) = open_test_session(
+            &handler,
+            backend.local_addr().unwrap(),
+            &listener,
+            addr_a,
+            &[0x01; 8],
+            &[],
+        )
+        .await;
+        let backend_socket_addr = local_addr(&backend_socket);
+
+        let server_cid = [0x5e; 8];
+        let mut short_header = vec![0x41];
+        short_header.extend_from_slice(&server_cid);
+        short_header.extend_from_slice(&[0xaa; 24]);
+
+        // Unknown connection ID from an unknown address: no session
+        handler
+            .handle_quic_packet(&short_header, addr_b, &listener)
+            .await
+            .unwrap();
+        assert_eq!(handler.sessions.find(Some(&server_cid), addr_b), None);
+
+        // The backend's Handshake packet announces its connection ID
+        let mut handshake = vec![0xe0, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08];
+        handshake.extend_from_slice(&server_cid);
+        handshake.extend_from_slice(&[0xbb; 30]);
+        backend
+            .send_to(&handshake, backend_socket_addr)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(2), client_a.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &handshake[..]);
+
+        // After a NAT rebinding the short header is matched by connection ID
+        handler
+            .handle_quic_packet(&short_header, addr_b, &listener)
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), backend.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &short_header[..]);
+        assert_eq!(handler.sessions.find(None, addr_b), Some(id));
+        assert_eq!(handler.sessions.find(None, addr_a), None);
+
+        // Responses follow the client to its new address
+        backend
+            .send_to(b"\x40response", backend_socket_addr)
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), client_b.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], b"\x40response");
+
+        // An Initial with a new DCID from the same address is a new connection
+        let hello = client_hello_message(Some("next.example.com"));
+        let initial = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x02; 8],
+            0,
+            &quic::crypto_frame(0, &hello[..20]),
+        );
+        handler
+            .handle_quic_packet(&initial, addr_b, &listener)
+            .await
+            .unwrap();
+        assert!(handler.pending.contains_key(&addr_b));
+
+        // Removing the session clears both indexes
+        handler.sessions.remove(id).unwrap();
+        assert_eq!(handler.sessions.find(Some(&server_cid), addr_b), None);
+        assert_eq!(handler.sessions.find(Some(&[0x01; 8]), addr_a), None);
+    }
+
+    #[tokio::test]
+    async fn test_backend_sockets_shared_by_connection_id() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+
+        let mut clients = Vec::new();
+        let mut sockets = Vec::new();
+        for i in 0..(BACKEND_SOCKETS_PER_UPSTREAM + 2) {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            // Mixed client connection ID lengths
+            let client_cid = vec![i as u8 + 1; 4 + (i % 2) * 4];
+            let (_, socket) = open_test_session(
+                &handler,
+                backend_addr,
+                &listener,
+                client.local_addr().unwrap(),
+                &[0xd0 + i as u8; 8],
+                &client_cid,
+            )
+            .await;
+            clients.push((client, client_cid));
+            sockets.push(socket);
+        }
+
+        // Sessions beyond the pool size share its sockets
+        assert_eq!(
+            handler.backends.sockets.get(&backend_addr).unwrap().len(),
+            BACKEND_SOCKETS_PER_UPSTREAM
+        );
+        let shared = &sockets[BACKEND_SOCKETS_PER_UPSTREAM];
+        assert!(
+            sockets[..BACKEND_SOCKETS_PER_UPSTREAM]
+                .iter()
+                .any(|socket| Arc::ptr_eq(socket, shared))
+        );
+        assert_eq!(shared.routes.len(), 2);
+
+        // Backend packets reach the client whose connection ID they carry
+        let mut buf = [0u8; 1500];
+        for (i, (client, client_cid)) in clients.iter().enumerate() {
+            let mut short_header = vec![0x40];
+            short_header.extend_from_slice(client_cid);
+            short_header.extend_from_slice(&[i as u8; 24]);
+            backend
+                .send_to(&short_header, local_addr(&sockets[i]))
+                .await
+                .unwrap();
+            let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..len], &short_header[..]);
+        }
+
+        // A clashing client connection ID avoids the socket that routes it,
+        // and gets a dedicated socket once every shared one does
+        let (socket, route_key, opened) = handler
+            .backends
+            .assign(backend_addr, &clients[0].1, 98)
+            .await
+            .unwrap();
+        assert!(!opened);
+        assert!(!Arc::ptr_eq(&socket, &sockets[0]));
+        assert_eq!(route_key, clients[0].1);
+
+        for socket in handler.backends.sockets.get(&backend_addr).unwrap().iter() {
+            socket.add_route(&[0xee; 8], 97);
+        }
+        let (_, route_key, opened) = handler
+            .backends
+            .assign(backend_addr, &[0xee; 8], 99)
+            .await
+            .unwrap();
+        assert!(opened);
+        assert!(route_key.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backend_switching_connection_ids() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+
+        let mut clients = Vec::new();
+        let mut sockets = Vec::new();
+        for i in 0..=BACKEND_SOCKETS_PER_UPSTREAM {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let (_, socket) = open_test_session(
+                &handler,
+                backend_addr,
+                &listener,
+                client.local_addr().unwrap(),
+                &[0xd0 + i as u8; 8],
+                &[i as u8 + 1; 8],
+            )
+            .await;
+            clients.push(client);
+            sockets.push(socket);
+        }
+        let shared = &sockets[BACKEND_SOCKETS_PER_UPSTREAM];
+        let sole = sockets
+            .iter()
+            .position(|socket| !Arc::ptr_eq(socket, shared))
+            .unwrap();
+
+        // After the handshake the backend moves to a connection ID the client
+        // issued in an encrypted frame
+        let mut short_header = vec![0x40];
+        short_header.extend_from_slice(&[0x77; 8]);
+        short_header.extend_from_slice(&[0xaa; 24]);
+
+        // On a shared socket it cannot be attributed and is dropped
+        backend
+            .send_to(&short_header, local_addr(shared))
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        for client in &clients {
+            assert!(
+                tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buf))
+                    .await
+                    .is_err()
+            );
+        }
+
+        // On a socket with a single session it belongs to that session
+        backend
+            .send_to(&short_header, local_addr(&sockets[sole]))
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), clients[sole].recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &short_header[..]);
+
+        // Later sessions to the backend get a dedicated socket and are matched
+        // by 4-tuple
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (_, socket) = open_test_session(
+            &handler,
+            backend_addr,
+            &listener,
+            client.local_addr().unwrap(),
+            &[0xdf; 8],
+            &[0xee; 8],
+        )
+        .await;
+        assert!(sockets.iter().all(|s| !Arc::ptr_eq(s, &socket)));
+        assert!(socket.routes.contains_key(&[][..]));
+        backend
+            .send_to(&short_header, local_addr(&socket))
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &short_header[..]);
+    }
+
+    #[tokio::test]
+    async fn test_backend_socket_closed_with_last_session() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let backend_addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+        let (pooled, pooled_socket) = open_test_session(
+            &handler,
+            backend_addr,
+            &listener,
+            "192.0.2.1:1111".parse().unwrap(),
+            &[0x01; 8],
+            &[0x02; 8],
+        )
+        .await;
+        let (dedicated, dedicated_socket) = open_test_session(
+            &handler,
+            backend_addr,
+            &listener,
+            "192.0.2.2:2222".parse().unwrap(),
+            &[0x03; 8],
+            &[],
+        )
+        .await;
+        assert!(handler.backends.sockets.contains_key(&backend_addr));
+
+        // Removing a session ends the receive task of its socket right away,
+        // long before the session timeout
+        handler.sessions.remove(pooled).unwrap();
+        handler.sessions.remove(dedicated).unwrap();
+        for socket in [&pooled_socket, &dedicated_socket] {
+            tokio::time::timeout(Duration::from_secs(2), async {
+                while Arc::strong_count(socket) > 1 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .unwrap();
+        }
+        assert!(!handler.backends.sockets.contains_key(&backend_addr));
+    }
+
+    #[tokio::test]
+    async fn test_session_limit_and_route_upstream() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+quic_config:
+  max_sessions: 1
+routes:
+  - host: "quic.example.com"
+    upstream: "{}"
+"#,
+            backend.local_addr().unwrap()
+        ))
+        .unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let hello = client_hello(Some("quic.example.com"));
+
+        let initial =
+            quic::seal_client_initial(quic::QUIC_V1, &[0x61; 8], &[0x62; 8], &[], 0, &hello)
+                .unwrap();
+        let client_a: SocketAddr = "192.0.2.1:1111".parse().unwrap();
+        handler
+            .handle_quic_packet(&initial, client_a, &listener)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(2), backend.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &initial[..]);
+
+        let other =
+            quic::seal_client_initial(quic::QUIC_V1, &[0x71; 8], &[0x72; 8], &[], 0, &hello)
+                .unwrap();
+        let client_b: SocketAddr = "192.0.2.2:2222".parse().unwrap();
+        let err = handler
+            .handle_quic_packet(&other, client_b, &listener)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Max UDP sessions"));
+        assert_eq!(handler.sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dtls_session_routed_by_sni() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+routes:
+  - host: "*.iot.example.com"
+    upstream: "{}"
+"#,
+            backend.local_addr().unwrap()
+        ))
+        .unwrap();
+        let registry = Registry::new();
+        let handler = UdpConnectionHandler::new(config, Some(&registry));
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        // The ClientHello is fragmented over three datagrams
+        let body = dtls::client_hello_body(Some("sensor.iot.example.com"), &[]);
+        let datagrams = dtls::client_hello_datagrams(0, &body, body.len() / 3 + 1);
+        assert_eq!(datagrams.len(), 3);
+        for datagram in &datagrams[..2] {
+            handler
+                .handle_dtls_packet(datagram, client_addr, &listener)
+                .await
+                .unwrap();
+        }
+        assert!(handler.pending_dtls.contains_key(&client_addr));
+        assert_eq!(handler.sessions.len(), 0);
+
+        handler
+            .handle_dtls_packet(&datagrams[2], client_addr, &listener)
+            .await
+            .unwrap();
+        assert!(handler.pending_dtls.is_empty());
+        let mut buf = [0u8; 1500];
+        let mut proxy_side = None;
+        for datagram in &datagrams {
+            let (len, from) =
+                tokio::time::timeout(Duration::from_secs(2), backend.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(&buf[..len], &datagram[..]);
+            proxy_side = Some(from);
+        }
+
+        // The session is keyed by address only, and answers flow back
+        let id = handler.sessions.find(None, client_addr).unwrap();
+        assert_eq!(
+            handler.sessions.sessions.get(&id).unwrap().protocol,
+            UdpProtocol::Dtls
+        );
+        assert!(handler.sessions.connection_ids.is_empty());
+        let hello_verify = [
+            0x16, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        backend
+            .send_to(&hello_verify, proxy_side.unwrap())
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &hello_verify[..]);
+
+        let metrics = handler.metrics.as_ref().unwrap();
+        assert_eq!(
+            metrics
+                .bytes_transferred
+                .with_label_values(&["sensor.iot.example.com-dtls", "tx"])
+                .get(),
+            datagrams.iter().map(|d| d.len() as u64).sum::<u64>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_applies_to_quic_and_dtls() {
+        let config = Config::parse(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: { connect: 10, client_hello: 10, idle: 300 }
+metrics: { enabled: false, address: "127.0.0.1:9000" }
+allowlist: ["*.allowed.example.com"]
+"#,
+        )
+        .unwrap();
+        let registry = Registry::new();
+        let handler = UdpConnectionHandler::new(config, Some(&registry));
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+
+        let body = dtls::client_hello_body(Some("blocked.example.com"), &[]);
+        let hello = &dtls::client_hello_datagrams(0, &body, 1000)[0];
+        let err = handler
+            .handle_dtls_packet(hello, "192.0.2.1:1111".parse().unwrap(), &listener)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not in allowlist"));
+
+        let initial = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x05; 8],
+            0,
+            &client_hello(Some("blocked.example.com")),
+        );
+        let err = handler
+            .handle_quic_packet(&initial, "192.0.2.2:2222".parse().unwrap(), &listener)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not in allowlist"));
+
+        assert_eq!(handler.sessions.len(), 0);
+        let metrics = handler.metrics.as_ref().unwrap();
+        assert_eq!(
+            metrics
+                .sessions_rejected
+                .with_label_values(&["not_allowed"])
+                .get(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_udp_route_forwards_flows() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+allowlist: ["only.example.com"]
+udp_routes:
+  - listen_port: {}
+    destination_host: "127.0.0.1"
+    destination_port: {}
+    idle_timeout: 1
+"#,
+            proxy_addr.port(),
+            backend.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        let runner = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = runner.run(listener).await {
+                eprintln!("UDP handler failed: {}", e);
+            }
+        });
+
+        // Two flows, with payloads that look like no known protocol
+        let client_a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client_a
+            .send_to(b"\x12\x34dns query", proxy_addr)
+            .await
+            .unwrap();
+        client_b
+            .send_to(b"\x01wireguard", proxy_addr)
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 1500];
+        let mut flows = Vec::new();
+        for _ in 0..2 {
+            let (len, from) =
+                tokio::time::timeout(Duration::from_secs(2), backend.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            flows.push((buf[..len].to_vec(), from));
+        }
+        flows.sort();
+        assert_eq!(flows[0].0, b"\x01wireguard");
+        assert_eq!(flows[1].0, b"\x12\x34dns query");
+        assert_ne!(flows[0].1, flows[1].1);
+
+        // Replies go back to the flow's client only
+        backend.send_to(b"reply b", flows[0].1).await.unwrap();
+        backend.send_to(b"reply a", flows[1].1).await.unwrap();
+        for (client, expected) in [(&client_a, &b"reply a"[..]), (&client_b, b"reply b")] {
+            let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..len], expected);
+        }
+        assert_eq!(handler.sessions.len(), 2);
+        let id = handler
+            .sessions
+            .find(None, client_a.local_addr().unwrap())
+            .unwrap();
+        let session = handler.sessions.sessions.get(&id).unwrap();
+        assert_eq!(session.protocol, UdpProtocol::Forward);
+        assert_eq!(session.idle_timeout, Duration::from_secs(1));
+        drop(session);
+
+        // Flows close after the route's idle timeout, not the session timeout
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(handler.sessions.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_quic_terminated_by_http3_terminator() {
+        use crate::quic_handler::{self, QuicHandler};
+        use crate::termination::CertificateStore;
+
+        let dir = std::env::temp_dir().join(format!("sniproxy-udp-h3-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["h3.example.com".to_string()]).unwrap();
+        std::fs::write(dir.join("h3.example.com.crt"), cert.cert.pem()).unwrap();
+        std::fs::write(
+            dir.join("h3.example.com.key"),
+            cert.signing_key.serialize_pem(),
+        )
+        .unwrap();
+
+        let backend = quic_handler::spawn_echo_backend(false).await;
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 2, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+tls_certificates: {{ directory: "{}" }}
+routes:
+  - {{ host: "h3.example.com", mode: terminate, upstream: "{}" }}
+"#,
+            dir.display(),
+            backend
+        ))
+        .unwrap();
+
+        // Without a terminator, the session is rejected instead of passed through
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let unterminated = listener.local_addr().unwrap();
+        let handler = UdpConnectionHandler::new(config.clone(), None);
+        tokio::spawn(async move {
+            if let Err(e) = handler.run(listener).await {
+                eprintln!("UDP handler failed: {}", e);
+            }
+        });
+        let attempt = tokio::time::timeout(
+            Duration::from_millis(500),
+            quic_handler::http3_request(
+                unterminated,
+                "h3.example.com",
+                cert.cert.der().clone(),
+                "GET",
+                "/",
+                b"",
+            ),
+        )
+        .await;
+        assert!(!matches!(attempt, Ok(Ok(_))));
+
+        let quic = Arc::new(
+            QuicHandler::new(
+                Arc::new(config.clone()),
+                Arc::new(CertificateStore::new(&dir)),
+            )
+            .unwrap(),
+        );
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let handler = UdpConnectionHandler::new(config, None)
+            .with_http3_terminator(quic.local_addr().unwrap());
+        let runner = handler.clone();
+        tokio::spawn(quic.run());
+        tokio::spawn(async move {
+            if let Err(e) = runner.run(listener).await {
+                eprintln!("UDP handler failed: {}", e);
+            }
+        });
+
+        let (response, body) = quic_handler::http3_request(
+            proxy_addr,
+            "h3.example.com",
+            cert.cert.der().clone(),
+            "GET",
+            "/index.html",
+            b"",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(body, "GET /index.html HTTP/1.1 host=h3.example.com\n");
+        assert_eq!(handler.sessions.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_skipped_for_terminated_sessions() {
+        use crate::quic_handler::{self, QuicHandler};
+        use crate::termination::CertificateStore;
+
+        let dir = std::env::temp_dir().join(format!("sniproxy-udp-retry-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["h3.example.com".to_string()]).unwrap();
+        std::fs::write(dir.join("h3.example.com.crt"), cert.cert.pem()).unwrap();
+        std::fs::write(
+            dir.join("h3.example.com.key"),
+            cert.signing_key.serialize_pem(),
+        )
+        .unwrap();
+
+        let backend = quic_handler::spawn_echo_backend(false).await;
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 2, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+tls_certificates: {{ directory: "{}" }}
+quic_config:
+  retry: true
+routes:
+  - {{ host: "h3.example.com", mode: terminate, upstream: "{}" }}
+"#,
+            dir.display(),
+            backend
+        ))
+        .unwrap();
+
+        let quic = Arc::new(
+            QuicHandler::new(
+                Arc::new(config.clone()),
+                Arc::new(CertificateStore::new(&dir)),
+            )
+            .unwrap(),
+        );
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let handler = UdpConnectionHandler::new(config, None)
+            .with_http3_terminator(quic.local_addr().unwrap());
+        let runner = handler.clone();
+        tokio::spawn(quic.run());
+        tokio::spawn(async move {
+            if let Err(e) = runner.run(listener).await {
+                eprintln!("UDP handler failed: {}", e);
+            }
+        });
+
+        // A proxy-issued Retry would make the client reject the terminator's
+        // transport parameters
+        let (response, body) = tokio::time::timeout(
+            Duration::from_secs(5),
+            quic_handler::http3_request(
+                proxy_addr,
+                "h3.example.com",
+                cert.cert.der().clone(),
+                "GET",
+                "/retry",
+                b"",
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(body, "GET /retry HTTP/1.1 host=h3.example.com\n");
+        assert_eq!(handler.sessions.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_validates_address_before_session() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+quic_config:
+  retry: true
+routes:
+  - host: "quic.example.com"
+    upstream: "{}"
+"#,
+            backend.local_addr().unwrap()
+        ))
+        .unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let hello = client_hello(Some("quic.example.com"));
+        let odcid = [0x61; 8];
+        let scid = [0x62; 8];
+
+        // The first Initial is answered with a Retry, not forwarded
+        let initial =
+            quic::seal_client_initial(quic::QUIC_V1, &odcid, &scid, &[], 0, &hello).unwrap();
+        handler
+            .handle_quic_packet(&initial, client_addr, &listener)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let retry = buf[..len].to_vec();
+        assert!(retry.len() < initial.len());
+        assert_eq!(retry[0] & 0xf0, 0xf0);
+        assert_eq!(quic::destination_cid(&retry, 0), Some(&scid[..]));
+        let retry_cid = quic::source_cid(&retry).unwrap().to_vec();
+        let token = &retry[6 + scid.len() + 1 + retry_cid.len()..len - 16];
+        assert_eq!(
+            quic::retry(quic::QUIC_V1, &odcid, &scid, &retry_cid, token).unwrap(),
+            retry
+        );
+        assert_eq!(handler.sessions.len(), 0);
+        assert!(handler.pending.is_empty());
+
+        // The token is bound to the client's address
+        let retried =
+            quic::seal_client_initial(quic::QUIC_V1, &retry_cid, &scid, token, 1, &hello).unwrap();
+        let spoofed: SocketAddr = "127.0.0.2:4433".parse().unwrap();
+        handler
+            .handle_quic_packet(&retried, spoofed, &listener)
+            .await
+            .unwrap();
+        assert_eq!(handler.sessions.len(), 0);
+
+        // The retried Initial from the client's address opens the session
+        handler
+            .handle_quic_packet(&retried, client_addr, &listener)
+            .await
+            .unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(2), backend.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &retried[..]);
+        assert_eq!(handler.sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_version_negotiation() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        let mut packet = vec![0xc0, 0x1a, 0x2a, 0x3a, 0x4a, 0x08];
+        packet.extend_from_slice(&[0x11; 8]);
+        packet.push(0x04);
+        packet.extend_from_slice(&[0x22; 4]);
+
+        // Too small to be answered without amplification
+        let mut small = packet.clone();
+        small.resize(200, 0);
+        handler
+            .handle_quic_packet(&small, client_addr, &listener)
+            .await
+            .unwrap();
+
+        packet.resize(quic::MIN_INITIAL_DATAGRAM, 0);
+        handler
+            .handle_quic_packet(&packet, client_addr, &listener)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &buf[..len],
+            &quic::version_negotiation(&[0x11; 8], &[0x22; 4])[..]
+        );
+        assert!(handler.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_session_rate_limit_per_prefix() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: false, address: "127.0.0.1:9000" }}
+quic_config:
+  new_sessions_per_prefix: 1
+routes:
+  - host: "quic.example.com"
+    upstream: "{}"
+"#,
+            backend.local_addr().unwrap()
+        ))
+        .unwrap();
+        let handler = UdpConnectionHandler::new(config, None);
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let hello = client_hello(Some("quic.example.com"));
+
+        let open = |cid: u8, client: &str| {
+            let initial =
+                quic::seal_client_initial(quic::QUIC_V1, &[cid; 8], &[cid; 8], &[], 0, &hello)
+                    .unwrap();
+            let client: SocketAddr = client.parse().unwrap();
+            let handler = handler.clone();
+            let listener = Arc::clone(&listener);
+            async move {
+                handler
+                    .handle_quic_packet(&initial, client, &listener)
+                    .await
+            }
+        };
+
+        open(0x01, "192.0.2.1:1000").await.unwrap();
+        let err = open(0x02, "192.0.2.200:2000").await.unwrap_err();
+        assert!(err.to_string().contains("rate limit"));
+        open(0x03, "198.51.100.1:1000").await.unwrap();
+        assert_eq!(handler.sessions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_udp_metrics() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::parse(&format!(
+            r#"
+listen_addrs: ["0.0.0.0:443"]
+timeouts: {{ connect: 10, client_hello: 10, idle: 300 }}
+metrics: {{ enabled: true, address: "127.0.0.1:9000" }}
+quic_config:
+  max_sessions: 1
+routes:
+  - host: "quic.example.com"
+    upstream: "{}"
+"#,
+            backend.local_addr().unwrap()
+        ))
+        .unwrap();
+        let registry = Registry::new();
+        let handler = UdpConnectionHandler::new(config, Some(&registry));
+        let metrics = Arc::clone(handler.metrics.as_ref().unwrap());
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let hello = client_hello(Some("quic.example.com"));
+
+        let initial =
+            quic::seal_client_initial(quic::QUIC_V1, &[0x61; 8], &[0x62; 8], &[], 0, &hello)
+                .unwrap();
+        handler
+            .handle_quic_packet(&initial, client_addr, &listener)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let (_, backend_socket_addr) =
+            tokio::time::timeout(Duration::from_secs(2), backend.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(metrics.sessions_created.get(), 1);
+        assert_eq!(metrics.sessions_active.get(), 1);
+
+        // Backend response addressed to the client's connection ID
+        let mut response = vec![0x40];
+        response.extend_from_slice(&[0x62; 8]);
+        response.extend_from_slice(&[0xaa; 91]);
+        backend
+            .send_to(&response, backend_socket_addr)
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let bytes = |direction: &str| {
+            metrics
+                .bytes_transferred
+                .with_label_values(&["quic.example.com-quic", direction])
+                .get()
+        };
+        assert_eq!(bytes("tx"), initial.len() as u64);
+        assert_eq!(bytes("rx"), 100);
+
+        // Rejections and SNI failures by reason
+        let other =
+            quic::seal_client_initial(quic::QUIC_V1, &[0x71; 8], &[0x72; 8], &[], 0, &hello)
+                .unwrap();
+        let other_addr: SocketAddr = "192.0.2.2:2222".parse().unwrap();
+        assert!(
+            handler
+                .handle_quic_packet(&other, other_addr, &listener)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            metrics
+                .sessions_rejected
+                .with_label_values(&["session_limit"])
+                .get(),
+            1
+        );
+
+        let no_sni = quic::build_client_initial(quic::QUIC_V1, &[0x81; 8], 0, &client_hello(None));
+        assert!(
+            handler
+                .handle_quic_packet(&no_sni, "192.0.2.3:3333".parse().unwrap(), &listener)
+                .await
+                .is_err()
+        );
+        let mut corrupted = other.clone();
+        corrupted[100] ^= 0x01;
+        assert!(
+            handler
+                .handle_quic_packet(&corrupted, "192.0.2.4:4444".parse().unwrap(), &listener)
+                .await
+                .is_err()
+        );
+        let failures = |reason: &str| metrics.sni_failures.with_label_values(&[reason]).get();
+        assert_eq!(failures("no_sni"), 1);
+        assert_eq!(failures("decryption_failed"), 1);
+
+        // Idle expiry closes the session and records its lifetime
+        for mut session in handler.sessions.sessions.iter_mut() {
+            session.last_activity -= Duration::from_secs(60);
+        }
+        handler.cleanup_sessions();
+        assert_eq!(metrics.sessions_active.get(), 0);
+        assert_eq!(
+            metrics.sessions_closed.with_label_values(&["idle"]).get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .session_duration
+                .with_label_values(&["quic.example.com-quic"])
+                .get_sample_count(),
+            1
+        );
+        assert!(!registry.gather().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_keeps_existing_sessions() {
+        let registry = Registry::new();
+        let handler = UdpConnectionHandler::new(create_test_config(), Some(&registry));
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr: SocketAddr = "192.0.2.1:1111".parse().unwrap();
+        open_test_session(
+            &handler,
+            backend.local_addr().unwrap(),
+            &listener,
+            client_addr,
+            &[0x01; 8],
+            &[0x02; 8],
+        )
+        .await;
+        let (idle, _) = open_test_session(
+            &handler,
+            backend.local_addr().unwrap(),
+            &listener,
+            "192.0.2.2:2222".parse().unwrap(),
+            &[0x03; 8],
+            &[0x04; 8],
+        )
+        .await;
+        handler
+            .sessions
+            .sessions
+            .get_mut(&idle)
+            .unwrap()
+            .last_activity -= Duration::from_secs(60);
+
+        handler.begin_drain();
+
+        // New clients are turned away
+        let initial = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x05; 8],
+            0,
+            &client_hello(Some("quic.example.com")),
+        );
+        handler
+            .handle_quic_packet(&initial, "192.0.2.3:3333".parse().unwrap(), &listener)
+            .await
+            .unwrap();
+        assert!(handler.pending.is_empty());
+
+        // Existing sessions are still forwarded
+        let mut packet = vec![0xc0, 0x00, 0x00, 0x00, 0x01, 0x08];
+        packet.extend_from_slice(&[0x01; 8]);
+        packet.extend_from_slice(&[0x00; 40]);
+        handler
+            .handle_quic_packet(&packet, client_addr, &listener)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(2), backend.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &packet[..]);
+
+        // The idle session closes; the busy one is cut off at the timeout
+        assert_eq!(handler.drain(Duration::from_millis(300)).await, 1);
+        assert_eq!(handler.sessions.len(), 0);
+        assert!(
+            handler
+                .sessions
+                .find(Some(&[0x01; 8]), client_addr)
+                .is_none()
+        );
+        let metrics = handler.metrics.as_ref().unwrap();
+        let closed = |reason: &str| metrics.sessions_closed.with_label_values(&[reason]).get();
+        assert_eq!(closed("idle"), 1);
+        assert_eq!(closed("shutdown"), 1);
+        assert_eq!(
+            metrics
+                .sessions_rejected
+                .with_label_values(&["draining"])
+                .get(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_without_sessions_returns_immediately() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let started = Instant::now();
+        assert_eq!(handler.drain(Duration::from_secs(30)).await, 0);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Opens a session to `backend_addr` the way `create_session` does
+    async fn open_test_session(
+        handler: &UdpConnectionHandler,
+        backend_addr: SocketAddr,
+        listener: &Arc<UdpSocket>,
+        client_addr: SocketAddr,
+        dcid: &[u8],
+        client_cid: &[u8],
+    ) -> (SessionId, Arc<BackendSocket>) {
+        let id = handler.sessions.next_id();
+        let (backend, route_key, opened) = handler
+            .backends
+            .assign(backend_addr, client_cid, id)
+            .await
+            .unwrap();
+        let slot = handler.sessions.reserve(handler.max_sessions).unwrap();
+        handler.sessions.insert(
+            slot,
+            id,
+            UdpSession {
+                backend: Arc::clone(&backend),
+                route_key,
+                client_socket: Arc::clone(listener),
+                client_addr,
+                connection_ids: vec![dcid.to_vec()],
+                created: Instant::now(),
+                last_activity: Instant::now(),
+                idle_timeout: handler.session_timeout,
+                protocol: UdpProtocol::Quic,
+                bytes_tx: 0,
+                bytes_rx: 0,
+                metrics: None,
+            },
+        );
+        if opened {
+            handler.spawn_response_handler(Arc::clone(&backend));
+        }
+        (id, backend)
+    }
+
+    /// Loopback address of a backend socket
+    fn local_addr(backend: &BackendSocket) -> SocketAddr {
+        let port = backend.socket.local_addr().unwrap().port();
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
     #[test]
     fn test_session_cleanup() {
         let config = create_test_config();
@@ -499,6 +3008,61 @@ mod tests {
         assert_eq!(handler.sessions.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_session_slots_reserved_atomically() {
+        let table = SessionTable::default();
+
+        // Concurrent handshakes never claim more slots than the limit
+        let claimed: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..50)
+                            .filter_map(|_| table.reserve(100))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert_eq!(claimed.len(), 100);
+        assert!(table.reserve(100).is_none());
+
+        // Rejected sessions give their slot back
+        drop(claimed);
+        let slot = table.reserve(1).unwrap();
+        assert!(table.reserve(1).is_none());
+
+        // Inserted sessions hold theirs until removed
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let id = table.next_id();
+        let session = UdpSession {
+            backend: Arc::new(
+                BackendSocket::bind("127.0.0.1:9".parse().unwrap())
+                    .await
+                    .unwrap(),
+            ),
+            route_key: Vec::new(),
+            client_socket: listener,
+            client_addr: "192.0.2.1:1111".parse().unwrap(),
+            connection_ids: vec![vec![0x01; 8]],
+            created: Instant::now(),
+            last_activity: Instant::now(),
+            idle_timeout: Duration::from_secs(30),
+            protocol: UdpProtocol::Quic,
+            bytes_tx: 0,
+            bytes_rx: 0,
+            metrics: None,
+        };
+        table.insert(slot, id, session);
+        assert!(table.reserve(1).is_none());
+        table.remove(id).unwrap();
+        assert!(table.reserve(1).is_some());
+    }
+
     #[test]
     fn test_quic_sni_extraction_too_small() {
         let small_packet = vec![0xC0; 10];
@@ -531,7 +3095,18 @@ mod tests {
 
     #[test]
     fn test_quic_sni_extraction_no_sni() {
-        // Valid QUIC structure but no SNI
+        // Correctly protected Initial whose ClientHello has no extensions
+        let hello = client_hello(None);
+        let packet = quic::build_client_initial(quic::QUIC_V1, &[0x5a; 8], 0, &hello);
+
+        let result = extract_quic_sni(&packet);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("No valid SNI"));
+    }
+
+    #[test]
+    fn test_quic_sni_extraction_plaintext_payload() {
+        // A ClientHello sent without packet protection must not be accepted
         let mut packet = vec![
             0xC0, // Long header
             0x00, 0x00, 0x00, 0x01, // Version
@@ -540,12 +3115,166 @@ mod tests {
         packet.extend_from_slice(&[0; 8]); // DCID
         packet.push(0x00); // SCID Length = 0
         packet.push(0x00); // Token Length = 0
-        packet.extend_from_slice(&[0x00, 0x10]); // Length field
-        packet.extend_from_slice(&[0; 50]); // Payload without TLS
+        packet.extend_from_slice(&[0x44, 0x9e]); // Length = 1182 (varint)
+        packet.extend_from_slice(&client_hello(Some("example.com")));
+        packet.resize(1200, 0);
 
         let result = extract_quic_sni(&packet);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("No valid SNI"));
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("decryption failed")
+        );
+    }
+
+    #[test]
+    fn test_quic_sni_extraction_encrypted_initial() {
+        let hello = client_hello(Some("quic.example.com"));
+        for version in [quic::QUIC_V1, quic::QUIC_V2] {
+            let packet = quic::build_client_initial(version, &[0x5a; 8], 0, &hello);
+            assert_eq!(extract_quic_sni(&packet).unwrap(), "quic.example.com");
+        }
+    }
+
+    /// CRYPTO frame holding a minimal ClientHello, with SNI if given
+    fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        quic::crypto_frame(0, &client_hello_message(sni))
+    }
+
+    /// Minimal ClientHello handshake message, with SNI if given
+    fn client_hello_message(sni: Option<&str>) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(name) = sni {
+            let name = name.as_bytes();
+            extensions.extend_from_slice(&[0x00, 0x00]);
+            extensions.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+            extensions.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+            extensions.push(0x00);
+            extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(name);
+        }
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]); // Random
+        body.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut hello = vec![0x01, 0x00];
+        hello.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&body);
+        hello
+    }
+
+    #[test]
+    fn test_buffer_initial_reassembles_split_client_hello() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let addr: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let dcid = [0x11; 8];
+        let hello = client_hello_message(Some("split.example.com"));
+        let first = quic::build_client_initial(
+            quic::QUIC_V1,
+            &dcid,
+            0,
+            &quic::crypto_frame(0, &hello[..40]),
+        );
+        let second = quic::build_client_initial(
+            quic::QUIC_V1,
+            &dcid,
+            1,
+            &quic::crypto_frame(40, &hello[40..]),
+        );
+
+        // The tail arrives first: nothing to route yet
+        assert!(handler.buffer_initial(addr, &second).unwrap().is_none());
+        assert_eq!(handler.pending.len(), 1);
+
+        let handshake = handler.buffer_initial(addr, &first).unwrap().unwrap();
+        assert_eq!(handshake.sni, "split.example.com");
+        assert_eq!(handshake.datagrams, vec![second, first]);
+        assert!(handler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_initial_keeps_other_packets_while_pending() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let addr: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let dcid = [0x22; 8];
+        let hello = client_hello_message(Some("zero-rtt.example.com"));
+        // v1 0-RTT long header, opaque to the proxy
+        let mut zero_rtt = vec![0xd0, 0x00, 0x00, 0x00, 0x01, 0x08];
+        zero_rtt.extend_from_slice(&dcid);
+        zero_rtt.extend_from_slice(&[0x00; 40]);
+
+        assert!(handler.buffer_initial(addr, &zero_rtt).is_err());
+        assert!(handler.pending.is_empty());
+
+        let first = quic::build_client_initial(
+            quic::QUIC_V1,
+            &dcid,
+            0,
+            &quic::crypto_frame(0, &hello[..20]),
+        );
+        let second = quic::build_client_initial(
+            quic::QUIC_V1,
+            &dcid,
+            1,
+            &quic::crypto_frame(20, &hello[20..]),
+        );
+        assert!(handler.buffer_initial(addr, &first).unwrap().is_none());
+        assert!(handler.buffer_initial(addr, &zero_rtt).unwrap().is_none());
+        let handshake = handler.buffer_initial(addr, &second).unwrap().unwrap();
+        assert_eq!(handshake.datagrams, vec![first, zero_rtt, second]);
+    }
+
+    #[test]
+    fn test_buffer_initial_limits() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let addr: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let hello = client_hello_message(Some("example.com"));
+        let fragment = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x33; 8],
+            0,
+            &quic::crypto_frame(0, &hello[..10]),
+        );
+
+        for _ in 0..MAX_PENDING_DATAGRAMS {
+            assert!(handler.buffer_initial(addr, &fragment).unwrap().is_none());
+        }
+        let err = handler.buffer_initial(addr, &fragment).unwrap_err();
+        assert!(err.to_string().contains("limits"));
+        assert!(handler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_initial_restarts_on_new_dcid_and_timeout() {
+        let handler = UdpConnectionHandler::new(create_test_config(), None);
+        let addr: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let hello = client_hello_message(Some("retry.example.com"));
+        let fragment = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x44; 8],
+            0,
+            &quic::crypto_frame(0, &hello[..10]),
+        );
+        let complete = quic::build_client_initial(
+            quic::QUIC_V1,
+            &[0x55; 8],
+            0,
+            &client_hello(Some("retry.example.com")),
+        );
+
+        assert!(handler.buffer_initial(addr, &fragment).unwrap().is_none());
+        let handshake = handler.buffer_initial(addr, &complete).unwrap().unwrap();
+        assert_eq!(handshake.datagrams, vec![complete]);
+
+        // Stale pending handshakes are dropped by cleanup
+        assert!(handler.buffer_initial(addr, &fragment).unwrap().is_none());
+        handler.pending.get_mut(&addr).unwrap().started = Instant::now() - Duration::from_secs(60);
+        handler.cleanup_sessions();
+        assert!(handler.pending.is_empty());
     }
 
     fn create_test_config() -> Config {
@@ -0,0 +1,1126 @@
+//! Frame-aware relaying for WebSocket connections (RFC 6455)
+//!
+//! Once a WebSocket handshake completes, the proxy relays the connection frame
+//! by frame instead of copying opaque bytes. That lets it count frames and
+//! messages by opcode, refuse messages over a size limit, and keep quiet
+//! connections alive with pings before closing them as idle. Frames are passed
+//! on unchanged, masking and extension bits included.
+//!
+//! When the proxy negotiated permessage-deflate with the client itself, the
+//! backend knows nothing of it: client messages are collected, decompressed
+//! and sent on masked, and server messages are compressed for the client.
+
+use crate::websocket_compression::{
+    CompressionStats, MessageDeflater, MessageInflater, WebSocketCompression,
+};
+use prometheus::{IntCounter, IntCounterVec, Opts, Registry};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::Mutex as StdMutex;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant, sleep_until};
+use tracing::debug;
+
+const READ_BUFFER_SIZE: usize = 16384;
+/// Largest payload of a control frame (RFC 6455 §5.5)
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+// Close status codes (RFC 6455 §7.4.1)
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+/// Frame opcodes (RFC 6455 §5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    /// Opcodes reserved for extensions; 0xB-0xF are control frames
+    Reserved(u8),
+}
+
+impl Opcode {
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xA => Opcode::Pong,
+            other => Opcode::Reserved(other),
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+            Opcode::Reserved(bits) => *bits,
+        }
+    }
+
+    /// Returns the opcode name used as a metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Opcode::Continuation => "continuation",
+            Opcode::Text => "text",
+            Opcode::Binary => "binary",
+            Opcode::Close => "close",
+            Opcode::Ping => "ping",
+            Opcode::Pong => "pong",
+            Opcode::Reserved(_) => "reserved",
+        }
+    }
+
+    /// Control frames may appear between the fragments of a message
+    pub fn is_control(&self) -> bool {
+        self.bits() & 0x8 != 0
+    }
+}
+
+/// The fixed part of a frame, before its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub fin: bool,
+    /// Marks the first frame of a compressed message (RFC 7692 §6)
+    pub rsv1: bool,
+    pub opcode: Opcode,
+    pub masked: bool,
+    pub payload_len: u64,
+    /// Encoded length of the header, masking key included
+    pub header_len: usize,
+}
+
+impl FrameHeader {
+    /// Parses the header at the start of `buf`, or `None` if it is incomplete
+    pub fn parse(buf: &[u8]) -> Result<Option<Self>, WebSocketError> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buf[0] & 0x80 != 0;
+        let rsv1 = buf[0] & 0x40 != 0;
+        let opcode = Opcode::from_bits(buf[0] & 0x0F);
+        let masked = buf[1] & 0x80 != 0;
+        let (payload_len, mut header_len) = match buf[1] & 0x7F {
+            126 if buf.len() < 4 => return Ok(None),
+            126 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+            127 if buf.len() < 10 => return Ok(None),
+            127 => {
+                let length = u64::from_be_bytes(buf[2..10].try_into().unwrap());
+                if length >> 63 != 0 {
+                    return Err(WebSocketError::Protocol("payload length out of range"));
+                }
+                (length, 10)
+            }
+            length => (length as u64, 2),
+        };
+        if masked {
+            header_len += 4;
+        }
+        if buf.len() < header_len {
+            return Ok(None);
+        }
+
+        if opcode.is_control() && (!fin || payload_len > MAX_CONTROL_PAYLOAD) {
+            return Err(WebSocketError::Protocol(
+                "fragmented or oversized control frame",
+            ));
+        }
+        Ok(Some(Self {
+            fin,
+            rsv1,
+            opcode,
+            masked,
+            payload_len,
+            header_len,
+        }))
+    }
+}
+
+/// Encodes an unfragmented frame sent by the proxy itself
+///
+/// Frames to the server must be masked (RFC 6455 §5.3), frames to the client
+/// must not. `compressed` sets RSV1.
+fn encode_frame(opcode: Opcode, compressed: bool, payload: &[u8], masked: bool) -> Vec<u8> {
+    let mut frame = vec![0x80 | if compressed { 0x40 } else { 0 } | opcode.bits()];
+    let mask_bit = if masked { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if masked {
+        let mut key = [0u8; 4];
+        SystemRandom::new()
+            .fill(&mut key)
+            .expect("system random generator failed");
+        frame.extend_from_slice(&key);
+        frame.extend(payload.iter().zip(key.iter().cycle()).map(|(b, k)| b ^ k));
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    frame
+}
+
+/// Encodes a Close frame with a status code and reason
+fn close_frame(code: u16, reason: &str, masked: bool) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    encode_frame(Opcode::Close, false, &payload, masked)
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    /// A frame broke RFC 6455
+    Protocol(&'static str),
+    /// A message grew past the configured limit
+    MessageTooBig(u64),
+}
+
+impl std::fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebSocketError::Io(e) => write!(f, "IO error: {}", e),
+            WebSocketError::Protocol(reason) => write!(f, "WebSocket protocol error: {}", reason),
+            WebSocketError::MessageTooBig(limit) => {
+                write!(f, "WebSocket message larger than {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl From<io::Error> for WebSocketError {
+    fn from(err: io::Error) -> Self {
+        WebSocketError::Io(err)
+    }
+}
+
+/// Limits and keep-alive for one tunnel
+#[derive(Debug, Clone)]
+pub struct TunnelSettings {
+    /// Largest message, summed over its fragments, in bytes
+    pub max_message_size: u64,
+    /// Close the tunnel when a side sends nothing for this long
+    pub idle_timeout: Duration,
+    /// Ping a side after it has been quiet this long
+    pub ping_interval: Option<Duration>,
+}
+
+impl From<&sniproxy_config::WebSocket> for TunnelSettings {
+    fn from(config: &sniproxy_config::WebSocket) -> Self {
+        Self {
+            max_message_size: config.max_message_size,
+            idle_timeout: Duration::from_secs(config.idle_timeout.max(1)),
+            ping_interval: (config.ping_interval > 0)
+                .then_some(Duration::from_secs(config.ping_interval)),
+        }
+    }
+}
+
+/// Prometheus counters shared by all WebSocket tunnels
+#[derive(Clone)]
+pub struct WebSocketMetrics {
+    frames: IntCounterVec,
+    messages: IntCounterVec,
+    compression_bytes: IntCounterVec,
+    compression_saved: IntCounterVec,
+    compression_messages: IntCounterVec,
+}
+
+impl WebSocketMetrics {
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let frames = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_websocket_frames_total",
+                "WebSocket frames relayed by direction and opcode",
+            ),
+            &["direction", "opcode"],
+        )?;
+        let messages = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_websocket_messages_total",
+                "Complete WebSocket messages relayed by direction and type",
+            ),
+            &["direction", "type"],
+        )?;
+        let compression_bytes = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_websocket_compression_bytes_total",
+                "Payload bytes of messages on proxy-compressed links, before and after compression",
+            ),
+            &["direction", "form"],
+        )?;
+        let compression_saved = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_websocket_compression_saved_bytes_total",
+                "Client link bytes saved by permessage-deflate the proxy negotiated",
+            ),
+            &["direction"],
+        )?;
+        let compression_messages = IntCounterVec::new(
+            Opts::new(
+                "sniproxy_websocket_compression_messages_total",
+                "Messages on proxy-compressed links by whether they were compressed",
+            ),
+            &["direction", "result"],
+        )?;
+        registry.register(Box::new(frames.clone()))?;
+        registry.register(Box::new(messages.clone()))?;
+        registry.register(Box::new(compression_bytes.clone()))?;
+        registry.register(Box::new(compression_saved.clone()))?;
+        registry.register(Box::new(compression_messages.clone()))?;
+        Ok(Self {
+            frames,
+            messages,
+            compression_bytes,
+            compression_saved,
+            compression_messages,
+        })
+    }
+
+    /// Adds the outcome of compressing or decompressing a message
+    fn record_compression(&self, direction: Direction, stats: &CompressionStats) {
+        let direction = direction.as_str();
+        for (form, bytes) in [
+            ("uncompressed", stats.bytes_in),
+            ("compressed", stats.bytes_out),
+        ] {
+            self.compression_bytes
+                .with_label_values(&[direction, form])
+                .inc_by(bytes as u64);
+        }
+        self.compression_saved
+            .with_label_values(&[direction])
+            .inc_by(stats.bytes_saved() as u64);
+        for (result, messages) in [
+            ("compressed", stats.messages_compressed),
+            ("uncompressed", stats.messages_uncompressed),
+        ] {
+            self.compression_messages
+                .with_label_values(&[direction, result])
+                .inc_by(messages as u64);
+        }
+    }
+}
+
+/// What the backend's 101 must carry to complete a client's handshake
+pub struct Handshake {
+    /// `Sec-WebSocket-Accept` computed from the client's key
+    pub accept: String,
+    /// permessage-deflate agreed with the client and the extension header
+    /// answering it, used only if the backend accepts no extension itself
+    pub compression: Option<(WebSocketCompression, String)>,
+}
+
+/// Which way frames travel; labels match `sniproxy_bytes_transferred_total`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Client to server
+    Tx,
+    /// Server to client
+    Rx,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Tx => "tx",
+            Direction::Rx => "rx",
+        }
+    }
+}
+
+/// Write half of one side, shared between its relay and the keep-alive
+struct FrameWriter<W> {
+    inner: W,
+    /// A frame is partly written; nothing else may be sent on this side
+    partial: bool,
+}
+
+/// permessage-deflate the proxy applies to one direction
+enum Codec {
+    /// Compress server messages for the client
+    Deflate(MessageDeflater),
+    /// Decompress client messages for the server
+    Inflate(MessageInflater),
+}
+
+impl Codec {
+    /// Encodes a complete message for the other side, with its compression stats
+    fn encode(
+        &mut self,
+        opcode: Opcode,
+        payload: &[u8],
+        compressed: bool,
+        limit: u64,
+    ) -> Result<(Vec<u8>, CompressionStats), WebSocketError> {
+        let mut stats = CompressionStats::default();
+        let frame = match self {
+            Codec::Deflate(deflater) if deflater.should_compress(payload.len()) => {
+                let deflated = deflater.compress(payload)?;
+                stats.add_compressed(payload.len(), deflated.len());
+                encode_frame(opcode, true, &deflated, false)
+            }
+            Codec::Inflate(inflater) if compressed => {
+                let inflated = inflater
+                    .decompress(payload, usize::try_from(limit).unwrap_or(usize::MAX))
+                    .map_err(|_| WebSocketError::Protocol("invalid compressed message"))?
+                    .ok_or(WebSocketError::MessageTooBig(limit))?;
+                stats.add_compressed(inflated.len(), payload.len());
+                encode_frame(opcode, false, &inflated, true)
+            }
+            codec => {
+                stats.add_uncompressed(payload.len());
+                encode_frame(opcode, false, payload, matches!(codec, Codec::Inflate(_)))
+            }
+        };
+        Ok((frame, stats))
+    }
+}
+
+/// Where one direction's frames go and what is done to them
+struct RelaySide<'a, W> {
+    writer: &'a Mutex<FrameWriter<W>>,
+    direction: Direction,
+    last_seen: &'a StdMutex<Instant>,
+    /// Bytes written to `writer`
+    counter: Option<&'a IntCounter>,
+    /// Set when the proxy compresses this direction's link to the client
+    codec: Option<Codec>,
+}
+
+/// Relays a WebSocket connection frame by frame until both sides close it
+///
+/// `client` and `server` carry the connection after the 101 response.
+/// `compression` is the permessage-deflate the proxy agreed with the client,
+/// if any. `bytes` are the tunnel's (tx, rx) byte counters. A message over the
+/// size limit, a protocol violation or an idle side ends the tunnel with a
+/// Close frame to both sides.
+pub async fn tunnel<C, S>(
+    client: C,
+    server: S,
+    settings: &TunnelSettings,
+    compression: Option<&WebSocketCompression>,
+    metrics: Option<&WebSocketMetrics>,
+    bytes: Option<(IntCounter, IntCounter)>,
+) -> Result<(), WebSocketError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (client_reader, client_writer) = io::split(client);
+    let (server_reader, server_writer) = io::split(server);
+    let client_writer = Mutex::new(FrameWriter {
+        inner: client_writer,
+        partial: false,
+    });
+    let server_writer = Mutex::new(FrameWriter {
+        inner: server_writer,
+        partial: false,
+    });
+    let last_seen = [StdMutex::new(Instant::now()), StdMutex::new(Instant::now())];
+    let (tx, rx) = bytes.unzip();
+    let (inflate, deflate) = compression
+        .map(|compression| {
+            (
+                Codec::Inflate(compression.inflater()),
+                Codec::Deflate(compression.deflater()),
+            )
+        })
+        .unzip();
+
+    let relays = async {
+        tokio::try_join!(
+            relay(
+                client_reader,
+                RelaySide {
+                    writer: &server_writer,
+                    direction: Direction::Tx,
+                    last_seen: &last_seen[0],
+                    counter: tx.as_ref(),
+                    codec: inflate,
+                },
+                settings,
+                metrics,
+            ),
+            relay(
+                server_reader,
+                RelaySide {
+                    writer: &client_writer,
+                    direction: Direction::Rx,
+                    last_seen: &last_seen[1],
+                    counter: rx.as_ref(),
+                    codec: deflate,
+                },
+                settings,
+                metrics,
+            ),
+        )
+    };
+    let (code, reason, result) = tokio::select! {
+        result = relays => match result {
+            Ok(_) => return Ok(()),
+            Err(WebSocketError::MessageTooBig(limit)) => (
+                CLOSE_MESSAGE_TOO_BIG,
+                "message too big",
+                Err(WebSocketError::MessageTooBig(limit)),
+            ),
+            Err(WebSocketError::Protocol(reason)) => (
+                CLOSE_PROTOCOL_ERROR,
+                "protocol error",
+                Err(WebSocketError::Protocol(reason)),
+            ),
+            Err(e) => return Err(e),
+        },
+        direction = keep_alive(&client_writer, &server_writer, &last_seen, settings) => {
+            debug!(side = direction.as_str(), "Closing idle WebSocket tunnel");
+            (CLOSE_GOING_AWAY, "idle timeout", Ok(()))
+        }
+    };
+
+    // Both sides learn why the tunnel ends, unless a frame was cut short
+    let mut client_writer = client_writer.into_inner();
+    if !client_writer.partial {
+        let _ = client_writer
+            .inner
+            .write_all(&close_frame(code, reason, false))
+            .await;
+    }
+    let _ = client_writer.inner.shutdown().await;
+    let mut server_writer = server_writer.into_inner();
+    if !server_writer.partial {
+        let _ = server_writer
+            .inner
+            .write_all(&close_frame(code, reason, true))
+            .await;
+    }
+    let _ = server_writer.inner.shutdown().await;
+    result
+}
+
+/// Reads more bytes into `buffer`, returning how many were read
+async fn fill<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    last_seen: &StdMutex<Instant>,
+) -> io::Result<usize> {
+    let mut chunk = [0u8; READ_BUFFER_SIZE];
+    let n = reader.read(&mut chunk).await?;
+    buffer.extend_from_slice(&chunk[..n]);
+    if n > 0 {
+        *last_seen.lock().unwrap() = Instant::now();
+    }
+    Ok(n)
+}
+
+/// Relays the frames one side sends until it closes its connection
+async fn relay<R, W>(
+    mut reader: R,
+    side: RelaySide<'_, W>,
+    settings: &TunnelSettings,
+    metrics: Option<&WebSocketMetrics>,
+) -> Result<(), WebSocketError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let RelaySide {
+        writer,
+        direction,
+        last_seen,
+        counter,
+        mut codec,
+    } = side;
+    let mut buffer = Vec::with_capacity(READ_BUFFER_SIZE);
+    // Opcode and size so far of a fragmented message
+    let mut message: Option<(Opcode, u64)> = None;
+    // Unmasked payload so far of a message the codec rewrites, and its RSV1
+    let mut pending = Vec::new();
+    let mut compressed = false;
+
+    loop {
+        let header = loop {
+            if let Some(header) = FrameHeader::parse(&buffer)? {
+                break header;
+            }
+            if fill(&mut reader, &mut buffer, last_seen).await? == 0 {
+                if !buffer.is_empty() {
+                    return Err(WebSocketError::Protocol("connection closed mid-frame"));
+                }
+                writer.lock().await.inner.shutdown().await?;
+                return Ok(());
+            }
+        };
+
+        // Clients mask every frame and servers none (RFC 6455 §5.1)
+        if header.masked != (direction == Direction::Tx) {
+            return Err(WebSocketError::Protocol("wrong frame masking"));
+        }
+        // Under the proxy's own permessage-deflate only the client compresses,
+        // and only the first frame of a data message (RFC 7692 §6.1)
+        if header.rsv1
+            && let Some(codec) = &codec
+            && (matches!(codec, Codec::Deflate(_))
+                || header.opcode.is_control()
+                || header.opcode == Opcode::Continuation)
+        {
+            return Err(WebSocketError::Protocol("unexpected compressed frame"));
+        }
+
+        let completed = match header.opcode {
+            opcode if opcode.is_control() => None,
+            Opcode::Continuation => {
+                let Some((opcode, size)) = message.as_mut() else {
+                    return Err(WebSocketError::Protocol("continuation without a message"));
+                };
+                *size = size.saturating_add(header.payload_len);
+                if *size > settings.max_message_size {
+                    return Err(WebSocketError::MessageTooBig(settings.max_message_size));
+                }
+                header.fin.then_some(*opcode)
+            }
+            opcode => {
+                if message.is_some() {
+                    return Err(WebSocketError::Protocol("message interleaved with another"));
+                }
+                if header.payload_len > settings.max_message_size {
+                    return Err(WebSocketError::MessageTooBig(settings.max_message_size));
+                }
+                message = Some((opcode, header.payload_len));
+                header.fin.then_some(opcode)
+            }
+        };
+        if completed.is_some() {
+            message = None;
+        }
+        if let Some(metrics) = metrics {
+            metrics
+                .frames
+                .with_label_values(&[direction.as_str(), header.opcode.as_str()])
+                .inc();
+            if let Some(opcode) = completed {
+                metrics
+                    .messages
+                    .with_label_values(&[direction.as_str(), opcode.as_str()])
+                    .inc();
+            }
+        }
+
+        if let Some(codec) = codec.as_mut()
+            && !header.opcode.is_control()
+        {
+            // The message is re-encoded as a whole, so collect its payload
+            let end = header.header_len + header.payload_len as usize;
+            while buffer.len() < end {
+                if fill(&mut reader, &mut buffer, last_seen).await? == 0 {
+                    return Err(WebSocketError::Protocol("connection closed mid-frame"));
+                }
+            }
+            let (head, payload) = buffer[..end].split_at_mut(header.header_len);
+            if header.masked {
+                let key = &head[head.len() - 4..];
+                for (byte, k) in payload.iter_mut().zip(key.iter().cycle()) {
+                    *byte ^= k;
+                }
+            }
+            pending.extend_from_slice(payload);
+            if header.opcode != Opcode::Continuation {
+                compressed = header.rsv1;
+            }
+            buffer.drain(..end);
+
+            let Some(opcode) = completed else {
+                continue;
+            };
+            let (frame, stats) =
+                codec.encode(opcode, &pending, compressed, settings.max_message_size)?;
+            pending.clear();
+            if let Some(metrics) = metrics {
+                metrics.record_compression(direction, &stats);
+            }
+            let mut out = writer.lock().await;
+            out.partial = true;
+            out.inner.write_all(&frame).await?;
+            out.inner.flush().await?;
+            out.partial = false;
+            if let Some(counter) = counter {
+                counter.inc_by(frame.len() as u64);
+            }
+            continue;
+        }
+
+        // Stream the frame through without holding all of it in memory
+        let mut out = writer.lock().await;
+        out.partial = true;
+        let mut remaining = header.header_len as u64 + header.payload_len;
+        while remaining > 0 {
+            if buffer.is_empty() && fill(&mut reader, &mut buffer, last_seen).await? == 0 {
+                return Err(WebSocketError::Protocol("connection closed mid-frame"));
+            }
+            let n = (buffer.len() as u64).min(remaining) as usize;
+            out.inner.write_all(&buffer[..n]).await?;
+            if let Some(counter) = counter {
+                counter.inc_by(n as u64);
+            }
+            buffer.drain(..n);
+            remaining -= n as u64;
+        }
+        out.inner.flush().await?;
+        out.partial = false;
+    }
+}
+
+/// Pings quiet sides and returns the first one idle past the timeout
+///
+/// Any frame a side sends, including the Pong to a ping, counts as activity.
+async fn keep_alive<C, S>(
+    client_writer: &Mutex<FrameWriter<C>>,
+    server_writer: &Mutex<FrameWriter<S>>,
+    last_seen: &[StdMutex<Instant>; 2],
+    settings: &TunnelSettings,
+) -> Direction
+where
+    C: AsyncWrite + Unpin,
+    S: AsyncWrite + Unpin,
+{
+    let mut pinged = [None::<Instant>; 2];
+    loop {
+        let now = Instant::now();
+        let mut wake = now + settings.idle_timeout;
+        for (side, direction) in [Direction::Tx, Direction::Rx].into_iter().enumerate() {
+            let seen = *last_seen[side].lock().unwrap();
+            if now >= seen + settings.idle_timeout {
+                return direction;
+            }
+            wake = wake.min(seen + settings.idle_timeout);
+
+            let Some(interval) = settings.ping_interval else {
+                continue;
+            };
+            let due = pinged[side].map_or(seen, |pinged| pinged.max(seen)) + interval;
+            if now < due {
+                wake = wake.min(due);
+                continue;
+            }
+            // Pings go to the side that has been quiet
+            let sent = match direction {
+                Direction::Tx => send_ping(client_writer, false).await,
+                Direction::Rx => send_ping(server_writer, true).await,
+            };
+            if let Err(e) = sent {
+                debug!(side = direction.as_str(), error = %e, "WebSocket ping failed");
+            }
+            pinged[side] = Some(now);
+            wake = wake.min(now + interval);
+        }
+        sleep_until(wake).await;
+    }
+}
+
+async fn send_ping<W: AsyncWrite + Unpin>(
+    writer: &Mutex<FrameWriter<W>>,
+    masked: bool,
+) -> io::Result<()> {
+    let mut out = writer.lock().await;
+    out.inner
+        .write_all(&encode_frame(Opcode::Ping, false, b"sniproxy", masked))
+        .await?;
+    out.inner.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{DuplexStream, duplex};
+
+    fn settings() -> TunnelSettings {
+        TunnelSettings {
+            max_message_size: 1024,
+            idle_timeout: Duration::from_secs(60),
+            ping_interval: None,
+        }
+    }
+
+    /// Encodes a data frame as a client (masked) or server (unmasked) would
+    fn frame(fin: bool, opcode: u8, payload: &[u8], masked: bool) -> Vec<u8> {
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        let mask_bit = if masked { 0x80 } else { 0 };
+        match payload.len() {
+            len if len < 126 => frame.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        if masked {
+            let key = [1, 2, 3, 4];
+            frame.extend_from_slice(&key);
+            frame.extend(payload.iter().zip(key.iter().cycle()).map(|(b, k)| b ^ k));
+        } else {
+            frame.extend_from_slice(payload);
+        }
+        frame
+    }
+
+    /// Reads one frame, returning its unmasked payload
+    async fn read_frame(stream: &mut DuplexStream) -> (FrameHeader, Vec<u8>) {
+        let mut buffer = Vec::new();
+        loop {
+            if let Some(header) = FrameHeader::parse(&buffer).unwrap() {
+                let mut payload = vec![0u8; header.payload_len as usize];
+                stream.read_exact(&mut payload).await.unwrap();
+                if header.masked {
+                    let key = &buffer[header.header_len - 4..];
+                    for (byte, k) in payload.iter_mut().zip(key.iter().cycle()) {
+                        *byte ^= k;
+                    }
+                }
+                return (header, payload);
+            }
+            let mut byte = [0u8; 1];
+            assert_eq!(stream.read(&mut byte).await.unwrap(), 1, "stream closed");
+            buffer.push(byte[0]);
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_header_lengths() {
+        let header = FrameHeader::parse(&frame(true, 0x1, b"hello", true))
+            .unwrap()
+            .unwrap();
+        assert!(header.fin && header.masked);
+        assert_eq!(header.opcode, Opcode::Text);
+        assert_eq!((header.payload_len, header.header_len), (5, 6));
+
+        let header = FrameHeader::parse(&frame(false, 0x2, &[0; 300], false))
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.opcode, Opcode::Binary);
+        assert_eq!((header.payload_len, header.header_len), (300, 4));
+
+        let header = FrameHeader::parse(&frame(true, 0x2, &[0; 70000], false))
+            .unwrap()
+            .unwrap();
+        assert_eq!((header.payload_len, header.header_len), (70000, 10));
+
+        // Incomplete headers need more bytes
+        assert!(FrameHeader::parse(&[0x81]).unwrap().is_none());
+        assert!(FrameHeader::parse(&[0x82, 0xFE, 0x01]).unwrap().is_none());
+        assert!(FrameHeader::parse(&[0x81, 0x85, 1, 2]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_frame_header_rejects_bad_control_frames() {
+        // Control frames can't be fragmented or carry more than 125 bytes
+        assert!(FrameHeader::parse(&frame(false, 0x9, b"", false)).is_err());
+        assert!(FrameHeader::parse(&frame(true, 0x8, &[0; 126], false)).is_err());
+        // The most significant bit of a 64-bit length must be zero
+        let mut header = vec![0x82, 127];
+        header.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(FrameHeader::parse(&header).is_err());
+    }
+
+    #[test]
+    fn test_control_frame_masking() {
+        let frame = encode_frame(Opcode::Ping, false, b"abc", true);
+        let header = FrameHeader::parse(&frame).unwrap().unwrap();
+        assert!(header.masked);
+        let key = &frame[2..6];
+        let payload: Vec<u8> = frame[6..]
+            .iter()
+            .zip(key.iter().cycle())
+            .map(|(b, k)| b ^ k)
+            .collect();
+        assert_eq!(payload, b"abc");
+
+        assert_eq!(close_frame(1009, "big", false), b"\x88\x05\x03\xf1big");
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_relays_and_counts_frames() {
+        let (client, mut client_peer) = duplex(65536);
+        let (server, mut server_peer) = duplex(65536);
+        let registry = Registry::new();
+        let metrics = WebSocketMetrics::new(&registry).unwrap();
+        let bytes = (
+            IntCounter::new("tx", "tx").unwrap(),
+            IntCounter::new("rx", "rx").unwrap(),
+        );
+
+        let settings = settings();
+        let task_metrics = metrics.clone();
+        let task_bytes = bytes.clone();
+        let tunnel = tokio::spawn(async move {
+            tunnel(
+                client,
+                server,
+                &settings,
+                None,
+                Some(&task_metrics),
+                Some(task_bytes),
+            )
+            .await
+        });
+
+        // A fragmented text message with a ping between its fragments
+        let sent = [
+            frame(false, 0x1, b"hel", true),
+            frame(true, 0x9, b"", true),
+            frame(true, 0x0, b"lo", true),
+        ]
+        .concat();
+        client_peer.write_all(&sent).await.unwrap();
+        let mut relayed = vec![0u8; sent.len()];
+        server_peer.read_exact(&mut relayed).await.unwrap();
+        assert_eq!(relayed, sent);
+
+        let reply = frame(true, 0x2, &[7; 200], false);
+        server_peer.write_all(&reply).await.unwrap();
+        let (header, payload) = read_frame(&mut client_peer).await;
+        assert_eq!(header.opcode, Opcode::Binary);
+        assert_eq!(payload, vec![7; 200]);
+
+        drop(client_peer);
+        drop(server_peer);
+        tunnel.await.unwrap().unwrap();
+
+        let frames = |direction: &str, opcode: &str| {
+            metrics.frames.with_label_values(&[direction, opcode]).get()
+        };
+        assert_eq!(frames("tx", "text"), 1);
+        assert_eq!(frames("tx", "ping"), 1);
+        assert_eq!(frames("tx", "continuation"), 1);
+        assert_eq!(frames("rx", "binary"), 1);
+        assert_eq!(metrics.messages.with_label_values(&["tx", "text"]).get(), 1);
+        assert_eq!(
+            metrics.messages.with_label_values(&["rx", "binary"]).get(),
+            1
+        );
+        assert_eq!(bytes.0.get(), sent.len() as u64);
+        assert_eq!(bytes.1.get(), reply.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_closes_on_message_too_big() {
+        let (client, mut client_peer) = duplex(65536);
+        let (server, mut server_peer) = duplex(65536);
+        let settings = settings();
+        let tunnel =
+            tokio::spawn(async move { tunnel(client, server, &settings, None, None, None).await });
+
+        // Fragments adding up to more than the limit
+        client_peer
+            .write_all(&frame(false, 0x2, &[0; 1000], true))
+            .await
+            .unwrap();
+        let mut relayed = vec![0u8; 1008];
+        server_peer.read_exact(&mut relayed).await.unwrap();
+        client_peer
+            .write_all(&frame(true, 0x0, &[0; 100], true))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            tunnel.await.unwrap(),
+            Err(WebSocketError::MessageTooBig(1024))
+        ));
+        let (header, payload) = read_frame(&mut client_peer).await;
+        assert_eq!(header.opcode, Opcode::Close);
+        assert!(!header.masked);
+        assert_eq!(&payload[..2], &CLOSE_MESSAGE_TOO_BIG.to_be_bytes());
+        let (header, _) = read_frame(&mut server_peer).await;
+        assert_eq!(header.opcode, Opcode::Close);
+        assert!(header.masked);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_rejects_unmasked_client_frames() {
+        let (client, mut client_peer) = duplex(65536);
+        let (server, _server_peer) = duplex(65536);
+        let settings = settings();
+        let tunnel =
+            tokio::spawn(async move { tunnel(client, server, &settings, None, None, None).await });
+
+        client_peer
+            .write_all(&frame(true, 0x1, b"hi", false))
+            .await
+            .unwrap();
+        assert!(matches!(
+            tunnel.await.unwrap(),
+            Err(WebSocketError::Protocol(_))
+        ));
+        let (_, payload) = read_frame(&mut client_peer).await;
+        assert_eq!(&payload[..2], &CLOSE_PROTOCOL_ERROR.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_pings_quiet_side_and_closes_when_idle() {
+        let (client, mut client_peer) = duplex(65536);
+        let (server, mut server_peer) = duplex(65536);
+        let settings = TunnelSettings {
+            max_message_size: 1024,
+            idle_timeout: Duration::from_millis(600),
+            ping_interval: Some(Duration::from_millis(200)),
+        };
+        let tunnel =
+            tokio::spawn(async move { tunnel(client, server, &settings, None, None, None).await });
+
+        // The client answers pings, so only the server goes idle
+        let (header, payload) = read_frame(&mut client_peer).await;
+        assert_eq!(header.opcode, Opcode::Ping);
+        assert!(!header.masked);
+        client_peer
+            .write_all(&frame(true, 0xA, &payload, true))
+            .await
+            .unwrap();
+        let (header, _) = read_frame(&mut server_peer).await;
+        assert_eq!(header.opcode, Opcode::Ping);
+        assert!(header.masked);
+
+        tunnel.await.unwrap().unwrap();
+
+        // Both sides see further pings, then the close; the server also got the pong
+        let mut server_opcodes = Vec::new();
+        for stream in [&mut client_peer, &mut server_peer] {
+            let payload = loop {
+                let (header, payload) = read_frame(stream).await;
+                if header.opcode == Opcode::Close {
+                    break payload;
+                }
+                assert!(matches!(header.opcode, Opcode::Ping | Opcode::Pong));
+                server_opcodes.push(header.opcode);
+            };
+            assert_eq!(&payload[..2], &CLOSE_GOING_AWAY.to_be_bytes());
+        }
+        assert!(server_opcodes.contains(&Opcode::Pong));
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_compresses_client_link() {
+        use crate::websocket_compression::WebSocketCompressionConfig;
+
+        let (client, mut client_peer) = duplex(65536);
+        let (server, mut server_peer) = duplex(65536);
+        let registry = Registry::new();
+        let metrics = WebSocketMetrics::new(&registry).unwrap();
+        let compression = WebSocketCompression::new(WebSocketCompressionConfig {
+            min_compress_size: 64,
+            ..Default::default()
+        });
+        // The client's side of the agreed extension
+        let mut client_deflater = compression.deflater();
+        let mut client_inflater = compression.inflater();
+
+        let settings = settings();
+        let task_metrics = metrics.clone();
+        let tunnel = tokio::spawn(async move {
+            tunnel(
+                client,
+                server,
+                &settings,
+                Some(&compression),
+                Some(&task_metrics),
+                None,
+            )
+            .await
+        });
+
+        // A compressed client message reaches the server decompressed and masked
+        let text = "compress me ".repeat(40);
+        let mut sent = frame(
+            true,
+            0x1,
+            &client_deflater.compress(text.as_bytes()).unwrap(),
+            true,
+        );
+        sent[0] |= 0x40;
+        client_peer.write_all(&sent).await.unwrap();
+        let (header, payload) = read_frame(&mut server_peer).await;
+        assert!(!header.rsv1 && header.masked && header.fin);
+        assert_eq!(header.opcode, Opcode::Text);
+        assert_eq!(payload, text.as_bytes());
+
+        // A fragmented server message reaches the client as one compressed frame
+        let reply = "echo ".repeat(100);
+        server_peer
+            .write_all(&frame(false, 0x1, &reply.as_bytes()[..200], false))
+            .await
+            .unwrap();
+        server_peer
+            .write_all(&frame(true, 0x0, &reply.as_bytes()[200..], false))
+            .await
+            .unwrap();
+        let (header, payload) = read_frame(&mut client_peer).await;
+        assert!(header.rsv1 && !header.masked && header.fin);
+        assert_eq!(header.opcode, Opcode::Text);
+        let compressed_len = payload.len();
+        assert!(compressed_len < reply.len());
+        let decompressed = client_inflater.decompress(&payload, 4096).unwrap().unwrap();
+        assert_eq!(decompressed, reply.as_bytes());
+
+        // Small messages and control frames pass uncompressed
+        server_peer
+            .write_all(&frame(true, 0x2, b"tiny", false))
+            .await
+            .unwrap();
+        let (header, payload) = read_frame(&mut client_peer).await;
+        assert!(!header.rsv1);
+        assert_eq!(payload, b"tiny");
+
+        // Only the client may compress
+        let mut compressed_reply = frame(true, 0x1, b"x", false);
+        compressed_reply[0] |= 0x40;
+        server_peer.write_all(&compressed_reply).await.unwrap();
+        assert!(matches!(
+            tunnel.await.unwrap(),
+            Err(WebSocketError::Protocol(_))
+        ));
+
+        let compression_bytes = |direction: &str, form: &str| {
+            metrics
+                .compression_bytes
+                .with_label_values(&[direction, form])
+                .get()
+        };
+        assert_eq!(compression_bytes("tx", "uncompressed"), text.len() as u64);
+        assert!(compression_bytes("tx", "compressed") < text.len() as u64);
+        assert_eq!(
+            compression_bytes("rx", "uncompressed"),
+            (reply.len() + 4) as u64
+        );
+        assert_eq!(
+            metrics.compression_saved.with_label_values(&["rx"]).get(),
+            (reply.len() - compressed_len) as u64
+        );
+        let compression_messages = |result: &str| {
+            metrics
+                .compression_messages
+                .with_label_values(&["rx", result])
+                .get()
+        };
+        assert_eq!(compression_messages("compressed"), 1);
+        assert_eq!(compression_messages("uncompressed"), 1);
+    }
+}
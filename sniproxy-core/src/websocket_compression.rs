@@ -16,11 +16,20 @@
 //!
 //! The permessage-deflate extension compresses each WebSocket message independently
 //! using DEFLATE. The compressed data is sent with the RSV1 bit set in the frame header.
+//!
+//! [`WebSocketCompression::negotiate`] answers a client's offer, and the
+//! [`MessageDeflater`] and [`MessageInflater`] it hands out keep the DEFLATE
+//! context between messages unless the agreed parameters forbid it.
 
-use flate2::Compression;
 use flate2::read::DeflateDecoder;
 use flate2::write::DeflateEncoder;
-use std::io::{Read, Write};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io::{self, Read, Write};
+
+/// Ends every sync-flushed DEFLATE block; stripped from messages (RFC 7692 §7.2.1)
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+/// Output buffer growth while compressing or decompressing a message
+const CODEC_CHUNK_SIZE: usize = 16384;
 
 /// Configuration for WebSocket compression
 #[derive(Debug, Clone)]
@@ -55,7 +64,22 @@ impl Default for WebSocketCompressionConfig {
     }
 }
 
+impl From<&sniproxy_config::WebSocketCompression> for WebSocketCompressionConfig {
+    fn from(config: &sniproxy_config::WebSocketCompression) -> Self {
+        Self {
+            enabled: true,
+            compression_level: config.compression_level.min(9),
+            server_no_context_takeover: config.server_no_context_takeover,
+            client_no_context_takeover: config.client_no_context_takeover,
+            server_max_window_bits: config.server_max_window_bits.clamp(8, 15),
+            client_max_window_bits: config.client_max_window_bits.clamp(8, 15),
+            min_compress_size: config.min_compress_size,
+        }
+    }
+}
+
 /// WebSocket message compression handler
+#[derive(Debug, Clone)]
 pub struct WebSocketCompression {
     config: WebSocketCompressionConfig,
 }
@@ -190,6 +214,217 @@ impl WebSocketCompression {
     pub fn config(&self) -> &WebSocketCompressionConfig {
         &self.config
     }
+
+    /// Accepts the first permessage-deflate offer in a client's
+    /// `Sec-WebSocket-Extensions` header that this configuration can serve
+    ///
+    /// # Returns
+    /// * `Some((handler, response))` - A handler with the agreed parameters and
+    ///   the `Sec-WebSocket-Extensions` value to answer with
+    /// * `None` - If there is no offer, or every offer has unknown, duplicate or
+    ///   invalid parameters
+    pub fn negotiate(&self, offers: &str) -> Option<(WebSocketCompression, String)> {
+        if !self.config.enabled {
+            return None;
+        }
+        offers.split(',').find_map(|offer| self.accept_offer(offer))
+    }
+
+    fn accept_offer(&self, offer: &str) -> Option<(WebSocketCompression, String)> {
+        let mut params = offer.split(';').map(str::trim);
+        if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+            return None;
+        }
+
+        let mut agreed = self.config.clone();
+        let mut seen = Vec::new();
+        let mut server_window_requested = false;
+        // The client's window can only be limited if it says so (RFC 7692 §7.1.2.2)
+        let mut client_window = None;
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            let name = name.to_ascii_lowercase();
+            if seen.contains(&name) {
+                return None;
+            }
+            let window_bits = |value: Option<&str>| {
+                value
+                    .and_then(|bits| bits.parse::<u8>().ok())
+                    .filter(|bits| (8..=15).contains(bits))
+            };
+            match (name.as_str(), value) {
+                ("server_no_context_takeover", None) => agreed.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => agreed.client_no_context_takeover = true,
+                ("server_max_window_bits", value) => {
+                    agreed.server_max_window_bits =
+                        agreed.server_max_window_bits.min(window_bits(value)?);
+                    server_window_requested = true;
+                }
+                ("client_max_window_bits", None) => client_window = Some(15),
+                ("client_max_window_bits", value) => client_window = Some(window_bits(value)?),
+                _ => return None,
+            }
+            seen.push(name);
+        }
+        agreed.client_max_window_bits =
+            client_window.map_or(15, |bits| agreed.client_max_window_bits.min(bits));
+
+        let agreed = WebSocketCompression::new(agreed);
+        let mut response = agreed.extension_header();
+        // A requested server window is always answered, even at the maximum
+        if server_window_requested && agreed.config.server_max_window_bits == 15 {
+            response.push_str("; server_max_window_bits=15");
+        }
+        Some((agreed, response))
+    }
+
+    /// Compressor for messages sent to the client, under the server_* parameters
+    pub fn deflater(&self) -> MessageDeflater {
+        MessageDeflater {
+            compress: Compress::new(Compression::new(self.config.compression_level), false),
+            window_bits: self.config.server_max_window_bits,
+            no_context_takeover: self.config.server_no_context_takeover,
+            min_compress_size: self.config.min_compress_size,
+        }
+    }
+
+    /// Decompressor for messages received from the client
+    ///
+    /// A 15-bit window decodes every window size, so only context takeover matters.
+    pub fn inflater(&self) -> MessageInflater {
+        MessageInflater {
+            decompress: Decompress::new(false),
+            no_context_takeover: self.config.client_no_context_takeover,
+        }
+    }
+}
+
+/// Compresses the messages of one WebSocket connection in order
+///
+/// With context takeover, every message may refer back to the ones before it,
+/// so each compressed message must be sent.
+pub struct MessageDeflater {
+    compress: Compress,
+    window_bits: u8,
+    no_context_takeover: bool,
+    min_compress_size: usize,
+}
+
+impl MessageDeflater {
+    /// Check if a message is large enough to compress
+    pub fn should_compress(&self, size: usize) -> bool {
+        size >= self.min_compress_size
+    }
+
+    /// Compresses one message payload
+    pub fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        let mut compressed = Vec::with_capacity(data.len() / 2 + 64);
+        if self.window_bits < 15 {
+            // The DEFLATE backend always uses a 32 KiB window; a full flush
+            // every 2^bits bytes keeps back-references within the agreed one
+            for chunk in data.chunks(1 << self.window_bits) {
+                self.deflate(chunk, FlushCompress::Full, &mut compressed)?;
+            }
+        } else {
+            self.deflate(data, FlushCompress::Sync, &mut compressed)?;
+            if self.no_context_takeover {
+                self.compress.reset();
+            }
+        }
+
+        if compressed.ends_with(&DEFLATE_TAIL) {
+            compressed.truncate(compressed.len() - DEFLATE_TAIL.len());
+        }
+        // An empty message compresses to a single empty block (RFC 7692 §7.2.3.6)
+        if compressed.is_empty() {
+            compressed.push(0x00);
+        }
+        Ok(compressed)
+    }
+
+    fn deflate(
+        &mut self,
+        mut input: &[u8],
+        flush: FlushCompress,
+        output: &mut Vec<u8>,
+    ) -> Result<(), std::io::Error> {
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(CODEC_CHUNK_SIZE);
+            }
+            let before = self.compress.total_in();
+            self.compress
+                .compress_vec(input, output, flush)
+                .map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - before) as usize..];
+            // Room left over means the flush is complete
+            if input.is_empty() && output.len() < output.capacity() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Decompresses the messages of one WebSocket connection in order
+pub struct MessageInflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl MessageInflater {
+    /// Decompresses one message payload
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<u8>))` - The decompressed message
+    /// * `Ok(None)` - If the message would exceed `limit` bytes
+    /// * `Err` - If the payload is not valid DEFLATE data
+    pub fn decompress(
+        &mut self,
+        data: &[u8],
+        limit: usize,
+    ) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let input = [data, &DEFLATE_TAIL].concat();
+        let mut input = &input[..];
+        let mut decompressed = Vec::with_capacity((data.len() * 2).min(limit) + 64);
+        loop {
+            if decompressed.len() == decompressed.capacity() {
+                decompressed.reserve(CODEC_CHUNK_SIZE);
+            }
+            let (total_in, produced) = (self.decompress.total_in(), decompressed.len());
+            let status = self
+                .decompress
+                .decompress_vec(input, &mut decompressed, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            input = &input[consumed..];
+
+            if decompressed.len() > limit {
+                return Ok(None);
+            }
+            // A final block ends the context; the next message starts afresh
+            if status == Status::StreamEnd {
+                self.decompress.reset(false);
+                return Ok(Some(decompressed));
+            }
+            if input.is_empty() && decompressed.len() < decompressed.capacity() {
+                break;
+            }
+            if consumed == 0 && decompressed.len() == produced {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "truncated compressed message",
+                ));
+            }
+        }
+
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(Some(decompressed))
+    }
 }
 
 /// Statistics about WebSocket compression
@@ -466,4 +701,160 @@ mod tests {
             .expect("Decompression failed");
         assert_eq!(decompressed, json.as_bytes());
     }
+
+    #[test]
+    fn test_negotiate_offer() {
+        let compression = WebSocketCompression::new(WebSocketCompressionConfig {
+            client_max_window_bits: 12,
+            ..Default::default()
+        });
+
+        // Unknown extensions are skipped; the client's window limit wins if smaller
+        let (agreed, response) = compression
+            .negotiate(
+                "x-webkit-deflate-frame, permessage-deflate; client_max_window_bits=10; server_no_context_takeover",
+            )
+            .unwrap();
+        assert_eq!(
+            response,
+            "permessage-deflate; server_no_context_takeover; client_max_window_bits=10"
+        );
+        assert_eq!(agreed.config().client_max_window_bits, 10);
+        assert!(agreed.config().server_no_context_takeover);
+
+        // Without client_max_window_bits the client's window can't be limited
+        let (agreed, response) = compression.negotiate("permessage-deflate").unwrap();
+        assert_eq!(response, "permessage-deflate");
+        assert_eq!(agreed.config().client_max_window_bits, 15);
+
+        // A requested server window is echoed even at the maximum
+        let (_, response) = compression
+            .negotiate("permessage-deflate; server_max_window_bits=15")
+            .unwrap();
+        assert_eq!(response, "permessage-deflate; server_max_window_bits=15");
+        let (agreed, response) = compression
+            .negotiate("permessage-deflate; server_max_window_bits=\"9\"")
+            .unwrap();
+        assert_eq!(response, "permessage-deflate; server_max_window_bits=9");
+        assert_eq!(agreed.config().server_max_window_bits, 9);
+
+        // Offers with bad parameters are declined in favour of later ones
+        for offers in [
+            "permessage-deflate; unknown_param",
+            "permessage-deflate; server_no_context_takeover; server_no_context_takeover",
+            "permessage-deflate; server_max_window_bits=7",
+            "permessage-deflate; server_max_window_bits",
+            "permessage-deflate; client_no_context_takeover=1",
+            "x-custom",
+        ] {
+            assert!(compression.negotiate(offers).is_none(), "{}", offers);
+        }
+        let (_, response) = compression
+            .negotiate(
+                "permessage-deflate; unknown_param, permessage-deflate; client_no_context_takeover",
+            )
+            .unwrap();
+        assert_eq!(response, "permessage-deflate; client_no_context_takeover");
+
+        let disabled = WebSocketCompression::new(WebSocketCompressionConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        assert!(disabled.negotiate("permessage-deflate").is_none());
+    }
+
+    #[test]
+    fn test_message_context_takeover() {
+        let message = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+
+        // With context takeover the repeated message refers back to the first
+        let compression = WebSocketCompression::new(WebSocketCompressionConfig::default());
+        let mut deflater = compression.deflater();
+        let mut inflater = compression.inflater();
+        let first = deflater.compress(message.as_bytes()).unwrap();
+        let second = deflater.compress(message.as_bytes()).unwrap();
+        assert!(second.len() < first.len() / 2);
+        for compressed in [&first, &second] {
+            let decompressed = inflater.decompress(compressed, 4096).unwrap().unwrap();
+            assert_eq!(decompressed, message.as_bytes());
+        }
+
+        // Without it every message stands alone
+        let compression = WebSocketCompression::new(WebSocketCompressionConfig {
+            server_no_context_takeover: true,
+            client_no_context_takeover: true,
+            ..Default::default()
+        });
+        let mut deflater = compression.deflater();
+        let first = deflater.compress(message.as_bytes()).unwrap();
+        let second = deflater.compress(message.as_bytes()).unwrap();
+        assert_eq!(first, second);
+        let mut inflater = compression.inflater();
+        for compressed in [&first, &second] {
+            let decompressed = inflater.decompress(compressed, 4096).unwrap().unwrap();
+            assert_eq!(decompressed, message.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_message_window_bits() {
+        // A 1000-byte pseudo-random block repeated: only a window wider than
+        // the block can refer back to the previous copy
+        let mut state = 1u32;
+        let block: Vec<u8> = (0..1000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let message = block.repeat(8);
+
+        let compress = |window_bits| {
+            WebSocketCompression::new(WebSocketCompressionConfig {
+                server_max_window_bits: window_bits,
+                ..Default::default()
+            })
+            .deflater()
+            .compress(&message)
+            .unwrap()
+        };
+        let wide = compress(15);
+        let narrow = compress(9);
+        assert!(wide.len() < 2000);
+        assert!(narrow.len() > 4 * wide.len());
+
+        let mut inflater =
+            WebSocketCompression::new(WebSocketCompressionConfig::default()).inflater();
+        for compressed in [&wide, &narrow] {
+            let decompressed = inflater.decompress(compressed, 8000).unwrap().unwrap();
+            assert_eq!(decompressed, message);
+        }
+    }
+
+    #[test]
+    fn test_message_inflater_limits() {
+        let compression = WebSocketCompression::new(WebSocketCompressionConfig::default());
+        let compressed = compression.deflater().compress(&[0u8; 100_000]).unwrap();
+        assert!(compressed.len() < 1000);
+
+        // Small on the wire, too big once decompressed
+        let mut inflater = compression.inflater();
+        assert!(inflater.decompress(&compressed, 65536).unwrap().is_none());
+
+        let mut inflater = compression.inflater();
+        assert!(inflater.decompress(&[0xff, 0xff, 0xff], 65536).is_err());
+
+        // An empty message still compresses to an empty block
+        for window_bits in [15, 9] {
+            let mut deflater = WebSocketCompression::new(WebSocketCompressionConfig {
+                server_max_window_bits: window_bits,
+                ..Default::default()
+            })
+            .deflater();
+            let empty = deflater.compress(b"").unwrap();
+            assert!(!empty.is_empty());
+            let mut inflater = compression.inflater();
+            assert!(inflater.decompress(&empty, 16).unwrap().unwrap().is_empty());
+        }
+    }
 }
@@ -0,0 +1,649 @@
+/// ACME Integration Tests
+///
+/// The proxy obtains a certificate for a terminate-mode route from an ACME
+/// server, answering the validation on its own listener.
+///
+/// Tests included:
+/// - http-01 and tls-alpn-01 against an in-process ACME server that verifies
+///   JWS signatures and nonces, validates through the proxy and signs the CSR
+/// - The same flows against Pebble (ignored by default, see `pebble_*`)
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use prometheus::Registry;
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateSigningRequestParams, IsCa, Issuer, KeyPair,
+};
+use ring::digest::{SHA256, digest};
+use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use serde_json::{Value, json};
+use sniproxy_config::{AcmeChallengeType, AcmeConfig, Config, Route, RouteMode, TlsCertificates};
+use sniproxy_core::run_proxy;
+use sniproxy_core::termination::crypto_provider;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const ACME_IDENTIFIER_OID: &str = "1.3.6.1.5.5.7.1.31";
+
+/// In-process ACME server issuing certificates from a throwaway CA
+struct MockAcme {
+    base: String,
+    proxy_addr: SocketAddr,
+    ca_der: CertificateDer<'static>,
+    issuer: Issuer<'static, KeyPair>,
+    state: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    next_nonce: u64,
+    nonces: HashSet<String>,
+    /// Reject the first signed request with badNonce to exercise the retry
+    reject_next_nonce: bool,
+    jwk: Option<Value>,
+    orders: Vec<MockOrder>,
+}
+
+struct MockOrder {
+    domain: String,
+    token: String,
+    authorization_status: &'static str,
+    certificate: Option<String>,
+}
+
+impl MockAcme {
+    fn new(base: String, proxy_addr: SocketAddr) -> Self {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_der = ca_params.self_signed(&ca_key).unwrap().der().clone();
+
+        Self {
+            base,
+            proxy_addr,
+            ca_der,
+            issuer: Issuer::new(ca_params, ca_key),
+            state: Mutex::new(MockState {
+                reject_next_nonce: true,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn nonce(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+        state.next_nonce += 1;
+        let nonce = format!("nonce-{}", state.next_nonce);
+        state.nonces.insert(nonce.clone());
+        nonce
+    }
+
+    fn reply(
+        &self,
+        status: StatusCode,
+        location: Option<String>,
+        body: Value,
+    ) -> Response<Full<Bytes>> {
+        let mut response = Response::builder()
+            .status(status)
+            .header("Replay-Nonce", self.nonce());
+        if let Some(location) = location {
+            response = response.header("Location", location);
+        }
+        response
+            .body(Full::new(Bytes::from(body.to_string())))
+            .unwrap()
+    }
+
+    fn problem(&self, kind: &str, detail: &str) -> Response<Full<Bytes>> {
+        self.reply(
+            StatusCode::BAD_REQUEST,
+            None,
+            json!({ "type": format!("urn:ietf:params:acme:error:{}", kind), "detail": detail }),
+        )
+    }
+
+    /// Checks nonce, URL and signature of a JWS request and returns its payload
+    ///
+    /// Returns `None` if the nonce is rejected.
+    fn verify(&self, body: &[u8], url: &str) -> Option<Value> {
+        let jws: Value = serde_json::from_slice(body).unwrap();
+        let decode = |field: &str| {
+            URL_SAFE_NO_PAD
+                .decode(jws[field].as_str().unwrap())
+                .unwrap()
+        };
+        let protected: Value = serde_json::from_slice(&decode("protected")).unwrap();
+
+        let mut state = self.state.lock().unwrap();
+        let nonce = protected["nonce"].as_str().unwrap();
+        if !state.nonces.remove(nonce) || std::mem::take(&mut state.reject_next_nonce) {
+            return None;
+        }
+        assert_eq!(protected["alg"], "ES256");
+        assert_eq!(protected["url"], url);
+
+        let jwk = match protected.get("jwk") {
+            Some(jwk) => jwk.clone(),
+            None => {
+                assert_eq!(protected["kid"], format!("{}/account/1", self.base));
+                state.jwk.clone().unwrap()
+            }
+        };
+        let mut point = vec![0x04];
+        point.extend(URL_SAFE_NO_PAD.decode(jwk["x"].as_str().unwrap()).unwrap());
+        point.extend(URL_SAFE_NO_PAD.decode(jwk["y"].as_str().unwrap()).unwrap());
+        let signing_input = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &point)
+            .verify(signing_input.as_bytes(), &decode("signature"))
+            .expect("JWS signature must verify");
+
+        state.jwk = Some(jwk);
+        let payload = decode("payload");
+        Some(if payload.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&payload).unwrap()
+        })
+    }
+
+    fn key_authorization(&self, token: &str) -> String {
+        let jwk = self.state.lock().unwrap().jwk.clone().unwrap();
+        let canonical = format!(
+            r#"{{"crv":"{}","kty":"{}","x":"{}","y":"{}"}}"#,
+            jwk["crv"].as_str().unwrap(),
+            jwk["kty"].as_str().unwrap(),
+            jwk["x"].as_str().unwrap(),
+            jwk["y"].as_str().unwrap()
+        );
+        let thumbprint = URL_SAFE_NO_PAD.encode(digest(&SHA256, canonical.as_bytes()));
+        format!("{}.{}", token, thumbprint)
+    }
+
+    fn order_json(&self, id: usize) -> Value {
+        let state = self.state.lock().unwrap();
+        let order = &state.orders[id];
+        let status = match (&order.certificate, order.authorization_status) {
+            (Some(_), _) => "valid",
+            (None, "valid") => "ready",
+            (None, _) => "pending",
+        };
+        json!({
+            "status": status,
+            "identifiers": [{ "type": "dns", "value": order.domain }],
+            "authorizations": [format!("{}/authz/{}", self.base, id)],
+            "finalize": format!("{}/finalize/{}", self.base, id),
+            "certificate": format!("{}/cert/{}", self.base, id),
+        })
+    }
+
+    async fn handle(self: Arc<Self>, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let url = format!("{}{}", self.base, path);
+        let body = request.into_body().collect().await.unwrap().to_bytes();
+
+        if path == "/dir" {
+            return self.reply(
+                StatusCode::OK,
+                None,
+                json!({
+                    "newNonce": format!("{}/nonce", self.base),
+                    "newAccount": format!("{}/account", self.base),
+                    "newOrder": format!("{}/order", self.base),
+                }),
+            );
+        }
+        if path == "/nonce" {
+            assert_eq!(method, hyper::Method::HEAD);
+            return self.reply(StatusCode::OK, None, Value::Null);
+        }
+
+        let payload = match self.verify(&body, &url) {
+            Some(payload) => payload,
+            None => return self.problem("badNonce", "unknown nonce"),
+        };
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        let id = segments.get(1).and_then(|id| id.parse::<usize>().ok());
+
+        match (segments[0], id) {
+            ("account", None) => {
+                assert_eq!(payload["termsOfServiceAgreed"], true);
+                self.reply(
+                    StatusCode::CREATED,
+                    Some(format!("{}/account/1", self.base)),
+                    json!({ "status": "valid" }),
+                )
+            }
+            ("order", None) => {
+                let domain = payload["identifiers"][0]["value"]
+                    .as_str()
+                    .unwrap()
+                    .to_string();
+                let id = {
+                    let mut state = self.state.lock().unwrap();
+                    let token = format!("token-{}", state.orders.len());
+                    state.orders.push(MockOrder {
+                        domain,
+                        token,
+                        authorization_status: "pending",
+                        certificate: None,
+                    });
+                    state.orders.len() - 1
+                };
+                self.reply(
+                    StatusCode::CREATED,
+                    Some(format!("{}/order/{}", self.base, id)),
+                    self.order_json(id),
+                )
+            }
+            ("order", Some(id)) => self.reply(StatusCode::OK, None, self.order_json(id)),
+            ("authz", Some(id)) => {
+                let state = self.state.lock().unwrap();
+                let order = &state.orders[id];
+                let body = json!({
+                    "status": order.authorization_status,
+                    "identifier": { "type": "dns", "value": order.domain },
+                    "challenges": [
+                        { "type": "http-01", "url": format!("{}/challenge/{}/http-01", self.base, id), "token": order.token },
+                        { "type": "tls-alpn-01", "url": format!("{}/challenge/{}/tls-alpn-01", self.base, id), "token": order.token },
+                    ],
+                });
+                drop(state);
+                self.reply(StatusCode::OK, None, body)
+            }
+            ("challenge", Some(id)) => {
+                let (domain, token) = {
+                    let state = self.state.lock().unwrap();
+                    (
+                        state.orders[id].domain.clone(),
+                        state.orders[id].token.clone(),
+                    )
+                };
+                let key_authorization = self.key_authorization(&token);
+                let valid = match segments[2] {
+                    "http-01" => {
+                        validate_http01(self.proxy_addr, &domain, &token, &key_authorization).await
+                    }
+                    _ => validate_tls_alpn01(self.proxy_addr, &domain, &key_authorization).await,
+                };
+                let status = if valid { "valid" } else { "invalid" };
+                self.state.lock().unwrap().orders[id].authorization_status = status;
+                self.reply(
+                    StatusCode::OK,
+                    None,
+                    json!({ "status": status, "token": token }),
+                )
+            }
+            ("finalize", Some(id)) => {
+                let csr = URL_SAFE_NO_PAD
+                    .decode(payload["csr"].as_str().unwrap())
+                    .unwrap();
+                let csr = CertificateSigningRequestParams::from_der(&csr.into()).unwrap();
+                let leaf = csr.signed_by(&self.issuer).unwrap();
+                let chain = format!("{}{}", leaf.pem(), pem_certificate(&self.ca_der));
+                self.state.lock().unwrap().orders[id].certificate = Some(chain);
+                self.reply(StatusCode::OK, None, self.order_json(id))
+            }
+            ("cert", Some(id)) => {
+                let chain = self.state.lock().unwrap().orders[id]
+                    .certificate
+                    .clone()
+                    .unwrap();
+                Response::builder()
+                    .header("Replay-Nonce", self.nonce())
+                    .header("Content-Type", "application/pem-certificate-chain")
+                    .body(Full::new(Bytes::from(chain)))
+                    .unwrap()
+            }
+            _ => self.problem("malformed", "unknown resource"),
+        }
+    }
+}
+
+fn pem_certificate(der: &[u8]) -> String {
+    let body = base64::engine::general_purpose::STANDARD.encode(der);
+    let lines: Vec<&str> = body
+        .as_bytes()
+        .chunks(64)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+    format!(
+        "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+        lines.join("\n")
+    )
+}
+
+async fn start_mock_acme(proxy_addr: SocketAddr) -> Arc<MockAcme> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let acme = Arc::new(MockAcme::new(base, proxy_addr));
+
+    let server = acme.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                break;
+            };
+            let server = server.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, hyper::Error>(server.handle(request).await) }
+                });
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    acme
+}
+
+/// Fetches the http-01 token through the proxy like a validation server would
+async fn validate_http01(proxy: SocketAddr, domain: &str, token: &str, expected: &str) -> bool {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    let request = format!(
+        "GET /.well-known/acme-challenge/{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        token, domain
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response).await;
+    let response = String::from_utf8_lossy(&response);
+    response.starts_with("HTTP/1.1 200") && response.ends_with(&format!("\r\n\r\n{}", expected))
+}
+
+/// Accepts any server certificate; validation servers inspect it themselves
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        crypto_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Completes an `acme-tls/1` handshake through the proxy and checks the acmeIdentifier
+async fn validate_tls_alpn01(proxy: SocketAddr, domain: &str, key_authorization: &str) -> bool {
+    let mut config = ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"acme-tls/1".to_vec()];
+
+    let stream = TcpStream::connect(proxy).await.unwrap();
+    let server_name = ServerName::try_from(domain.to_string()).unwrap();
+    let Ok(tls) = TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await
+    else {
+        return false;
+    };
+
+    let session = tls.get_ref().1;
+    if session.alpn_protocol() != Some(b"acme-tls/1".as_slice()) {
+        return false;
+    }
+    let certificate = &session.peer_certificates().unwrap()[0];
+    let (_, parsed) = x509_parser::parse_x509_certificate(certificate).unwrap();
+
+    let mut expected = vec![0x04, 0x20];
+    expected.extend_from_slice(digest(&SHA256, key_authorization.as_bytes()).as_ref());
+    parsed.extensions().iter().any(|ext| {
+        ext.oid.to_id_string() == ACME_IDENTIFIER_OID && ext.critical && ext.value == expected
+    })
+}
+
+fn acme_proxy_config(
+    listen_addrs: Vec<String>,
+    base_dir: &Path,
+    directory_url: String,
+    ca_file: Option<String>,
+    challenge: AcmeChallengeType,
+    domain: &str,
+) -> Config {
+    Config {
+        listen_addrs,
+        timeouts: sniproxy_config::Timeouts {
+            connect: 5,
+            client_hello: 3,
+            idle: 60,
+        },
+        metrics: sniproxy_config::Metrics {
+            enabled: false,
+            address: "127.0.0.1:0".to_string(),
+        },
+        allowlist: None,
+        max_connections: Some(1000),
+        shutdown_timeout: Some(1),
+        connection_pool: None,
+        grpc_pool: None,
+        websocket: None,
+        protocol_routing: None,
+        udp_listen_addrs: None,
+        quic_config: None,
+        http3_config: None,
+        ssh_routes: None,
+        udp_routes: None,
+        routes: Some(vec![Route {
+            host: domain.to_string(),
+            mode: RouteMode::Terminate,
+            acme: true,
+            ..Default::default()
+        }]),
+        error_responses: None,
+        tls_certificates: Some(TlsCertificates {
+            directory: base_dir.join("certs").display().to_string(),
+            reload_interval: 0,
+        }),
+        acme: Some(AcmeConfig {
+            directory_url,
+            contact: vec!["mailto:ops@example.com".to_string()],
+            state_dir: base_dir.join("state").display().to_string(),
+            challenge,
+            ca_file,
+            renew_before_days: 30,
+            check_interval: 3600,
+        }),
+    }
+}
+
+/// Runs the proxy until the certificate for `domain` is installed and served
+///
+/// Returns the certificate chain the proxy presents for `domain`.
+async fn issue_through_proxy(
+    config: Config,
+    base_dir: &Path,
+    domain: &str,
+) -> Vec<CertificateDer<'static>> {
+    let proxy_addr: SocketAddr = config.listen_addrs[0].parse().unwrap();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let proxy = tokio::spawn(async move {
+        let _ = run_proxy(config, Some(Registry::new()), shutdown_rx).await;
+    });
+
+    let cert_path = base_dir.join("certs").join(format!("{}.crt", domain));
+    for _ in 0..100 {
+        if cert_path.exists() {
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert!(cert_path.exists(), "certificate was not issued");
+    assert!(
+        base_dir
+            .join("certs")
+            .join(format!("{}.key", domain))
+            .exists()
+    );
+    assert!(base_dir.join("state").join("account.key").exists());
+
+    // The store is reloaded right after installation; allow for that to land
+    sleep(Duration::from_millis(200)).await;
+    let config = ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+    let stream = TcpStream::connect(proxy_addr).await.unwrap();
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from(domain.to_string()).unwrap(), stream)
+        .await
+        .expect("terminate route should serve the issued certificate");
+    let chain = tls.get_ref().1.peer_certificates().unwrap().to_vec();
+
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(5), proxy).await;
+    chain
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sniproxy-acme-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn find_available_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+async fn run_mock_issuance(challenge: AcmeChallengeType, name: &str) {
+    let base_dir = scratch_dir(name);
+    let proxy_addr: SocketAddr = format!("127.0.0.1:{}", find_available_port().await)
+        .parse()
+        .unwrap();
+    let acme = start_mock_acme(proxy_addr).await;
+
+    let config = acme_proxy_config(
+        vec![proxy_addr.to_string()],
+        &base_dir,
+        format!("{}/dir", acme.base),
+        None,
+        challenge,
+        "acme.test",
+    );
+    let chain = issue_through_proxy(config, &base_dir, "acme.test").await;
+
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[1], acme.ca_der);
+    let (_, leaf) = x509_parser::parse_x509_certificate(&chain[0]).unwrap();
+    let san = leaf.subject_alternative_name().unwrap().unwrap();
+    assert_eq!(
+        san.value.general_names,
+        vec![x509_parser::extensions::GeneralName::DNSName("acme.test")]
+    );
+
+    std::fs::remove_dir_all(base_dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_acme_http01_issues_certificate() {
+    run_mock_issuance(AcmeChallengeType::Http01, "http01").await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_acme_tls_alpn01_issues_certificate() {
+    run_mock_issuance(AcmeChallengeType::TlsAlpn01, "tls-alpn01").await;
+}
+
+/// Runs against a local Pebble instance:
+///
+/// ```text
+/// pebble-challtestsrv -defaultIPv4 127.0.0.1 &
+/// PEBBLE_VA_NOSLEEP=1 pebble -config test/config/pebble-config.json -dnsserver 127.0.0.1:8053
+/// PEBBLE_CA_FILE=test/certs/pebble.minica.pem cargo test --test acme_tests -- --ignored
+/// ```
+///
+/// Pebble validates http-01 on port 5002 and tls-alpn-01 on port 5001, so the
+/// proxy listens on both.
+async fn run_pebble_issuance(challenge: AcmeChallengeType, name: &str) {
+    let directory_url = std::env::var("PEBBLE_DIRECTORY_URL")
+        .unwrap_or_else(|_| "https://localhost:14000/dir".to_string());
+    let ca_file =
+        std::env::var("PEBBLE_CA_FILE").expect("PEBBLE_CA_FILE must point to pebble.minica.pem");
+    let domain = std::env::var("PEBBLE_DOMAIN").unwrap_or_else(|_| "sniproxy.test".to_string());
+    let base_dir = scratch_dir(name);
+
+    let config = acme_proxy_config(
+        vec!["127.0.0.1:5001".to_string(), "127.0.0.1:5002".to_string()],
+        &base_dir,
+        directory_url,
+        Some(ca_file),
+        challenge,
+        &domain,
+    );
+    let chain = issue_through_proxy(config, &base_dir, &domain).await;
+    assert!(!chain.is_empty());
+
+    std::fs::remove_dir_all(base_dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[ignore = "Requires a local Pebble ACME server"]
+async fn pebble_http01_issues_certificate() {
+    run_pebble_issuance(AcmeChallengeType::Http01, "pebble-http01").await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[ignore = "Requires a local Pebble ACME server"]
+async fn pebble_tls_alpn01_issues_certificate() {
+    run_pebble_issuance(AcmeChallengeType::TlsAlpn01, "pebble-tls-alpn01").await;
+}
@@ -58,11 +58,18 @@ fn create_test_config(proxy_port: u16, metrics_port: u16) -> Config {
         max_connections: Some(1000),
         shutdown_timeout: Some(10),
         connection_pool: None,
+        grpc_pool: None,
+        websocket: None,
         protocol_routing: None,
         udp_listen_addrs: None,
         quic_config: None,
         http3_config: None,
         ssh_routes: None,
+        udp_routes: None,
+        routes: None,
+        error_responses: None,
+        tls_certificates: None,
+        acme: None,
     }
 }
 
@@ -518,10 +525,10 @@ async fn test_comprehensive_concurrent_mixed_protocols() {
 Host: 127.0.0.1:{}\r\n\
 Upgrade: websocket\r\n\
 Connection: Upgrade\r\n\
-Sec-WebSocket-Key: test{}\r\n\
+Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
 Sec-WebSocket-Version: 13\r\n\
 \r\n",
-                    i, backend, i
+                    i, backend
                 );
                 if stream.write_all(request.as_bytes()).await.is_ok() {
                     let mut response = vec![0u8; 4096];
@@ -35,11 +35,18 @@ fn create_test_config(proxy_port: u16, metrics_port: u16) -> Config {
         max_connections: Some(1000),
         shutdown_timeout: Some(10),
         connection_pool: None,
+        grpc_pool: None,
+        websocket: None,
         protocol_routing: None,
         udp_listen_addrs: None,
         quic_config: None,
         http3_config: None,
         ssh_routes: None,
+        udp_routes: None,
+        routes: None,
+        error_responses: None,
+        tls_certificates: None,
+        acme: None,
     }
 }
 
@@ -146,11 +153,18 @@ async fn test_multiple_listen_addresses() {
         max_connections: Some(1000),
         shutdown_timeout: Some(10),
         connection_pool: None,
+        grpc_pool: None,
+        websocket: None,
         protocol_routing: None,
         udp_listen_addrs: None,
         quic_config: None,
         http3_config: None,
         ssh_routes: None,
+        udp_routes: None,
+        routes: None,
+        error_responses: None,
+        tls_certificates: None,
+        acme: None,
     };
 
     let proxy_handle = tokio::spawn(async move {
@@ -224,11 +238,18 @@ async fn test_proxy_with_allowlist() {
         max_connections: Some(1000),
         shutdown_timeout: Some(10),
         connection_pool: None,
+        grpc_pool: None,
+        websocket: None,
         protocol_routing: None,
         udp_listen_addrs: None,
         quic_config: None,
         http3_config: None,
         ssh_routes: None,
+        udp_routes: None,
+        routes: None,
+        error_responses: None,
+        tls_certificates: None,
+        acme: None,
     };
 
     let proxy_handle = tokio::spawn(async move {
@@ -501,6 +522,992 @@ async fn test_tls_sni_proxy_accepts_connection() {
     println!("✅ TLS/SNI connection accepted by proxy");
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_tls_policy_rejects_with_alert() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "*.policy.test".to_string(),
+        tls_policy: Some(sniproxy_config::TlsPolicy {
+            min_version: Some(sniproxy_config::TlsVersion::Tls13),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }]);
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+
+    // The test ClientHello only offers TLS 1.2 (no supported_versions extension)
+    stream
+        .write_all(&create_client_hello("legacy.policy.test"))
+        .await
+        .expect("Failed to send ClientHello");
+
+    let mut alert = [0u8; 7];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut alert))
+        .await
+        .expect("Timeout reading alert")
+        .expect("Failed to read alert");
+
+    // Fatal protocol_version alert
+    assert_eq!(alert, [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 70]);
+
+    proxy_handle.abort();
+
+    println!("✅ TLS policy violation answered with protocol_version alert");
+}
+
+/// Passthrough TLS backend limited to the given provider, echoing 4 bytes per connection
+async fn start_tls_echo_backend(
+    port: u16,
+    provider: rustls::crypto::CryptoProvider,
+    cert: &rcgen::CertifiedKey<rcgen::KeyPair>,
+) {
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    let server_config = rustls::ServerConfig::builder_with_provider(std::sync::Arc::new(provider))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.cert.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der())),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(server_config));
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(socket).await else {
+                    return;
+                };
+                let mut buf = [0u8; 4];
+                if tls.read_exact(&mut buf).await.is_ok() {
+                    let _ = tls.write_all(&buf).await;
+                }
+                let _ = tls.shutdown().await;
+            });
+        }
+    });
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_tls_policy_rejects_negotiated_down_server_hello() {
+    use rustls::pki_types::ServerName;
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let aes256_port = find_available_port().await;
+    let p256_port = find_available_port().await;
+
+    let cert = rcgen::generate_simple_self_signed(vec![
+        "suite.policy.test".to_string(),
+        "group.policy.test".to_string(),
+        "ok.policy.test".to_string(),
+    ])
+    .unwrap();
+
+    // One backend only speaks TLS_AES_256_GCM_SHA384, the other only secp256r1
+    let mut aes256 = rustls::crypto::ring::default_provider();
+    aes256
+        .cipher_suites
+        .retain(|suite| suite.suite() == rustls::CipherSuite::TLS13_AES_256_GCM_SHA384);
+    start_tls_echo_backend(aes256_port, aes256, &cert).await;
+    let mut p256 = rustls::crypto::ring::default_provider();
+    p256.kx_groups
+        .retain(|group| group.name() == rustls::NamedGroup::secp256r1);
+    start_tls_echo_backend(p256_port, p256, &cert).await;
+
+    let route =
+        |host: &str, port: u16, policy: sniproxy_config::TlsPolicy| sniproxy_config::Route {
+            host: host.to_string(),
+            upstream: Some(format!("127.0.0.1:{}", port)),
+            tls_policy: Some(policy),
+            ..Default::default()
+        };
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![
+        route(
+            "suite.policy.test",
+            aes256_port,
+            sniproxy_config::TlsPolicy {
+                forbidden_cipher_suites: vec![0x1302],
+                ..Default::default()
+            },
+        ),
+        route(
+            "group.policy.test",
+            p256_port,
+            sniproxy_config::TlsPolicy {
+                forbidden_groups: vec![0x0017],
+                ..Default::default()
+            },
+        ),
+        route(
+            "ok.policy.test",
+            aes256_port,
+            sniproxy_config::TlsPolicy {
+                forbidden_cipher_suites: vec![0x1301],
+                forbidden_groups: vec![0x0017],
+                ..Default::default()
+            },
+        ),
+    ]);
+
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // The client offers every default suite and group, so each ClientHello passes
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let client_config =
+        rustls::ClientConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+    let connect = |host: &'static str| {
+        let connector = connector.clone();
+        async move {
+            let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+                .await
+                .expect("Failed to connect to proxy");
+            tokio::time::timeout(
+                Duration::from_secs(5),
+                connector.connect(ServerName::try_from(host).unwrap(), stream),
+            )
+            .await
+            .expect("Timeout during TLS handshake")
+        }
+    };
+
+    // The backend negotiates down to a forbidden suite, or retries with a forbidden group
+    for host in ["suite.policy.test", "group.policy.test"] {
+        let error = connect(host).await.expect_err(host).to_string();
+        assert!(error.contains("HandshakeFailure"), "{}: {}", host, error);
+    }
+
+    let mut tls = connect("ok.policy.test")
+        .await
+        .expect("Allowed handshake failed");
+    tls.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    tls.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"ping");
+    assert_eq!(
+        tls.get_ref().1.negotiated_cipher_suite().unwrap().suite(),
+        rustls::CipherSuite::TLS13_AES_256_GCM_SHA384
+    );
+
+    let rejections = |reason: &str| {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.name() == "sniproxy_tls_policy_rejections_total")
+            .flat_map(|family| family.get_metric().iter())
+            .find(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.value() == reason)
+            })
+            .map_or(0.0, |metric| metric.get_counter().value())
+    };
+    assert_eq!(rejections("cipher_suite"), 1.0);
+    assert_eq!(rejections("group"), 1.0);
+
+    proxy_handle.abort();
+
+    println!("✅ Backend ServerHello held to the route's TLS policy");
+}
+
+/// TLS 1.3 ServerHello record; a HelloRetryRequest when `key_share` is only a group
+fn server_hello_record(cipher_suite: u16, key_share: &[u8]) -> Vec<u8> {
+    const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+        0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8,
+        0x91, 0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8,
+        0x33, 0x9c,
+    ];
+
+    let mut extensions = vec![0x00, 0x2b, 0x00, 0x02, 0x03, 0x04];
+    extensions.extend_from_slice(&[0x00, 0x33]);
+    extensions.extend_from_slice(&(key_share.len() as u16).to_be_bytes());
+    extensions.extend_from_slice(key_share);
+
+    let mut body = vec![0x03, 0x03];
+    if key_share.len() == 2 {
+        body.extend_from_slice(&HELLO_RETRY_REQUEST_RANDOM);
+    } else {
+        body.extend_from_slice(&[0x42; 32]);
+    }
+    body.push(0x00);
+    body.extend_from_slice(&cipher_suite.to_be_bytes());
+    body.push(0x00);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+    record.push(0x02);
+    record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&body);
+    record
+}
+
+async fn read_record(stream: &mut TcpStream) -> Vec<u8> {
+    let mut record = vec![0u8; 5];
+    stream.read_exact(&mut record).await.unwrap();
+    let length = u16::from_be_bytes([record[3], record[4]]) as usize;
+    record.resize(5 + length, 0);
+    stream.read_exact(&mut record[5..]).await.unwrap();
+    record
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_tls_policy_checks_server_hello_after_hello_retry() {
+    use rustls::pki_types::ServerName;
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let p256_port = find_available_port().await;
+    let sneaky_port = find_available_port().await;
+
+    // A real backend that only speaks secp256r1, so x25519 clients get a retry
+    let cert = rcgen::generate_simple_self_signed(vec!["retry.policy.test".to_string()]).unwrap();
+    let mut p256 = rustls::crypto::ring::default_provider();
+    p256.kx_groups
+        .retain(|group| group.name() == rustls::NamedGroup::secp256r1);
+    start_tls_echo_backend(p256_port, p256, &cert).await;
+
+    // A backend whose retry is acceptable but whose ServerHello is not
+    let sneaky = TcpListener::bind(format!("127.0.0.1:{}", sneaky_port))
+        .await
+        .unwrap();
+    let sneaky_handle = tokio::spawn(async move {
+        let (mut socket, _) = sneaky.accept().await.unwrap();
+        read_record(&mut socket).await;
+        socket
+            .write_all(&server_hello_record(0x1301, &[0x00, 0x17]))
+            .await
+            .unwrap();
+        // ChangeCipherSpec, then the second ClientHello
+        let mut records = vec![read_record(&mut socket).await];
+        if records[0][0] == 0x14 {
+            records.push(read_record(&mut socket).await);
+        }
+        let mut key_share = vec![0x00, 0x17, 0x00, 0x41, 0x04];
+        key_share.extend_from_slice(&[0x11; 64]);
+        let _ = socket
+            .write_all(&server_hello_record(0x1302, &key_share))
+            .await;
+        records
+    });
+
+    let policy = sniproxy_config::TlsPolicy {
+        forbidden_cipher_suites: vec![0x1302, 0x1303],
+        ..Default::default()
+    };
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![
+        sniproxy_config::Route {
+            host: "retry.policy.test".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", p256_port)),
+            tls_policy: Some(policy.clone()),
+            ..Default::default()
+        },
+        sniproxy_config::Route {
+            host: "sneaky.policy.test".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", sneaky_port)),
+            tls_policy: Some(policy),
+            ..Default::default()
+        },
+    ]);
+
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // The retried handshake completes through the proxy
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let mut client_provider = rustls::crypto::ring::default_provider();
+    client_provider
+        .cipher_suites
+        .retain(|suite| suite.suite() == rustls::CipherSuite::TLS13_AES_128_GCM_SHA256);
+    let client_config = rustls::ClientConfig::builder_with_provider(client_provider.into())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .unwrap();
+    let mut tls = tokio::time::timeout(
+        Duration::from_secs(5),
+        connector.connect(ServerName::try_from("retry.policy.test").unwrap(), stream),
+    )
+    .await
+    .expect("Timeout during TLS handshake")
+    .expect("Retried handshake failed");
+    assert_eq!(
+        tls.get_ref()
+            .1
+            .negotiated_key_exchange_group()
+            .unwrap()
+            .name(),
+        rustls::NamedGroup::secp256r1
+    );
+    tls.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    tls.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"ping");
+
+    // The ServerHello after an acceptable retry is still checked
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(&create_client_hello("sneaky.policy.test"))
+        .await
+        .unwrap();
+    let retry = tokio::time::timeout(Duration::from_secs(5), read_record(&mut stream))
+        .await
+        .expect("Timeout reading HelloRetryRequest");
+    assert_eq!(retry, server_hello_record(0x1301, &[0x00, 0x17]));
+    stream
+        .write_all(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01])
+        .await
+        .unwrap();
+    let second_hello = create_client_hello("sneaky.policy.test");
+    stream.write_all(&second_hello).await.unwrap();
+
+    let mut alert = [0u8; 7];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut alert))
+        .await
+        .expect("Timeout reading alert")
+        .expect("Failed to read alert");
+    // Fatal handshake_failure alert
+    assert_eq!(alert, [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 40]);
+
+    let forwarded = sneaky_handle.await.unwrap();
+    assert_eq!(forwarded.last(), Some(&second_hello));
+
+    let rejections = registry
+        .gather()
+        .iter()
+        .filter(|family| family.name() == "sniproxy_tls_policy_rejections_total")
+        .flat_map(|family| family.get_metric().iter())
+        .map(|metric| metric.get_counter().value())
+        .sum::<f64>();
+    assert_eq!(rejections, 1.0);
+
+    proxy_handle.abort();
+
+    println!("✅ ServerHello after a HelloRetryRequest held to the route's TLS policy");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_sni_rewritten_in_passthrough_client_hello() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![
+        sniproxy_config::Route {
+            host: "legacy.example.com".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            upstream_sni: Some("new.example.net".to_string()),
+            ..Default::default()
+        },
+        sniproxy_config::Route {
+            host: "ip-only.example.com".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            strip_sni: true,
+            ..Default::default()
+        },
+    ]);
+
+    // Backend captures the forwarded ClientHellos
+    let backend = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    let backend_handle = tokio::spawn(async move {
+        let mut records = Vec::new();
+        for _ in 0..2 {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            records.push(read_record(&mut socket).await);
+        }
+        records
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    let mut clients = Vec::new();
+    for host in ["legacy.example.com", "ip-only.example.com"] {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+            .await
+            .expect("Failed to connect to proxy");
+        stream
+            .write_all(&create_client_hello(host))
+            .await
+            .expect("Failed to send ClientHello");
+        clients.push(stream);
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    let records = tokio::time::timeout(Duration::from_secs(5), backend_handle)
+        .await
+        .expect("Timeout waiting for backend")
+        .expect("Backend task failed");
+
+    // The rewritten records still parse, with every length field fixed up.
+    // The backend may accept the two connections in either order.
+    let names: Vec<_> = records
+        .iter()
+        .map(|record| sniproxy_core::extract_sni(record).ok())
+        .collect();
+    assert!(names.contains(&Some("new.example.net".to_string())));
+    assert!(names.contains(&None));
+    for record in &records {
+        assert_eq!(
+            record.len(),
+            5 + u16::from_be_bytes([record[3], record[4]]) as usize
+        );
+        assert!(sniproxy_core::tls::parse_client_hello(record).is_ok());
+    }
+
+    proxy_handle.abort();
+
+    println!("✅ SNI rewritten and stripped before forwarding to passthrough upstreams");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_sni_rewritten_for_upstream() {
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+
+    let base_dir =
+        std::env::temp_dir().join(format!("sniproxy-live-rename-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base_dir);
+    let cert_dir = base_dir.join("certs");
+    let backend_dir = base_dir.join("backend");
+    std::fs::create_dir_all(&cert_dir).unwrap();
+    std::fs::create_dir_all(&backend_dir).unwrap();
+    let proxy_cert = rcgen::generate_simple_self_signed(vec!["*.rename.test".to_string()]).unwrap();
+    std::fs::write(
+        cert_dir.join("_wildcard.rename.test.crt"),
+        proxy_cert.cert.pem(),
+    )
+    .unwrap();
+    std::fs::write(
+        cert_dir.join("_wildcard.rename.test.key"),
+        proxy_cert.signing_key.serialize_pem(),
+    )
+    .unwrap();
+    let backend_cert = write_server_cert(&backend_dir, "new.example.net");
+    let backend_key = std::fs::read_to_string(backend_dir.join("new.example.net.key")).unwrap();
+    let ca_file = backend_dir
+        .join("new.example.net.crt")
+        .to_string_lossy()
+        .into_owned();
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.tls_certificates = Some(sniproxy_config::TlsCertificates {
+        directory: cert_dir.to_string_lossy().into_owned(),
+        reload_interval: 0,
+    });
+    let upstream_tls = |server_name: Option<&str>| sniproxy_config::UpstreamTls {
+        ca_file: Some(ca_file.clone()),
+        server_name: server_name.map(str::to_string),
+        alpn: Vec::new(),
+        pins: Vec::new(),
+    };
+    config.routes = Some(vec![
+        sniproxy_config::Route {
+            host: "renamed.rename.test".to_string(),
+            mode: sniproxy_config::RouteMode::Terminate,
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            upstream_sni: Some("new.example.net".to_string()),
+            upstream_tls: Some(upstream_tls(None)),
+            ..Default::default()
+        },
+        sniproxy_config::Route {
+            host: "stripped.rename.test".to_string(),
+            mode: sniproxy_config::RouteMode::Terminate,
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            strip_sni: true,
+            upstream_tls: Some(upstream_tls(Some("new.example.net"))),
+            ..Default::default()
+        },
+    ]);
+
+    // TLS backend answering each connection with the SNI it received
+    let key = rcgen::KeyPair::from_pem(&backend_key).unwrap();
+    let server_config =
+        rustls::ServerConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![backend_cert],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+            )
+            .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(server_config));
+    let backend = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = backend.accept().await {
+            let Ok(mut tls) = acceptor.accept(socket).await else {
+                continue;
+            };
+            let sni = tls.get_ref().1.server_name().unwrap_or("-").to_string();
+            let mut ping = [0u8; 4];
+            if tls.read_exact(&mut ping).await.is_ok() {
+                let _ = tls.write_all(sni.as_bytes()).await;
+            }
+            let _ = tls.shutdown().await;
+        }
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // Client trusts the proxy's certificate; both handshakes complete end to end
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(proxy_cert.cert.der().clone()).unwrap();
+    let client_config =
+        rustls::ClientConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+
+    for (host, expected_sni) in [
+        ("renamed.rename.test", "new.example.net"),
+        ("stripped.rename.test", "-"),
+    ] {
+        let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+            .await
+            .expect("Failed to connect to proxy");
+        let server_name = ServerName::try_from(host).unwrap();
+        let mut tls = tokio::time::timeout(
+            Duration::from_secs(5),
+            connector.connect(server_name, stream),
+        )
+        .await
+        .expect("Timeout during TLS handshake")
+        .expect("TLS handshake with proxy failed");
+
+        tls.write_all(b"ping").await.unwrap();
+        let mut sni = String::new();
+        tokio::time::timeout(Duration::from_secs(5), tls.read_to_string(&mut sni))
+            .await
+            .expect("Timeout reading backend reply")
+            .expect("Failed to read backend reply");
+        assert_eq!(sni, expected_sni, "{}", host);
+    }
+
+    proxy_handle.abort();
+    let _ = std::fs::remove_dir_all(&base_dir);
+
+    println!("✅ Backend handshake uses the route's upstream SNI or none");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_tls_termination_forwards_plaintext() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+
+    // Wildcard certificate in a scratch directory
+    let cert_dir = std::env::temp_dir().join(format!("sniproxy-live-certs-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cert_dir);
+    std::fs::create_dir_all(&cert_dir).unwrap();
+    let cert = rcgen::generate_simple_self_signed(vec!["*.terminate.test".to_string()]).unwrap();
+    std::fs::write(
+        cert_dir.join("_wildcard.terminate.test.crt"),
+        cert.cert.pem(),
+    )
+    .unwrap();
+    std::fs::write(
+        cert_dir.join("_wildcard.terminate.test.key"),
+        cert.signing_key.serialize_pem(),
+    )
+    .unwrap();
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.tls_certificates = Some(sniproxy_config::TlsCertificates {
+        directory: cert_dir.to_string_lossy().into_owned(),
+        reload_interval: 0,
+    });
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "*.terminate.test".to_string(),
+        mode: sniproxy_config::RouteMode::Terminate,
+        upstream: Some(format!("127.0.0.1:{}", backend_port)),
+        ..Default::default()
+    }]);
+
+    // Plaintext echo backend
+    let backend = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        let (mut socket, _) = backend.accept().await.unwrap();
+        let mut buf = [0u8; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        socket.write_all(&buf).await.unwrap();
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // Client trusts the proxy's self-signed certificate
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let client_config =
+        rustls::ClientConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let server_name = rustls::pki_types::ServerName::try_from("app.terminate.test").unwrap();
+    let mut tls = tokio::time::timeout(
+        Duration::from_secs(5),
+        connector.connect(server_name, stream),
+    )
+    .await
+    .expect("Timeout during TLS handshake")
+    .expect("TLS handshake with proxy failed");
+
+    tls.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    tokio::time::timeout(Duration::from_secs(5), tls.read_exact(&mut echo))
+        .await
+        .expect("Timeout reading echo")
+        .expect("Failed to read echo");
+    assert_eq!(&echo, b"ping");
+
+    proxy_handle.abort();
+    let _ = std::fs::remove_dir_all(&cert_dir);
+
+    println!("✅ TLS terminated with wildcard certificate and forwarded in plaintext");
+}
+
+/// Writes a self-signed server certificate for `name` into `dir` as `<name>.crt`/`.key`
+fn write_server_cert(
+    dir: &std::path::Path,
+    name: &str,
+) -> rustls::pki_types::CertificateDer<'static> {
+    let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    std::fs::write(dir.join(format!("{}.crt", name)), cert.cert.pem()).unwrap();
+    std::fs::write(
+        dir.join(format!("{}.key", name)),
+        cert.signing_key.serialize_pem(),
+    )
+    .unwrap();
+    cert.cert.der().clone()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_mutual_tls_forwards_identity_header() {
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+
+    let base_dir = std::env::temp_dir().join(format!("sniproxy-live-mtls-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base_dir);
+    let cert_dir = base_dir.join("certs");
+    std::fs::create_dir_all(&cert_dir).unwrap();
+    let server_der = write_server_cert(&cert_dir, "mtls.test");
+
+    // Client CA and a client certificate it issued
+    let ca_key = rcgen::KeyPair::generate().unwrap();
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let issuer = rcgen::Issuer::new(ca_params, ca_key);
+    let client_key = rcgen::KeyPair::generate().unwrap();
+    let client_cert = rcgen::CertificateParams::new(vec!["billing.payments.internal".to_string()])
+        .unwrap()
+        .signed_by(&client_key, &issuer)
+        .unwrap();
+    let ca_file = base_dir.join("clients-ca.pem");
+    std::fs::write(&ca_file, ca.pem()).unwrap();
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.tls_certificates = Some(sniproxy_config::TlsCertificates {
+        directory: cert_dir.to_string_lossy().into_owned(),
+        reload_interval: 0,
+    });
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "mtls.test".to_string(),
+        mode: sniproxy_config::RouteMode::Terminate,
+        upstream: Some(format!("127.0.0.1:{}", backend_port)),
+        client_auth: Some(sniproxy_config::ClientAuth {
+            ca_file: ca_file.to_string_lossy().into_owned(),
+            rules: vec![sniproxy_config::IdentityRule {
+                identity: "*.payments.internal".to_string(),
+                upstream: None,
+            }],
+            identity_header: "X-Client-Identity".to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }]);
+
+    // Backend reports the request head it received
+    let backend = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    let backend_handle = tokio::spawn(async move {
+        let (mut socket, _) = backend.accept().await.unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            socket.read_exact(&mut byte).await.unwrap();
+            head.push(byte[0]);
+        }
+        socket
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8(head).unwrap()
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(server_der).unwrap();
+    let builder = || {
+        rustls::ClientConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots.clone())
+    };
+    let server_name = ServerName::try_from("mtls.test").unwrap();
+
+    // Authenticated client: spoofed identity header is replaced
+    let client_config = builder()
+        .with_client_auth_cert(
+            vec![client_cert.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client_key.serialize_der())),
+        )
+        .unwrap();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .unwrap();
+    let mut tls = connector
+        .connect(server_name.clone(), stream)
+        .await
+        .expect("mTLS handshake failed");
+    tls.write_all(b"GET / HTTP/1.1\r\nHost: mtls.test\r\nX-Client-Identity: admin\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = [0u8; 12];
+    tokio::time::timeout(Duration::from_secs(5), tls.read_exact(&mut response))
+        .await
+        .expect("Timeout reading response")
+        .unwrap();
+    assert_eq!(&response, b"HTTP/1.1 204");
+
+    let head = backend_handle.await.unwrap();
+    assert!(head.contains("X-Client-Identity: billing.payments.internal\r\n"));
+    assert!(!head.contains("admin"));
+
+    // Client without a certificate is refused
+    let connector =
+        tokio_rustls::TlsConnector::from(std::sync::Arc::new(builder().with_no_client_auth()));
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .unwrap();
+    let refused = async {
+        let mut tls = connector.connect(server_name, stream).await?;
+        tls.write_all(b"GET / HTTP/1.1\r\nHost: mtls.test\r\n\r\n")
+            .await?;
+        let mut buf = [0u8; 1];
+        tls.read_exact(&mut buf).await
+    };
+    assert!(
+        tokio::time::timeout(Duration::from_secs(5), refused)
+            .await
+            .expect("Timeout waiting for refusal")
+            .is_err()
+    );
+
+    proxy_handle.abort();
+    let _ = std::fs::remove_dir_all(&base_dir);
+
+    println!("✅ Mutual TLS identity verified and forwarded in header");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_blocked_sni_gets_access_denied_alert() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.allowlist = Some(vec!["allowed.test".to_string()]);
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    stream
+        .write_all(&create_client_hello("blocked.test"))
+        .await
+        .expect("Failed to send ClientHello");
+
+    let mut alert = [0u8; 7];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut alert))
+        .await
+        .expect("Timeout reading alert")
+        .expect("Failed to read alert");
+
+    // Fatal access_denied alert
+    assert_eq!(alert, [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 49]);
+
+    proxy_handle.abort();
+
+    println!("✅ Blocked SNI answered with access_denied alert");
+}
+
+// Helper to send one HTTP request through the proxy and return the full response
+async fn send_http_request(proxy_port: u16, host: &str) -> String {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .expect("Failed to send request");
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("Timeout reading response")
+        .expect("Failed to read response");
+    String::from_utf8_lossy(&response).to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_http_error_responses() {
+    // A port with nothing listening on it
+    let closed_port = find_available_port().await;
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.allowlist = Some(vec![format!("127.0.0.1:{}", closed_port)]);
+    config.error_responses = Some(sniproxy_config::ErrorResponses {
+        body_template: "sniproxy: {reason} ({message})".to_string(),
+        content_type: "text/plain".to_string(),
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // Blocked by policy
+    let response = send_http_request(proxy_port, "blocked.test").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+        "Expected 403, got: {}",
+        response
+    );
+    assert!(response.ends_with("sniproxy: Forbidden (Host not in allowlist)"));
+
+    // Allowed, but the backend is down
+    let response = send_http_request(proxy_port, &format!("127.0.0.1:{}", closed_port)).await;
+    assert!(
+        response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"),
+        "Expected 502, got: {}",
+        response
+    );
+
+    proxy_handle.abort();
+
+    println!("✅ HTTP clients get 403/502 responses instead of a silent close");
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_multiple_concurrent_connections() {
     // Start backend server
@@ -508,74 +1515,1182 @@ async fn test_multiple_concurrent_connections() {
     let backend_handle = start_http11_backend(backend_port).await;
     sleep(Duration::from_millis(300)).await;
 
-    // Start proxy
+    // Start proxy
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let config = create_test_config(proxy_port, metrics_port);
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    // Send 10 concurrent requests
+    let mut handles = vec![];
+
+    for i in 0..10 {
+        let proxy_addr = format!("127.0.0.1:{}", proxy_port);
+        let backend_port_clone = backend_port;
+
+        let handle = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(proxy_addr)
+                .await
+                .expect("Failed to connect to proxy");
+
+            let request = format!(
+                "GET /test{} HTTP/1.1\r\n\
+Host: 127.0.0.1:{}\r\n\
+Connection: close\r\n\
+\r\n",
+                i, backend_port_clone
+            );
+
+            stream
+                .write_all(request.as_bytes())
+                .await
+                .expect("Failed to send request");
+
+            // Read response with timeout
+            let mut response = vec![0u8; 4096];
+            let read_future = stream.read(&mut response);
+            let bytes_read = match tokio::time::timeout(Duration::from_secs(5), read_future).await {
+                Ok(Ok(n)) => n,
+                _ => 0,
+            };
+
+            bytes_read > 0 && String::from_utf8_lossy(&response[..bytes_read]).contains("200 OK")
+        });
+
+        handles.push(handle);
+    }
+
+    // Wait for all requests to complete
+    let mut success_count = 0;
+    for handle in handles {
+        if handle.await.unwrap() {
+            success_count += 1;
+        }
+    }
+
+    assert_eq!(
+        success_count, 10,
+        "All 10 concurrent requests should succeed"
+    );
+
+    // Cleanup
+    proxy_handle.abort();
+    backend_handle.abort();
+
+    println!("✅ Proxy handles multiple concurrent connections");
+}
+
+#[tokio::test]
+async fn test_unusable_upstream_tls_config_stops_startup() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "broken.origin.test".to_string(),
+        upstream_tls: Some(sniproxy_config::UpstreamTls {
+            ca_file: Some("/nonexistent/sniproxy-ca.pem".to_string()),
+            server_name: None,
+            alpn: Vec::new(),
+            pins: Vec::new(),
+        }),
+        ..Default::default()
+    }]);
+
+    let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        run_proxy(config, Some(Registry::new()), shutdown_rx),
+    )
+    .await
+    .expect("run_proxy should fail instead of serving");
+    let error = result.expect_err("a missing ca_file must stop startup");
+    assert!(
+        error.to_string().contains("broken.origin.test"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_http_request_originates_tls_to_backend() {
+    use base64::Engine as _;
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+
+    let cert_dir =
+        std::env::temp_dir().join(format!("sniproxy-live-origin-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cert_dir);
+    std::fs::create_dir_all(&cert_dir).unwrap();
+    let backend_cert = write_server_cert(&cert_dir, "backend.origin.test");
+    let backend_key = std::fs::read_to_string(cert_dir.join("backend.origin.test.key")).unwrap();
+    let ca_file = cert_dir
+        .join("backend.origin.test.crt")
+        .to_string_lossy()
+        .into_owned();
+
+    let upstream_tls = |pins: Vec<String>| sniproxy_config::UpstreamTls {
+        ca_file: Some(ca_file.clone()),
+        server_name: Some("backend.origin.test".to_string()),
+        alpn: vec!["http/1.1".to_string()],
+        pins,
+    };
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![
+        sniproxy_config::Route {
+            host: "legacy.origin.test".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            upstream_tls: Some(upstream_tls(Vec::new())),
+            ..Default::default()
+        },
+        sniproxy_config::Route {
+            host: "pinned.origin.test".to_string(),
+            upstream: Some(format!("127.0.0.1:{}", backend_port)),
+            // Hash of no key in particular: verification succeeds, the pin does not
+            upstream_tls: Some(upstream_tls(vec![format!(
+                "sha256/{}",
+                base64::engine::general_purpose::STANDARD.encode([0u8; 32])
+            )])),
+            ..Default::default()
+        },
+    ]);
+
+    // HTTPS-only backend reporting the SNI and ALPN it saw
+    let key = rcgen::KeyPair::from_pem(&backend_key).unwrap();
+    let mut server_config =
+        rustls::ServerConfig::builder_with_provider(sniproxy_core::termination::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![backend_cert],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+            )
+            .unwrap();
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(server_config));
+    let backend = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = backend.accept().await {
+            let Ok(mut tls) = acceptor.accept(socket).await else {
+                continue;
+            };
+            let (_, session) = tls.get_ref();
+            let sni = session.server_name().unwrap_or("-").to_string();
+            let alpn =
+                String::from_utf8_lossy(session.alpn_protocol().unwrap_or(b"-")).into_owned();
+
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                tls.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+            let body = format!(
+                "{} {} {}",
+                sni,
+                alpn,
+                String::from_utf8_lossy(&head).lines().next().unwrap()
+            );
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            tls.write_all(response.as_bytes()).await.unwrap();
+            let _ = tls.shutdown().await;
+        }
+    });
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+
+    sleep(Duration::from_millis(800)).await;
+
+    let response = send_http_request(proxy_port, "legacy.origin.test").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(
+        response.ends_with("backend.origin.test http/1.1 GET / HTTP/1.1"),
+        "{}",
+        response
+    );
+
+    // A backend key matching no pin is refused like an unreachable backend
+    let response = send_http_request(proxy_port, "pinned.origin.test").await;
+    assert!(response.starts_with("HTTP/1.1 502"), "{}", response);
+
+    proxy_handle.abort();
+    let _ = std::fs::remove_dir_all(&cert_dir);
+
+    println!("✅ Plain HTTP request forwarded over verified, pinned TLS to the backend");
+}
+
+/// h2c backend answering every call with its name and the path it received
+async fn start_h2c_backend(port: u16, name: &'static str) {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let Ok(mut connection) = h2::server::handshake(socket).await else {
+                    return;
+                };
+                while let Some(Ok((request, mut respond))) = connection.accept().await {
+                    let response = hyper::Response::builder()
+                        .status(200)
+                        .header("content-type", "application/grpc")
+                        .header("x-backend", name)
+                        .header("x-path", request.uri().path())
+                        .header("grpc-status", "0")
+                        .body(())
+                        .unwrap();
+                    let _ = respond.send_response(response, true);
+                }
+            });
+        }
+    });
+}
+
+/// Sends a gRPC call without a body, returning the response headers
+async fn grpc_call(
+    client: &mut h2::client::SendRequest<bytes::Bytes>,
+    path: &str,
+) -> Result<hyper::HeaderMap, h2::Error> {
+    let request = hyper::Request::builder()
+        .method("POST")
+        .uri(format!("http://grpc.test{}", path))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())
+        .unwrap();
+    let mut ready = client.clone().ready().await?;
+    let (response, _) = ready.send_request(request, true)?;
+    Ok(response.await?.headers().clone())
+}
+
+async fn h2c_connect(proxy_port: u16) -> h2::client::SendRequest<bytes::Bytes> {
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let (client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    client
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_grpc_h2c_routing_by_method_path() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let greeter_port = find_available_port().await;
+    let ledger_port = find_available_port().await;
+    start_h2c_backend(greeter_port, "greeter").await;
+    start_h2c_backend(ledger_port, "ledger").await;
+
+    let rule = |path: &str, upstream: Option<u16>, deny: bool| sniproxy_config::GrpcRule {
+        path: path.to_string(),
+        upstream: upstream.map(|port| format!("127.0.0.1:{}", port)),
+        deny,
+    };
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "grpc.test".to_string(),
+        grpc_rules: vec![
+            rule("/helloworld.Greeter/*", Some(greeter_port), false),
+            rule("/billing.Ledger/*", Some(ledger_port), false),
+            rule("/admin.*", None, true),
+        ],
+        ..Default::default()
+    }]);
+
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    // Calls for the same backend share a connection
+    let mut client = h2c_connect(proxy_port).await;
+    for path in [
+        "/helloworld.Greeter/SayHello",
+        "/helloworld.Greeter/SayGoodbye",
+    ] {
+        let headers = grpc_call(&mut client, path).await.unwrap();
+        assert_eq!(headers["x-backend"], "greeter");
+        assert_eq!(headers["x-path"], path);
+    }
+
+    let mut client = h2c_connect(proxy_port).await;
+    let headers = grpc_call(&mut client, "/billing.Ledger/Post")
+        .await
+        .unwrap();
+    assert_eq!(headers["x-backend"], "ledger");
+
+    // A denied first call gets a Trailers-Only PERMISSION_DENIED response
+    let mut client = h2c_connect(proxy_port).await;
+    let headers = grpc_call(&mut client, "/admin.Console/Shutdown")
+        .await
+        .unwrap();
+    assert_eq!(headers["grpc-status"], "7");
+    assert!(!headers.contains_key("x-backend"));
+
+    // Later calls that are denied or belong elsewhere close the connection
+    for path in ["/admin.Console/Shutdown", "/billing.Ledger/Post"] {
+        let mut client = h2c_connect(proxy_port).await;
+        grpc_call(&mut client, "/helloworld.Greeter/SayHello")
+            .await
+            .unwrap();
+        assert!(grpc_call(&mut client, path).await.is_err(), "{}", path);
+    }
+
+    let counter = |service: &str, result: &str| {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.name() == "sniproxy_grpc_requests_total")
+            .flat_map(|family| family.get_metric().iter())
+            .find(|metric| {
+                let labels: Vec<_> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| (label.name(), label.value()))
+                    .collect();
+                labels.contains(&("service", service)) && labels.contains(&("result", result))
+            })
+            .map_or(0.0, |metric| metric.get_counter().value())
+    };
+    assert_eq!(counter("helloworld.Greeter", "forwarded"), 4.0);
+    assert_eq!(counter("billing.Ledger", "forwarded"), 1.0);
+    assert_eq!(counter("billing.Ledger", "misrouted"), 1.0);
+    assert_eq!(counter("admin.Console", "denied"), 2.0);
+
+    proxy_handle.abort();
+
+    println!("✅ gRPC calls over h2c routed and filtered by method path");
+}
+
+/// h2c backend echoing each call's body, counting the connections it accepts
+async fn start_h2c_echo_backend(
+    port: u16,
+    connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+) {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::spawn(async move {
+                let Ok(mut connection) = h2::server::handshake(socket).await else {
+                    return;
+                };
+                while let Some(Ok((request, mut respond))) = connection.accept().await {
+                    tokio::spawn(async move {
+                        let mut body = request.into_body();
+                        let mut message = Vec::new();
+                        while let Some(Ok(chunk)) = body.data().await {
+                            let _ = body.flow_control().release_capacity(chunk.len());
+                            message.extend_from_slice(&chunk);
+                        }
+                        let response = hyper::Response::builder()
+                            .status(200)
+                            .header("content-type", "application/grpc")
+                            .body(())
+                            .unwrap();
+                        let mut stream = respond.send_response(response, false).unwrap();
+                        stream.send_data(message.into(), false).unwrap();
+                        let mut trailers = hyper::HeaderMap::new();
+                        trailers.insert("grpc-status", "0".parse().unwrap());
+                        stream.send_trailers(trailers).unwrap();
+                    });
+                }
+            });
+        }
+    });
+}
+
+/// Sends a gRPC call with `message` as its body, returning the echoed body and grpc-status
+async fn grpc_echo_call(
+    client: &mut h2::client::SendRequest<bytes::Bytes>,
+    path: &str,
+    message: &[u8],
+) -> (Vec<u8>, String) {
+    let request = hyper::Request::builder()
+        .method("POST")
+        .uri(format!("http://grpc.test{}", path))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())
+        .unwrap();
+    let mut ready = client.clone().ready().await.unwrap();
+    let (response, mut request_body) = ready.send_request(request, false).unwrap();
+    // Refused calls may be answered and closed before the body is sent
+    let _ = request_body.send_data(bytes::Bytes::copy_from_slice(message), true);
+
+    let response = response.await.unwrap();
+    let headers = response.headers().clone();
+    // A Trailers-Only response carries the status in its headers and has no body
+    if let Some(status) = headers.get("grpc-status") {
+        return (Vec::new(), status.to_str().unwrap().to_string());
+    }
+    let mut body = response.into_body();
+    let mut echoed = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        let _ = body.flow_control().release_capacity(chunk.len());
+        echoed.extend_from_slice(&chunk);
+    }
+    let trailers = body.trailers().await.unwrap().unwrap_or_default();
+    let status = trailers
+        .get("grpc-status")
+        .map(|value| value.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    (echoed, status)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_grpc_h2c_pooled_channels() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     let proxy_port = find_available_port().await;
     let metrics_port = find_available_port().await;
-    let config = create_test_config(proxy_port, metrics_port);
+    let greeter_port = find_available_port().await;
+    let ledger_port = find_available_port().await;
+    let greeter_connections = Arc::new(AtomicUsize::new(0));
+    let ledger_connections = Arc::new(AtomicUsize::new(0));
+    start_h2c_echo_backend(greeter_port, greeter_connections.clone()).await;
+    start_h2c_echo_backend(ledger_port, ledger_connections.clone()).await;
+
+    let rule = |path: &str, upstream: Option<u16>, deny: bool| sniproxy_config::GrpcRule {
+        path: path.to_string(),
+        upstream: upstream.map(|port| format!("127.0.0.1:{}", port)),
+        deny,
+    };
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.grpc_pool = Some(sniproxy_config::GrpcPool {
+        max_channels_per_host: 1,
+        ..Default::default()
+    });
+    config.routes = Some(vec![sniproxy_config::Route {
+        host: "grpc.test".to_string(),
+        grpc_rules: vec![
+            rule("/helloworld.Greeter/*", Some(greeter_port), false),
+            rule("/billing.Ledger/*", Some(ledger_port), false),
+            rule("/admin.*", None, true),
+        ],
+        ..Default::default()
+    }]);
 
     let proxy_handle = tokio::spawn(async move {
         let registry = Registry::new();
         let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
         let _ = run_proxy(config, Some(registry), shutdown_rx).await;
     });
+    sleep(Duration::from_millis(800)).await;
+
+    // Calls from several clients share one channel per backend
+    for _ in 0..3 {
+        let mut client = h2c_connect(proxy_port).await;
+        let (echoed, status) = grpc_echo_call(
+            &mut client,
+            "/helloworld.Greeter/SayHello",
+            b"\0\0\0\0\x01a",
+        )
+        .await;
+        assert_eq!(echoed, b"\0\0\0\0\x01a");
+        assert_eq!(status, "0");
+    }
+    assert_eq!(greeter_connections.load(Ordering::SeqCst), 1);
+
+    // One client connection reaches different backends, and a denied call
+    // no longer closes it
+    let mut client = h2c_connect(proxy_port).await;
+    let (_, status) = grpc_echo_call(&mut client, "/admin.Console/Shutdown", b"").await;
+    assert_eq!(status, "7");
+    let (echoed, status) = grpc_echo_call(&mut client, "/billing.Ledger/Post", b"ledger").await;
+    assert_eq!((echoed.as_slice(), status.as_str()), (&b"ledger"[..], "0"));
+
+    // Bodies larger than the initial flow-control window are relayed whole
+    let large = vec![7u8; 256 * 1024];
+    let (echoed, status) = grpc_echo_call(&mut client, "/helloworld.Greeter/Upload", &large).await;
+    assert_eq!(status, "0");
+    assert_eq!(echoed.len(), large.len());
+    assert_eq!(greeter_connections.load(Ordering::SeqCst), 1);
+    assert_eq!(ledger_connections.load(Ordering::SeqCst), 1);
+
+    proxy_handle.abort();
+
+    println!("✅ gRPC calls multiplexed onto pooled backend channels");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_h2c_host_outside_allowlist_is_refused() {
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
 
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.allowlist = Some(vec!["allowed.test".to_string()]);
+
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
     sleep(Duration::from_millis(800)).await;
 
-    // Send 10 concurrent requests
-    let mut handles = vec![];
+    // gRPC calls get a Trailers-Only PERMISSION_DENIED response
+    let mut client = h2c_connect(proxy_port).await;
+    let headers = grpc_call(&mut client, "/helloworld.Greeter/SayHello")
+        .await
+        .unwrap();
+    assert_eq!(headers["grpc-status"], "7");
+    assert_eq!(headers["grpc-message"], "Host not in allowlist");
+
+    // Plain h2c requests get a 403
+    let client = h2c_connect(proxy_port).await;
+    let request = hyper::Request::builder()
+        .uri("http://blocked.test/")
+        .body(())
+        .unwrap();
+    let mut ready = client.ready().await.unwrap();
+    let (response, _) = ready.send_request(request, true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 403);
+
+    sleep(Duration::from_millis(100)).await;
+    let errors: f64 = registry
+        .gather()
+        .iter()
+        .filter(|family| family.name() == "sniproxy_errors_total")
+        .flat_map(|family| family.get_metric().iter())
+        .map(|metric| metric.get_counter().value())
+        .sum();
+    assert_eq!(errors, 2.0);
 
-    for i in 0..10 {
-        let proxy_addr = format!("127.0.0.1:{}", proxy_port);
-        let backend_port_clone = backend_port;
+    proxy_handle.abort();
 
-        let handle = tokio::spawn(async move {
-            let mut stream = TcpStream::connect(proxy_addr)
-                .await
-                .expect("Failed to connect to proxy");
+    println!("✅ h2c requests for hosts outside the allowlist are refused");
+}
 
-            let request = format!(
-                "GET /test{} HTTP/1.1\r\n\
-Host: 127.0.0.1:{}\r\n\
-Connection: close\r\n\
-\r\n",
-                i, backend_port_clone
-            );
+/// HTTP/1.1 keep-alive backend answering each request with its name and path
+///
+/// With `close_idle`, the backend closes each connection after one response
+/// even though the response allows keep-alive.
+async fn start_keep_alive_backend(
+    port: u16,
+    name: &'static str,
+    connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    close_idle: bool,
+) {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut buffer = Vec::new();
+                loop {
+                    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let mut chunk = [0u8; 1024];
+                        match socket.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let end = buffer.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+                    let head: Vec<u8> = buffer.drain(..end).collect();
+                    let head = String::from_utf8_lossy(&head);
+                    let path = head.split(' ').nth(1).unwrap_or("-");
+                    let body = format!("{} {}", name, path);
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    if socket.write_all(response.as_bytes()).await.is_err() || close_idle {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+}
 
-            stream
-                .write_all(request.as_bytes())
-                .await
-                .expect("Failed to send request");
+/// Reads one response with a Content-Length body, returning status line and body
+async fn read_http_response(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> (String, String) {
+    let read_more = async |stream: &mut TcpStream, buffer: &mut Vec<u8>| {
+        let mut chunk = [0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .expect("Timeout reading response")
+            .expect("Failed to read response");
+        assert!(n > 0, "Connection closed mid-response");
+        buffer.extend_from_slice(&chunk[..n]);
+    };
 
-            // Read response with timeout
-            let mut response = vec![0u8; 4096];
-            let read_future = stream.read(&mut response);
-            let bytes_read = match tokio::time::timeout(Duration::from_secs(5), read_future).await {
-                Ok(Ok(n)) => n,
-                _ => 0,
-            };
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        read_more(stream, buffer).await;
+    }
+    let end = buffer.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&buffer.drain(..end).collect::<Vec<_>>()).to_string();
+    let length: usize = head
+        .lines()
+        .find_map(|line| {
+            line.to_lowercase()
+                .strip_prefix("content-length:")
+                .map(|v| v.trim().parse().unwrap())
+        })
+        .unwrap_or(0);
+    while buffer.len() < length {
+        read_more(stream, buffer).await;
+    }
+    let body = String::from_utf8_lossy(&buffer.drain(..length).collect::<Vec<_>>()).to_string();
+    (head.lines().next().unwrap_or_default().to_string(), body)
+}
 
-            bytes_read > 0 && String::from_utf8_lossy(&response[..bytes_read]).contains("200 OK")
-        });
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_http_keep_alive_routes_every_request() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-        handles.push(handle);
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let alpha_port = find_available_port().await;
+    let beta_port = find_available_port().await;
+    let denied_port = find_available_port().await;
+    let alpha_connections = Arc::new(AtomicUsize::new(0));
+    let beta_connections = Arc::new(AtomicUsize::new(0));
+    start_keep_alive_backend(alpha_port, "alpha", alpha_connections.clone(), false).await;
+    start_keep_alive_backend(beta_port, "beta", beta_connections.clone(), false).await;
+
+    let alpha = format!("127.0.0.1:{}", alpha_port);
+    let beta = format!("127.0.0.1:{}", beta_port);
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.allowlist = Some(vec![alpha.clone(), beta.clone()]);
+
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let mut buffer = Vec::new();
+
+    let request = format!("GET /one HTTP/1.1\r\nHost: {}\r\n\r\n", alpha);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let (status, body) = read_http_response(&mut stream, &mut buffer).await;
+    assert_eq!(
+        (status.as_str(), body.as_str()),
+        ("HTTP/1.1 200 OK", "alpha /one")
+    );
+
+    // Pipelined requests for different hosts each reach their own backend
+    let requests = format!(
+        "POST /two HTTP/1.1\r\nHost: {}\r\nContent-Length: 4\r\n\r\nbodyGET /three HTTP/1.1\r\nHost: {}\r\n\r\n",
+        beta, alpha
+    );
+    stream.write_all(requests.as_bytes()).await.unwrap();
+    let (_, body) = read_http_response(&mut stream, &mut buffer).await;
+    assert_eq!(body, "beta /two");
+    let (_, body) = read_http_response(&mut stream, &mut buffer).await;
+    assert_eq!(body, "alpha /three");
+    assert_eq!(alpha_connections.load(Ordering::SeqCst), 2);
+    assert_eq!(beta_connections.load(Ordering::SeqCst), 1);
+
+    // A later request for a host outside the allowlist is refused
+    let request = format!(
+        "GET /four HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+        denied_port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let (status, _) = read_http_response(&mut stream, &mut buffer).await;
+    assert!(status.starts_with("HTTP/1.1 403"), "{}", status);
+
+    proxy_handle.abort();
+
+    println!("✅ Every request on a keep-alive connection routed and checked on its own Host");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_http_connection_pool_reuses_backend_connections() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let alpha_port = find_available_port().await;
+    let beta_port = find_available_port().await;
+    let alpha_connections = Arc::new(AtomicUsize::new(0));
+    let beta_connections = Arc::new(AtomicUsize::new(0));
+    start_keep_alive_backend(alpha_port, "alpha", alpha_connections.clone(), false).await;
+    start_keep_alive_backend(beta_port, "beta", beta_connections.clone(), true).await;
+
+    let alpha = format!("127.0.0.1:{}", alpha_port);
+    let beta = format!("127.0.0.1:{}", beta_port);
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.connection_pool = Some(sniproxy_config::ConnectionPool::default());
+
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    // Each request comes from a separate client connection
+    let request_once = async |host: &str, path: &str| {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+            .await
+            .expect("Failed to connect to proxy");
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let (status, body) = read_http_response(&mut stream, &mut Vec::new()).await;
+        drop(stream);
+        // Let the proxy see the client close and return the backend connection
+        sleep(Duration::from_millis(100)).await;
+        (status, body)
+    };
+
+    // Later clients reuse the idle backend connection
+    for path in ["/one", "/two", "/three"] {
+        let (status, body) = request_once(&alpha, path).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, format!("alpha {}", path));
     }
+    assert_eq!(alpha_connections.load(Ordering::SeqCst), 1);
 
-    // Wait for all requests to complete
-    let mut success_count = 0;
-    for handle in handles {
-        if handle.await.unwrap() {
-            success_count += 1;
+    // A pooled connection the backend closed is never handed out
+    for path in ["/one", "/two"] {
+        let (status, body) = request_once(&beta, path).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, format!("beta {}", path));
+    }
+    assert_eq!(beta_connections.load(Ordering::SeqCst), 2);
+
+    let metric = |name: &str| {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.name() == name)
+            .flat_map(|family| family.get_metric().iter())
+            .map(|metric| {
+                if metric.get_gauge().has_value() {
+                    metric.get_gauge().value()
+                } else {
+                    metric.get_counter().value()
+                }
+            })
+            .sum::<f64>()
+    };
+    assert_eq!(metric("sniproxy_pool_hits_total"), 2.0);
+    assert_eq!(metric("sniproxy_pool_evictions_total"), 1.0);
+    // Requests two and three rode the connection opened for request one
+    assert_eq!(metric("sniproxy_keep_alive_reuses_total"), 2.0);
+    // Every checked-out connection was returned or closed
+    assert_eq!(metric("sniproxy_pool_active_connections"), 0.0);
+    assert_eq!(metric("sniproxy_pool_size"), 2.0);
+
+    proxy_handle.abort();
+
+    println!("✅ Idle HTTP/1.1 backend connections reused across client connections");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_http_retries_request_on_closed_reused_connection() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let backend_port = find_available_port().await;
+
+    // The backend answers the first request on a connection and closes the
+    // connection when a second one arrives, as if its idle timeout had fired
+    let connections = Arc::new(AtomicUsize::new(0));
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", backend_port))
+        .await
+        .expect("Failed to bind backend");
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut buffer = Vec::new();
+                let mut served = 0;
+                loop {
+                    let mut chunk = [0u8; 1024];
+                    match socket.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                    }
+                    if !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                        continue;
+                    }
+                    if served == 1 {
+                        return;
+                    }
+                    served += 1;
+                    let head = String::from_utf8_lossy(&buffer).to_string();
+                    buffer.clear();
+                    let path = head.split(' ').nth(1).unwrap_or("-").to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        path.len(),
+                        path
+                    );
+                    if socket.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    let backend = format!("127.0.0.1:{}", backend_port);
+    let config = create_test_config(proxy_port, metrics_port);
+    let proxy_handle = tokio::spawn(async move {
+        let registry = Registry::new();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let mut buffer = Vec::new();
+
+    // A request without a body is retried on a new backend connection
+    for path in ["/one", "/two"] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, backend);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let (status, body) = read_http_response(&mut stream, &mut buffer).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, path);
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    // A request with a body may already have been processed, so it is not
+    let request = format!(
+        "POST /three HTTP/1.1\r\nHost: {}\r\nContent-Length: 4\r\n\r\nbody",
+        backend
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let (status, _) = read_http_response(&mut stream, &mut buffer).await;
+    assert!(status.starts_with("HTTP/1.1 502"), "{}", status);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    proxy_handle.abort();
+
+    println!("✅ Bodyless request retried after a reused backend connection closed");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_websocket_upgrade_relayed_frame_by_frame() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let echo_port = find_available_port().await;
+    let forged_port = find_available_port().await;
+
+    // WebSocket echo backend
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", echo_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let Ok(mut ws) = tokio_tungstenite::accept_async(socket).await else {
+                    return;
+                };
+                while let Some(Ok(message)) = ws.next().await {
+                    if message.is_text() || message.is_binary() {
+                        let _ = ws.send(message).await;
+                    }
+                }
+            });
+        }
+    });
+
+    // Backend answering every handshake with a wrong Sec-WebSocket-Accept
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", forged_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket
+                .write_all(
+                    b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                      Connection: Upgrade\r\nSec-WebSocket-Accept: Zm9yZ2VkIGFjY2VwdCBrZXk=\r\n\r\n",
+                )
+                .await;
         }
+    });
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.websocket = Some(sniproxy_config::WebSocket {
+        max_message_size: 1024,
+        ..Default::default()
+    });
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let (mut ws, response) =
+        tokio_tungstenite::client_async(format!("ws://127.0.0.1:{}/chat", echo_port), stream)
+            .await
+            .expect("WebSocket handshake through the proxy failed");
+    assert_eq!(response.status(), 101);
+
+    ws.send(Message::text("hello")).await.unwrap();
+    let echoed = tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .expect("Timeout waiting for echo")
+        .unwrap()
+        .unwrap();
+    assert_eq!(echoed, Message::text("hello"));
+
+    // A message over the limit ends the tunnel with 1009
+    ws.send(Message::binary(vec![0u8; 2000])).await.unwrap();
+    let closed = tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .expect("Timeout waiting for close")
+        .unwrap()
+        .unwrap();
+    match closed {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Size),
+        other => panic!("expected a close frame, got {:?}", other),
     }
 
+    let counter = |name: &str, labels: &[(&str, &str)]| {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.name() == name)
+            .flat_map(|family| family.get_metric().iter())
+            .find(|metric| {
+                labels.iter().all(|(name, value)| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|label| label.name() == *name && label.value() == *value)
+                })
+            })
+            .map_or(0.0, |metric| metric.get_counter().value())
+    };
+    let messages = "sniproxy_websocket_messages_total";
     assert_eq!(
-        success_count, 10,
-        "All 10 concurrent requests should succeed"
+        counter(messages, &[("direction", "tx"), ("type", "text")]),
+        1.0
+    );
+    assert_eq!(
+        counter(messages, &[("direction", "rx"), ("type", "text")]),
+        1.0
+    );
+    assert_eq!(
+        counter(messages, &[("direction", "tx"), ("type", "binary")]),
+        0.0
     );
 
-    // Cleanup
+    // Handshakes are checked on both sides
+    let handshake = |port: u16, key: &str| {
+        format!(
+            "GET /chat HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            port, key
+        )
+    };
+    for (request, expected) in [
+        (handshake(echo_port, "bm90IDE2IGJ5dGVz"), "HTTP/1.1 400"),
+        (
+            handshake(forged_port, "dGhlIHNhbXBsZSBub25jZQ=="),
+            "HTTP/1.1 502",
+        ),
+    ] {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+            .await
+            .expect("Failed to connect to proxy");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let (status, _) = read_http_response(&mut stream, &mut Vec::new()).await;
+        assert!(status.starts_with(expected), "{}", status);
+    }
+
     proxy_handle.abort();
-    backend_handle.abort();
 
-    println!("✅ Proxy handles multiple concurrent connections");
+    println!("✅ WebSocket handshake validated and frames relayed with limits");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_websocket_compression_negotiated_by_proxy() {
+    use futures::{SinkExt, StreamExt};
+    use sniproxy_core::websocket::FrameHeader;
+    use sniproxy_core::websocket_compression::{WebSocketCompression, WebSocketCompressionConfig};
+
+    let proxy_port = find_available_port().await;
+    let metrics_port = find_available_port().await;
+    let echo_port = find_available_port().await;
+
+    // Echo backend without permessage-deflate support
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", echo_port))
+        .await
+        .expect("Failed to bind backend");
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let Ok(mut ws) = tokio_tungstenite::accept_async(socket).await else {
+                    return;
+                };
+                while let Some(Ok(message)) = ws.next().await {
+                    if message.is_text() || message.is_binary() {
+                        let _ = ws.send(message).await;
+                    }
+                }
+            });
+        }
+    });
+
+    let mut config = create_test_config(proxy_port, metrics_port);
+    config.websocket = Some(sniproxy_config::WebSocket {
+        compression: Some(Default::default()),
+        ..Default::default()
+    });
+    let registry = Registry::new();
+    let proxy_registry = registry.clone();
+    let proxy_handle = tokio::spawn(async move {
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let _ = run_proxy(config, Some(proxy_registry), shutdown_rx).await;
+    });
+    sleep(Duration::from_millis(800)).await;
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let handshake = format!(
+        "GET /chat HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\
+         Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n",
+        echo_port
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+
+    let mut buffer = Vec::new();
+    let read_more = async |stream: &mut TcpStream, buffer: &mut Vec<u8>| {
+        let mut chunk = [0u8; 4096];
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .expect("Timeout reading from proxy")
+            .expect("Failed to read from proxy");
+        assert!(n > 0, "Proxy closed the connection");
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        read_more(&mut stream, &mut buffer).await;
+    }
+    let end = buffer.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&buffer.drain(..end).collect::<Vec<_>>()).to_string();
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(
+        head.contains("Sec-WebSocket-Extensions: permessage-deflate\r\n"),
+        "{}",
+        head
+    );
+
+    // The client's side of the extension
+    let compression = WebSocketCompression::new(WebSocketCompressionConfig::default());
+    let mut deflater = compression.deflater();
+    let mut inflater = compression.inflater();
+
+    for round in 0..2 {
+        let text = format!("round {} of a compressible message. ", round).repeat(50);
+        let compressed = deflater.compress(text.as_bytes()).unwrap();
+        assert!(compressed.len() < 126);
+        let key = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0xC1, 0x80 | compressed.len() as u8];
+        frame.extend_from_slice(&key);
+        frame.extend(
+            compressed
+                .iter()
+                .zip(key.iter().cycle())
+                .map(|(b, k)| b ^ k),
+        );
+        stream.write_all(&frame).await.unwrap();
+
+        let header = loop {
+            if let Some(header) = FrameHeader::parse(&buffer).unwrap() {
+                break header;
+            }
+            read_more(&mut stream, &mut buffer).await;
+        };
+        let end = header.header_len + header.payload_len as usize;
+        while buffer.len() < end {
+            read_more(&mut stream, &mut buffer).await;
+        }
+        assert!(header.rsv1 && !header.masked);
+        let payload: Vec<u8> = buffer.drain(..end).skip(header.header_len).collect();
+        let echoed = inflater.decompress(&payload, 1 << 20).unwrap().unwrap();
+        assert_eq!(echoed, text.as_bytes());
+    }
+
+    let counter = |name: &str, direction: &str| {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.name() == name)
+            .flat_map(|family| family.get_metric().iter())
+            .find(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.name() == "direction" && label.value() == direction)
+            })
+            .map_or(0.0, |metric| metric.get_counter().value())
+    };
+    for direction in ["tx", "rx"] {
+        assert!(
+            counter(
+                "sniproxy_websocket_compression_saved_bytes_total",
+                direction
+            ) > 1000.0
+        );
+    }
+
+    proxy_handle.abort();
+
+    println!("✅ Proxy negotiated permessage-deflate and recompressed frames");
 }
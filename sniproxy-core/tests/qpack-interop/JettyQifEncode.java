@@ -0,0 +1,227 @@
+import java.io.ByteArrayOutputStream;
+import java.io.DataOutputStream;
+import java.io.IOException;
+import java.nio.ByteBuffer;
+import java.nio.charset.StandardCharsets;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.util.ArrayList;
+import java.util.List;
+
+import org.eclipse.jetty.http.HttpField;
+import org.eclipse.jetty.http.HttpFields;
+import org.eclipse.jetty.http.HttpVersion;
+import org.eclipse.jetty.http.MetaData;
+import org.eclipse.jetty.http3.qpack.Instruction;
+import org.eclipse.jetty.http3.qpack.QpackDecoder;
+import org.eclipse.jetty.http3.qpack.QpackEncoder;
+import org.eclipse.jetty.io.ByteBufferPool;
+
+/**
+ * Encodes a .qif file with Jetty's QPACK encoder into the qpack-offline-interop
+ * format: <name>.out.<table capacity>.<max blocked streams>.<ack mode>.
+ *
+ * Header blocks are written before the encoder instructions they depend on and
+ * encoder instructions are flushed every few blocks, so a decoder with a
+ * non-zero blocked-streams limit has to park sections until the encoder stream
+ * catches up. Every file is decoded again with Jetty's decoder as a self-check.
+ *
+ * Usage: java JettyQifEncode <file.qif> <capacity> <max blocked> <ack mode> <output dir>
+ */
+public class JettyQifEncode
+{
+    private static final int FLUSH_EVERY = 3;
+
+    public static void main(String[] args) throws Exception
+    {
+        Path qif = Path.of(args[0]);
+        int capacity = Integer.parseInt(args[1]);
+        int maxBlocked = Integer.parseInt(args[2]);
+        boolean ackMode = Integer.parseInt(args[3]) == 1;
+
+        List<HttpFields> lists = parseQif(qif);
+        List<Instruction> pending = new ArrayList<>();
+        QpackEncoder encoder = new QpackEncoder(pending::addAll);
+        encoder.setMaxTableCapacity(capacity);
+        encoder.setTableCapacity(capacity);
+        encoder.setMaxBlockedStreams(maxBlocked);
+
+        long inserted = 0;
+        long known = 0;
+        ByteArrayOutputStream out = new ByteArrayOutputStream();
+        DataOutputStream data = new DataOutputStream(out);
+        for (int i = 0; i < lists.size(); i++)
+        {
+            long streamId = i + 1;
+            ByteBuffer block = ByteBuffer.allocate(64 * 1024);
+            encoder.encode(block, streamId, new MetaData(HttpVersion.HTTP_3, lists.get(i)));
+            block.flip();
+            inserted += countInserts(pending);
+            // With no blocking allowed the encoder never references unacknowledged
+            // entries, but keep the instructions ahead of the block all the same.
+            if (maxBlocked == 0)
+                flush(data, pending);
+            writeChunk(data, streamId, bytes(block));
+            if (maxBlocked > 0 && (i + 1) % FLUSH_EVERY == 0)
+                flush(data, pending);
+            if (ackMode)
+            {
+                // Acknowledge the way an eager decoder would: a Section Acknowledgment
+                // for sections that reference the dynamic table, then an Insert Count
+                // Increment for any inserts the acknowledgment did not cover.
+                flush(data, pending);
+                long required = requiredInsertCount(bytes(block), capacity, inserted);
+                if (required > 0)
+                {
+                    encoder.parseInstructions(prefixed(0x80, 7, streamId));
+                    known = Math.max(known, required);
+                }
+                if (inserted > known)
+                {
+                    encoder.parseInstructions(prefixed(0x00, 6, inserted - known));
+                    known = inserted;
+                }
+            }
+        }
+        flush(data, pending);
+
+        String name = qif.getFileName().toString().replaceFirst("\\.qif$", "");
+        Path target = Path.of(args[4]).resolve(name + ".out." + capacity + "." + maxBlocked + "." + (ackMode ? 1 : 0));
+        Files.write(target, out.toByteArray());
+        verify(out.toByteArray(), capacity, maxBlocked, lists.size());
+        System.out.println(target);
+    }
+
+    private static List<HttpFields> parseQif(Path qif) throws IOException
+    {
+        List<HttpFields> lists = new ArrayList<>();
+        HttpFields.Mutable current = null;
+        for (String line : Files.readAllLines(qif, StandardCharsets.UTF_8))
+        {
+            if (line.startsWith("#"))
+                continue;
+            if (line.isEmpty())
+            {
+                if (current != null)
+                    lists.add(current.asImmutable());
+                current = null;
+                continue;
+            }
+            int tab = line.indexOf('\t');
+            if (current == null)
+                current = HttpFields.build();
+            current.add(new HttpField(line.substring(0, tab), line.substring(tab + 1)));
+        }
+        if (current != null)
+            lists.add(current.asImmutable());
+        return lists;
+    }
+
+    private static long countInserts(List<Instruction> instructions)
+    {
+        return instructions.stream()
+            .filter(instruction -> !instruction.getClass().getSimpleName().equals("SetCapacityInstruction"))
+            .count();
+    }
+
+    private static long requiredInsertCount(byte[] block, int capacity, long totalInserts)
+    {
+        // RFC 9204, section 4.5.1.1.
+        long encoded = prefixedValue(block, 8);
+        if (encoded == 0)
+            return 0;
+        long maxEntries = capacity / 32;
+        long fullRange = 2 * maxEntries;
+        long maxValue = totalInserts + maxEntries;
+        long required = maxValue / fullRange * fullRange + encoded - 1;
+        return required > maxValue ? required - fullRange : required;
+    }
+
+    private static long prefixedValue(byte[] bytes, int prefix)
+    {
+        int mask = (1 << prefix) - 1;
+        long value = bytes[0] & mask;
+        if (value < mask)
+            return value;
+        int shift = 0;
+        for (int i = 1; ; i++)
+        {
+            value += (long)(bytes[i] & 0x7f) << shift;
+            if ((bytes[i] & 0x80) == 0)
+                return value;
+            shift += 7;
+        }
+    }
+
+    private static ByteBuffer prefixed(int pattern, int prefix, long value)
+    {
+        ByteArrayOutputStream out = new ByteArrayOutputStream();
+        int mask = (1 << prefix) - 1;
+        if (value < mask)
+        {
+            out.write(pattern | (int)value);
+        }
+        else
+        {
+            out.write(pattern | mask);
+            long rest = value - mask;
+            while (rest >= 0x80)
+            {
+                out.write((int)(rest & 0x7f) | 0x80);
+                rest >>>= 7;
+            }
+            out.write((int)rest);
+        }
+        return ByteBuffer.wrap(out.toByteArray());
+    }
+
+    private static void flush(DataOutputStream data, List<Instruction> pending) throws IOException
+    {
+        if (pending.isEmpty())
+            return;
+        ByteBufferPool.Accumulator accumulator = new ByteBufferPool.Accumulator();
+        for (Instruction instruction : pending)
+            instruction.encode(ByteBufferPool.NON_POOLING, accumulator);
+        pending.clear();
+        ByteArrayOutputStream stream = new ByteArrayOutputStream();
+        for (ByteBuffer buffer : accumulator.getByteBuffers())
+            stream.write(bytes(buffer));
+        writeChunk(data, 0, stream.toByteArray());
+    }
+
+    private static void writeChunk(DataOutputStream data, long streamId, byte[] payload) throws IOException
+    {
+        data.writeLong(streamId);
+        data.writeInt(payload.length);
+        data.write(payload);
+    }
+
+    private static byte[] bytes(ByteBuffer buffer)
+    {
+        byte[] copy = new byte[buffer.remaining()];
+        buffer.slice().get(copy);
+        return copy;
+    }
+
+    private static void verify(byte[] file, int capacity, int maxBlocked, int expected) throws Exception
+    {
+        int[] decoded = {0};
+        QpackDecoder decoder = new QpackDecoder(instructions -> {});
+        decoder.setMaxTableCapacity(capacity);
+        decoder.setMaxBlockedStreams(maxBlocked);
+        decoder.setBeginNanoTimeSupplier(System::nanoTime);
+        ByteBuffer in = ByteBuffer.wrap(file);
+        while (in.hasRemaining())
+        {
+            long streamId = in.getLong();
+            byte[] payload = new byte[in.getInt()];
+            in.get(payload);
+            if (streamId == 0)
+                decoder.parseInstructions(ByteBuffer.wrap(payload));
+            else
+                decoder.decode(streamId, ByteBuffer.wrap(payload), (id, metaData, wasBlocked) -> decoded[0]++);
+        }
+        if (decoded[0] != expected)
+            throw new IllegalStateException("decoded " + decoded[0] + " of " + expected + " header lists");
+    }
+}
@@ -0,0 +1,201 @@
+/// QPACK Decoder Interop Tests
+///
+/// Decodes field sections produced by other QPACK encoders and checks them
+/// against the header lists they were encoded from. The fixtures under
+/// `tests/qpack-interop` use the qpack-offline-interop layout:
+///
+/// - `qifs/<name>.qif` - header lists, one `name\tvalue` per line, separated
+///   by blank lines
+/// - `encoded/<encoder>/<name>.out.<capacity>.<max blocked>.<ack mode>` -
+///   chunks of an 8-byte stream ID and a 4-byte length, both big-endian;
+///   stream 0 is the encoder stream and stream N carries header list N
+///
+/// Tests included:
+/// - Every encoded file decodes to its `.qif` source
+/// - Files with a non-zero table capacity use the dynamic table
+/// - Files with a blocked-streams limit actually block the decoder
+use sniproxy_core::qpack::{QpackConfig, QpackDecoder};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+type HeaderList = Vec<(String, String)>;
+
+fn interop_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/qpack-interop")
+}
+
+/// Parse a `.qif` file into its header lists
+fn parse_qif(path: &Path) -> Vec<HeaderList> {
+    let text = fs::read_to_string(path).unwrap();
+    let mut lists = Vec::new();
+    let mut current = HeaderList::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if !current.is_empty() {
+                lists.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let (name, value) = line.split_once('\t').unwrap();
+        current.push((name.to_string(), value.to_string()));
+    }
+    if !current.is_empty() {
+        lists.push(current);
+    }
+    lists
+}
+
+/// Parse an encoded file into its `(stream_id, payload)` chunks
+fn parse_encoded(path: &Path) -> Vec<(u64, Vec<u8>)> {
+    let data = fs::read(path).unwrap();
+    let mut chunks = Vec::new();
+    let mut rest = &data[..];
+    while !rest.is_empty() {
+        let stream_id = u64::from_be_bytes(rest[..8].try_into().unwrap());
+        let len = u32::from_be_bytes(rest[8..12].try_into().unwrap()) as usize;
+        chunks.push((stream_id, rest[12..12 + len].to_vec()));
+        rest = &rest[12 + len..];
+    }
+    chunks
+}
+
+/// An encoded file and the decoder settings encoded in its name
+struct EncodedFile {
+    path: PathBuf,
+    qif: PathBuf,
+    capacity: usize,
+    max_blocked: u16,
+    ack_mode: u8,
+}
+
+fn encoded_files() -> Vec<EncodedFile> {
+    let root = interop_dir();
+    let mut files = Vec::new();
+    for encoder in fs::read_dir(root.join("encoded")).unwrap() {
+        for entry in fs::read_dir(encoder.unwrap().path()).unwrap() {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let (name, settings) = file_name.split_once(".out.").unwrap();
+            let settings: Vec<&str> = settings.split('.').collect();
+            files.push(EncodedFile {
+                qif: root.join("qifs").join(format!("{}.qif", name)),
+                capacity: settings[0].parse().unwrap(),
+                max_blocked: settings[1].parse().unwrap(),
+                ack_mode: settings[2].parse().unwrap(),
+                path,
+            });
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+/// What decoding one file exercised
+#[derive(Default)]
+struct DecodeReport {
+    sections: BTreeMap<u64, HeaderList>,
+    max_blocked_seen: usize,
+    dynamic_inserts: u64,
+}
+
+fn decode_file(file: &EncodedFile) -> DecodeReport {
+    let mut decoder = QpackDecoder::new(QpackConfig {
+        max_table_capacity: file.capacity,
+        max_blocked_streams: file.max_blocked,
+        ..QpackConfig::default()
+    });
+    let mut report = DecodeReport::default();
+
+    for (stream_id, payload) in parse_encoded(&file.path) {
+        if stream_id == 0 {
+            let unblocked = decoder
+                .on_encoder_instructions(&payload)
+                .unwrap_or_else(|e| panic!("{}: encoder stream: {}", file.path.display(), e));
+            for (id, headers) in unblocked {
+                assert!(report.sections.insert(id, headers).is_none());
+            }
+        } else {
+            let decoded = decoder
+                .decode(stream_id, &payload)
+                .unwrap_or_else(|e| panic!("{}: stream {}: {}", file.path.display(), stream_id, e));
+            if let Some(headers) = decoded {
+                assert!(report.sections.insert(stream_id, headers).is_none());
+            }
+            report.max_blocked_seen = report.max_blocked_seen.max(decoder.blocked_streams());
+        }
+    }
+
+    assert_eq!(
+        decoder.blocked_streams(),
+        0,
+        "{}: sections left blocked",
+        file.path.display()
+    );
+    report.dynamic_inserts = decoder.table().insert_count();
+    report
+}
+
+#[test]
+fn test_decode_interop_files() {
+    let files = encoded_files();
+    assert!(!files.is_empty(), "no encoded interop files found");
+
+    for file in &files {
+        let expected = parse_qif(&file.qif);
+        let report = decode_file(file);
+
+        assert_eq!(
+            report.sections.len(),
+            expected.len(),
+            "{}: decoded section count",
+            file.path.display()
+        );
+        for (stream_id, headers) in &report.sections {
+            assert_eq!(
+                headers,
+                &expected[*stream_id as usize - 1],
+                "{}: stream {}",
+                file.path.display(),
+                stream_id
+            );
+        }
+    }
+}
+
+#[test]
+fn test_interop_files_cover_dynamic_table_and_blocking() {
+    let files = encoded_files();
+    for ack_mode in [0, 1] {
+        assert!(
+            files
+                .iter()
+                .any(|f| f.ack_mode == ack_mode && f.capacity > 0),
+            "no file with ack mode {} and a dynamic table",
+            ack_mode
+        );
+    }
+
+    for file in &files {
+        let report = decode_file(file);
+        if file.capacity == 0 {
+            assert_eq!(report.dynamic_inserts, 0, "{}", file.path.display());
+            continue;
+        }
+        assert!(
+            report.dynamic_inserts > 0,
+            "{}: dynamic table unused",
+            file.path.display()
+        );
+        if file.max_blocked > 0 {
+            assert!(
+                report.max_blocked_seen > 0,
+                "{}: decoder never blocked",
+                file.path.display()
+            );
+        }
+    }
+}